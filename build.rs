@@ -0,0 +1,17 @@
+use std::path::Path;
+use wayland_scanner::{generate_code, Side};
+
+// Wayland protocols that neither wayland-protocols nor smithay-client-toolkit
+// ship bindings for.
+fn main() {
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let protocols = [
+        ("blur", "protocols/blur.xml"),
+        ("fractional_scale", "protocols/fractional-scale-v1.xml"),
+    ];
+
+    for (name, path) in protocols.iter() {
+        println!("cargo:rerun-if-changed={}", path);
+        generate_code(path, Path::new(&out_dir).join(format!("{}_client_api.rs", name)), Side::Client);
+    }
+}
@@ -0,0 +1,60 @@
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+// How an animation speeds up and slows down between its start and end.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    // Maps the share of the duration that has passed, between 0 and 1, to
+    // how far along the animation is.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+// A value on its way from one number to another, starting when it was
+// created. With no duration it's at the end straight away.
+#[derive(Debug, Clone, Copy)]
+pub struct Tween {
+    from: f64,
+    to: f64,
+    started: Instant,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl Tween {
+    pub fn new(from: f64, to: f64, duration: f64, easing: Easing) -> Tween {
+        Tween { from, to, started: Instant::now(), duration: Duration::from_secs_f64(duration.max(0.0)), easing }
+    }
+
+    pub fn target(&self) -> f64 {
+        self.to
+    }
+
+    pub fn value(&self, now: Instant) -> f64 {
+        if self.is_done(now) {
+            return self.to;
+        }
+
+        let elapsed = now.saturating_duration_since(self.started).as_secs_f64();
+        self.from + (self.to - self.from) * self.easing.apply(elapsed / self.duration.as_secs_f64())
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.started) >= self.duration
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use zbus::MatchRule;
+use zbus::blocking::{Connection, MessageIterator, Proxy};
+use zbus::message::Type as MessageType;
+use zbus::zvariant::{OwnedValue, Value};
+use crate::Error;
+
+// Whether the desktop prefers dark or light colors, as the freedesktop
+// settings portal reports it. Desktops without a portal have no preference.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorScheme {
+    Default,
+    Dark,
+    Light,
+}
+
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+const SETTINGS: &str = "org.freedesktop.portal.Settings";
+const NAMESPACE: &str = "org.freedesktop.appearance";
+const KEY: &str = "color-scheme";
+
+// The portal's own numbering, 0 is no preference, 1 dark and 2 light.
+static CURRENT: AtomicU32 = AtomicU32::new(0);
+
+pub fn current() -> ColorScheme {
+    match CURRENT.load(Ordering::SeqCst) {
+        1 => ColorScheme::Dark,
+        2 => ColorScheme::Light,
+        _ => ColorScheme::Default,
+    }
+}
+
+// Older portals only have Read, which wraps the value in a second variant.
+fn scheme_of(value: &Value) -> Option<u32> {
+    match value {
+        Value::U32(scheme) => Some(*scheme),
+        Value::Value(inner) => scheme_of(inner),
+        _ => None,
+    }
+}
+
+// Asks the portal once, for the config to be loaded with.
+pub fn read() -> Result<(), Error> {
+    let connection = Connection::session()?;
+    let proxy = Proxy::new(&connection, PORTAL, PATH, SETTINGS)?;
+    let value: OwnedValue = proxy.call("ReadOne", &(NAMESPACE, KEY))
+        .or_else(|_| proxy.call("Read", &(NAMESPACE, KEY)))?;
+
+    if let Some(scheme) = scheme_of(&value) {
+        CURRENT.store(scheme, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+// Calls on_change whenever the desktop switches between dark and light,
+// after which current() has the new preference.
+pub fn watch<F: Fn() + Send + 'static>(on_change: F) -> Result<(), Error> {
+    let connection = Connection::session()?;
+    let rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .interface(SETTINGS)?
+        .member("SettingChanged")?
+        .path(PATH)?
+        .arg(0, NAMESPACE)?
+        .build();
+    let messages = MessageIterator::for_match_rule(rule, &connection, Some(16))?;
+
+    std::thread::spawn(move || {
+        for message in messages.flatten() {
+            let scheme = message.body().deserialize::<(String, String, OwnedValue)>().ok()
+                .filter(|(_, key, _)| key == KEY)
+                .and_then(|(_, _, value)| scheme_of(&value));
+
+            if let Some(scheme) = scheme {
+                if CURRENT.swap(scheme, Ordering::SeqCst) != scheme {
+                    on_change();
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
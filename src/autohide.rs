@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+use crate::animation::Tween;
+use crate::config::Autohide;
+
+// Tracks how far the bar is shown, from 0 when only the strip is left on
+// screen to 1 when it's fully in place. The pointer has to rest on the bar
+// for the dwell time before it slides in, and be gone for the delay before it
+// slides back out.
+pub struct Slide {
+    config: Autohide,
+    hovered: bool,
+    hovered_since: Instant,
+    tween: Tween,
+    current: f64,
+}
+
+impl Slide {
+    pub fn new(config: Autohide) -> Slide {
+        let tween = Tween::new(0.0, 0.0, 0.0, config.easing);
+        Slide { config, hovered: false, hovered_since: Instant::now(), tween, current: 0.0 }
+    }
+
+    pub fn set_hovered(&mut self, hovered: bool) {
+        if hovered != self.hovered {
+            self.hovered = hovered;
+            self.hovered_since = Instant::now();
+        }
+    }
+
+    // Logical pixels the bar is pushed past the edge it's anchored to.
+    pub fn offset(&self, size: f64) -> f64 {
+        (1.0 - self.current) * (size - self.config.strip.max(1.0)).max(0.0)
+    }
+
+    // When the pointer will have been on or off the bar for long enough to
+    // turn it around, if it's not already heading that way.
+    fn turn_at(&self) -> Option<Instant> {
+        let (target, wait) = if self.hovered { (1.0, self.config.dwell) } else { (0.0, self.config.delay) };
+        if target == self.tween.target() {
+            return None;
+        }
+
+        Some(self.hovered_since + Duration::from_secs_f64(wait.max(0.0)))
+    }
+
+    // Moves the slide along, returning true when the bar has to be moved.
+    pub fn advance(&mut self) -> bool {
+        let now = Instant::now();
+        if self.turn_at().is_some_and(|at| now >= at) {
+            let target = if self.hovered { 1.0 } else { 0.0 };
+            self.tween = Tween::new(self.tween.value(now), target, self.config.duration, self.config.easing);
+        }
+
+        let shown = self.tween.value(now);
+        let moved = shown != self.current;
+        self.current = shown;
+        moved
+    }
+
+    // While sliding the bar moves every frame, frames being the given time
+    // apart.
+    pub fn next_update(&self, frame: Duration) -> Option<Instant> {
+        if self.current != self.tween.target() {
+            return Some(Instant::now() + frame);
+        }
+
+        self.turn_at()
+    }
+}
@@ -0,0 +1,1748 @@
+use glium::glutin;
+use glutin::event_loop::EventLoopWindowTarget;
+use glutin::monitor::MonitorHandle;
+use glutin::window::WindowId;
+use glutin::platform::unix::{WindowBuilderExtUnix, WindowExtUnix};
+use glutin::dpi::{Size, Position, PhysicalPosition, PhysicalSize};
+use serde::Deserialize;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use crate::{Error, Vector2};
+use crate::animation::Tween;
+use crate::autohide::Slide;
+use crate::color::Color;
+use crate::config::{Animations, BarBorder, Config, Gradient, Tooltips, WindowHints, XEmbedTray, DEFAULT_FONT_SIZE};
+use crate::error::BarError;
+use crate::fullscreen::Fullscreen;
+use crate::ipc::Command;
+use crate::popup::{self, Placement, Popup};
+use crate::logging;
+use crate::render::{self, Border, Context, Fonts, Frame, Primitive, Renderer, RenderCtx, Scissor, Shader};
+use crate::wayland::LayerSurface;
+use crate::widgets::{self, Button, Registry, Scroll, Waker, Widget};
+use crate::x11::X11;
+use crate::xembed::Tray;
+
+#[derive(Debug)]
+pub enum BarEvent {
+    ConfigChanged,
+    OutputsChanged,
+    Command(Command),
+    Wake,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Anchor {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Debug)]
+pub struct AnchorParseError(String);
+
+impl std::fmt::Display for AnchorParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown anchor '{}', expected top, bottom, left or right", self.0)
+    }
+}
+
+impl std::error::Error for AnchorParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+impl FromStr for Anchor {
+    type Err = AnchorParseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "top" => Ok(Anchor::Top),
+            "bottom" => Ok(Anchor::Bottom),
+            "left" => Ok(Anchor::Left),
+            "right" => Ok(Anchor::Right),
+            _ => Err(AnchorParseError(name.to_string())),
+        }
+    }
+}
+
+// How modules are laid out on a bar anchored to the left or right edge.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerticalLayout {
+    // Every module is drawn upright in a square as wide as the bar, stacked
+    // from the top, which suits icons and short labels.
+    Stack,
+    // The bar is drawn as if it were horizontal and turned a quarter to the
+    // right, so text reads from top to bottom.
+    Rotate,
+}
+
+// A size in the config, written as a number of logical pixels like "24" or
+// "24px", a percentage like "50%", a multiple of the font size like "1.5em"
+// or a number of points like "10pt". What a percentage is of depends on
+// the setting, see resolve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Pixels(f64),
+    Percent(f64),
+    Em(f64),
+    Points(f64),
+}
+
+impl Default for Length {
+    fn default() -> Length {
+        Length::Pixels(0.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct LengthParseError(String);
+
+impl std::fmt::Display for LengthParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid length '{}', expected a size such as 24px, 50%, 1.5em or 10pt", self.0)
+    }
+}
+
+impl std::error::Error for LengthParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+impl FromStr for Length {
+    type Err = LengthParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Length::parse(text, false)
+    }
+}
+
+// Accepts a plain number of pixels as well as a string.
+impl<'de> Deserialize<'de> for Length {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Length::deserialize_with_sign(deserializer, false)
+    }
+}
+
+// For the offset, which moves the bar back the other way when negative.
+pub fn signed_length<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Length, D::Error> {
+    Length::deserialize_with_sign(deserializer, true)
+}
+
+impl Length {
+    // Sizes can't be negative, only distances can be when `signed` is set.
+    pub fn parse(text: &str, signed: bool) -> Result<Length, LengthParseError> {
+        let number = |number: &str| number.trim().parse::<f64>().ok()
+            .filter(|number| number.is_finite() && (signed || *number >= 0.0));
+        let text = text.trim();
+        let length = if let Some(percent) = text.strip_suffix('%') {
+            number(percent).map(Length::Percent)
+        } else if let Some(ems) = text.strip_suffix("em") {
+            number(ems).map(Length::Em)
+        } else if let Some(points) = text.strip_suffix("pt") {
+            number(points).map(Length::Points)
+        } else {
+            number(text.strip_suffix("px").unwrap_or(text)).map(Length::Pixels)
+        };
+        length.ok_or_else(|| LengthParseError(text.to_string()))
+    }
+
+    fn deserialize_with_sign<'de, D: serde::Deserializer<'de>>(deserializer: D, signed: bool) -> Result<Length, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Pixels(f64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Pixels(pixels) if pixels.is_finite() && (signed || pixels >= 0.0) => Ok(Length::Pixels(pixels)),
+            Raw::Pixels(pixels) => Err(serde::de::Error::custom(LengthParseError(pixels.to_string()))),
+            Raw::Text(text) => Length::parse(&text, signed).map_err(serde::de::Error::custom),
+        }
+    }
+
+    // In logical pixels, with percentages taken of `whole` and ems of the
+    // given font size. A point is 1/72 of an inch and a logical pixel 1/96.
+    pub fn resolve(&self, whole: f64, em: f64) -> f64 {
+        match self {
+            Length::Pixels(pixels) => *pixels,
+            Length::Percent(percent) => whole * percent / 100.0,
+            Length::Em(ems) => ems * em,
+            Length::Points(points) => points * 96.0 / 72.0,
+        }
+    }
+
+    // Like resolve, but never more than what's available.
+    pub fn of(&self, available: f64, em: f64) -> f64 {
+        self.resolve(available, em).min(available)
+    }
+}
+
+// The bar's sizes in logical pixels on a monitor of a given logical size.
+// The thickness takes percentages of the monitor's height on a bar anchored
+// to the top or bottom, and of its width on the left or right. Whatever the
+// anchor, the vertical gaps take them of the height and the horizontal gaps
+// of the width, and the offset of the length of the edge. Ems are of the
+// font size, or the default one when that's a percentage itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub size: f64,
+    pub gap_v: Vector2<f64>,
+    pub gap_h: Vector2<f64>,
+    pub offset: f64,
+    pub em: f64,
+}
+
+impl Geometry {
+    pub fn new(config: &Config, monitor: Vector2<f64>) -> Geometry {
+        let em = match config.font_size {
+            Length::Percent(_) => DEFAULT_FONT_SIZE,
+            size => size.resolve(0.0, DEFAULT_FONT_SIZE),
+        };
+        let (across, along) = match config.anchor {
+            Anchor::Top | Anchor::Bottom => (monitor.1, monitor.0),
+            Anchor::Left | Anchor::Right => (monitor.0, monitor.1),
+        };
+        let pair = |lengths: Vector2<Length>, whole: f64| (lengths.0.resolve(whole, em), lengths.1.resolve(whole, em));
+
+        Geometry {
+            size: config.size.resolve(across, em).max(1.0),
+            gap_v: pair(config.gap_v, monitor.1),
+            gap_h: pair(config.gap_h, monitor.0),
+            offset: config.offset.resolve(along, em),
+            em,
+        }
+    }
+}
+
+// The size of the bar's font in logical pixels, where percentages are of
+// the thickness of the bar and ems of the default size.
+pub fn font_size(config: &Config, thickness: f64) -> f32 {
+    config.font_size.resolve(thickness, DEFAULT_FONT_SIZE) as f32
+}
+
+// Which parts of the bar let the pointer through to the windows beneath it.
+// Spacers set to let clicks through always do. An autohiding bar can't be
+// revealed from the parts that let it through.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClickThrough {
+    Spacers,
+    // Wherever no module is drawn.
+    Empty,
+    All,
+}
+
+// Spans along the bar of the given length that take pointer input, given
+// the span every module covers.
+fn input_spans(click_through: ClickThrough, modules: &[Module], regions: &[(f32, f32)], length: f32)
+    -> Vec<(f32, f32)> {
+    let spans = modules.iter().zip(regions).filter(|(_, (start, end))| end > start);
+    match click_through {
+        ClickThrough::All => Vec::new(),
+        ClickThrough::Empty => spans.filter(|(module, _)| !module.widget.click_through())
+            .map(|(_, span)| *span)
+            .collect(),
+        ClickThrough::Spacers => {
+            let mut through: Vec<(f32, f32)> = spans.filter(|(module, _)| module.widget.click_through())
+                .map(|(_, span)| *span)
+                .collect();
+            through.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let (mut input, mut from) = (Vec::new(), 0.0);
+            for (start, end) in through {
+                if start > from {
+                    input.push((from, start));
+                }
+                from = end.max(from);
+            }
+            if length > from {
+                input.push((from, length));
+            }
+            input
+        },
+    }
+}
+
+// Which end of its edge a bar shorter than the edge is pushed to, or
+// whether it's centered on it.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeAlign {
+    Start,
+    Center,
+    End,
+}
+
+// Where along an edge of the given length the bar starts and how long it
+// is, in logical pixels from the left or top end. The bar is moved the
+// configured offset away from the end it's aligned to, or towards the right
+// or bottom when it's centered.
+pub fn edge_span(config: &Config, geometry: &Geometry, edge: f64) -> Vector2<f64> {
+    let gaps = match config.anchor {
+        Anchor::Top | Anchor::Bottom => geometry.gap_h,
+        Anchor::Left | Anchor::Right => geometry.gap_v,
+    };
+    let available = (edge - gaps.0 - gaps.1).max(0.0);
+    let length = match config.length {
+        Some(length) => length.of(available, geometry.em),
+        None => return (gaps.0, available),
+    };
+
+    let start = match config.align {
+        EdgeAlign::Start => gaps.0 + geometry.offset,
+        EdgeAlign::Center => gaps.0 + (available - length) / 2.0 + geometry.offset,
+        EdgeAlign::End => gaps.0 + available - length - geometry.offset,
+    };
+    (start, length)
+}
+
+fn compute_window_bounds(desktop_size: Vector2<f64>, config: &Config) -> (Vector2<f64>, Vector2<f64>) {
+    let geometry = Geometry::new(config, desktop_size);
+    let (anchor, size, gap_v, gap_h) = (config.anchor, geometry.size, geometry.gap_v, geometry.gap_h);
+    let (start, length) = match anchor {
+        Anchor::Top | Anchor::Bottom => edge_span(config, &geometry, desktop_size.0),
+        Anchor::Left | Anchor::Right => edge_span(config, &geometry, desktop_size.1),
+    };
+
+    let position_x = match anchor {
+        Anchor::Top | Anchor::Bottom => start,
+        Anchor::Left => gap_h.0,
+        Anchor::Right => desktop_size.0 - gap_h.1 - size,
+    };
+
+    let position_y = match anchor {
+        Anchor::Left | Anchor::Right => start,
+        Anchor::Top => gap_v.0,
+        Anchor::Bottom => desktop_size.1 - gap_v.1 - size,
+    };
+
+    let size = match anchor {
+        Anchor::Top | Anchor::Bottom => (length, size),
+        Anchor::Left | Anchor::Right => (size, length),
+    };
+
+    ((position_x, position_y), size)
+}
+
+// The window the bar is drawn into. X11 windows are created through winit,
+// along with where they were placed, the scale factor of the monitor they
+// were laid out for and the connection their hints and input region are set
+// through, layer-shell surfaces are managed by the Wayland backend itself.
+pub enum Surface {
+    X11(render::Window, PhysicalPosition<i32>, f64, Rc<X11>),
+    Wayland(LayerSurface),
+}
+
+impl Surface {
+    pub fn context(&self) -> &Context {
+        match self {
+            Surface::X11(window, ..) => window.context(),
+            Surface::Wayland(layer) => layer.context(),
+        }
+    }
+
+    // The size in physical pixels, see scale_factor for how many make up a
+    // logical one.
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Surface::X11(window, ..) => window.dimensions(),
+            Surface::Wayland(layer) => layer.context().dimensions(),
+        }
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        match self {
+            Surface::X11(_, _, scale, _) => *scale,
+            Surface::Wayland(layer) => layer.scale_factor(),
+        }
+    }
+
+    // How far the bar reaches out from the edge it's anchored to, in
+    // logical pixels.
+    pub fn thickness(&self, anchor: Anchor) -> f64 {
+        let (width, height) = match self {
+            Surface::X11(window, _, scale, _) => {
+                let (width, height) = window.dimensions();
+                (width as f64 / scale, height as f64 / scale)
+            },
+            Surface::Wayland(layer) => {
+                let (width, height) = layer.logical_size();
+                (width as f64, height as f64)
+            },
+        };
+        match anchor {
+            Anchor::Top | Anchor::Bottom => height,
+            Anchor::Left | Anchor::Right => width,
+        }
+    }
+
+    fn window_id(&self) -> Option<WindowId> {
+        match self {
+            Surface::X11(window, ..) => Some(window.window().id()),
+            Surface::Wayland(_) => None,
+        }
+    }
+
+    fn x11_window(&self) -> Option<u32> {
+        match self {
+            Surface::X11(window, ..) => window.window().xlib_window().map(|window| window as u32),
+            Surface::Wayland(_) => None,
+        }
+    }
+
+    // Pushes the bar the given number of logical pixels past the edge it's
+    // anchored to.
+    fn set_offset(&self, anchor: Anchor, offset: f64) {
+        match self {
+            Surface::X11(window, home, scale, _) => {
+                let window = window.window();
+                let distance = (offset * scale).round() as i32;
+                let (x, y) = match anchor {
+                    Anchor::Top => (home.x, home.y - distance),
+                    Anchor::Bottom => (home.x, home.y + distance),
+                    Anchor::Left => (home.x - distance, home.y),
+                    Anchor::Right => (home.x + distance, home.y),
+                };
+                window.set_outer_position(Position::Physical(PhysicalPosition::new(x, y)));
+            },
+            Surface::Wayland(layer) => layer.set_offset(offset),
+        }
+    }
+
+    // Lets only the given rectangles take pointer input, as x, y, width and
+    // height in logical pixels.
+    fn set_input_region(&self, rectangles: &[(f32, f32, f32, f32)]) -> Result<(), Error> {
+        let scale = self.scale_factor() as f32;
+        let rounded = |scale: f32| -> Vec<(i32, i32, i32, i32)> {
+            rectangles.iter()
+                .map(|&(x, y, width, height)| {
+                    let (left, top) = ((x * scale).round(), (y * scale).round());
+                    let (right, bottom) = (((x + width) * scale).round(), ((y + height) * scale).round());
+                    (left as i32, top as i32, (right - left) as i32, (bottom - top) as i32)
+                })
+                .collect()
+        };
+
+        match self {
+            Surface::X11(window, _, _, x11) => match window.window().xlib_window() {
+                Some(window) => x11.set_input_region(window as u32, &rounded(scale)),
+                None => Ok(()),
+            },
+            Surface::Wayland(layer) => {
+                layer.set_input_region(&rounded(1.0));
+                Ok(())
+            },
+        }
+    }
+}
+
+// Which monitors get a bar: the primary one, all of them, or a single
+// monitor picked by name.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "String")]
+pub enum OutputSelection {
+    Primary,
+    All,
+    Named(String),
+}
+
+impl From<String> for OutputSelection {
+    fn from(name: String) -> OutputSelection {
+        match name.as_str() {
+            "primary" => OutputSelection::Primary,
+            "all" => OutputSelection::All,
+            _ => OutputSelection::Named(name),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct NoMatchingOutputError(pub String);
+
+impl std::fmt::Display for NoMatchingOutputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No monitor named '{}' is connected", self.0)
+    }
+}
+
+impl std::error::Error for NoMatchingOutputError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+fn create_window<T>(target: &EventLoopWindowTarget<T>, config: &Config, monitor: &MonitorHandle)
+    -> Result<Surface, Error> {
+    let dpi = monitor.scale_factor();
+    let monitor_size = monitor.size().to_logical(dpi);
+    let origin = monitor.position();
+
+    let (pos, size) = compute_window_bounds((monitor_size.width, monitor_size.height), config);
+
+    // Monitors are laid out next to each other in one big root window, so
+    // the bar is placed relative to the origin of its own monitor.
+    let position = (
+        (origin.x as f64 + pos.0 * dpi).round().max(0.0) as u32,
+        (origin.y as f64 + pos.1 * dpi).round().max(0.0) as u32,
+    );
+
+    // Logical sizes would be converted with the scale of whichever monitor
+    // winit opens the window on, which needn't be this one.
+    let physical = |value: f64| (value * dpi).round().max(1.0) as u32;
+    let wb = glutin::window::WindowBuilder::new()
+        .with_transparent(true)
+        .with_visible(false)
+        .with_inner_size(Size::Physical(PhysicalSize::new(physical(size.0), physical(size.1))))
+        .with_override_redirect(config.window.override_redirect)
+        .with_x11_window_type(vec![glutin::platform::unix::XWindowType::Dock]);
+
+    let surface = render::Window::new(target, wb, config.vsync)?;
+    let x11 = Rc::new(X11::connect()?);
+
+    let home = PhysicalPosition::new(position.0 as i32, position.1 as i32);
+    surface.window().set_outer_position(Position::Physical(home));
+
+    let window = surface.window().xlib_window();
+    if let Some(window) = window {
+        set_window_state(&x11, window as u32, &config.window);
+        if let Err(err) = x11.set_input_hint(window as u32, config.keyboard_focus) {
+            warn!("Failed to keep the bar from taking keyboard focus: {}", err);
+        }
+    }
+    surface.window().set_visible(true);
+
+    // An autohiding bar covers other windows rather than pushing them aside.
+    if let Some(window) = window.filter(|_| config.autohide.is_none()) {
+        let result = x11.set_struts(
+                window as u32,
+                config.anchor,
+                position,
+                (physical(size.0), physical(size.1)),
+            );
+
+        if let Err(err) = result {
+            warn!("Failed to reserve screen space for the bar: {}", err);
+        }
+    }
+
+    if let Some(window) = window.filter(|_| config.blur) {
+        let region = blur_region((physical(size.0), physical(size.1)), config.corner_radius * dpi as f32);
+        if let Err(err) = x11.set_blur(window as u32, &region) {
+            warn!("Failed to ask for blur behind the bar: {}", err);
+        }
+    }
+
+    Ok(Surface::X11(surface, home, dpi, x11))
+}
+
+fn set_window_state(x11: &X11, window: u32, hints: &WindowHints) {
+    if hints.override_redirect {
+        return;
+    }
+    if let Err(err) = x11.set_window_state(window, hints) {
+        warn!("Failed to set the state of the bar's window: {}", err);
+    }
+}
+
+// Rectangles covering the bar with its rounded corners cut off, a row at a
+// time along the corners, for compositors that take the region to blur as
+// a list of them. Each is x, y, width and height.
+pub fn blur_region((width, height): (u32, u32), radius: f32) -> Vec<(i32, i32, i32, i32)> {
+    let (width, height) = (width as i32, height as i32);
+    let radius = (radius.max(0.0) as f64).min(width.min(height) as f64 / 2.0);
+    let rows = radius.ceil() as i32;
+
+    // How far the corner curves in from the side at the middle of a row.
+    let inset = |row: i32| {
+        let dy = radius - (row as f64 + 0.5);
+        (radius - (radius * radius - dy * dy).max(0.0).sqrt()).round() as i32
+    };
+
+    let mut region = Vec::new();
+    for row in 0..rows {
+        let inset = inset(row);
+        region.push((inset, row, width - 2 * inset, 1));
+        region.push((inset, height - 1 - row, width - 2 * inset, 1));
+    }
+    region.push((0, rows, width, height - 2 * rows));
+
+    region.retain(|&(_, _, width, height)| width > 0 && height > 0);
+    region
+}
+
+// Creates one window on every monitor the config selects.
+pub fn create_windows<T>(target: &EventLoopWindowTarget<T>, config: &Config) -> Result<Vec<Surface>, Error> {
+    let monitors: Vec<MonitorHandle> = match &config.output {
+        OutputSelection::Primary => target.primary_monitor().into_iter().collect(),
+        OutputSelection::All => target.available_monitors().collect(),
+        OutputSelection::Named(name) => target.available_monitors()
+            .filter(|monitor| monitor.name().as_deref() == Some(name.as_str()))
+            .collect(),
+    };
+
+    if monitors.is_empty() {
+        let name = match &config.output {
+            OutputSelection::Named(name) => name.clone(),
+            _ => "primary".to_string(),
+        };
+        return Err(Box::new(NoMatchingOutputError(name)));
+    }
+
+    monitors.iter()
+        .map(|monitor| create_window(target, config, monitor))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+// A single bar window together with the GL resources that belong to its
+// context.
+pub struct Output {
+    surface: Surface,
+    renderer: Renderer,
+    // Extent of every widget along the bar as of the last draw, and on a
+    // stacked vertical bar where its content starts across it, which is
+    // what clicks are measured from.
+    regions: Vec<(f32, f32)>,
+    offsets: Vec<f32>,
+    // The bar is drawn into the renderer's canvas, only redrawing the span
+    // that changed, and the canvas copied to the window. Along with it go
+    // where every module was drawn and with which primitives.
+    drawn: Vec<Drawn>,
+    background: Vec<Primitive>,
+    crossfades: Crossfades,
+    // Shown at the start of the bar in place of a background shader that
+    // couldn't be loaded.
+    shader_error: Option<Box<dyn Widget>>,
+    // The spans along the bar that take pointer input, as last set.
+    input: Option<Vec<(f32, f32)>>,
+    // Whether the window is shown, which it is once created.
+    mapped: Cell<bool>,
+}
+
+type Drawn = ((f32, f32), Vec<Primitive>);
+
+// What a module rendered, relative to its own block.
+#[derive(Clone, PartialEq)]
+struct Content {
+    width: f32,
+    primitives: Vec<Primitive>,
+}
+
+struct Fade {
+    old: Content,
+    tween: Tween,
+}
+
+// What every module rendered last time, and what modules that changed
+// showed before while it fades out.
+#[derive(Default)]
+struct Crossfades {
+    contents: Vec<Content>,
+    fades: Vec<Option<Fade>>,
+}
+
+impl Crossfades {
+    // Fades into content that differs from what the module showed last
+    // time, returning what to draw in its place and how wide that is.
+    fn apply(&mut self, module: usize, content: Content, animations: &Animations, now: Instant)
+        -> (f32, Vec<Primitive>) {
+        if self.contents.len() <= module {
+            self.contents.resize(module + 1, Content { width: 0.0, primitives: Vec::new() });
+            self.fades.resize_with(module + 1, || None);
+            self.contents[module] = content.clone();
+        }
+
+        let old = std::mem::replace(&mut self.contents[module], content.clone());
+        if old != content && animations.crossfade > 0.0 {
+            self.fades[module] = Some(Fade { old, tween: Tween::new(0.0, 1.0, animations.crossfade, animations.easing) });
+        }
+        if self.fades[module].as_ref().is_some_and(|fade| fade.tween.is_done(now)) {
+            self.fades[module] = None;
+        }
+
+        match &self.fades[module] {
+            Some(Fade { old, tween }) => {
+                let shown = tween.value(now) as f32;
+                let primitives = old.primitives.iter().map(|primitive| primitive.faded(1.0 - shown))
+                    .chain(content.primitives.iter().map(|primitive| primitive.faded(shown)))
+                    .collect();
+                (content.width.max(old.width), primitives)
+            },
+            None => (content.width, content.primitives),
+        }
+    }
+}
+
+impl Output {
+    fn new(surface: Surface, config: &Config) -> Result<Output, BarError> {
+        let font_size = font_size(config, surface.thickness(config.anchor));
+        let fonts = Fonts::load(&config.font, font_size).map_err(BarError::Renderer)?;
+        let mut renderer = Renderer::new(surface.context(), fonts).map_err(BarError::Renderer)?;
+
+        let shader_error = config.background_shader.as_ref().and_then(|path| {
+            let loaded = std::fs::read_to_string(path).map_err(Error::from)
+                .and_then(|source| renderer.set_background_shader(&source));
+            let err = loaded.err()?;
+            error!("Background shader {} failed: {}", path.display(), err);
+            Some(widgets::failed("background-shader", &err))
+        });
+
+        Ok(Output {
+            surface,
+            renderer,
+            regions: Vec::new(),
+            offsets: Vec::new(),
+            drawn: Vec::new(),
+            background: Vec::new(),
+            crossfades: Crossfades::default(),
+            shader_error,
+            input: None,
+            mapped: Cell::new(true),
+        })
+    }
+
+    pub fn surface(&self) -> &Surface {
+        &self.surface
+    }
+
+    // Draws the span of the bar that changed into the canvas and puts the
+    // canvas on screen. Returns false without drawing anything when the bar
+    // looks the same as before, unless the window system asked for it.
+    //
+    // A rotated bar's canvas is as wide as the window is high and turned
+    // into place when it's copied. Modules of a stacked bar are drawn below
+    // rather than next to each other, their spans run from the top.
+    //
+    // Everything is given in logical pixels and scaled to the window's
+    // physical ones here.
+    fn present(&mut self, clear: Color, background: &[Primitive], modules: Vec<Drawn>, overlay: &[Primitive],
+        asked: bool, vertical: Option<VerticalLayout>) -> Result<bool, Error> {
+        let window = self.surface.dimensions();
+        let dimensions = match vertical {
+            Some(VerticalLayout::Rotate) => (window.1, window.0),
+            _ => window,
+        };
+        let (width, height) = (dimensions.0 as f32, dimensions.1 as f32);
+        let length = if vertical == Some(VerticalLayout::Stack) { height } else { width };
+        let scale = self.surface.scale_factor() as f32;
+
+        // What was drawn before only counts once it's on screen.
+        let drawn = std::mem::take(&mut self.drawn);
+        let fresh = self.renderer.prepare(dimensions)?;
+
+        // A background that changed, like one drawn by a shader as time
+        // goes on, is behind everything.
+        let span = match damage(&drawn, &modules) {
+            _ if fresh || !overlay.is_empty() || background != self.background.as_slice() => Some((0.0, f32::MAX)),
+            Some(span) => Some(span),
+            None if asked => None,
+            None => {
+                self.drawn = drawn;
+                return Ok(false);
+            },
+        };
+
+        if let Some((start, end)) = span {
+            let (start, end) = ((start * scale).floor().clamp(0.0, length), (end * scale).ceil().clamp(0.0, length));
+            let scissor = match vertical {
+                Some(VerticalLayout::Stack) => Scissor {
+                    x: 0,
+                    y: start as u32,
+                    width: width as u32,
+                    height: (end - start) as u32,
+                },
+                _ => Scissor { x: start as u32, y: 0, width: (end - start) as u32, height: height as u32 },
+            };
+            let primitives: Vec<Primitive> = background.iter()
+                .chain(modules.iter().flat_map(|(_, primitives)| primitives))
+                .chain(overlay)
+                .map(|primitive| primitive.scaled(scale))
+                .collect();
+            self.renderer.draw_clipped(clear, &primitives, Some(scissor))?;
+        }
+
+        self.renderer.present(vertical == Some(VerticalLayout::Rotate))?;
+        self.drawn = modules;
+        self.background = background.to_vec();
+
+        Ok(true)
+    }
+
+    fn is_fading(&self) -> bool {
+        self.crossfades.fades.iter().any(Option::is_some)
+    }
+
+    // A failed shader's error, as a red block at the start of the bar.
+    fn shader_error(&self, thickness: f32) -> Vec<Primitive> {
+        let failed = match &self.shader_error {
+            Some(failed) => failed,
+            None => return Vec::new(),
+        };
+
+        let mut ctx = RenderCtx::new(self.renderer.fonts(), Color::TRANSPARENT, thickness);
+        failed.render(&mut ctx);
+        let mut primitives = vec![Primitive::Rect {
+            x: 0.0,
+            y: 0.0,
+            width: ctx.width(),
+            height: thickness,
+            radius: 0.0,
+            color: failed.background().unwrap_or(Color::TRANSPARENT),
+            border: None,
+        }];
+        primitives.extend(ctx.into_primitives(0.0, 0.0));
+        primitives
+    }
+}
+
+// Where on the bar modules look different from before, as the horizontal
+// span covering both where they were and where they are now.
+fn damage(before: &[Drawn], after: &[Drawn]) -> Option<(f32, f32)> {
+    if before.len() != after.len() {
+        return Some((0.0, f32::MAX));
+    }
+
+    before.iter().zip(after)
+        .filter(|(old, new)| old != new)
+        .map(|((old, _), (new, _))| (old.0.min(new.0), old.1.max(new.1)))
+        .reduce(|(start, end), (other_start, other_end)| (start.min(other_start), end.max(other_end)))
+}
+
+// A configured module: the widget and where it goes, along with text set
+// over IPC that is shown in its place.
+struct Module {
+    name: String,
+    align: Align,
+    widget: Box<dyn Widget>,
+    text: Option<String>,
+    // How often the widget changed, for the debug overlay.
+    updates: u64,
+}
+
+// What the debug overlay reports about drawing.
+#[derive(Default)]
+struct Frames {
+    // When recent frames were drawn, for the frame rate over the last
+    // second.
+    drawn: VecDeque<Instant>,
+    // How long the last frame took and what asked for it.
+    last: Duration,
+    last_cause: String,
+    // Why the next frame is drawn, empty when the window system asked.
+    cause: String,
+}
+
+// Where the pointer rests on the bar: the output, the module under it and
+// the position on that output, along with when it got to that module.
+struct Hover {
+    output: usize,
+    module: usize,
+    position: Vector2<f32>,
+    since: Instant,
+}
+
+// The popup the bar should be showing: a widget's own popup or the tooltip
+// of the hovered module.
+struct Request {
+    output: usize,
+    module: usize,
+    // Where along a vertical bar the pointer was.
+    pointer: f32,
+    text: String,
+}
+
+// Widgets are shared by every output, so background work such as sampling
+// /proc or following pactl only happens once no matter how many monitors
+// show the bar.
+pub struct Bar {
+    // Declared first so that it is dropped first, icons are handed back
+    // before the windows they're docked into go away.
+    tray: Option<(Tray, XEmbedTray)>,
+    outputs: Vec<Output>,
+    modules: Vec<Module>,
+    visible: bool,
+    // Set while the focused window is fullscreen, which hides the bar
+    // regardless of `visible` when hide-on-fullscreen is on.
+    fullscreen: Option<Fullscreen>,
+    covered: bool,
+    background: Color,
+    foreground: Color,
+    gradient: Option<Gradient>,
+    corner_radius: f32,
+    border: Option<BarBorder>,
+    anchor: Anchor,
+    // Set for bars anchored to the left or right edge.
+    vertical: Option<VerticalLayout>,
+    click_through: ClickThrough,
+    window: WindowHints,
+    autohide: Option<Slide>,
+    animations: Animations,
+    // How far the bar is shown while it slides in or out after being shown
+    // or hidden, from 0 when it's off screen to 1 when it's in place.
+    reveal: Option<Tween>,
+    // Popups take the size of the font from the bar they're shown for.
+    font: Vec<String>,
+    tooltips: Tooltips,
+    hover: Option<Hover>,
+    // Where the pointer is on which output, wherever it is on the bar, for
+    // background shaders.
+    pointer: Option<(usize, Vector2<f32>)>,
+    // What background shaders count their time from.
+    started: Instant,
+    // The output and pointer position of the last click, which is where
+    // popups widgets open on their own go.
+    clicked: Option<(usize, f32)>,
+    popup: Option<Popup>,
+    // A popup that couldn't be created, so it isn't retried on every event.
+    failed: Option<(usize, String)>,
+    // Modules that changed, until they're announced on D-Bus.
+    updated: Vec<String>,
+    debug: bool,
+    frames: Frames,
+    // The shortest time between two frames.
+    frame_interval: Duration,
+}
+
+impl Bar {
+    pub fn new<T>(target: &EventLoopWindowTarget<T>, config: &Config, registry: &Registry, waker: &Waker)
+        -> Result<Bar, BarError> {
+        let surfaces = create_windows(target, config).map_err(BarError::Window)?;
+        Bar::with_surfaces(surfaces, config, registry, waker)
+    }
+
+    // Modules that fail to build are shown as an error on the bar rather
+    // than keeping it from starting.
+    pub fn with_surfaces(surfaces: Vec<Surface>, config: &Config, registry: &Registry, waker: &Waker)
+        -> Result<Bar, BarError> {
+        let groups = [
+            (Align::Left, &config.modules),
+            (Align::Left, &config.modules_left),
+            (Align::Center, &config.modules_center),
+            (Align::Right, &config.modules_right),
+        ];
+
+        let modules = groups.iter()
+            .flat_map(|(align, names)| names.iter().map(move |name| (*align, name)))
+            .map(|(align, name)| Module {
+                name: name.clone(),
+                align,
+                widget: registry.build(name, config.module.get(name), waker).unwrap_or_else(|err| {
+                    error!("Module {} failed: {}", name, err);
+                    widgets::failed(name, &err)
+                }),
+                text: None,
+                updates: 0,
+            })
+            .collect();
+
+        let outputs = surfaces.into_iter()
+            .map(|surface| Output::new(surface, config))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Icons can only be docked into one window, the bar on the first
+        // output gets them.
+        let parent = outputs.first().and_then(|output| output.surface.x11_window());
+        let tray = match (&config.xembed_tray, parent) {
+            (Some(style), Some(parent)) => Tray::spawn(parent, waker.clone())
+                .map_err(|err| info!("Not docking tray icons: {}", err))
+                .ok()
+                .map(|tray| (tray, style.clone())),
+            _ => None,
+        };
+
+        let bar = Bar {
+            tray,
+            outputs,
+            modules,
+            visible: true,
+            fullscreen: Some(waker.clone()).filter(|_| config.hide_on_fullscreen).map(Fullscreen::spawn),
+            covered: false,
+            background: config.background,
+            foreground: config.foreground,
+            gradient: config.gradient.clone(),
+            corner_radius: config.corner_radius,
+            border: config.border.clone(),
+            anchor: config.anchor,
+            vertical: match config.anchor {
+                Anchor::Top | Anchor::Bottom => None,
+                Anchor::Left | Anchor::Right => Some(config.vertical_layout),
+            },
+            click_through: config.click_through,
+            window: config.window.clone(),
+            autohide: config.autohide.clone().map(Slide::new),
+            animations: config.animations.clone(),
+            reveal: None,
+            font: config.font.clone(),
+            tooltips: config.tooltips.clone(),
+            hover: None,
+            pointer: None,
+            started: Instant::now(),
+            clicked: None,
+            popup: None,
+            failed: None,
+            updated: Vec::new(),
+            debug: config.debug_overlay,
+            frames: Frames::default(),
+            frame_interval: Duration::from_secs_f64(1.0 / config.max_fps.max(1.0)),
+        };
+        bar.slide_surfaces();
+
+        Ok(bar)
+    }
+
+    // Swaps the windows the bar is shown in while keeping the widgets, and
+    // with them any background state, alive.
+    pub fn set_surfaces(&mut self, surfaces: Vec<Surface>, config: &Config) -> Result<(), Error> {
+        let outputs = surfaces.into_iter()
+            .map(|surface| Output::new(surface, config))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let (Some((tray, _)), Some(parent)) = (&self.tray, outputs.first().and_then(|output| output.surface.x11_window())) {
+            tray.set_parent(parent);
+        }
+        // The popup belongs to one of the old windows.
+        self.hover = None;
+        self.clicked = None;
+        self.popup = None;
+        self.outputs = outputs;
+        self.frames.cause = "outputs".to_string();
+        self.slide_surfaces();
+        self.map_surfaces();
+
+        Ok(())
+    }
+
+    // Moves every window to where the autohide slide or the bar sliding in
+    // and out currently has it, whichever is further off screen. Bars can be
+    // thicker on some monitors than on others.
+    fn slide_surfaces(&self) {
+        let reveal = self.reveal.map(|reveal| 1.0 - reveal.value(Instant::now()));
+        for output in &self.outputs {
+            let size = output.surface.thickness(self.anchor);
+            let autohide = self.autohide.as_ref().map(|slide| slide.offset(size));
+            if let Some(offset) = autohide.into_iter().chain(reveal.map(|hidden| hidden * size)).reduce(f64::max) {
+                output.surface.set_offset(self.anchor, offset);
+            }
+        }
+    }
+
+    // Whether something other than a widget's own update changes what's on
+    // screen every frame. Nothing is drawn while the bar is hidden.
+    fn is_animating(&self) -> bool {
+        let fading = self.outputs.iter().any(|output| output.is_fading() || output.renderer.has_background_shader());
+        self.is_mapped() && (fading || self.modules.iter().any(|module| module.widget.animating()))
+    }
+
+    pub fn set_hovered(&mut self, hovered: bool) {
+        if let Some(slide) = &mut self.autohide {
+            slide.set_hovered(hovered);
+        }
+    }
+
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    pub fn update(&mut self) -> bool {
+        let mut causes = Vec::new();
+        if self.autohide.as_mut().is_some_and(|slide| slide.advance()) {
+            self.slide_surfaces();
+            causes.push("autohide");
+        }
+        // A bar that slid out of sight is unmapped once it got there.
+        if let Some(reveal) = self.reveal {
+            self.slide_surfaces();
+            if reveal.is_done(Instant::now()) {
+                self.reveal = None;
+                self.map_surfaces();
+            }
+            causes.push("reveal");
+        }
+        if self.is_animating() {
+            causes.push("animation");
+        }
+
+        if self.tray.as_ref().is_some_and(|(tray, _)| tray.take_changed()) {
+            causes.push("tray");
+        }
+        if let Some(covered) = self.fullscreen.as_ref().and_then(|fullscreen| fullscreen.take()) {
+            if covered != self.covered {
+                let shown = self.is_shown();
+                self.covered = covered;
+                self.reveal(shown);
+                causes.push("fullscreen");
+            }
+        }
+        for module in &mut self.modules {
+            if module.widget.update() {
+                module.updates += 1;
+                causes.push(&module.name);
+                if !self.updated.contains(&module.name) {
+                    self.updated.push(module.name.clone());
+                }
+            }
+        }
+
+        if causes.is_empty() {
+            return false;
+        }
+        self.frames.cause = format!("update {}", causes.join(", "));
+        true
+    }
+
+    // Names of the modules whose widgets changed since the last call.
+    pub fn take_updated(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.updated)
+    }
+
+    pub fn next_update(&self) -> Option<Instant> {
+        let slide = self.autohide.as_ref().and_then(|slide| slide.next_update(self.frame_interval));
+        let popup = self.popup_due().filter(|due| *due > Instant::now());
+        let animation = Some(Instant::now()).filter(|_| self.reveal.is_some() || self.is_animating());
+        let due = self.modules.iter().filter_map(|module| module.widget.next_update())
+            .chain(slide).chain(popup).chain(animation).min();
+
+        // Whatever is due waits for the frame cap, so something animating
+        // can't make the bar draw faster than that.
+        let paced = self.frames.drawn.back().map(|drawn| *drawn + self.frame_interval);
+        due.map(|due| paced.map_or(due, |paced| due.max(paced)))
+    }
+
+    pub fn request_redraw(&self) {
+        let popup = self.popup.as_ref().map(|popup| popup.surface());
+        for surface in self.outputs.iter().map(|output| &output.surface).chain(popup) {
+            if let Surface::X11(window, ..) = surface {
+                window.window().request_redraw();
+            }
+        }
+    }
+
+    // Finds the module under a point on the given output's window, along
+    // with the point's x relative to the module's widget.
+    fn module_at(&self, output: usize, position: Vector2<f32>) -> Option<(usize, f32)> {
+        let output = self.outputs.get(output)?;
+        let along = if self.vertical.is_some() { position.1 } else { position.0 };
+        let module = output.regions.iter().position(|(start, end)| along >= *start && along < *end)?;
+        let x = match self.vertical {
+            Some(VerticalLayout::Stack) => position.0 - output.offsets[module],
+            _ => along - output.regions[module].0,
+        };
+
+        Some((module, x))
+    }
+
+    // Follows the pointer across the bar, None once it has left it.
+    pub fn hover(&mut self, position: Option<(usize, f32, f32)>) {
+        self.pointer = position.map(|(output, x, y)| (output, (x, y)));
+        let hovered = position.and_then(|(output, x, y)| {
+            let (module, _) = self.module_at(output, (x, y))?;
+            Some((output, module, (x, y)))
+        });
+
+        self.hover = match (self.hover.take(), hovered) {
+            (Some(hover), Some((output, module, position))) if hover.output == output && hover.module == module => {
+                Some(Hover { position, ..hover })
+            },
+            (_, Some((output, module, position))) => {
+                Some(Hover { output, module, position, since: Instant::now() })
+            },
+            (_, None) => None,
+        };
+    }
+
+    // When the tooltip for the hovered module is due.
+    fn popup_due(&self) -> Option<Instant> {
+        let hover = self.hover.as_ref().filter(|_| self.tooltips.enabled && self.is_shown())?;
+        Some(hover.since + Duration::from_secs_f64(self.tooltips.delay.max(0.0)))
+    }
+
+    // Popups widgets keep open themselves win over tooltips.
+    fn request(&self) -> Option<Request> {
+        if !self.is_shown() {
+            return None;
+        }
+
+        let shown = |module: &Module| module.text.is_none();
+        let pinned = self.modules.iter().enumerate()
+            .filter(|(_, module)| shown(module))
+            .find_map(|(index, module)| Some((index, module.widget.popup()?)));
+        if let Some((module, text)) = pinned {
+            let (output, pointer) = self.clicked.unwrap_or((0, 0.0));
+            return Some(Request { output, module, pointer, text }).filter(|_| output < self.outputs.len());
+        }
+
+        let hover = self.hover.as_ref().filter(|_| self.popup_due().is_some_and(|due| due <= Instant::now()))?;
+        let module = &self.modules[hover.module];
+        let (_, x) = self.module_at(hover.output, hover.position).filter(|(under, _)| *under == hover.module)?;
+        let text = module.widget.tooltip(x).filter(|_| shown(module))?;
+        Some(Request { output: hover.output, module: hover.module, pointer: hover.position.1, text })
+            .filter(|request| !request.text.trim().is_empty())
+    }
+
+    // Opens, updates or closes the popup to match what's wanted, creating
+    // windows through the given function. Returns true when the popup
+    // changed and needs to be drawn.
+    pub fn sync_popup<F: FnOnce(&Surface, &Placement) -> Result<Surface, Error>>(&mut self, create: F) -> bool {
+        let Request { output, module, pointer, text } = match self.request() {
+            Some(request) => request,
+            None => {
+                self.failed = None;
+                return self.popup.take().is_some();
+            },
+        };
+        if self.popup.as_ref().is_some_and(|popup| popup.shows(module, &text)) {
+            return false;
+        }
+        if self.failed.as_ref().is_some_and(|(failed, failed_text)| *failed == module && *failed_text == text) {
+            return false;
+        }
+
+        let output = &self.outputs[output];
+        let along = match output.regions.get(module) {
+            Some((start, end)) => (start + end) / 2.0,
+            None => pointer,
+        };
+        let placement = Placement {
+            anchor: self.anchor,
+            along,
+            size: popup::measure(output.renderer.fonts(), &text, &self.tooltips),
+            gap: self.tooltips.gap,
+        };
+
+        // Content that still fits the open popup is swapped in place, which
+        // keeps a popup with text that changes from flickering.
+        if let Some(popup) = self.popup.as_mut().filter(|popup| popup.fits(module, placement.size)) {
+            popup.set_text(text);
+            return true;
+        }
+
+        // The old popup goes first so that it's never shown next to the new
+        // one.
+        self.popup = None;
+        let created = create(&output.surface, &placement)
+            .and_then(|surface| Ok((surface, Fonts::load(&self.font, output.renderer.fonts().size())?)))
+            .and_then(|(surface, fonts)| Popup::new(surface, fonts, module, placement.size, text.clone()));
+        match created {
+            Ok(created) => self.popup = Some(created),
+            Err(err) => {
+                warn!("Failed to show a popup: {}", err);
+                self.failed = Some((module, text));
+            },
+        }
+
+        true
+    }
+
+    // Scrolling over the popup goes to the widget it belongs to.
+    pub fn scroll_popup(&mut self, direction: Scroll) -> bool {
+        match &self.popup {
+            Some(popup) => self.modules[popup.module()].widget.scroll(0.0, direction),
+            None => false,
+        }
+    }
+
+    // Forwards a click at a point on the popup, in its logical pixels, to the
+    // widget it's showing, returning true when the bar needs to be redrawn.
+    pub fn click_popup(&mut self, position: Vector2<f32>, button: Button) -> bool {
+        let (module, line) = match &self.popup {
+            Some(popup) => match popup.line_at(position.1, &self.tooltips) {
+                Some(line) => (popup.module(), line),
+                None => return false,
+            },
+            None => return false,
+        };
+        self.modules[module].widget.popup_click(line, button)
+    }
+
+    pub fn popup_surface(&self) -> Option<&Surface> {
+        self.popup.as_ref().map(|popup| popup.surface())
+    }
+
+    pub fn is_popup_window(&self, window: WindowId) -> bool {
+        self.popup_surface().is_some_and(|surface| surface.window_id() == Some(window))
+    }
+
+    pub fn draw_popup(&mut self) {
+        if let Some(popup) = &mut self.popup {
+            popup.draw(&self.tooltips, self.background, self.foreground);
+        }
+    }
+
+    // Shows text in place of every module with the given name, or hands the
+    // modules back to their widgets when the text is empty. Returns false
+    // when there is no such module.
+    pub fn set_text(&mut self, name: &str, text: &str) -> bool {
+        let mut found = false;
+        for module in self.modules.iter_mut().filter(|module| module.name == name) {
+            module.text = Some(text.to_string()).filter(|text| !text.is_empty());
+            found = true;
+        }
+        if found && !self.updated.iter().any(|updated| updated == name) {
+            self.updated.push(name.to_string());
+        }
+        if found {
+            self.frames.cause = format!("set {}", name);
+        }
+
+        found
+    }
+
+    // Runs a built-in action of every module with the given name. Returns
+    // None when there is no such module, otherwise whether one of them needs
+    // to be redrawn.
+    pub fn run_action(&mut self, name: &str, action: &str) -> Option<bool> {
+        let mut changed = None;
+        for module in self.modules.iter_mut().filter(|module| module.name == name) {
+            let redraw = module.widget.action(action).unwrap_or_else(|err| {
+                warn!("Action '{}' of '{}' failed: {}", action, name, err);
+                false
+            });
+            changed = Some(changed.unwrap_or(false) || redraw);
+        }
+        if changed == Some(true) {
+            self.frames.cause = format!("action {}", name);
+        }
+
+        changed
+    }
+
+    // Whether the bar was shown or hidden on request, see is_shown for
+    // whether it's actually on screen.
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.visible && !self.covered
+    }
+
+    // Whether the windows are on screen, which a hidden bar still is while
+    // it slides out.
+    pub fn is_mapped(&self) -> bool {
+        self.is_shown() || self.reveal.is_some()
+    }
+
+    pub fn toggle_debug(&mut self) {
+        self.debug = !self.debug;
+        self.frames.cause = "debug".to_string();
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        let shown = self.is_shown();
+        self.visible = visible;
+        self.reveal(shown);
+    }
+
+    // Slides the bar in or out once it's been shown or hidden, starting from
+    // wherever it is when it turns around halfway.
+    fn reveal(&mut self, was_shown: bool) {
+        let shown = self.is_shown();
+        if shown != was_shown && self.animations.reveal > 0.0 {
+            let (from, to) = if shown { (0.0, 1.0) } else { (1.0, 0.0) };
+            let from = self.reveal.map_or(from, |reveal| reveal.value(Instant::now()));
+            self.reveal = Some(Tween::new(from, to, self.animations.reveal, self.animations.easing));
+        }
+
+        self.slide_surfaces();
+        self.map_surfaces();
+    }
+
+    // Maps or unmaps the X11 windows. Layer surfaces can't be hidden once
+    // mapped, the Wayland backend drops and recreates them instead.
+    fn map_surfaces(&self) {
+        let mapped = self.is_mapped();
+        for output in &self.outputs {
+            if let Surface::X11(window, _, _, x11) = &output.surface {
+                if mapped && !output.mapped.get() {
+                    if let Some(id) = window.window().xlib_window() {
+                        set_window_state(x11, id as u32, &self.window);
+                    }
+                }
+                window.window().set_visible(mapped);
+            }
+            output.mapped.set(mapped);
+        }
+    }
+
+    pub fn output_for_window(&self, window: WindowId) -> Option<usize> {
+        self.outputs.iter().position(|output| output.surface.window_id() == Some(window))
+    }
+
+    fn widget_at(&mut self, index: usize, position: Vector2<f32>) -> Option<(&mut Box<dyn Widget>, f32)> {
+        let (module, x) = self.module_at(index, position)?;
+        Some((&mut self.modules[module].widget, x))
+    }
+
+    // Forwards a click at a point on the given output's window to the widget
+    // under it, returning true when the bar needs to be redrawn.
+    pub fn click(&mut self, index: usize, position: Vector2<f32>, button: Button) -> bool {
+        self.clicked = Some((index, position.1));
+        let changed = self.widget_at(index, position).is_some_and(|(widget, x)| widget.click(x, button));
+        if changed {
+            self.frames.cause = "click".to_string();
+        }
+        changed
+    }
+
+    pub fn scroll(&mut self, index: usize, position: Vector2<f32>, direction: Scroll) -> bool {
+        let changed = self.widget_at(index, position).is_some_and(|(widget, x)| widget.scroll(x, direction));
+        if changed {
+            self.frames.cause = "scroll".to_string();
+        }
+        changed
+    }
+
+    pub fn draw(&mut self, index: usize) {
+        let started = Instant::now();
+        let output = &mut self.outputs[index];
+        let scale = output.surface.scale_factor() as f32;
+        let dimensions = output.surface.dimensions();
+        let dimensions = (dimensions.0 as f32 / scale, dimensions.1 as f32 / scale);
+        let stacked = self.vertical == Some(VerticalLayout::Stack);
+
+        // Modules are laid out along the bar and rendered as high as the bar
+        // is thick, in logical pixels. A rotated bar is drawn like a
+        // horizontal one as long as the window is high, the canvas of a
+        // stacked one is the window.
+        let (length, thickness) = match self.vertical {
+            Some(_) => (dimensions.1, dimensions.0),
+            None => (dimensions.0, dimensions.1),
+        };
+        let (width, height) = match self.vertical {
+            Some(VerticalLayout::Stack) => (thickness, length),
+            _ => (length, thickness),
+        };
+
+        let (fonts, foreground) = (output.renderer.fonts(), self.foreground);
+        // Separators blend into the backgrounds of the modules next to them
+        // in the same group.
+        let modules = &self.modules;
+        let background = |module: Option<&Module>, align: Align| {
+            module.filter(|module| module.align == align).and_then(|module| module.widget.background())
+        };
+
+        let rendered: Vec<_> = modules.iter().enumerate()
+            .map(|(index, module)| {
+                let mut ctx = RenderCtx::new(fonts, foreground, thickness);
+                ctx.set_neighbours(
+                    background(index.checked_sub(1).and_then(|before| modules.get(before)), module.align),
+                    background(modules.get(index + 1), module.align),
+                );
+                match &module.text {
+                    Some(text) => ctx.text(text, foreground),
+                    None => module.widget.render(&mut ctx),
+                }
+                (module.align, ctx)
+            })
+            .collect();
+
+        // The center group is centered on the whole bar rather than on the
+        // space left between the other two, so it may overlap them when the
+        // bar is crowded.
+        // Stacked modules each take a square.
+        let extent = |ctx: &RenderCtx| if stacked { thickness } else { ctx.width() };
+        let group_length = |group: Align| -> f32 {
+            rendered.iter().filter(|(align, _)| *align == group).map(|(_, ctx)| extent(ctx)).sum()
+        };
+
+        // Docked icons take the very end of the right group on the output
+        // they're docked into, which is the bottom of a vertical bar.
+        let tray = self.tray.as_ref().filter(|_| index == 0);
+        let tray_length = tray.map(|(tray, style)| {
+            let size = (thickness - 2.0 * style.padding).max(1.0);
+            let count = tray.count() as f32;
+            (count * (size + style.spacing)).max(0.0)
+        }).unwrap_or(0.0);
+
+        let mut left = 0.0;
+        let mut center = (length - group_length(Align::Center)) / 2.0;
+        let mut right = length - tray_length - group_length(Align::Right);
+
+        // Icons are windows of their own, placed in physical pixels.
+        if let Some((tray, style)) = tray {
+            let size = (thickness - 2.0 * style.padding).max(1.0) * scale;
+            let start = (length - tray_length + style.spacing) * scale;
+            let (padding, spacing) = (style.padding * scale, style.spacing * scale);
+            match self.vertical {
+                Some(_) => tray.place((padding, start), size, spacing, true),
+                None => tray.place((start, padding), size, spacing, false),
+            }
+        }
+
+        // Rounded and gradient bars leave the window transparent and draw
+        // their background as a shape; otherwise clearing is all that's
+        // needed.
+        let mut primitives = Vec::new();
+        let mut clear = Color::TRANSPARENT;
+        let radius = self.corner_radius;
+        // The pointer is passed on in the canvas's own coordinates.
+        let vertical = self.vertical;
+        let mouse = self.pointer.filter(|(pointer, _)| *pointer == index).map(|(_, (x, y))| match vertical {
+            Some(VerticalLayout::Rotate) => (y, thickness - x),
+            _ => (x, y),
+        });
+        match &self.gradient {
+            _ if output.renderer.has_background_shader() => primitives.push(Primitive::Shader(Shader {
+                x: 0.0,
+                y: 0.0,
+                width,
+                height,
+                radius,
+                time: self.started.elapsed().as_secs_f32(),
+                mouse,
+            })),
+            Some(gradient) => primitives.push(Primitive::Gradient {
+                x: 0.0,
+                y: 0.0,
+                width,
+                height,
+                radius,
+                angle: gradient.angle,
+                colors: gradient.colors.clone(),
+            }),
+            None if radius > 0.0 => primitives.push(Primitive::Rect {
+                x: 0.0,
+                y: 0.0,
+                width,
+                height,
+                radius,
+                color: self.background,
+                border: None,
+            }),
+            None => clear = self.background,
+        }
+
+        let now = Instant::now();
+        let mut regions = Vec::new();
+        let mut offsets = Vec::new();
+        let mut drawn = Vec::new();
+        for (module, (align, ctx)) in rendered.into_iter().enumerate() {
+            let x = match align {
+                Align::Left => &mut left,
+                Align::Center => &mut center,
+                Align::Right => &mut right,
+            };
+
+            let start = *x;
+            *x += extent(&ctx);
+            regions.push((start, *x));
+
+            // Modules fading out of wider content cover it until it's gone.
+            let offset = if stacked { (thickness - ctx.width()) / 2.0 } else { start };
+            let content = Content { width: ctx.width(), primitives: ctx.into_primitives(0.0, 0.0) };
+            let (faded, mut primitives) = output.crossfades.apply(module, content, &self.animations, now);
+            let (span, position) = match stacked {
+                true => ((start, *x), (offset, start)),
+                false => ((start, start + faded), (offset, 0.0)),
+            };
+            for primitive in &mut primitives {
+                primitive.translate(position.0, position.1);
+            }
+            offsets.push(offset);
+            drawn.push((span, primitives));
+        }
+
+        // The border goes over the modules, as one more entry after them
+        // that stays the same, so it's only redrawn where they change. A
+        // rotated canvas has its left edge end up at the top.
+        if let Some(border) = &self.border {
+            let mut edges = border.edges(self.foreground);
+            if self.vertical == Some(VerticalLayout::Rotate) {
+                edges.rotate_left(1);
+            }
+            let frame = Frame { x: 0.0, y: 0.0, width, height, radius, edges };
+            drawn.push(((0.0, length), vec![Primitive::Frame(frame)]));
+        }
+        let mut overlay = output.shader_error(thickness);
+        overlay.extend(match self.debug {
+            true => {
+                let labels: Vec<String> = self.modules.iter()
+                    .map(|module| format!("{} ×{}", module.name, module.updates))
+                    .collect();
+                let boxes: Vec<_> = regions.iter()
+                    .map(|(start, end)| if stacked { (0.0, *start, width, end - start) } else { (*start, 0.0, end - start, height) })
+                    .collect();
+                debug_overlay(output.renderer.fonts(), &labels, &boxes, &self.frames, (width, height))
+            },
+            false => Vec::new(),
+        });
+        let input = input_spans(self.click_through, &self.modules, &regions, length);
+        if output.input.as_ref() != Some(&input) {
+            let vertical = self.vertical.is_some();
+            let rectangles: Vec<_> = input.iter()
+                .map(|(start, end)| if vertical { (0.0, *start, thickness, end - start) } else { (*start, 0.0, end - start, thickness) })
+                .collect();
+            if let Err(err) = output.surface.set_input_region(&rectangles) {
+                warn!("Failed to let clicks through the bar: {}", err);
+            }
+            output.input = Some(input);
+        }
+        output.regions = regions;
+        output.offsets = offsets;
+
+        let asked = self.frames.cause.is_empty();
+        match output.present(clear, &primitives, drawn, &overlay, asked, self.vertical) {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(err) => error!("Failed to draw the bar: {}", err),
+        }
+
+        let frames = &mut self.frames;
+        frames.last = started.elapsed();
+        frames.last_cause = match std::mem::take(&mut frames.cause) {
+            cause if cause.is_empty() => "window system".to_string(),
+            cause => cause,
+        };
+        frames.drawn.push_back(started);
+        while frames.drawn.front().is_some_and(|drawn| started.duration_since(*drawn) > Duration::from_secs(1)) {
+            frames.drawn.pop_front();
+        }
+    }
+}
+
+const DEBUG_COLOR: Color = Color::rgb(0xff, 0x00, 0xff);
+const DEBUG_SCALE: f32 = 0.5;
+
+// Outlines every module's box, given as x, y, width and height, with its
+// name and update count in the corner, puts how the last frame went in the
+// bottom left and the latest warning in the bottom right.
+fn debug_overlay(fonts: &Fonts, labels: &[String], boxes: &[(f32, f32, f32, f32)], frames: &Frames,
+    size: Vector2<f32>) -> Vec<Primitive> {
+    let (width, height) = size;
+    let line = fonts.size() * DEBUG_SCALE;
+    let mut primitives = Vec::new();
+
+    let backdrop = Color::rgb(0x00, 0x00, 0x00).faded(0.7);
+    let mut label = |x: f32, text: String| {
+        let text_width = fonts.measure_scaled(&text, DEBUG_SCALE);
+        primitives.push(Primitive::Rect {
+            x,
+            y: height - line - 2.0,
+            width: text_width + 2.0,
+            height: line + 2.0,
+            radius: 0.0,
+            color: backdrop,
+            border: None,
+        });
+        primitives.push(Primitive::Text { x: x + 1.0, y: height - 2.0, text, color: DEBUG_COLOR, scale: DEBUG_SCALE });
+    };
+
+    let frame = format!("{:.1} ms, {} fps, {}", frames.last.as_secs_f64() * 1000.0, frames.drawn.len(),
+        frames.last_cause);
+    label(0.0, frame);
+    if let Some(record) = logging::latest() {
+        let text = format!("{} {}: {}", record.level, record.target, record.message);
+        let x = (width - fonts.measure_scaled(&text, DEBUG_SCALE) - 2.0).max(0.0);
+        label(x, text);
+    }
+
+    for (text, (x, y, width, height)) in labels.iter().zip(boxes) {
+        primitives.push(Primitive::Rect {
+            x: *x,
+            y: *y,
+            width: *width,
+            height: *height,
+            radius: 0.0,
+            color: Color::TRANSPARENT,
+            border: Some(Border { width: 1.0, color: DEBUG_COLOR }),
+        });
+        primitives.push(Primitive::Text { x: x + 2.0, y: y + line, text: text.clone(), color: DEBUG_COLOR, scale: DEBUG_SCALE });
+    }
+
+    primitives
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Lengths {
+        size: Length,
+        #[serde(deserialize_with = "signed_length")]
+        offset: Length,
+    }
+
+    fn lengths(text: &str) -> Option<Lengths> {
+        toml::from_str(text).ok()
+    }
+
+    #[test]
+    fn lengths_parse_every_unit() {
+        assert_eq!("24".parse::<Length>().ok(), Some(Length::Pixels(24.0)));
+        assert_eq!(" 24px ".parse::<Length>().ok(), Some(Length::Pixels(24.0)));
+        assert_eq!("50%".parse::<Length>().ok(), Some(Length::Percent(50.0)));
+        assert_eq!("1.5em".parse::<Length>().ok(), Some(Length::Em(1.5)));
+        assert_eq!("10pt".parse::<Length>().ok(), Some(Length::Points(10.0)));
+    }
+
+    #[test]
+    fn only_signed_lengths_may_be_negative() {
+        assert!("-10".parse::<Length>().is_err());
+        assert!(Length::parse("-5%", false).is_err());
+        assert_eq!(Length::parse("-10", true).ok(), Some(Length::Pixels(-10.0)));
+        assert_eq!(Length::parse("-5%", true).ok(), Some(Length::Percent(-5.0)));
+    }
+
+    #[test]
+    fn malformed_lengths_are_errors() {
+        for text in ["", "px", "wide", "10km", "inf", "NaN", "1e999", "5%%"] {
+            assert!(Length::parse(text, true).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn lengths_deserialize_from_numbers_and_strings() {
+        let parsed = lengths("size = 30\noffset = \"-2em\"").unwrap();
+        assert_eq!((parsed.size, parsed.offset), (Length::Pixels(30.0), Length::Em(-2.0)));
+        let parsed = lengths("size = \"10%\"\noffset = -4.5").unwrap();
+        assert_eq!((parsed.size, parsed.offset), (Length::Percent(10.0), Length::Pixels(-4.5)));
+
+        assert!(lengths("size = -30\noffset = 0").is_none());
+        assert!(lengths("size = \"-30px\"\noffset = 0").is_none());
+        assert!(lengths("size = nan\noffset = 0").is_none());
+    }
+
+    #[test]
+    fn lengths_resolve_to_pixels() {
+        assert_eq!(Length::Pixels(24.0).resolve(1000.0, 16.0), 24.0);
+        assert_eq!(Length::Percent(50.0).resolve(200.0, 16.0), 100.0);
+        assert_eq!(Length::Em(1.5).resolve(200.0, 16.0), 24.0);
+        assert_eq!(Length::Points(12.0).resolve(200.0, 16.0), 16.0);
+        assert_eq!(Length::Percent(150.0).of(200.0, 16.0), 200.0);
+    }
+
+    #[test]
+    fn gaps_take_percentages_of_their_own_axis_on_every_edge() {
+        let mut config = Config {
+            size: Length::Percent(10.0),
+            gap_v: (Length::Percent(1.0), Length::Percent(2.0)),
+            gap_h: (Length::Percent(1.0), Length::Percent(2.0)),
+            offset: Length::Percent(50.0),
+            ..Config::default()
+        };
+        for (anchor, size, offset) in [(Anchor::Top, 100.0, 1000.0), (Anchor::Left, 200.0, 500.0)] {
+            config.anchor = anchor;
+            let geometry = Geometry::new(&config, (2000.0, 1000.0));
+            assert_eq!((geometry.size, geometry.offset), (size, offset), "{:?}", anchor);
+            assert_eq!((geometry.gap_v, geometry.gap_h), ((10.0, 20.0), (20.0, 40.0)), "{:?}", anchor);
+        }
+    }
+}
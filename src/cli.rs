@@ -0,0 +1,123 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::Vector2;
+use crate::bar::{Anchor, Length, OutputSelection};
+use crate::color::Color;
+use crate::config::Config;
+
+#[derive(Debug, Parser)]
+#[command(name = "rustybar", version, about)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Read the configuration from this file instead of the default location
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Monitor to show the bar on: primary, all, or a monitor name
+    #[arg(long)]
+    pub output: Option<OutputSelection>,
+
+    /// Screen edge the bar is attached to (top, bottom, left or right)
+    #[arg(long)]
+    pub anchor: Option<Anchor>,
+
+    /// Thickness of the bar, such as 32, 3% or 2em
+    #[arg(long)]
+    pub size: Option<Length>,
+
+    /// Vertical gaps as top,bottom
+    #[arg(long, value_parser = parse_gap)]
+    pub gap_v: Option<Vector2<Length>>,
+
+    /// Horizontal gaps as left,right
+    #[arg(long, value_parser = parse_gap)]
+    pub gap_h: Option<Vector2<Length>>,
+
+    /// Length of the bar along its edge, such as 800, 50% or 40em
+    #[arg(long)]
+    pub length: Option<Length>,
+
+    /// Background color as #rrggbb or #rrggbbaa
+    #[arg(long, value_parser = parse_color)]
+    pub background: Option<Color>,
+
+    /// What to log, a level optionally followed by per-module levels such as rustybar::widgets::battery=debug
+    #[arg(long, default_value = "info")]
+    pub log_level: String,
+
+    /// Write the log to this file instead of the terminal
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+
+    /// Start with frame times, module update counts and the latest warning drawn on the bar
+    #[arg(long)]
+    pub debug_overlay: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Send a command to the running bar: show, hide, toggle, reload, debug, set <module> <text> or <module> <action>
+    Msg {
+        #[arg(required = true, trailing_var_arg = true)]
+        message: Vec<String>,
+    },
+    /// Print a rustybar config translated from a polybar config
+    ImportPolybar {
+        path: PathBuf,
+        /// Name of the bar section to translate instead of the first one
+        bar: Option<String>,
+    },
+}
+
+impl Args {
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(output) = &self.output {
+            config.output = output.clone();
+        }
+
+        if let Some(anchor) = self.anchor {
+            config.anchor = anchor;
+        }
+
+        if let Some(size) = self.size {
+            config.size = size;
+        }
+
+        if let Some(gap_v) = self.gap_v {
+            config.gap_v = gap_v;
+        }
+
+        if let Some(gap_h) = self.gap_h {
+            config.gap_h = gap_h;
+        }
+
+        if let Some(length) = self.length {
+            config.length = Some(length);
+        }
+
+        if let Some(background) = self.background {
+            config.background = background;
+        }
+
+        if self.debug_overlay {
+            config.debug_overlay = true;
+        }
+    }
+}
+
+fn parse_gap(value: &str) -> Result<Vector2<Length>, String> {
+    let mut parts = value.split(',').map(Length::from_str);
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(first)), None, None) => Ok((first, first)),
+        (Some(Ok(first)), Some(Ok(second)), None) => Ok((first, second)),
+        _ => Err(format!("invalid gap '{}', expected a size or two sizes separated by a comma", value)),
+    }
+}
+
+fn parse_color(value: &str) -> Result<Color, String> {
+    Color::from_str(value).map_err(|err| err.to_string())
+}
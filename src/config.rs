@@ -0,0 +1,340 @@
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use crate::{Error, Vector2};
+use crate::animation::Easing;
+use crate::bar::{Anchor, ClickThrough, EdgeAlign, Length, OutputSelection, VerticalLayout};
+use crate::color::Color;
+use crate::render::Border;
+use crate::palette;
+
+// The font size when none is set, in logical pixels.
+pub const DEFAULT_FONT_SIZE: f64 = 14.0;
+
+// A linear gradient drawn instead of the plain background color. The angle
+// is in degrees clockwise, 0 runs from left to right.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Gradient {
+    pub colors: Vec<Color>,
+    #[serde(default)]
+    pub angle: f32,
+}
+
+// A frame drawn over the bar along its edges. The width and color apply to
+// every edge that doesn't set its own, the color falls back to the
+// foreground:
+//
+//     border = { width = 2, color = "#5588ff", top = { width = 0 } }
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BarBorder {
+    pub width: f32,
+    pub color: Option<Color>,
+    pub top: EdgeBorder,
+    pub right: EdgeBorder,
+    pub bottom: EdgeBorder,
+    pub left: EdgeBorder,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct EdgeBorder {
+    pub width: Option<f32>,
+    pub color: Option<Color>,
+}
+
+impl Default for BarBorder {
+    fn default() -> BarBorder {
+        BarBorder {
+            width: 1.0,
+            color: None,
+            top: EdgeBorder::default(),
+            right: EdgeBorder::default(),
+            bottom: EdgeBorder::default(),
+            left: EdgeBorder::default(),
+        }
+    }
+}
+
+impl BarBorder {
+    // Top, right, bottom and left.
+    pub fn edges(&self, foreground: Color) -> [Border; 4] {
+        let edge = |edge: &EdgeBorder| Border {
+            width: edge.width.unwrap_or(self.width).max(0.0),
+            color: edge.color.or(self.color).unwrap_or(foreground),
+        };
+
+        [edge(&self.top), edge(&self.right), edge(&self.bottom), edge(&self.left)]
+    }
+}
+
+// Accepts a single font family as well as a list of them.
+fn families<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Families {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Families::deserialize(deserializer)? {
+        Families::One(family) => vec![family],
+        Families::Many(families) => families,
+    })
+}
+
+// Docks the icons of applications using the XEmbed tray protocol at the
+// end of the right modules. X11 only.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct XEmbedTray {
+    pub padding: f32,
+    pub spacing: f32,
+}
+
+impl Default for XEmbedTray {
+    fn default() -> XEmbedTray {
+        XEmbedTray { padding: 2.0, spacing: 4.0 }
+    }
+}
+
+// What the window manager is asked to do with the bar's windows: keep them
+// on every desktop and above other windows, and out of taskbars and pagers.
+// Window managers that mishandle dock windows can be bypassed altogether
+// with an override-redirect window, which they don't manage, so none of
+// the rest applies and no screen space is reserved. X11 only.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct WindowHints {
+    pub sticky: bool,
+    pub above: bool,
+    pub skip_taskbar: bool,
+    pub skip_pager: bool,
+    pub override_redirect: bool,
+}
+
+impl Default for WindowHints {
+    fn default() -> WindowHints {
+        WindowHints { sticky: true, above: true, skip_taskbar: true, skip_pager: true, override_redirect: false }
+    }
+}
+
+// Slides the bar off its screen edge, leaving a strip of it behind that
+// brings it back once the pointer rests on it. Times are in seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Autohide {
+    pub strip: f64,
+    pub dwell: f64,
+    pub delay: f64,
+    pub duration: f64,
+    pub easing: Easing,
+}
+
+impl Default for Autohide {
+    fn default() -> Autohide {
+        Autohide { strip: 1.0, dwell: 0.3, delay: 0.5, duration: 0.2, easing: Easing::EaseInOut }
+    }
+}
+
+// Popups with a module's tooltip, shown once the pointer has rested on the
+// module for the delay in seconds. Colors default to the bar's own.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Tooltips {
+    pub enabled: bool,
+    pub delay: f64,
+    pub padding: f32,
+    // Distance between the bar and the popup.
+    pub gap: f32,
+    pub corner_radius: f32,
+    pub background: Option<Color>,
+    pub foreground: Option<Color>,
+}
+
+impl Default for Tooltips {
+    fn default() -> Tooltips {
+        Tooltips {
+            enabled: true,
+            delay: 0.6,
+            padding: 6.0,
+            gap: 4.0,
+            corner_radius: 0.0,
+            background: None,
+            foreground: None,
+        }
+    }
+}
+
+// How long, in seconds, the bar takes to slide in and out when it's shown
+// or hidden, and modules take to fade from what they showed before to what
+// they show now. A duration of 0 turns that animation off.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Animations {
+    pub easing: Easing,
+    pub reveal: f64,
+    pub crossfade: f64,
+}
+
+impl Default for Animations {
+    fn default() -> Animations {
+        Animations { easing: Easing::EaseInOut, reveal: 0.0, crossfade: 0.0 }
+    }
+}
+
+// What's left of the palette once palette.rs has substituted its colors
+// into the rest of the config, kept to check they're all valid.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PaletteConfig {
+    #[serde(flatten)]
+    pub colors: HashMap<String, Color>,
+    pub dark: HashMap<String, Color>,
+    pub light: HashMap<String, Color>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    pub output: OutputSelection,
+    pub anchor: Anchor,
+    // Sizes here and in module sections are in logical pixels, which each
+    // monitor's bar scales by that monitor's scale factor. The bar's own
+    // sizes can also be given in other units, see bar::Length and
+    // bar::Geometry for what they're relative to.
+    pub size: Length,
+    pub gap_v: Vector2<Length>,
+    pub gap_h: Vector2<Length>,
+    // Unless set the bar covers its whole edge between the gaps. A shorter
+    // bar is aligned to one end of the edge, pushed away from it by the
+    // offset, or centered. A negative offset pushes the other way.
+    pub length: Option<Length>,
+    pub align: EdgeAlign,
+    #[serde(deserialize_with = "crate::bar::signed_length")]
+    pub offset: Length,
+    pub background: Color,
+    pub gradient: Option<Gradient>,
+    pub corner_radius: f32,
+    pub border: Option<BarBorder>,
+    // A fragment shader drawing the background in place of the color or
+    // gradient, relative to the config's directory. It's GLSL, or WGSL with
+    // the wgpu feature, see Renderer::set_background_shader for what it gets
+    // to work with.
+    pub background_shader: Option<PathBuf>,
+    // Asks the compositor to blur what's behind a translucent bar, on KWin
+    // and compositors that support its blur protocol.
+    pub blur: bool,
+    pub foreground: Color,
+    // Colors the rest of the config can refer to by name, see palette.rs.
+    pub colorscheme: Option<String>,
+    pub theme: Option<String>,
+    pub palette: PaletteConfig,
+    // Later fonts fill in the characters earlier ones have no glyph for,
+    // such as icons from a symbols font.
+    #[serde(deserialize_with = "families")]
+    pub font: Vec<String>,
+    pub font_size: Length,
+    // `modules` predates the alignment groups and is laid out on the left.
+    // On vertical bars the groups go at the top, middle and bottom, and can
+    // be named that way too.
+    pub modules: Vec<String>,
+    #[serde(alias = "modules-top")]
+    pub modules_left: Vec<String>,
+    #[serde(alias = "modules-middle")]
+    pub modules_center: Vec<String>,
+    #[serde(alias = "modules-bottom")]
+    pub modules_right: Vec<String>,
+    // Only used when the bar is anchored to the left or right.
+    pub vertical_layout: VerticalLayout,
+    // "empty" lets clicks between modules through to the windows beneath
+    // a mostly transparent bar, "all" everywhere.
+    pub click_through: ClickThrough,
+    // Lets the bar take keyboard focus, for widgets that need typing into.
+    // On Wayland it then holds on to the keyboard for as long as it's
+    // shown, the version of layer-shell used has nothing in between.
+    pub keyboard_focus: bool,
+    pub xembed_tray: Option<XEmbedTray>,
+    pub window: WindowHints,
+    // No screen space is reserved for an autohiding bar.
+    pub autohide: Option<Autohide>,
+    pub hide_on_fullscreen: bool,
+    // Waits for the display's refresh before showing a frame.
+    pub vsync: bool,
+    // However much is animating, the bar isn't drawn more often than this.
+    pub max_fps: f64,
+    pub animations: Animations,
+    pub tooltips: Tooltips,
+    // Outlines every module with how often it changed, and shows frame
+    // times and the latest warning. Also toggled with `rustybar msg debug`.
+    pub debug_overlay: bool,
+    pub module: HashMap<String, toml::Value>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            output: OutputSelection::Primary,
+            anchor: Anchor::Top,
+            size: Length::Pixels(32.0),
+            gap_v: (Length::default(), Length::default()),
+            gap_h: (Length::default(), Length::default()),
+            length: None,
+            align: EdgeAlign::Center,
+            offset: Length::default(),
+            background: Color::rgb(0x22, 0x22, 0x22),
+            gradient: None,
+            corner_radius: 0.0,
+            border: None,
+            background_shader: None,
+            blur: false,
+            foreground: Color::rgb(0xdd, 0xdd, 0xdd),
+            colorscheme: None,
+            theme: None,
+            palette: PaletteConfig::default(),
+            font: vec!["monospace".to_string()],
+            font_size: Length::Pixels(DEFAULT_FONT_SIZE),
+            modules: Vec::new(),
+            modules_left: Vec::new(),
+            modules_center: Vec::new(),
+            modules_right: Vec::new(),
+            vertical_layout: VerticalLayout::Stack,
+            click_through: ClickThrough::Spacers,
+            keyboard_focus: false,
+            xembed_tray: None,
+            window: WindowHints::default(),
+            autohide: None,
+            hide_on_fullscreen: false,
+            vsync: false,
+            max_fps: 60.0,
+            animations: Animations::default(),
+            tooltips: Tooltips::default(),
+            debug_overlay: false,
+            module: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("rustybar").join("config.toml"))
+    }
+
+    pub fn load() -> Result<Config, Error> {
+        match Config::path() {
+            Some(path) if path.exists() => Config::load_from(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    pub fn load_from(path: &std::path::Path) -> Result<Config, Error> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut config: toml::Value = toml::from_str(&contents)?;
+        let config_dir = path.parent().unwrap_or_else(|| std::path::Path::new("."));
+        palette::apply(&mut config, config_dir);
+
+        let mut config: Config = config.try_into()?;
+        config.background_shader = config.background_shader.map(|shader| config_dir.join(shader));
+        Ok(config)
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+use zbus::blocking::Connection;
+use zbus::object_server::SignalEmitter;
+use tracing::warn;
+use crate::Error;
+use crate::ipc::Command;
+
+// The same commands as the IPC socket, offered on the session bus for
+// scripts and daemons that already speak D-Bus:
+//
+//     busctl --user call org.rustybar.Control /org/rustybar/Control \
+//         org.rustybar.Control SetModuleText ss clock "lunch"
+//
+// ModuleUpdated is emitted with the module's name whenever what it shows
+// changes.
+const NAME: &str = "org.rustybar.Control";
+const PATH: &str = "/org/rustybar/Control";
+
+struct Control {
+    on_command: Arc<dyn Fn(Command) + Send + Sync>,
+}
+
+#[zbus::interface(name = "org.rustybar.Control")]
+impl Control {
+    fn show(&self) {
+        (self.on_command)(Command::Show);
+    }
+
+    fn hide(&self) {
+        (self.on_command)(Command::Hide);
+    }
+
+    fn toggle(&self) {
+        (self.on_command)(Command::Toggle);
+    }
+
+    fn reload(&self) {
+        (self.on_command)(Command::Reload);
+    }
+
+    fn toggle_debug(&self) {
+        (self.on_command)(Command::Debug);
+    }
+
+    // An empty text hands the module back to its widget.
+    fn set_module_text(&self, module: String, text: String) {
+        (self.on_command)(Command::Set { module, text });
+    }
+
+    fn run_action(&self, module: String, action: String) {
+        (self.on_command)(Command::Action { module, action });
+    }
+
+    #[zbus(signal)]
+    async fn module_updated(emitter: &SignalEmitter<'_>, module: &str) -> zbus::Result<()>;
+}
+
+// Owns the bus name while it's alive.
+pub struct Service {
+    connection: Connection,
+}
+
+impl Service {
+    pub fn start<F: Fn(Command) + Send + Sync + 'static>(on_command: F) -> Result<Service, Error> {
+        let connection = Connection::session()?;
+        connection.object_server().at(PATH, Control { on_command: Arc::new(on_command) })?;
+        connection.request_name(NAME)?;
+
+        Ok(Service { connection })
+    }
+
+    pub fn module_updated(&self, module: &str) {
+        let emitted = SignalEmitter::new(self.connection.inner(), PATH)
+            .and_then(|emitter| zbus::block_on(Control::module_updated(&emitter, module)));
+        if let Err(err) = emitted {
+            warn!("Failed to announce an update of '{}' on D-Bus: {}", module, err);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use crate::Error;
+
+// What keeps the bar from running at all. Problems confined to one module
+// don't end up here, that module shows them on the bar instead.
+#[derive(Debug)]
+pub enum BarError {
+    Config(Error),
+    Window(Error),
+    Renderer(Error),
+    Display(Error),
+}
+
+impl std::fmt::Display for BarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BarError::Config(err) => write!(f, "Failed to load the config: {}", err),
+            BarError::Window(err) => write!(f, "Failed to create the bar's windows: {}", err),
+            BarError::Renderer(err) => write!(f, "Failed to set up drawing: {}", err),
+            BarError::Display(err) => write!(f, "Lost the display: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BarError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BarError::Config(err) | BarError::Window(err) | BarError::Renderer(err) | BarError::Display(err) => {
+                Some(err.as_ref())
+            },
+        }
+    }
+}
+
+impl BarError {
+    // Errors that already say what failed are passed through as they are.
+    pub fn or(err: Error, variant: fn(Error) -> BarError) -> BarError {
+        match err.downcast::<BarError>() {
+            Ok(err) => *err,
+            Err(err) => variant(err),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::Error;
+use crate::widgets::Waker;
+use crate::widgets::ewmh::Watcher;
+use crate::widgets::i3ipc::Subscription;
+use crate::x11::X11;
+
+fn query(x11: &X11) -> Result<bool, Error> {
+    let atoms = x11.atoms();
+    let active = match x11.window(x11.root(), atoms._NET_ACTIVE_WINDOW)? {
+        Some(window) => window,
+        None => return Ok(false),
+    };
+
+    // Selecting the active window brings its state changes, such as going
+    // fullscreen without losing the focus.
+    let fullscreen = || -> Result<bool, Error> {
+        x11.select_property_changes(active)?;
+        Ok(x11.atom_list(active, atoms._NET_WM_STATE)?.contains(&atoms._NET_WM_STATE_FULLSCREEN))
+    };
+
+    Ok(fullscreen().unwrap_or(false))
+}
+
+// Follows whether the focused window is fullscreen, through sway's IPC when
+// it's running and EWMH otherwise.
+pub enum Fullscreen {
+    Ipc(Subscription<bool>),
+    X11(Watcher<bool>),
+}
+
+impl Fullscreen {
+    pub fn spawn(waker: Waker) -> Fullscreen {
+        if std::env::var_os("SWAYSOCK").is_some() {
+            Fullscreen::Ipc(Subscription::spawn(&["window", "workspace"], waker, |connection| {
+                connection.focused_fullscreen()
+            }))
+        } else {
+            Fullscreen::X11(Watcher::spawn(waker, query))
+        }
+    }
+
+    pub fn take(&self) -> Option<bool> {
+        match self {
+            Fullscreen::Ipc(subscription) => subscription.take(),
+            Fullscreen::X11(watcher) => watcher.take(),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use crate::Error;
+
+// Runtime control over a unix socket. Clients send a single line holding a
+// command and get a single line back, either "ok" or "error: <reason>".
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Show,
+    Hide,
+    Toggle,
+    Reload,
+    // Turns the debug overlay on or off.
+    Debug,
+    // Replaces what a module shows with fixed text, an empty text hands the
+    // module back to its widget.
+    Set { module: String, text: String },
+    // Runs one of a module's built-in actions, along with whatever follows
+    // its name, such as "start 10m".
+    Action { module: String, action: String },
+}
+
+#[derive(Debug)]
+pub struct CommandParseError(String);
+
+impl std::fmt::Display for CommandParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown command '{}', expected show, hide, toggle, reload, debug, set <module> <text> or <module> <action>", self.0)
+    }
+}
+
+impl std::error::Error for CommandParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+impl std::str::FromStr for Command {
+    type Err = CommandParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        match name {
+            "show" => Ok(Command::Show),
+            "hide" => Ok(Command::Hide),
+            "toggle" => Ok(Command::Toggle),
+            "reload" => Ok(Command::Reload),
+            "debug" => Ok(Command::Debug),
+            "set" if !rest.trim().is_empty() => {
+                let (module, text) = rest.trim().split_once(' ').unwrap_or((rest.trim(), ""));
+                Ok(Command::Set { module: module.to_string(), text: text.trim().to_string() })
+            },
+            _ if !rest.trim().is_empty() => Ok(Command::Action { module: name.to_string(), action: rest.trim().to_string() }),
+            _ => Err(CommandParseError(line.to_string())),
+        }
+    }
+}
+
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("rustybar.sock"),
+        None => {
+            let uid = std::fs::metadata("/proc/self").map(|metadata| metadata.uid()).unwrap_or(0);
+            std::env::temp_dir().join(format!("rustybar-{}.sock", uid))
+        },
+    }
+}
+
+// Removes the socket again when the bar shuts down.
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Server {
+    pub fn listen<F: Fn(Command) + Send + 'static>(on_command: F) -> Result<Server, Error> {
+        let path = socket_path();
+
+        // A socket file nobody answers on is left over from a bar that
+        // didn't shut down cleanly.
+        if path.exists() && UnixStream::connect(&path).is_err() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        std::thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                let mut line = String::new();
+                if BufReader::new(&stream).read_line(&mut line).is_err() {
+                    continue;
+                }
+
+                let reply = match line.parse() {
+                    Ok(command) => {
+                        on_command(command);
+                        "ok".to_string()
+                    },
+                    Err(err) => format!("error: {}", err),
+                };
+
+                let _ = writeln!(&stream, "{}", reply);
+            }
+        });
+
+        Ok(Server { path })
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Sends a command to a running bar and returns its reply.
+pub fn send(message: &str) -> Result<String, Error> {
+    let stream = UnixStream::connect(socket_path())?;
+    writeln!(&stream, "{}", message)?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply)?;
+
+    Ok(reply.trim().to_string())
+}
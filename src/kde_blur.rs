@@ -0,0 +1,15 @@
+// Client bindings for KWin's blur protocol, generated by build.rs from
+// protocols/blur.xml.
+#![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#![allow(non_upper_case_globals, non_snake_case, unused_imports)]
+#![allow(static_mut_refs, clippy::all)]
+
+pub(crate) use wayland_client::{Main, Attached, Proxy, ProxyMap, AnonymousObject};
+pub(crate) use wayland_commons::map::{Object, ObjectMetadata};
+pub(crate) use wayland_commons::{Interface, MessageGroup};
+pub(crate) use wayland_commons::wire::{Argument, MessageDesc, ArgumentType, Message};
+pub(crate) use wayland_commons::smallvec;
+pub(crate) use wayland_client::protocol::{wl_region, wl_surface};
+pub(crate) use wayland_client::sys;
+
+include!(concat!(env!("OUT_DIR"), "/blur_client_api.rs"));
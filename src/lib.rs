@@ -0,0 +1,363 @@
+// Everything but parsing the command line lives here, so binaries can add
+// their own widgets to the registry before handing it to `run`:
+//
+//     let mut registry = Registry::default();
+//     registry.register("uptime", Uptime::build);
+//     if let Err(err) = rustybar::run(Args::parse(), registry) {
+//         eprintln!("{}", err);
+//     }
+pub mod animation;
+mod appearance;
+mod autohide;
+pub mod bar;
+pub mod cli;
+pub mod color;
+pub mod config;
+mod dbus;
+pub mod error;
+mod fractional_scale;
+mod fullscreen;
+mod kde_blur;
+pub mod ipc;
+mod logging;
+mod palette;
+mod polybar;
+mod popup;
+pub mod render;
+mod signals;
+mod watch;
+mod wayland;
+pub mod widgets;
+mod x11;
+mod xembed;
+
+pub use bar::Bar;
+pub use config::Config;
+pub use error::BarError;
+pub use render::Renderer;
+pub use widgets::{Registry, Widget};
+
+use glium::glutin;
+use glutin::dpi::PhysicalSize;
+use glutin::event::{ElementState, Event, MouseButton, MouseScrollDelta, StartCause, WindowEvent};
+use glutin::event_loop::{ControlFlow, EventLoop};
+use bar::BarEvent;
+use std::sync::Mutex;
+use tracing::{error, info, warn};
+use widgets::{Button, Scroll, Waker};
+
+pub type Vector2<T> = (T, T);
+
+pub type Error = Box<dyn std::error::Error>;
+
+// Touchpads scroll in pixels rather than lines, this many pixels count as
+// one step of a scroll wheel.
+const PIXELS_PER_SCROLL_STEP: f64 = 20.0;
+
+fn load_config(args: &cli::Args) -> Result<Config, Error> {
+    let mut config = match &args.config {
+        Some(path) => Config::load_from(path)?,
+        None => Config::load()?,
+    };
+    args.apply(&mut config);
+
+    Ok(config)
+}
+
+// The colorscheme and theme are watched along with the config, so that the
+// bar follows pywal as it changes the wallpaper. They're watched again after
+// every reload in case the config now names different ones.
+fn watch_config_files<F: Fn() + Send + 'static>(args: &cli::Args, config: &Config, on_change: F) -> Option<notify::RecommendedWatcher> {
+    let path = args.config.clone().or_else(Config::path)?;
+    let config_dir = path.parent().unwrap_or_else(|| std::path::Path::new("."));
+    let paths: Vec<_> = config.colorscheme.as_deref().and_then(|colorscheme| palette::colorscheme_path(colorscheme, config_dir))
+        .into_iter()
+        .chain(config.theme.as_deref().map(|theme| palette::theme_path(theme, config_dir)))
+        .chain(config.background_shader.clone())
+        .chain(std::iter::once(path.clone()))
+        .collect();
+
+    let names: Vec<_> = paths.iter().map(|path| path.display().to_string()).collect();
+    watch::watch_files(&paths, on_change)
+        .map_err(|err| warn!("Not watching {} for changes: {}", names.join(", "), err))
+        .ok()
+}
+
+// Keeps taking commands for as long as it's alive.
+struct Listeners {
+    _server: Option<ipc::Server>,
+    service: Option<dbus::Service>,
+}
+
+impl Listeners {
+    // Tells D-Bus clients which modules changed since the last call.
+    fn announce(&self, bar: &mut Bar) {
+        let updated = bar.take_updated();
+        if let Some(service) = &self.service {
+            for module in updated {
+                service.module_updated(&module);
+            }
+        }
+    }
+}
+
+// Commands arrive over the IPC socket, D-Bus and as signals.
+fn listen_commands<F: Fn(ipc::Command) + Send + Sync + 'static>(on_command: F) -> Listeners {
+    let on_command = std::sync::Arc::new(on_command);
+
+    let on_signal = std::sync::Arc::clone(&on_command);
+    if let Err(err) = signals::listen(move |command| on_signal(command)) {
+        warn!("Not handling signals: {}", err);
+    }
+
+    let on_call = std::sync::Arc::clone(&on_command);
+    let service = dbus::Service::start(move |command| on_call(command))
+        .map_err(|err| info!("Not offering the D-Bus control interface: {}", err))
+        .ok();
+
+    let server = ipc::Server::listen(move |command| on_command(command))
+        .map_err(|err| warn!("Not listening on {}: {}", ipc::socket_path().display(), err))
+        .ok();
+
+    Listeners { _server: server, service }
+}
+
+pub fn run(args: cli::Args, mut registry: Registry) -> Result<(), BarError> {
+
+    if let Some(cli::Command::Msg { message }) = &args.command {
+        match ipc::send(&message.join(" ")) {
+            Ok(reply) if reply.starts_with("error") => {
+                eprintln!("{}", reply);
+                std::process::exit(1);
+            },
+            Ok(_) => return Ok(()),
+            Err(err) => {
+                eprintln!("Failed to reach the bar at {}: {}", ipc::socket_path().display(), err);
+                std::process::exit(1);
+            },
+        }
+    }
+
+    if let Some(cli::Command::ImportPolybar { path, bar }) = &args.command {
+        match polybar::import(path, bar.as_deref()) {
+            Ok(config) => print!("{}", config),
+            Err(err) => {
+                eprintln!("Failed to import {}: {}", path.display(), err);
+                std::process::exit(1);
+            },
+        }
+        return Ok(());
+    }
+
+    if let Err(err) = logging::init(&args.log_level, args.log_file.as_deref()) {
+        eprintln!("Not logging: {}", err);
+    }
+
+    if let Err(err) = appearance::read() {
+        info!("Not following the desktop's dark mode: {}", err);
+    }
+
+    let mut config = load_config(&args).map_err(BarError::Config)?;
+    widgets::plugin::load_all(&mut registry);
+
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        match wayland::Session::connect() {
+            Ok(session) => {
+                return session.run(&args, config, &registry).map_err(|err| BarError::or(err, BarError::Display));
+            },
+            Err(err) => info!("Falling back to X11: {}", err),
+        }
+    }
+
+    let event_loop = EventLoop::<BarEvent>::with_user_event();
+    let proxy = Mutex::new(event_loop.create_proxy());
+    let waker = Waker::new(move || {
+        let _ = proxy.lock().unwrap().send_event(BarEvent::Wake);
+    });
+    let mut bar = Bar::new(&event_loop, &config, &registry, &waker)?;
+
+    let watcher_proxy = event_loop.create_proxy();
+    let watch = move |args: &cli::Args, config: &Config| {
+        let proxy = watcher_proxy.clone();
+        watch_config_files(args, config, move || {
+            let _ = proxy.send_event(BarEvent::ConfigChanged);
+        })
+    };
+    let mut _watcher = watch(&args, &config);
+
+    let appearance_proxy = event_loop.create_proxy();
+    let _ = appearance::watch(move || {
+        let _ = appearance_proxy.send_event(BarEvent::ConfigChanged);
+    });
+
+    let ipc_proxy = Mutex::new(event_loop.create_proxy());
+    let listeners = listen_commands(move |command| {
+        let _ = ipc_proxy.lock().unwrap().send_event(BarEvent::Command(command));
+    });
+
+    let outputs_proxy = Mutex::new(event_loop.create_proxy());
+    let outputs = x11::X11::connect().and_then(|x11| x11.watch_outputs(move || {
+        let _ = outputs_proxy.lock().unwrap().send_event(BarEvent::OutputsChanged);
+    }));
+    if let Err(err) = outputs {
+        info!("Not watching for monitor changes: {}", err);
+    }
+
+    let reload_proxy = event_loop.create_proxy();
+    let mut cursor = None;
+    // Where the pointer is on the popup, if it's on it.
+    let mut popup_cursor = None;
+    let mut scrolled = 0.0;
+    // Widgets are only updated when one of them was woken or a timer ran
+    // out, not for every pointer motion and other unrelated window events.
+    let mut update_due = true;
+    event_loop.run(move |ev, target, control_flow| {
+        match ev {
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => update_due = true,
+            Event::UserEvent(BarEvent::Wake) => update_due = true,
+            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => *control_flow = ControlFlow::Exit,
+            // The bar works in logical pixels, winit reports physical ones.
+            Event::WindowEvent { window_id, event: WindowEvent::CursorMoved { position, .. } } => {
+                popup_cursor = bar.popup_surface().filter(|_| bar.is_popup_window(window_id)).map(|surface| {
+                    let position = position.to_logical::<f32>(surface.scale_factor());
+                    (position.x, position.y)
+                });
+                let output = bar.output_for_window(window_id);
+                cursor = output.map(|index| {
+                    let position = position.to_logical::<f32>(bar.outputs()[index].surface().scale_factor());
+                    (index, (position.x, position.y))
+                });
+                bar.hover(cursor.map(|(index, (x, y))| (index, x, y)));
+            },
+            // The bar keeps the size it was laid out for on its monitor, and
+            // is laid out again when that monitor's scale changed.
+            Event::WindowEvent { window_id, event: WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } } => {
+                if let Some(index) = bar.output_for_window(window_id) {
+                    let surface = bar.outputs()[index].surface();
+                    let (width, height) = surface.dimensions();
+                    *new_inner_size = PhysicalSize::new(width, height);
+                    if (scale_factor - surface.scale_factor()).abs() > f64::EPSILON {
+                        let _ = reload_proxy.send_event(BarEvent::OutputsChanged);
+                    }
+                }
+            },
+            Event::WindowEvent { event: WindowEvent::CursorEntered { .. }, .. } => bar.set_hovered(true),
+            Event::WindowEvent { event: WindowEvent::CursorLeft { .. }, .. } => {
+                cursor = None;
+                popup_cursor = None;
+                bar.set_hovered(false);
+                bar.hover(None);
+            },
+            Event::WindowEvent { window_id, event: WindowEvent::MouseInput { state: ElementState::Pressed, button, .. } } => {
+                let button = match button {
+                    MouseButton::Left => Some(Button::Left),
+                    MouseButton::Middle => Some(Button::Middle),
+                    MouseButton::Right => Some(Button::Right),
+                    MouseButton::Other(_) => None,
+                };
+
+                if let (Some(position), Some(button)) = (popup_cursor.filter(|_| bar.is_popup_window(window_id)), button) {
+                    if bar.click_popup(position, button) {
+                        bar.request_redraw();
+                    }
+                } else if let (Some((index, position)), Some(button)) = (cursor, button) {
+                    if bar.click(index, position, button) {
+                        bar.request_redraw();
+                    }
+                }
+            },
+            Event::MainEventsCleared => {
+                if update_due && bar.update() {
+                    bar.request_redraw();
+                }
+                update_due = false;
+                listeners.announce(&mut bar);
+
+                if bar.sync_popup(|parent, placement| popup::create_window(target, parent, placement)) {
+                    bar.request_redraw();
+                }
+
+                *control_flow = match bar.next_update() {
+                    Some(deadline) => ControlFlow::WaitUntil(deadline),
+                    None => ControlFlow::Wait,
+                };
+            },
+            Event::WindowEvent { window_id, event: WindowEvent::MouseWheel { delta, .. } } => {
+                scrolled += match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => lines as f64,
+                    MouseScrollDelta::PixelDelta(pixels) => pixels.y / PIXELS_PER_SCROLL_STEP,
+                };
+
+                let direction = if scrolled > 0.0 { Scroll::Up } else { Scroll::Down };
+                let mut redraw = false;
+                while scrolled.abs() >= 1.0 {
+                    scrolled -= scrolled.signum();
+                    if bar.is_popup_window(window_id) {
+                        redraw |= bar.scroll_popup(direction);
+                    } else if let Some((index, position)) = cursor {
+                        redraw |= bar.scroll(index, position, direction);
+                    }
+                }
+
+                if redraw {
+                    bar.request_redraw();
+                }
+            },
+            Event::RedrawRequested(window) => {
+                if let Some(index) = bar.output_for_window(window) {
+                    bar.draw(index);
+                } else if bar.is_popup_window(window) {
+                    bar.draw_popup();
+                }
+            },
+            Event::UserEvent(BarEvent::Command(command)) => match command {
+                ipc::Command::Show => bar.set_visible(true),
+                ipc::Command::Hide => bar.set_visible(false),
+                ipc::Command::Toggle => bar.set_visible(!bar.is_visible()),
+                ipc::Command::Debug => {
+                    bar.toggle_debug();
+                    bar.request_redraw();
+                },
+                ipc::Command::Reload => {
+                    let _ = reload_proxy.send_event(BarEvent::ConfigChanged);
+                },
+                ipc::Command::Set { module, text } => {
+                    if bar.set_text(&module, &text) {
+                        bar.request_redraw();
+                    } else {
+                        warn!("No module named '{}'", module);
+                    }
+                },
+                ipc::Command::Action { module, action } => match bar.run_action(&module, &action) {
+                    Some(true) => bar.request_redraw(),
+                    Some(false) => (),
+                    None => warn!("No module named '{}'", module),
+                },
+            },
+            Event::UserEvent(BarEvent::ConfigChanged) => {
+                let reloaded = load_config(&args)
+                    .and_then(|new_config| Ok((Bar::new(target, &new_config, &registry, &waker)?, new_config)));
+                match reloaded {
+                    Ok((new_bar, new_config)) => {
+                        cursor = None;
+                        update_due = true;
+                        bar = new_bar;
+                        config = new_config;
+                        _watcher = watch(&args, &config);
+                    },
+                    Err(err) => error!("Failed to reload config: {}", err),
+                }
+            },
+            Event::UserEvent(BarEvent::OutputsChanged) => {
+                match bar::create_windows(target, &config).and_then(|surfaces| bar.set_surfaces(surfaces, &config)) {
+                    Ok(()) => {
+                        cursor = None;
+                        bar.request_redraw();
+                    },
+                    Err(err) => warn!("Failed to move the bar to the new monitor layout: {}", err),
+                }
+            },
+            _ => (),
+        }
+    });
+}
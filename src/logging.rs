@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{Event, Level, Subscriber};
+use tracing::field::{Field, Visit};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use crate::Error;
+
+// Diagnostics go through tracing, with the module path as the target, so
+// `--log-level warn,rustybar::widgets::battery=debug` adds one module's
+// debug messages to everyone's warnings. Modules just use tracing's macros.
+
+// How many warnings and errors are kept for the debug overlay.
+const KEPT: usize = 20;
+
+static RECENT: Mutex<VecDeque<Record>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+struct Recent;
+
+impl<S: Subscriber> Layer<S> for Recent {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        // More verbose levels compare greater.
+        if *metadata.level() > Level::WARN {
+            return;
+        }
+
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        if let Ok(mut recent) = RECENT.lock() {
+            if recent.len() == KEPT {
+                recent.pop_front();
+            }
+            recent.push_back(Record { level: *metadata.level(), target: metadata.target().to_string(), message: message.0 });
+        }
+    }
+}
+
+// Logs to the terminal, or only to the file when one is given.
+pub fn init(filter: &str, file: Option<&Path>) -> Result<(), Error> {
+    let targets: Targets = filter.parse()?;
+    let registry = tracing_subscriber::registry().with(targets).with(Recent);
+
+    match file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let layer = tracing_subscriber::fmt::layer().with_ansi(false).with_writer(Mutex::new(file));
+            registry.with(layer).try_init()?;
+        },
+        None => registry.with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr)).try_init()?,
+    }
+
+    Ok(())
+}
+
+// The latest warning or error.
+pub fn latest() -> Option<Record> {
+    RECENT.lock().ok()?.back().cloned()
+}
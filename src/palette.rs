@@ -0,0 +1,155 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use crate::Error;
+use crate::appearance::{self, ColorScheme};
+
+// Names colors so the rest of the config can refer to them as "$name" or
+// "color.name":
+//
+//     theme = "themes/gruvbox-dark.yaml"
+//     background = "color.base00"
+//
+//     [palette]
+//     accent = "color.base0D"
+//
+//     [module.cpu.block]
+//     background = "$accent"
+//
+// Names come from, in increasing order of precedence:
+//
+// - a colorscheme in the pywal format, providing "background",
+//   "foreground", "cursor" and "color0" to "color15";
+// - a Base16 theme, providing "base00" to "base0F";
+// - the `palette` table, for plain colors or names for the others;
+// - its `dark` or `light` table, whichever the desktop prefers, or dark if
+//   it has no preference. The config is reloaded when that changes.
+//
+//     [palette.dark]
+//     accent = "#88aaff"
+//
+//     [palette.light]
+//     accent = "#2244aa"
+//
+// Relative paths are relative to the directory of the config.
+#[derive(Debug, Default)]
+struct Palette(HashMap<String, String>);
+
+// The file pywal writes whenever it generates a colorscheme, which wallust
+// writes in the same format with its pywal template.
+fn wal_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("wal").join("colors.json"))
+}
+
+fn resolve(path: &str, config_dir: &Path) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => config_dir.join(path),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Colorscheme {
+    #[serde(default)]
+    special: HashMap<String, String>,
+    #[serde(default)]
+    colors: HashMap<String, String>,
+}
+
+// "wal" is short for the file pywal writes, anything else is a path.
+pub fn colorscheme_path(colorscheme: &str, config_dir: &Path) -> Option<PathBuf> {
+    if colorscheme == "wal" {
+        wal_path()
+    } else {
+        Some(resolve(colorscheme, config_dir))
+    }
+}
+
+pub fn theme_path(theme: &str, config_dir: &Path) -> PathBuf {
+    resolve(theme, config_dir)
+}
+
+// Older Base16 themes list the colors at the top level next to the scheme's
+// name and author, newer ones in a `palette` mapping. Either way they're
+// usually written without the leading #.
+fn read_base16(path: &Path) -> Result<HashMap<String, String>, Error> {
+    let mut theme: HashMap<String, serde_yaml::Value> = serde_yaml::from_str(&std::fs::read_to_string(path)?)?;
+    let colors = match theme.remove("palette") {
+        Some(palette) => serde_yaml::from_value(palette)?,
+        None => theme,
+    };
+
+    Ok(colors.into_iter()
+        .filter(|(name, _)| name.starts_with("base"))
+        .filter_map(|(name, color)| {
+            let color = color.as_str()?.trim();
+            let color = if color.starts_with('#') { color.to_string() } else { format!("#{}", color) };
+            Some((name, color))
+        })
+        .collect())
+}
+
+impl Palette {
+    fn read_colorscheme(path: &Path) -> Result<HashMap<String, String>, Error> {
+        let scheme: Colorscheme = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(scheme.special.into_iter().chain(scheme.colors).collect())
+    }
+
+    // Colors that fail to load are only logged, leaving their names
+    // unresolved, so that say pywal not having run yet doesn't keep the bar
+    // from starting.
+    fn extend(&mut self, path: &Path, colors: Result<HashMap<String, String>, Error>) {
+        match colors {
+            Ok(colors) => self.0.extend(colors),
+            Err(err) => warn!("Not using the colors in {}: {}", path.display(), err),
+        }
+    }
+
+    fn get(&self, reference: &str) -> Option<&String> {
+        let name = reference.strip_prefix('$').or_else(|| reference.strip_prefix("color."))?;
+        self.0.get(name)
+    }
+
+    fn substitute(&self, value: &mut toml::Value) {
+        match value {
+            toml::Value::String(string) => {
+                if let Some(color) = self.get(string) {
+                    *string = color.clone();
+                }
+            },
+            toml::Value::Array(values) => values.iter_mut().for_each(|value| self.substitute(value)),
+            toml::Value::Table(table) => table.iter_mut().for_each(|(_, value)| self.substitute(value)),
+            _ => (),
+        }
+    }
+}
+
+// Replaces every string in the config that refers to a palette color.
+pub fn apply(config: &mut toml::Value, config_dir: &Path) {
+    let setting = |key: &str| config.get(key).and_then(|value| value.as_str());
+    let mut palette = Palette::default();
+
+    if let Some(path) = setting("colorscheme").and_then(|colorscheme| colorscheme_path(colorscheme, config_dir)) {
+        palette.extend(&path, Palette::read_colorscheme(&path));
+    }
+
+    if let Some(path) = setting("theme").map(|theme| theme_path(theme, config_dir)) {
+        palette.extend(&path, read_base16(&path));
+    }
+
+    let mode = match appearance::current() {
+        ColorScheme::Light => "light",
+        ColorScheme::Dark | ColorScheme::Default => "dark",
+    };
+    let table = config.get("palette").and_then(|palette| palette.as_table());
+    let mode_table = table.and_then(|table| table.get(mode)).and_then(|mode| mode.as_table());
+    for (name, color) in table.into_iter().chain(mode_table).flatten() {
+        if let Some(color) = color.as_str() {
+            let color = palette.get(color).cloned().unwrap_or_else(|| color.to_string());
+            palette.0.insert(name.clone(), color);
+        }
+    }
+
+    palette.substitute(config);
+}
@@ -0,0 +1,637 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+use crate::Error;
+
+// Translates a polybar config into a rustybar one. Only the common bar
+// settings and built-in modules are covered, anything else is reported on
+// stderr and left out.
+
+#[derive(Debug)]
+pub struct NoBarError(Option<String>);
+
+impl std::fmt::Display for NoBarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(name) => write!(f, "The polybar config has no [bar/{}] section", name),
+            None => write!(f, "The polybar config has no bar sections"),
+        }
+    }
+}
+
+impl std::error::Error for NoBarError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// References nest, this many levels are followed before giving up on one.
+const MAX_DEPTH: usize = 16;
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+// Sections in the order they appear, each with its raw values.
+#[derive(Debug, Default)]
+struct Ini {
+    sections: Vec<(String, HashMap<String, String>)>,
+}
+
+impl Ini {
+    fn load(path: &Path, ini: &mut Ini, depth: usize) -> Result<(), Error> {
+        let contents = std::fs::read_to_string(path)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut section = String::new();
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+            let value = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value);
+
+            if key == "include-file" && depth < MAX_DEPTH {
+                Ini::load(&directory.join(expand_home(value)), ini, depth + 1)?;
+                continue;
+            }
+
+            match ini.sections.iter_mut().find(|(name, _)| *name == section) {
+                Some((_, values)) => {
+                    values.insert(key.to_string(), value.to_string());
+                },
+                None => ini.sections.push((section.clone(), HashMap::from([(key.to_string(), value.to_string())]))),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Looks through the sections a section inherits from as well.
+    fn raw(&self, section: &str, key: &str, depth: usize) -> Option<&str> {
+        let values = &self.sections.iter().find(|(name, _)| name == section)?.1;
+        match values.get(key) {
+            Some(value) => Some(value),
+            None if depth < MAX_DEPTH => self.raw(values.get("inherit")?, key, depth + 1),
+            None => None,
+        }
+    }
+
+    fn get(&self, section: &str, key: &str) -> Option<String> {
+        self.raw(section, key, 0).map(|value| self.resolve(section, value, 0))
+    }
+
+    // Replaces ${section.key}, ${root.key}, ${self.key} and ${env:NAME}
+    // references, each optionally followed by :fallback.
+    fn resolve(&self, section: &str, value: &str, depth: usize) -> String {
+        let mut resolved = String::new();
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            resolved.push_str(&rest[..start]);
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+
+            let reference = &rest[start + 2..end];
+            let (reference, fallback) = match reference.strip_prefix("env:") {
+                Some(env) => match env.split_once(':') {
+                    Some((name, fallback)) => (format!("env:{}", name), Some(fallback)),
+                    None => (reference.to_string(), None),
+                },
+                None => match reference.split_once(':') {
+                    Some((reference, fallback)) => (reference.to_string(), Some(fallback)),
+                    None => (reference.to_string(), None),
+                },
+            };
+
+            let found = match reference.strip_prefix("env:") {
+                Some(name) => std::env::var(name).ok(),
+                None if depth < MAX_DEPTH => reference.rsplit_once('.').and_then(|(target, key)| {
+                    let target = match target {
+                        "self" => section,
+                        "root" => self.sections.iter().find(|(name, _)| name.starts_with("bar/"))
+                            .map_or(section, |(name, _)| name.as_str()),
+                        target => target,
+                    };
+                    self.raw(target, key, 0).map(|value| self.resolve(target, value, depth + 1))
+                }),
+                None => None,
+            };
+
+            resolved.push_str(&found.or_else(|| fallback.map(str::to_string)).unwrap_or_default());
+            rest = &rest[end + 1..];
+        }
+        resolved.push_str(rest);
+
+        resolved
+    }
+}
+
+// Polybar puts the alpha first, as in #AARRGGBB. Anything but hex digits
+// would keep the translated config from loading.
+fn color(value: &str) -> Option<Value> {
+    let digits = value.trim().strip_prefix('#').filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))?;
+    let converted = match digits.len() {
+        4 => format!("#{}{}", &digits[1..], &digits[..1]),
+        8 => format!("#{}{}", &digits[2..], &digits[..2]),
+        3 | 6 => format!("#{}", digits),
+        _ => return None,
+    };
+    Some(Value::String(converted))
+}
+
+// Sizes may carry a unit, points are turned into pixels and percentages
+// can't be translated.
+fn number(value: &str) -> Option<f64> {
+    let value = value.trim();
+    if value.contains('%') {
+        return None;
+    }
+    match value.strip_suffix("pt") {
+        Some(points) => points.trim().parse::<f64>().ok().map(|points| (points * 4.0 / 3.0).round()),
+        None => value.trim_end_matches(|c: char| c.is_ascii_alphabetic()).parse().ok(),
+    }
+}
+
+// Like number, but keeps a plain percentage such as "3%" for the settings
+// that take one. Percentages with an offset like "50%:-10" are dropped.
+fn length(value: &str) -> Option<Value> {
+    let value = value.trim();
+    match value.strip_suffix('%').and_then(|percent| percent.trim().parse::<f64>().ok()) {
+        Some(percent) => Some(Value::String(format!("{}%", percent))),
+        None => number(value).map(Value::Float),
+    }
+}
+
+// Removes %{...} formatting tags.
+fn strip_tags(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("%{") {
+        stripped.push_str(&rest[..start]);
+        rest = rest[start..].split_once('}').map_or("", |(_, rest)| rest);
+    }
+    stripped.push_str(rest);
+
+    stripped
+}
+
+// Fonts are fontconfig patterns like "Fira Code:style=Bold:size=10;2",
+// sizes in points are turned into pixels.
+fn font(pattern: &str) -> (String, Option<f64>) {
+    let pattern = pattern.split(';').next().unwrap_or(pattern);
+    let mut parts = pattern.split(':');
+    let family = parts.next().unwrap_or("").trim().to_string();
+
+    let size = parts.filter_map(|part| part.split_once('=')).find_map(|(key, value)| match key.trim() {
+        "pixelsize" => value.trim().parse().ok(),
+        "size" => value.trim().parse::<f64>().ok().map(|points| (points * 4.0 / 3.0).round()),
+        _ => None,
+    });
+
+    (family, size)
+}
+
+// Turns %token% placeholders into rustybar ones, dropping formatting tags
+// and tokens without a counterpart.
+fn label(text: &str, tokens: &[(&str, &str)]) -> String {
+    let text = strip_tags(text);
+    let mut translated = String::new();
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find('%') {
+        translated.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let end = match rest.find('%') {
+            Some(end) => end,
+            None => {
+                translated.push('%');
+                break;
+            },
+        };
+
+        let token = rest[..end].split(':').next().unwrap_or("");
+        match tokens.iter().find(|(known, _)| *known == token) {
+            Some((_, placeholder)) => translated.push_str(&format!("{{{}}}", placeholder)),
+            None if token.is_empty() => translated.push('%'),
+            None => (),
+        }
+        rest = &rest[end + 1..];
+    }
+    translated.push_str(rest);
+
+    translated.trim().to_string()
+}
+
+struct Module<'a> {
+    ini: &'a Ini,
+    section: String,
+    table: Table,
+}
+
+impl Module<'_> {
+    fn get(&self, key: &str) -> Option<String> {
+        self.ini.get(&self.section, key)
+    }
+
+    fn copy(&mut self, from: &str, to: &str) {
+        if let Some(value) = self.get(from) {
+            self.table.insert(to.to_string(), Value::String(value));
+        }
+    }
+
+    fn copy_number(&mut self, from: &str, to: &str) {
+        if let Some(value) = self.get(from).as_deref().and_then(number) {
+            self.table.insert(to.to_string(), Value::Float(value));
+        }
+    }
+
+    fn label(&mut self, from: &str, to: &str, tokens: &[(&str, &str)]) {
+        if let Some(value) = self.get(from) {
+            self.table.insert(to.to_string(), Value::String(label(&value, tokens)));
+        }
+    }
+}
+
+// Returns the rustybar section for a polybar module, or None for module
+// types that have no counterpart.
+fn module(ini: &Ini, name: &str) -> Option<Table> {
+    let section = format!("module/{}", name);
+    let kind = ini.get(&section, "type")?;
+    let mut module = Module { ini, section, table: Table::new() };
+
+    let rustybar = match kind.as_str() {
+        "internal/date" => {
+            let date = module.get("date").unwrap_or_default();
+            let time = module.get("time").unwrap_or_default();
+            let template = strip_tags(&module.get("label").unwrap_or_else(|| "%date%".to_string()));
+            let format = template.replace("%date%", &date).replace("%time%", &time);
+            module.table.insert("format".to_string(), Value::String(format.trim().to_string()));
+            "clock"
+        },
+        "internal/cpu" => {
+            module.label("label", "format", &[("percentage", "usage")]);
+            module.copy_number("interval", "interval");
+            "cpu"
+        },
+        "internal/memory" => {
+            let tokens = [
+                ("percentage_used", "percent"),
+                ("gb_used", "used"),
+                ("gb_free", "free"),
+                ("gb_total", "total"),
+                ("mb_used", "used"),
+                ("mb_free", "free"),
+                ("mb_total", "total"),
+                ("percentage_swap_used", "swap_percent"),
+            ];
+            module.label("label", "format", &tokens);
+            module.copy_number("interval", "interval");
+            "memory"
+        },
+        "internal/battery" => {
+            let tokens = [("percentage", "capacity"), ("percentage_raw", "capacity")];
+            module.copy("battery", "battery");
+            module.label("label-discharging", "format", &tokens);
+            module.label("label-charging", "format-charging", &tokens);
+            module.copy_number("poll-interval", "interval");
+            "battery"
+        },
+        "internal/network" => {
+            let tokens = [
+                ("ifname", "interface"),
+                ("local_ip", "ip"),
+                ("local_ip6", "ipv6"),
+                ("downspeed", "down"),
+                ("upspeed", "up"),
+                ("essid", "ssid"),
+                ("signal", "signal"),
+            ];
+            module.copy("interface", "interface");
+            module.label("label-connected", "format", &tokens);
+            module.label("label-disconnected", "format-disconnected", &tokens);
+            module.copy_number("interval", "interval");
+
+            let wireless = module.get("interface-type").as_deref() == Some("wireless")
+                || module.get("interface").is_some_and(|interface| interface.starts_with('w'));
+            if wireless {
+                "wireless"
+            } else {
+                if let Some(format) = module.table.remove("format-disconnected") {
+                    module.table.insert("format-down".to_string(), format);
+                }
+                "network"
+            }
+        },
+        "internal/pulseaudio" | "internal/alsa" => {
+            module.label("label-volume", "format", &[("percentage", "volume")]);
+            module.label("label-muted", "format-muted", &[("percentage", "volume")]);
+            "volume"
+        },
+        "internal/backlight" | "internal/xbacklight" => {
+            module.copy("card", "device");
+            module.label("label", "format", &[("percentage", "percent")]);
+            "backlight"
+        },
+        "internal/temperature" => {
+            // The module shows one unit, Fahrenheit when that's all the
+            // label asks for.
+            let label = module.get("label").unwrap_or_default();
+            if label.contains("%temperature-f%") && !label.contains("%temperature-c%") {
+                module.table.insert("unit".to_string(), Value::String("fahrenheit".to_string()));
+            }
+            module.label("label", "format", &[("temperature-c", "temperature"), ("temperature-f", "temperature")]);
+            module.copy_number("interval", "interval");
+            "temperature"
+        },
+        "internal/fs" => {
+            let mounts: Vec<Value> = (0..).map_while(|index| module.get(&format!("mount-{}", index)))
+                .map(Value::String)
+                .collect();
+            if !mounts.is_empty() {
+                module.table.insert("mounts".to_string(), Value::Array(mounts));
+            }
+            let tokens = [
+                ("mountpoint", "mount"),
+                ("free", "free"),
+                ("used", "used"),
+                ("total", "total"),
+                ("percentage_used", "percent"),
+            ];
+            module.label("label-mounted", "format", &tokens);
+            module.copy_number("interval", "interval");
+            "disk"
+        },
+        "internal/xwindow" => {
+            module.label("label", "format", &[("title", "title")]);
+            "title"
+        },
+        "internal/xworkspaces" | "internal/bspwm" => {
+            module.label("label-active", "format", &[("name", "name"), ("index", "index")]);
+            "desktops"
+        },
+        "internal/i3" => {
+            module.label("label-focused", "format", &[("name", "name"), ("index", "index")]);
+            "workspaces"
+        },
+        "internal/xkeyboard" => {
+            module.label("label-layout", "format", &[("layout", "short"), ("name", "name")]);
+            "keyboard"
+        },
+        "custom/script" => {
+            module.copy("exec", "command");
+            module.label("label", "format", &[("output", "output")]);
+            module.copy_number("interval", "interval");
+            if module.get("tail").as_deref() == Some("true") {
+                eprintln!("Module '{}' tails its script, rustybar runs it every interval instead", name);
+            }
+            "custom"
+        },
+        "custom/text" => {
+            let text = module.get("format").or_else(|| module.get("content")).unwrap_or_default();
+            let quoted = label(&text, &[]).replace('\'', "'\\''");
+            module.table.insert("command".to_string(), Value::String(format!("printf '%s\\n' '{}'", quoted)));
+            module.table.insert("interval".to_string(), Value::Float(3600.0));
+            "custom"
+        },
+        _ => {
+            eprintln!("Skipping module '{}', rustybar has nothing like {}", name, kind);
+            return None;
+        },
+    };
+
+    if let Some(foreground) = module.get("format-foreground").as_deref().and_then(color) {
+        module.table.insert("color".to_string(), foreground);
+    }
+
+    let mut block = Table::new();
+    if let Some(background) = module.get("format-background").as_deref().and_then(color) {
+        block.insert("background".to_string(), background);
+    }
+    for line in ["underline", "overline"] {
+        if let Some(line_color) = module.get(&format!("format-{}", line)).as_deref().and_then(color) {
+            block.insert(line.to_string(), Value::Table(vec![("color".to_string(), line_color)].into_iter().collect()));
+        }
+    }
+    if !block.is_empty() {
+        module.table.insert("block".to_string(), Value::Table(block));
+    }
+
+    if name != rustybar {
+        module.table.insert("type".to_string(), Value::String(rustybar.to_string()));
+    }
+    Some(module.table)
+}
+
+pub fn import(path: &Path, bar: Option<&str>) -> Result<String, Error> {
+    let mut ini = Ini::default();
+    Ini::load(path, &mut ini, 0)?;
+
+    let section = match bar {
+        Some(name) => format!("bar/{}", name),
+        None => ini.sections.iter().find(|(name, _)| name.starts_with("bar/")).map(|(name, _)| name.clone())
+            .ok_or(NoBarError(None))?,
+    };
+    if !ini.sections.iter().any(|(name, _)| *name == section) {
+        return Err(Box::new(NoBarError(bar.map(str::to_string))));
+    }
+
+    let get = |key: &str| ini.get(&section, key);
+    let mut config = Table::new();
+
+    if get("bottom").as_deref() == Some("true") {
+        config.insert("anchor".to_string(), Value::String("bottom".to_string()));
+    }
+    if let Some(height) = get("height").as_deref().and_then(length) {
+        config.insert("size".to_string(), height);
+    }
+    if let Some(monitor) = get("monitor").filter(|monitor| !monitor.is_empty()) {
+        config.insert("output".to_string(), Value::String(monitor));
+    }
+    for (from, to) in [("background", "background"), ("foreground", "foreground")] {
+        if let Some(value) = get(from).as_deref().and_then(color) {
+            config.insert(to.to_string(), value);
+        }
+    }
+    if let Some(radius) = get("radius").as_deref().and_then(number) {
+        config.insert("corner-radius".to_string(), Value::Float(radius));
+    }
+    // Gaps can't be negative, so offsets pushing the bar off the screen are
+    // left out.
+    for (from, to) in [("offset-x", "gap-h"), ("offset-y", "gap-v")] {
+        if let Some(offset) = get(from).filter(|offset| !offset.trim().starts_with('-')).as_deref().and_then(length) {
+            config.insert(to.to_string(), Value::Array(vec![offset.clone(), offset]));
+        }
+    }
+
+    let fonts: Vec<(String, Option<f64>)> = (0..).map_while(|index| get(&format!("font-{}", index)))
+        .map(|pattern| font(&pattern))
+        .filter(|(family, _)| !family.is_empty())
+        .collect();
+    if let Some(size) = fonts.first().and_then(|(_, size)| *size) {
+        config.insert("font-size".to_string(), Value::Float(size));
+    }
+    if !fonts.is_empty() {
+        let families = fonts.into_iter().map(|(family, _)| Value::String(family)).collect();
+        config.insert("font".to_string(), Value::Array(families));
+    }
+
+    // The tray is a module since polybar 3.7 and a bar setting before.
+    let mut modules = Table::new();
+    let mut tray = get("tray-position").is_some_and(|position| position != "none");
+    for group in ["left", "center", "right"] {
+        let names = get(&format!("modules-{}", group)).unwrap_or_default();
+        let mut kept = Vec::new();
+        for name in names.split_whitespace() {
+            if ini.get(&format!("module/{}", name), "type").as_deref() == Some("internal/tray") {
+                tray = true;
+            } else if let Some(table) = module(&ini, name) {
+                modules.insert(name.to_string(), Value::Table(table));
+                kept.push(Value::String(name.to_string()));
+            }
+        }
+        if !kept.is_empty() {
+            config.insert(format!("modules-{}", group), Value::Array(kept));
+        }
+    }
+
+    if tray {
+        config.insert("xembed-tray".to_string(), Value::Table(Table::new()));
+    }
+    if !modules.is_empty() {
+        config.insert("module".to_string(), Value::Table(modules));
+    }
+
+    Ok(toml::to_string(&Value::Table(config))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: Option<Value>) -> Option<String> {
+        value.and_then(|value| value.as_str().map(str::to_string))
+    }
+
+    #[test]
+    fn colors_move_the_alpha_to_the_end() {
+        assert_eq!(string(color("#FF282828")), Some("#282828FF".to_string()));
+        assert_eq!(string(color("#8f00")), Some("#f008".to_string()));
+        assert_eq!(string(color(" #282828 ")), Some("#282828".to_string()));
+        assert_eq!(string(color("#fff")), Some("#fff".to_string()));
+    }
+
+    #[test]
+    fn colors_that_are_not_hex_are_dropped() {
+        assert_eq!(color("#zzzzzzzz"), None);
+        assert_eq!(color("#ééé"), None);
+        assert_eq!(color("#ff00ff0"), None);
+        assert_eq!(color("ff0000"), None);
+        assert_eq!(color("#"), None);
+    }
+
+    #[test]
+    fn lengths_keep_percentages_and_convert_points() {
+        assert_eq!(length("30"), Some(Value::Float(30.0)));
+        assert_eq!(length("24px"), Some(Value::Float(24.0)));
+        assert_eq!(length("12pt"), Some(Value::Float(16.0)));
+        assert_eq!(length("3%"), Some(Value::String("3%".to_string())));
+        assert_eq!(length("50%:-10"), None);
+        assert_eq!(length("wide"), None);
+    }
+
+    #[test]
+    fn labels_translate_tokens_and_drop_tags() {
+        assert_eq!(label("%{F#f00}CPU%{F-} %percentage%%", &[("percentage", "usage")]), "CPU {usage}%");
+        assert_eq!(label("%unknown% %percentage:3%", &[("percentage", "usage")]), "{usage}");
+    }
+
+    #[test]
+    fn imports_a_bar() {
+        let path = std::env::temp_dir().join(format!("rustybar-polybar-{}.ini", std::process::id()));
+        std::fs::write(&path, "\
+[colors]
+background = #FF282828
+
+[bar/main]
+bottom = true
+height = 30
+background = ${colors.background}
+foreground = #ccc
+offset-x = -10
+offset-y = 5
+font-0 = Fira Code:size=12;2
+modules-left = date temperature
+modules-right = cpu unknown
+tray-position = right
+
+[module/date]
+type = internal/date
+date = %H:%M
+label = %date%
+format-foreground = #FFff0000
+
+[module/temperature]
+type = internal/temperature
+label = %temperature-f%
+
+[module/cpu]
+type = internal/cpu
+label = CPU %percentage%%
+interval = 2
+
+[module/unknown]
+type = internal/mpd
+").unwrap();
+        let imported = import(&path, None);
+        std::fs::remove_file(&path).unwrap();
+
+        let config: Value = toml::from_str(&imported.unwrap()).unwrap();
+        assert_eq!(config["anchor"].as_str(), Some("bottom"));
+        assert_eq!(config["size"].as_float(), Some(30.0));
+        assert_eq!(config["background"].as_str(), Some("#282828FF"));
+        assert_eq!(config["foreground"].as_str(), Some("#ccc"));
+        assert!(config.get("gap-h").is_none());
+        assert_eq!(config["gap-v"], Value::Array(vec![Value::Float(5.0), Value::Float(5.0)]));
+        assert_eq!(config["font"], Value::Array(vec![Value::String("Fira Code".to_string())]));
+        assert_eq!(config["font-size"].as_float(), Some(16.0));
+        assert!(config.get("xembed-tray").is_some());
+
+        let left = Value::Array(vec![Value::String("date".to_string()), Value::String("temperature".to_string())]);
+        assert_eq!(config["modules-left"], left);
+        assert_eq!(config["modules-right"], Value::Array(vec![Value::String("cpu".to_string())]));
+
+        let modules = &config["module"];
+        assert_eq!(modules["date"]["type"].as_str(), Some("clock"));
+        assert_eq!(modules["date"]["format"].as_str(), Some("%H:%M"));
+        assert_eq!(modules["date"]["color"].as_str(), Some("#ff0000FF"));
+        assert_eq!(modules["temperature"]["format"].as_str(), Some("{temperature}"));
+        assert_eq!(modules["temperature"]["unit"].as_str(), Some("fahrenheit"));
+        assert!(modules["cpu"].get("type").is_none());
+        assert_eq!(modules["cpu"]["format"].as_str(), Some("CPU {usage}%"));
+        assert_eq!(modules["cpu"]["interval"].as_float(), Some(2.0));
+    }
+
+    #[test]
+    fn a_missing_bar_is_an_error() {
+        let path = std::env::temp_dir().join(format!("rustybar-polybar-missing-{}.ini", std::process::id()));
+        std::fs::write(&path, "[bar/main]\nheight = 30\n").unwrap();
+        let imported = import(&path, Some("other"));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(imported.unwrap_err().to_string(), "The polybar config has no [bar/other] section");
+    }
+}
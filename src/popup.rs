@@ -0,0 +1,185 @@
+use glium::glutin;
+use glutin::dpi::{PhysicalPosition, PhysicalSize, Position, Size};
+use glutin::event_loop::EventLoopWindowTarget;
+use glutin::platform::unix::{WindowBuilderExtUnix, XWindowType};
+use std::rc::Rc;
+use tracing::error;
+use crate::{Error, Vector2};
+use crate::bar::{Anchor, Surface};
+use crate::color::Color;
+use crate::config::Tooltips;
+use crate::render::{self, Fonts, Primitive, Renderer, RenderCtx};
+
+// Where a popup goes: next to the bar on the side away from the screen edge
+// it's anchored to, centered on `along`, a position along the bar in its
+// own logical pixels. Sizes are in logical pixels too.
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    pub anchor: Anchor,
+    pub along: f32,
+    pub size: Vector2<u32>,
+    pub gap: f32,
+}
+
+fn line_height(fonts: &Fonts) -> f32 {
+    (fonts.size() * 1.3).ceil()
+}
+
+// Lays out lines of markup one below the other.
+fn layout<'a>(fonts: &'a Fonts, text: &str, color: Color) -> Vec<RenderCtx<'a>> {
+    text.lines()
+        .map(|line| {
+            let mut ctx = RenderCtx::new(fonts, color, line_height(fonts));
+            ctx.set_markup(true);
+            ctx.text(line, color);
+            ctx
+        })
+        .collect()
+}
+
+// The size a popup needs to show the text.
+pub fn measure(fonts: &Fonts, text: &str, style: &Tooltips) -> Vector2<u32> {
+    let lines = layout(fonts, text, Color::TRANSPARENT);
+    let width = lines.iter().map(|line| line.width()).fold(0.0, f32::max);
+    let height = lines.len() as f32 * line_height(fonts);
+
+    ((width + 2.0 * style.padding).ceil() as u32, (height + 2.0 * style.padding).ceil() as u32)
+}
+
+// A popup window showing a few lines of text for one of the modules.
+pub struct Popup {
+    surface: Surface,
+    renderer: Renderer,
+    module: usize,
+    size: Vector2<u32>,
+    text: String,
+}
+
+impl Popup {
+    pub fn new(surface: Surface, fonts: Fonts, module: usize, size: Vector2<u32>, text: String)
+        -> Result<Popup, Error> {
+        let renderer = Renderer::new(surface.context(), fonts)?;
+        Ok(Popup { surface, renderer, module, size, text })
+    }
+
+    pub fn surface(&self) -> &Surface {
+        &self.surface
+    }
+
+    pub fn module(&self) -> usize {
+        self.module
+    }
+
+    pub fn shows(&self, module: usize, text: &str) -> bool {
+        self.module == module && self.text == text
+    }
+
+    // Whether text for the module measuring the given size can replace the
+    // current text without a new window.
+    pub fn fits(&self, module: usize, size: Vector2<u32>) -> bool {
+        self.module == module && self.size == size
+    }
+
+    // The line of text at the given height in logical pixels.
+    pub fn line_at(&self, y: f32, style: &Tooltips) -> Option<usize> {
+        let line = ((y - style.padding) / line_height(self.renderer.fonts())).floor();
+        Some(line).filter(|line| *line >= 0.0)
+            .map(|line| line as usize)
+            .filter(|line| *line < self.text.lines().count())
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        self.text = text;
+    }
+
+    pub fn draw(&mut self, style: &Tooltips, background: Color, foreground: Color) {
+        let dimensions = self.surface.dimensions();
+        let scale = self.surface.scale_factor() as f32;
+        let (width, height) = (dimensions.0 as f32 / scale, dimensions.1 as f32 / scale);
+
+        let mut primitives = vec![Primitive::Rect {
+            x: 0.0,
+            y: 0.0,
+            width,
+            height,
+            radius: style.corner_radius,
+            color: style.background.unwrap_or(background),
+            border: None,
+        }];
+
+        let fonts = self.renderer.fonts();
+        let color = style.foreground.unwrap_or(foreground);
+        for (index, line) in layout(fonts, &self.text, color).into_iter().enumerate() {
+            let y = style.padding + index as f32 * line_height(fonts);
+            primitives.extend(line.into_primitives(style.padding, y));
+        }
+
+        let primitives: Vec<Primitive> = primitives.iter().map(|primitive| primitive.scaled(scale)).collect();
+        if let Err(err) = self.renderer.draw(dimensions, Color::TRANSPARENT, &primitives) {
+            error!("Failed to draw a popup: {}", err);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnsupportedSurfaceError;
+
+impl std::fmt::Display for UnsupportedSurfaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Popups for Wayland surfaces are created by the Wayland backend")
+    }
+}
+
+impl std::error::Error for UnsupportedSurfaceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// Opens an override-redirect window next to the bar window, kept on the
+// bar's monitor and scaled like the bar.
+pub fn create_window<T>(target: &EventLoopWindowTarget<T>, parent: &Surface, placement: &Placement)
+    -> Result<Surface, Error> {
+    let (origin, size, monitor, scale, x11) = match parent {
+        Surface::X11(window, home, scale, x11) => {
+            let window = window.window();
+            (window.inner_position().unwrap_or(*home), window.inner_size(), window.current_monitor(), *scale, x11)
+        },
+        Surface::Wayland(_) => return Err(Box::new(UnsupportedSurfaceError)),
+    };
+
+    let physical = |value: f32| (value as f64 * scale).round() as i32;
+    let (width, height) = (physical(placement.size.0 as f32).max(1), physical(placement.size.1 as f32).max(1));
+    let (along, gap) = (physical(placement.along), physical(placement.gap));
+    let (x, y) = match placement.anchor {
+        Anchor::Top => (origin.x + along - width / 2, origin.y + size.height as i32 + gap),
+        Anchor::Bottom => (origin.x + along - width / 2, origin.y - height - gap),
+        Anchor::Left => (origin.x + size.width as i32 + gap, origin.y + along - height / 2),
+        Anchor::Right => (origin.x - width - gap, origin.y + along - height / 2),
+    };
+
+    let (x, y) = match monitor {
+        Some(monitor) => {
+            let (start, extent) = (monitor.position(), monitor.size());
+            (
+                x.min(start.x + extent.width as i32 - width).max(start.x),
+                y.min(start.y + extent.height as i32 - height).max(start.y),
+            )
+        },
+        None => (x, y),
+    };
+
+    let wb = glutin::window::WindowBuilder::new()
+        .with_transparent(true)
+        .with_decorations(false)
+        .with_override_redirect(true)
+        .with_x11_window_type(vec![XWindowType::Tooltip])
+        .with_inner_size(Size::Physical(PhysicalSize::new(width as u32, height as u32)));
+
+    let surface = render::Window::new(target, wb, false)?;
+
+    let position = PhysicalPosition::new(x, y);
+    surface.window().set_outer_position(Position::Physical(position));
+
+    Ok(Surface::X11(surface, position, scale, Rc::clone(x11)))
+}
@@ -0,0 +1,861 @@
+use glium::{glutin, Surface, implement_vertex, uniform};
+use glium::backend::Facade;
+use glutin::{ContextError, PossiblyCurrent, RawContext};
+use glutin::event_loop::EventLoopWindowTarget;
+use glutin::platform::unix::RawContextExt;
+use glutin::window::WindowBuilder;
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::os::raw::c_void;
+use std::rc::Rc;
+use std::sync::{Arc, Weak};
+use cgmath::{
+    Matrix4 as Matrix,
+    Vector3 as Vector,
+};
+use crate::Error;
+use crate::color::Color;
+use super::{Border, Fonts, GraphVertex, Image, Primitive, Progress, Ring, Scissor};
+
+mod text;
+
+use text::{Line, TextRenderer};
+
+// glium only knows how to drive winit windows, so the EGL context glutin
+// creates on a raw Wayland surface is handed to it through its Backend
+// trait.
+struct RawBackend {
+    context: RefCell<Option<RawContext<PossiblyCurrent>>>,
+    dimensions: Cell<(u32, u32)>,
+}
+
+#[derive(Clone)]
+struct SharedBackend(Rc<RawBackend>);
+
+unsafe impl glium::backend::Backend for SharedBackend {
+    fn swap_buffers(&self) -> Result<(), glium::SwapBuffersError> {
+        match self.0.context.borrow().as_ref().map(|context| context.swap_buffers()) {
+            Some(Ok(())) => Ok(()),
+            Some(Err(ContextError::ContextLost)) | None => Err(glium::SwapBuffersError::ContextLost),
+            Some(Err(_)) => Err(glium::SwapBuffersError::AlreadySwapped),
+        }
+    }
+
+    unsafe fn get_proc_address(&self, symbol: &str) -> *const c_void {
+        match self.0.context.borrow().as_ref() {
+            Some(context) => context.get_proc_address(symbol),
+            None => std::ptr::null(),
+        }
+    }
+
+    fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+        self.0.dimensions.get()
+    }
+
+    fn is_current(&self) -> bool {
+        self.0.context.borrow().as_ref().is_some_and(|context| context.is_current())
+    }
+
+    unsafe fn make_current(&self) {
+        let mut slot = self.0.context.borrow_mut();
+        if let Some(context) = slot.take() {
+            *slot = Some(match context.make_current() {
+                Ok(context) => context,
+                Err((context, _)) => context,
+            });
+        }
+    }
+}
+
+// What a renderer draws to the screen through, shared with the window or
+// surface it belongs to.
+#[derive(Clone)]
+pub struct Context {
+    gl: Rc<glium::backend::Context>,
+    // Only set for raw Wayland surfaces, glutin manages its own windows.
+    raw: Option<Rc<RawBackend>>,
+}
+
+impl Context {
+    // Creates an EGL context on a Wayland surface, given the wl_display and
+    // wl_surface pointers, which have to outlive it.
+    pub(crate) unsafe fn wayland(display: *mut c_void, surface: *mut c_void, (width, height): (u32, u32), vsync: bool)
+        -> Result<Context, Error> {
+        let raw = glutin::ContextBuilder::new().with_vsync(vsync)
+            .build_raw_wayland_context(display as *const _, surface, width, height)?;
+        let raw = raw.make_current().map_err(|(_, err)| err)?;
+
+        let backend = Rc::new(RawBackend {
+            context: RefCell::new(Some(raw)),
+            dimensions: Cell::new((width, height)),
+        });
+        let gl = glium::backend::Context::new(SharedBackend(Rc::clone(&backend)), true, Default::default())?;
+
+        Ok(Context { gl, raw: Some(backend) })
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.gl.get_framebuffer_dimensions()
+    }
+
+    // Only needed for Wayland surfaces, windows follow their own size.
+    pub fn resize(&self, (width, height): (u32, u32)) {
+        if let Some(raw) = &self.raw {
+            if let Some(context) = raw.context.borrow().as_ref() {
+                context.resize(glutin::dpi::PhysicalSize::new(width, height));
+            }
+            raw.dimensions.set((width, height));
+        }
+    }
+
+    // Lets go of a Wayland surface's EGL context ahead of the surface.
+    pub fn release(&self) {
+        if let Some(raw) = &self.raw {
+            raw.context.borrow_mut().take();
+        }
+    }
+}
+
+// A winit window along with the context drawing into it.
+pub struct Window {
+    display: glium::Display,
+    context: Context,
+}
+
+impl Window {
+    pub fn new<T>(target: &EventLoopWindowTarget<T>, builder: WindowBuilder, vsync: bool) -> Result<Window, Error> {
+        let cb = glutin::ContextBuilder::new().with_vsync(vsync);
+        let display = glium::Display::from_gl_window(cb.build_windowed(builder, target)?)?;
+        let context = Context { gl: display.get_context().clone(), raw: None };
+        Ok(Window { display, context })
+    }
+
+    pub fn window(&self) -> Ref<'_, glutin::window::Window> {
+        Ref::map(self.display.gl_window(), |gl_window| gl_window.window())
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.display.get_framebuffer_dimensions()
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 2],
+}
+
+impl Vertex {
+    pub fn new(x: f32, y: f32) -> Vertex {
+        Vertex {
+            position: [x, y],
+        }
+    }
+}
+
+implement_vertex!(Vertex, position);
+
+implement_vertex!(GraphVertex, position, color);
+
+// Every color the shaders see is premultiplied by its alpha, whether it
+// comes from Color::gl_red() and the like or from a texture, and so is
+// everything they output. That's also what compositors expect of the
+// contents of a transparent window, the clear color included.
+const PREMULTIPLIED: glium::Blend = glium::Blend {
+    color: glium::BlendingFunction::Addition {
+        source: glium::LinearBlendingFactor::One,
+        destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+    },
+    alpha: glium::BlendingFunction::Addition {
+        source: glium::LinearBlendingFactor::One,
+        destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+    },
+    constant_value: (0.0, 0.0, 0.0, 0.0),
+};
+
+const VERTEX_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 position;
+    out vec2 v_local;
+    uniform mat4 matrix;
+    uniform mat4 model;
+    uniform vec2 size;
+
+    void main() {
+        gl_Position = matrix * model * vec4(position, 0.0, 1.0);
+        v_local = position * size;
+    }
+"#;
+
+// Rounded corners come from the signed distance to a rounded box, which
+// also gives a pixel wide antialiased edge for free. The fill color is
+// premultiplied, so coverage scales all four channels.
+const FRAGMENT_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_local;
+    out vec4 color;
+    uniform vec4 fill;
+    uniform vec4 border;
+    uniform float border_width;
+    uniform vec2 size;
+    uniform float radius;
+
+    void main() {
+        float r = min(radius, min(size.x, size.y) / 2.0);
+        vec2 q = abs(v_local - size / 2.0) - (size / 2.0 - vec2(r));
+        float distance = length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - r;
+
+        float outside = clamp(0.5 - distance, 0.0, 1.0);
+        float inside = clamp(0.5 - distance - border_width, 0.0, 1.0);
+        color = (fill * inside + border * (1.0 - inside)) * outside;
+    }
+"#;
+
+// Same shape as the rect shader, with the color looked up from a texture
+// holding one texel per gradient color.
+const GRADIENT_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_local;
+    out vec4 color;
+    uniform sampler1D colors;
+    uniform float count;
+    uniform vec2 direction;
+    uniform vec2 size;
+    uniform float radius;
+
+    void main() {
+        float r = min(radius, min(size.x, size.y) / 2.0);
+        vec2 q = abs(v_local - size / 2.0) - (size / 2.0 - vec2(r));
+        float distance = length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - r;
+
+        float extent = dot(abs(direction), size);
+        float t = clamp(0.5 + dot(v_local - size / 2.0, direction) / extent, 0.0, 1.0);
+        vec4 fill = texture(colors, (t * (count - 1.0) + 0.5) / count);
+
+        color = fill * clamp(0.5 - distance, 0.0, 1.0);
+    }
+"#;
+
+// Images are uploaded premultiplied, see icons::premultiply(). A tint keeps
+// only their alpha.
+const IMAGE_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_local;
+    out vec4 color;
+    uniform sampler2D image;
+    uniform vec2 size;
+    uniform bool tinted;
+    uniform vec4 tint;
+
+    void main() {
+        vec4 texel = texture(image, v_local / size);
+        color = tinted ? tint * texel.a : texel;
+    }
+"#;
+
+// The border is what lies inside the rounded box but outside the inner one,
+// which is inset by each edge's width and rounded less. Every pixel belongs
+// to the edge it's closest to relative to that edge's width, an edge
+// without width never being the closest.
+const FRAME_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_local;
+    out vec4 color;
+    uniform vec4 widths;
+    uniform vec4 top;
+    uniform vec4 right;
+    uniform vec4 bottom;
+    uniform vec4 left;
+    uniform vec2 size;
+    uniform float radius;
+
+    float rounded_box(vec2 p, vec2 start, vec2 end, float r) {
+        vec2 half_size = max((end - start) / 2.0, vec2(0.0));
+        r = min(r, min(half_size.x, half_size.y));
+        vec2 q = abs(p - (start + end) / 2.0) - (half_size - vec2(r));
+        return length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - r;
+    }
+
+    void main() {
+        float outer = rounded_box(v_local, vec2(0.0), size, radius);
+        float widest = max(max(widths.x, widths.y), max(widths.z, widths.w));
+        float inner = rounded_box(v_local, vec2(widths.w, widths.x), size - vec2(widths.y, widths.z),
+            max(radius - widest, 0.0));
+        float coverage = clamp(0.5 - outer, 0.0, 1.0) * clamp(0.5 + inner, 0.0, 1.0);
+
+        vec4 distances = vec4(v_local.y, size.x - v_local.x, size.y - v_local.y, v_local.x)
+            / max(widths, vec4(1e-6));
+        float nearest = min(min(distances.x, distances.y), min(distances.z, distances.w));
+        vec4 edge = nearest == distances.x ? top
+            : nearest == distances.y ? right
+            : nearest == distances.z ? bottom
+            : left;
+
+        color = edge * coverage;
+    }
+"#;
+
+// The left color covers the part of the box where d is positive, d being
+// scaled to pixels through its screen space derivative for a smooth edge.
+const TRANSITION_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_local;
+    out vec4 color;
+    uniform vec4 left;
+    uniform vec4 right;
+    uniform int shape;
+    uniform vec2 size;
+
+    void main() {
+        vec2 p = v_local / size;
+        float d;
+        if (shape == 0) {
+            d = 1.0 - abs(2.0 * p.y - 1.0) - p.x;
+        } else if (shape == 1) {
+            d = abs(2.0 * p.y - 1.0) - p.x;
+        } else if (shape == 2) {
+            d = 1.0 - p.y - p.x;
+        } else if (shape == 3) {
+            d = p.y - p.x;
+        } else if (shape == 4) {
+            d = 1.0 - length(vec2(p.x, 2.0 * p.y - 1.0));
+        } else {
+            d = length(vec2(1.0 - p.x, 2.0 * p.y - 1.0)) - 1.0;
+        }
+
+        float coverage = clamp(d / max(fwidth(d), 0.0001) + 0.5, 0.0, 1.0);
+        color = mix(right, left, coverage);
+    }
+"#;
+
+// The rect shader with the fill switching from filled to empty where the
+// value ends, antialiased across that pixel like the edges are.
+const PROGRESS_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_local;
+    out vec4 color;
+    uniform vec4 filled;
+    uniform vec4 empty;
+    uniform vec4 border;
+    uniform float border_width;
+    uniform vec2 size;
+    uniform float radius;
+    uniform float value;
+    uniform bool vertical;
+
+    void main() {
+        float r = min(radius, min(size.x, size.y) / 2.0);
+        vec2 q = abs(v_local - size / 2.0) - (size / 2.0 - vec2(r));
+        float distance = length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - r;
+
+        float along = vertical ? size.y - v_local.y : v_local.x;
+        float extent = vertical ? size.y : size.x;
+        vec4 fill = mix(empty, filled, clamp(value * extent - along + 0.5, 0.0, 1.0));
+
+        float outside = clamp(0.5 - distance, 0.0, 1.0);
+        float inside = clamp(0.5 - distance - border_width, 0.0, 1.0);
+        color = (fill * inside + border * (1.0 - inside)) * outside;
+    }
+"#;
+
+// Distance to the middle circle of the ring gives its antialiased inner and
+// outer edge, the angle from the top decides which part is filled.
+const RING_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_local;
+    out vec4 color;
+    uniform vec4 filled;
+    uniform vec4 empty;
+    uniform vec2 size;
+    uniform float thickness;
+    uniform float value;
+
+    const float TAU = 6.28318530718;
+
+    void main() {
+        vec2 p = v_local - size / 2.0;
+        float radius = size.x / 2.0 - thickness / 2.0;
+        float distance = abs(length(p) - radius) - thickness / 2.0;
+
+        float angle = atan(p.x, -p.y);
+        float t = angle / TAU + (angle < 0.0 ? 1.0 : 0.0);
+        float along = (value - t) * TAU * radius;
+        vec4 fill = value >= 1.0 ? filled : mix(empty, filled, clamp(along + 0.5, 0.0, 1.0));
+
+        color = fill * clamp(0.5 - distance, 0.0, 1.0);
+    }
+"#;
+
+const GRAPH_VERTEX_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 position;
+    in vec4 color;
+    out vec4 v_color;
+    uniform mat4 matrix;
+
+    void main() {
+        gl_Position = matrix * vec4(position, 0.0, 1.0);
+        v_color = color;
+    }
+"#;
+
+const GRAPH_FRAGMENT_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec4 v_color;
+    out vec4 color;
+
+    void main() {
+        color = v_color;
+    }
+"#;
+
+// Covers the whole target with a texture, turned a quarter clockwise if
+// asked so the texture's left edge ends up at the top.
+const COPY_VERTEX_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 position;
+    out vec2 v_tex_coords;
+    uniform bool rotated;
+
+    void main() {
+        gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+        v_tex_coords = rotated ? vec2(1.0 - position.y, position.x) : position;
+    }
+"#;
+
+// Sampling the canvas turns it back into linear colors, which are encoded
+// here rather than by GL so windows look the same whether or not their
+// framebuffer is sRGB capable. Compositors blend the way most desktops
+// always have, in sRGB space, so the alpha is taken out for the encoding.
+const COPY_FRAGMENT_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_tex_coords;
+    out vec4 color;
+    uniform sampler2D canvas;
+
+    void main() {
+        vec4 texel = texture(canvas, v_tex_coords);
+        vec3 straight = texel.a > 0.0 ? texel.rgb / texel.a : vec3(0.0);
+        vec3 encoded = mix(straight * 12.92, 1.055 * pow(straight, vec3(1.0 / 2.4)) - 0.055,
+            step(0.0031308, straight));
+        color = vec4(encoded * texel.a, texel.a);
+    }
+"#;
+
+// Everything is drawn to one of these first. Its values are stored sRGB
+// encoded but blended in linear space, so gradients, antialiased edges and
+// translucent colors mix the way light does instead of coming out dark.
+type Canvas = glium::texture::SrgbTexture2d;
+
+pub struct Renderer {
+    context: Context,
+    program: glium::Program,
+    gradient_program: glium::Program,
+    image_program: glium::Program,
+    transition_program: glium::Program,
+    frame_program: glium::Program,
+    progress_program: glium::Program,
+    ring_program: glium::Program,
+    graph_program: glium::Program,
+    copy_program: glium::Program,
+    background_program: Option<glium::Program>,
+    canvas: Option<Canvas>,
+    rectangle_buffer: glium::VertexBuffer<Vertex>,
+    // The texture of the last gradient drawn, bars rarely use more than one.
+    gradient: Option<(Vec<Color>, glium::texture::Texture1d)>,
+    images: HashMap<u64, (Weak<Image>, glium::texture::SrgbTexture2d)>,
+    // Rewritten for every graph drawn and only replaced when a graph needs
+    // more room than it has.
+    graph_buffer: Option<glium::VertexBuffer<GraphVertex>>,
+    text: TextRenderer,
+}
+
+impl Renderer {
+    pub fn new(context: &Context, fonts: Fonts) -> Result<Renderer, Error> {
+        let facade = &context.gl;
+        let rectangle = vec![
+            Vertex::new(0., 0.),
+            Vertex::new(1., 0.),
+            Vertex::new(0., 1.),
+            Vertex::new(1., 1.),
+        ];
+
+        let rectangle_buffer = glium::VertexBuffer::new(facade, &rectangle)?;
+        let program = glium::Program::from_source(facade, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC, None)?;
+        let gradient_program = glium::Program::from_source(facade, VERTEX_SHADER_SRC, GRADIENT_SHADER_SRC, None)?;
+        let image_program = glium::Program::from_source(facade, VERTEX_SHADER_SRC, IMAGE_SHADER_SRC, None)?;
+        let transition_program = glium::Program::from_source(facade, VERTEX_SHADER_SRC, TRANSITION_SHADER_SRC, None)?;
+        let frame_program = glium::Program::from_source(facade, VERTEX_SHADER_SRC, FRAME_SHADER_SRC, None)?;
+        let progress_program = glium::Program::from_source(facade, VERTEX_SHADER_SRC, PROGRESS_SHADER_SRC, None)?;
+        let ring_program = glium::Program::from_source(facade, VERTEX_SHADER_SRC, RING_SHADER_SRC, None)?;
+        let graph_program = glium::Program::from_source(facade, GRAPH_VERTEX_SHADER_SRC, GRAPH_FRAGMENT_SHADER_SRC, None)?;
+        let copy_program = glium::Program::new(facade, glium::program::ProgramCreationInput::SourceCode {
+            vertex_shader: COPY_VERTEX_SHADER_SRC,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            geometry_shader: None,
+            fragment_shader: COPY_FRAGMENT_SHADER_SRC,
+            transform_feedback_varyings: None,
+            // Keeps GL from encoding the output a second time.
+            outputs_srgb: true,
+            uses_point_size: false,
+        })?;
+
+        let text = TextRenderer::new(facade, fonts)?;
+
+        Ok(Renderer {
+            context: context.clone(),
+            program,
+            gradient_program,
+            image_program,
+            transition_program,
+            frame_program,
+            progress_program,
+            ring_program,
+            graph_program,
+            copy_program,
+            background_program: None,
+            canvas: None,
+            rectangle_buffer,
+            gradient: None,
+            images: HashMap::new(),
+            graph_buffer: None,
+            text,
+        })
+    }
+
+    pub fn fonts(&self) -> &Fonts {
+        self.text.fonts()
+    }
+
+    // Compiles the fragment shader Shader primitives are drawn with. It gets
+    // v_local, the position in pixels from the top left of the rect, and
+    // the uniforms resolution, the rect's size, time in seconds, mouse, the
+    // pointer's position on the rect or -1 when it's elsewhere, and radius.
+    // Its output is premultiplied like the other shaders'. Nothing is drawn
+    // for Shader primitives while there is no shader or it didn't compile.
+    pub fn set_background_shader(&mut self, source: &str) -> Result<(), Error> {
+        self.background_program = None;
+        self.background_program = Some(glium::Program::from_source(&self.context.gl, VERTEX_SHADER_SRC, source, None)?);
+        Ok(())
+    }
+
+    pub fn has_background_shader(&self) -> bool {
+        self.background_program.is_some()
+    }
+
+    // Makes the canvas the given size. Returns true when that took a new
+    // one, which starts out blank.
+    pub fn prepare(&mut self, (width, height): (u32, u32)) -> Result<bool, Error> {
+        if self.canvas.as_ref().is_some_and(|canvas| canvas.dimensions() == (width, height)) {
+            return Ok(false);
+        }
+
+        self.canvas = Some(Canvas::empty_with_format(
+            &self.context.gl,
+            glium::texture::SrgbFormat::U8U8U8U8,
+            glium::texture::MipmapsOption::NoMipmap,
+            width,
+            height,
+        )?);
+        Ok(true)
+    }
+
+    // Copies the canvas onto the whole window. A rotated canvas is as high
+    // as the window is wide and turned a quarter clockwise.
+    pub fn present(&mut self, rotated: bool) -> Result<(), Error> {
+        let canvas = match &self.canvas {
+            Some(canvas) => canvas,
+            None => return Ok(()),
+        };
+
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+        let uniforms = uniform! {
+            rotated: rotated,
+            canvas: canvas.sampled()
+                .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+                .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest),
+        };
+
+        let mut target = glium::Frame::new(self.context.gl.clone(), self.context.dimensions());
+        let copied = target.draw(&self.rectangle_buffer, indices, &self.copy_program, &uniforms, &Default::default());
+        target.finish()?;
+        Ok(copied?)
+    }
+
+    // Draws a whole window of the given size.
+    pub fn draw(&mut self, dimensions: (u32, u32), background: Color, primitives: &[Primitive]) -> Result<(), Error> {
+        self.prepare(dimensions)?;
+        self.draw_clipped(background, primitives, None)?;
+        self.present(false)
+    }
+
+    // Draws into the canvas, only touching the pixels inside the scissor
+    // rectangle.
+    pub fn draw_clipped(&mut self, background: Color, primitives: &[Primitive], scissor: Option<Scissor>)
+        -> Result<(), Error> {
+        let canvas = match self.canvas.take() {
+            Some(canvas) => canvas,
+            None => return Ok(()),
+        };
+
+        // GL counts from the bottom left.
+        let scissor = scissor.map(|scissor| glium::Rect {
+            left: scissor.x,
+            bottom: canvas.dimensions().1.saturating_sub(scissor.y + scissor.height),
+            width: scissor.width,
+            height: scissor.height,
+        });
+        let drawn = glium::framebuffer::SimpleFrameBuffer::new(&self.context.gl, &canvas).map_err(Error::from)
+            .and_then(|mut target| self.render(&mut target, background, primitives, scissor));
+        self.canvas = Some(canvas);
+        drawn
+    }
+
+    fn render<S: Surface>(&mut self, target: &mut S, background: Color, primitives: &[Primitive],
+        scissor: Option<glium::Rect>) -> Result<(), Error> {
+        let facade = self.context.gl.clone();
+        let clear = (background.gl_red(), background.gl_green(), background.gl_blue(), background.gl_alpha());
+        target.clear(scissor.as_ref(), Some(clear), false, None, None);
+
+        let (width, height) = target.get_dimensions();
+        let screenspace: [[f32; 4]; 4] = cgmath::ortho(
+                0.0, width as f32,
+                height as f32, 0.0,
+                -1000.0, 1000.0
+            ).into();
+
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+        let params = glium::DrawParameters {
+            blend: PREMULTIPLIED,
+            scissor,
+            ..Default::default()
+        };
+
+        for primitive in primitives {
+            match primitive {
+                Primitive::Rect { x, y, width, height, radius, color, border } => {
+                    let border = border.unwrap_or(Border { width: 0.0, color: Color::TRANSPARENT });
+                    let model: [[f32; 4]; 4] = (Matrix::from_translation(Vector::new(*x, *y, 0.0))
+                        * Matrix::from_nonuniform_scale(*width, *height, 1.0)).into();
+
+                    let uniforms = uniform! {
+                        matrix: screenspace,
+                        model: model,
+                        size: [*width, *height],
+                        radius: *radius,
+                        fill: [color.gl_red(), color.gl_green(), color.gl_blue(), color.gl_alpha()],
+                        border: [border.color.gl_red(), border.color.gl_green(), border.color.gl_blue(),
+                            border.color.gl_alpha()],
+                        border_width: border.width,
+                    };
+
+                    target.draw(&self.rectangle_buffer, indices, &self.program, &uniforms, &params)?;
+                },
+                Primitive::Text { x, y, text, color, scale } => {
+                    let line = Line { text, position: (*x, *y), scale: *scale, color: *color };
+                    self.text.draw(&facade, target, screenspace, line, scissor)?;
+                },
+                Primitive::Gradient { x, y, width, height, radius, angle, colors } => {
+                    if colors.is_empty() {
+                        continue;
+                    }
+
+                    if self.gradient.as_ref().map(|(cached, _)| cached) != Some(colors) {
+                        let texels: Vec<_> = colors.iter()
+                            .map(|color| (color.gl_red(), color.gl_green(), color.gl_blue(), color.gl_alpha()))
+                            .collect();
+                        self.gradient = Some((colors.clone(), glium::texture::Texture1d::new(&facade, texels)?));
+                    }
+
+                    let texture = match &self.gradient {
+                        Some((_, texture)) => texture,
+                        None => continue,
+                    };
+                    let model: [[f32; 4]; 4] = (Matrix::from_translation(Vector::new(*x, *y, 0.0))
+                        * Matrix::from_nonuniform_scale(*width, *height, 1.0)).into();
+                    let angle = angle.to_radians();
+
+                    let uniforms = uniform! {
+                        matrix: screenspace,
+                        model: model,
+                        size: [*width, *height],
+                        radius: *radius,
+                        direction: [angle.cos(), angle.sin()],
+                        count: colors.len() as f32,
+                        colors: texture.sampled()
+                            .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear)
+                            .minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
+                            .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp),
+                    };
+
+                    target.draw(&self.rectangle_buffer, indices, &self.gradient_program, &uniforms, &params)?;
+                },
+                Primitive::Image { x, y, width, height, image, tint } => {
+                    if image.width == 0 || image.height == 0 {
+                        continue;
+                    }
+
+                    let (_, texture) = match self.images.entry(image.id) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            let raw = glium::texture::RawImage2d::from_raw_rgba(image.pixels.clone(), (image.width, image.height));
+                            entry.insert((Arc::downgrade(image), glium::texture::SrgbTexture2d::new(&facade, raw)?))
+                        },
+                    };
+
+                    let model: [[f32; 4]; 4] = (Matrix::from_translation(Vector::new(*x, *y, 0.0))
+                        * Matrix::from_nonuniform_scale(*width, *height, 1.0)).into();
+
+                    let uniforms = uniform! {
+                        matrix: screenspace,
+                        model: model,
+                        size: [*width, *height],
+                        tinted: tint.is_some(),
+                        tint: tint.map_or([0.0; 4], |tint| [tint.gl_red(), tint.gl_green(), tint.gl_blue(), tint.gl_alpha()]),
+                        image: texture.sampled()
+                            .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear)
+                            .minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
+                            .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp),
+                    };
+
+                    target.draw(&self.rectangle_buffer, indices, &self.image_program, &uniforms, &params)?;
+                },
+                Primitive::Transition { x, y, width, height, shape, left, right } => {
+                    let model: [[f32; 4]; 4] = (Matrix::from_translation(Vector::new(*x, *y, 0.0))
+                        * Matrix::from_nonuniform_scale(*width, *height, 1.0)).into();
+
+                    let uniforms = uniform! {
+                        matrix: screenspace,
+                        model: model,
+                        size: [*width, *height],
+                        shape: shape.index(),
+                        left: [left.gl_red(), left.gl_green(), left.gl_blue(), left.gl_alpha()],
+                        right: [right.gl_red(), right.gl_green(), right.gl_blue(), right.gl_alpha()],
+                    };
+
+                    target.draw(&self.rectangle_buffer, indices, &self.transition_program, &uniforms, &params)?;
+                },
+                Primitive::Progress(Progress { x, y, width, height, radius, value, vertical, filled, empty, border }) => {
+                    let border = border.unwrap_or(Border { width: 0.0, color: Color::TRANSPARENT });
+                    let model: [[f32; 4]; 4] = (Matrix::from_translation(Vector::new(*x, *y, 0.0))
+                        * Matrix::from_nonuniform_scale(*width, *height, 1.0)).into();
+
+                    let uniforms = uniform! {
+                        matrix: screenspace,
+                        model: model,
+                        size: [*width, *height],
+                        radius: *radius,
+                        value: value.clamp(0.0, 1.0),
+                        vertical: *vertical,
+                        filled: [filled.gl_red(), filled.gl_green(), filled.gl_blue(), filled.gl_alpha()],
+                        empty: [empty.gl_red(), empty.gl_green(), empty.gl_blue(), empty.gl_alpha()],
+                        border: [border.color.gl_red(), border.color.gl_green(), border.color.gl_blue(),
+                            border.color.gl_alpha()],
+                        border_width: border.width,
+                    };
+
+                    target.draw(&self.rectangle_buffer, indices, &self.progress_program, &uniforms, &params)?;
+                },
+                Primitive::Ring(Ring { x, y, size, thickness, value, filled, empty }) => {
+                    let model: [[f32; 4]; 4] = (Matrix::from_translation(Vector::new(*x, *y, 0.0))
+                        * Matrix::from_nonuniform_scale(*size, *size, 1.0)).into();
+
+                    let uniforms = uniform! {
+                        matrix: screenspace,
+                        model: model,
+                        size: [*size, *size],
+                        thickness: thickness.clamp(0.0, *size / 2.0),
+                        value: value.clamp(0.0, 1.0),
+                        filled: [filled.gl_red(), filled.gl_green(), filled.gl_blue(), filled.gl_alpha()],
+                        empty: [empty.gl_red(), empty.gl_green(), empty.gl_blue(), empty.gl_alpha()],
+                    };
+
+                    target.draw(&self.rectangle_buffer, indices, &self.ring_program, &uniforms, &params)?;
+                },
+                Primitive::Graph(graph) => {
+                    let vertices = graph.vertices();
+                    if vertices.is_empty() {
+                        continue;
+                    }
+
+                    if self.graph_buffer.as_ref().is_none_or(|buffer| buffer.len() < vertices.len()) {
+                        let capacity = vertices.len().next_power_of_two();
+                        self.graph_buffer = Some(glium::VertexBuffer::empty_dynamic(&facade, capacity)?);
+                    }
+
+                    let buffer = match self.graph_buffer.as_ref().and_then(|buffer| buffer.slice(0..vertices.len())) {
+                        Some(buffer) => buffer,
+                        None => continue,
+                    };
+                    buffer.write(&vertices);
+
+                    let uniforms = uniform! { matrix: screenspace };
+                    let triangles = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+                    target.draw(buffer, triangles, &self.graph_program, &uniforms, &params)?;
+                },
+                Primitive::Frame(frame) => {
+                    let model: [[f32; 4]; 4] = (Matrix::from_translation(Vector::new(frame.x, frame.y, 0.0))
+                        * Matrix::from_nonuniform_scale(frame.width, frame.height, 1.0)).into();
+                    let [top, right, bottom, left] = frame.edges;
+                    let gl = |edge: Border| [edge.color.gl_red(), edge.color.gl_green(), edge.color.gl_blue(),
+                        edge.color.gl_alpha()];
+
+                    let uniforms = uniform! {
+                        matrix: screenspace,
+                        model: model,
+                        size: [frame.width, frame.height],
+                        radius: frame.radius,
+                        widths: [top.width, right.width, bottom.width, left.width],
+                        top: gl(top),
+                        right: gl(right),
+                        bottom: gl(bottom),
+                        left: gl(left),
+                    };
+
+                    target.draw(&self.rectangle_buffer, indices, &self.frame_program, &uniforms, &params)?;
+                },
+                Primitive::Shader(shader) => {
+                    let program = match &self.background_program {
+                        Some(program) => program,
+                        None => continue,
+                    };
+                    let model: [[f32; 4]; 4] = (Matrix::from_translation(Vector::new(shader.x, shader.y, 0.0))
+                        * Matrix::from_nonuniform_scale(shader.width, shader.height, 1.0)).into();
+                    let mouse = shader.mouse.map_or([-1.0, -1.0], |(x, y)| [x - shader.x, y - shader.y]);
+
+                    let uniforms = uniform! {
+                        matrix: screenspace,
+                        model: model,
+                        size: [shader.width, shader.height],
+                        resolution: [shader.width, shader.height],
+                        time: shader.time,
+                        mouse: mouse,
+                        radius: shader.radius,
+                    };
+
+                    target.draw(&self.rectangle_buffer, indices, program, &uniforms, &params)?;
+                },
+            }
+        }
+
+        self.images.retain(|_, (image, _)| image.strong_count() > 0);
+        Ok(())
+    }
+}
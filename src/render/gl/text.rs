@@ -0,0 +1,151 @@
+use glium::{Surface, implement_vertex, uniform};
+use glium::texture::{ClientFormat, RawImage2d, Texture2d, UncompressedFloatFormat, MipmapsOption};
+use rusttype::gpu_cache::Cache;
+use crate::{Error, Vector2};
+use crate::color::Color;
+use crate::render::Fonts;
+
+const CACHE_SIZE: u32 = 1024;
+
+#[derive(Copy, Clone)]
+struct GlyphVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+}
+
+implement_vertex!(GlyphVertex, position, tex_coords);
+
+const VERTEX_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 position;
+    in vec2 tex_coords;
+    out vec2 v_tex_coords;
+    uniform mat4 matrix;
+
+    void main() {
+        gl_Position = matrix * vec4(position, 0.0, 1.0);
+        v_tex_coords = tex_coords;
+    }
+"#;
+
+// The fill is premultiplied like every other color, so the glyph's coverage
+// scales all four channels.
+const FRAGMENT_SHADER_SRC: &str = r#"
+    #version 140
+
+    in vec2 v_tex_coords;
+    out vec4 color;
+    uniform sampler2D glyphs;
+    uniform vec4 fill;
+
+    void main() {
+        color = fill * texture(glyphs, v_tex_coords).r;
+    }
+"#;
+
+// A line of text with its baseline starting at position, scaled relative to
+// the font size.
+pub struct Line<'a> {
+    pub text: &'a str,
+    pub position: Vector2<f32>,
+    pub scale: f32,
+    pub color: Color,
+}
+
+pub struct TextRenderer {
+    fonts: Fonts,
+    cache: Cache<'static>,
+    texture: Texture2d,
+    program: glium::Program,
+}
+
+impl TextRenderer {
+    pub fn new<F: glium::backend::Facade>(facade: &F, fonts: Fonts) -> Result<TextRenderer, Error> {
+        let cache = Cache::builder().dimensions(CACHE_SIZE, CACHE_SIZE).build();
+
+        let texture = Texture2d::with_format(
+                facade,
+                RawImage2d {
+                    data: std::borrow::Cow::Owned(vec![0u8; (CACHE_SIZE * CACHE_SIZE) as usize]),
+                    width: CACHE_SIZE,
+                    height: CACHE_SIZE,
+                    format: ClientFormat::U8,
+                },
+                UncompressedFloatFormat::U8,
+                MipmapsOption::NoMipmap,
+            )?;
+
+        let program = glium::Program::from_source(facade, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC, None)?;
+
+        Ok(TextRenderer { fonts, cache, texture, program })
+    }
+
+    pub fn fonts(&self) -> &Fonts {
+        &self.fonts
+    }
+
+    pub fn draw<F: glium::backend::Facade, S: Surface>(&mut self, facade: &F, target: &mut S, screenspace: [[f32; 4]; 4],
+        line: Line, scissor: Option<glium::Rect>) -> Result<(), Error> {
+        let Line { text, position, scale, color } = line;
+        let glyphs = self.fonts.layout(text, position.0, position.1, scale);
+        for (font, glyph) in &glyphs {
+            self.cache.queue_glyph(*font, glyph.clone());
+        }
+
+        let texture = &self.texture;
+        self.cache.cache_queued(|rect, data| {
+            texture.main_level().write(
+                glium::Rect {
+                    left: rect.min.x,
+                    bottom: rect.min.y,
+                    width: rect.width(),
+                    height: rect.height(),
+                },
+                RawImage2d {
+                    data: std::borrow::Cow::Borrowed(data),
+                    width: rect.width(),
+                    height: rect.height(),
+                    format: ClientFormat::U8,
+                });
+        })?;
+
+        let mut vertices = Vec::new();
+        for (font, glyph) in &glyphs {
+            if let Ok(Some((uv, screen))) = self.cache.rect_for(*font, glyph) {
+                let (left, top) = (screen.min.x as f32, screen.min.y as f32);
+                let (right, bottom) = (screen.max.x as f32, screen.max.y as f32);
+
+                vertices.extend_from_slice(&[
+                    GlyphVertex { position: [left, top], tex_coords: [uv.min.x, uv.min.y] },
+                    GlyphVertex { position: [right, top], tex_coords: [uv.max.x, uv.min.y] },
+                    GlyphVertex { position: [left, bottom], tex_coords: [uv.min.x, uv.max.y] },
+                    GlyphVertex { position: [right, top], tex_coords: [uv.max.x, uv.min.y] },
+                    GlyphVertex { position: [right, bottom], tex_coords: [uv.max.x, uv.max.y] },
+                    GlyphVertex { position: [left, bottom], tex_coords: [uv.min.x, uv.max.y] },
+                ]);
+            }
+        }
+
+        if vertices.is_empty() {
+            return Ok(());
+        }
+
+        let vertex_buffer = glium::VertexBuffer::new(facade, &vertices)?;
+        let uniforms = uniform! {
+            matrix: screenspace,
+            glyphs: self.texture.sampled().magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest),
+            fill: [color.gl_red(), color.gl_green(), color.gl_blue(), color.gl_alpha()],
+        };
+
+        let params = glium::DrawParameters {
+            blend: super::PREMULTIPLIED,
+            scissor,
+            ..Default::default()
+        };
+
+        target.draw(&vertex_buffer, glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+            &self.program, &uniforms, &params)?;
+        Ok(())
+    }
+}
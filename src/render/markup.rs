@@ -0,0 +1,310 @@
+use std::str::FromStr;
+use crate::color::Color;
+
+// A subset of Pango markup: <span> with foreground, alpha, background, size,
+// underline and strikethrough attributes, and the <u>, <s>, <big> and
+// <small> shorthands. Anything that isn't well-formed markup, such as a
+// stray '<' in a window title, is kept as plain text.
+
+// Steps between the named sizes, the same factor Pango uses.
+const SIZE_STEP: f32 = 1.2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub color: Option<Color>,
+    // How opaque the text is, whatever its color.
+    pub alpha: f32,
+    pub background: Option<Color>,
+    // Relative to the configured font size.
+    pub scale: f32,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style { color: None, alpha: 1.0, background: None, scale: 1.0, underline: false, strikethrough: false }
+    }
+}
+
+// A stretch of text sharing one style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub text: String,
+    pub style: Style,
+}
+
+fn size(value: &str, current: f32) -> Option<f32> {
+    let steps = match value {
+        "xx-small" => -3,
+        "x-small" => -2,
+        "small" => -1,
+        "medium" => 0,
+        "large" => 1,
+        "x-large" => 2,
+        "xx-large" => 3,
+        "smaller" => return Some(current / SIZE_STEP),
+        "larger" => return Some(current * SIZE_STEP),
+        _ => {
+            let percent: f32 = value.strip_suffix('%')?.parse().ok()?;
+            return Some(percent / 100.0).filter(|scale| *scale > 0.0);
+        },
+    };
+
+    Some(SIZE_STEP.powi(steps))
+}
+
+// Pango takes a percentage or a number up to 65535.
+fn alpha(value: &str) -> Option<f32> {
+    let alpha = match value.strip_suffix('%') {
+        Some(percent) => percent.parse::<f32>().ok()? / 100.0,
+        None => value.parse::<f32>().ok()? / 65535.0,
+    };
+    Some(alpha).filter(|alpha| (0.0..=1.0).contains(alpha))
+}
+
+fn flag(value: &str) -> Option<bool> {
+    match value {
+        "true" | "single" | "double" | "low" => Some(true),
+        "false" | "none" => Some(false),
+        _ => None,
+    }
+}
+
+// Applies the attributes of a span on top of the enclosing style, or fails
+// on attributes and values it doesn't know.
+fn span(attributes: &[(String, String)], mut style: Style) -> Option<Style> {
+    for (name, value) in attributes {
+        match name.as_str() {
+            "foreground" | "fgcolor" | "color" => style.color = Some(Color::from_str(value).ok()?),
+            "alpha" | "fgalpha" => style.alpha = alpha(value)?,
+            "background" | "bgcolor" => style.background = Some(Color::from_str(value).ok()?),
+            "size" | "font_size" => style.scale = size(value, style.scale)?,
+            "underline" => style.underline = flag(value)?,
+            "strikethrough" => style.strikethrough = flag(value)?,
+            _ => return None,
+        }
+    }
+
+    Some(style)
+}
+
+fn open(name: &str, attributes: &[(String, String)], style: Style) -> Option<Style> {
+    match name {
+        "span" => span(attributes, style),
+        _ if !attributes.is_empty() => None,
+        "u" => Some(Style { underline: true, ..style }),
+        "s" => Some(Style { strikethrough: true, ..style }),
+        "big" => Some(Style { scale: style.scale * SIZE_STEP, ..style }),
+        "small" => Some(Style { scale: style.scale / SIZE_STEP, ..style }),
+        _ => None,
+    }
+}
+
+enum Tag {
+    Open(String, Vec<(String, String)>),
+    Close(String),
+}
+
+// Reads a tag at the start of text, returning it along with its length.
+fn tag(text: &str) -> Option<(Tag, usize)> {
+    let end = text.find('>')?;
+    let inner = &text[1..end];
+
+    if let Some(name) = inner.strip_prefix('/') {
+        let name = name.trim();
+        return Some((Tag::Close(name.to_string()), end + 1)).filter(|_| is_name(name));
+    }
+
+    let name_end = inner.find(char::is_whitespace).unwrap_or(inner.len());
+    let name = &inner[..name_end];
+    if !is_name(name) {
+        return None;
+    }
+
+    let mut attributes = Vec::new();
+    let mut rest = inner[name_end..].trim_start();
+    while !rest.is_empty() {
+        let equals = rest.find('=')?;
+        let attribute = rest[..equals].trim();
+        let value = rest[equals + 1..].trim_start();
+        let quote = value.chars().next().filter(|quote| *quote == '\'' || *quote == '"')?;
+        let close = value[1..].find(quote)? + 1;
+
+        if !is_name(attribute) {
+            return None;
+        }
+        attributes.push((attribute.to_string(), unescape(&value[1..close])));
+        rest = value[close + 1..].trim_start();
+    }
+
+    Some((Tag::Open(name.to_string(), attributes), end + 1))
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+// Replaces the XML entities, leaving unknown ones as they are.
+fn unescape(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let decoded = entity.and_then(|(name, end)| {
+            let character = match name {
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                _ => {
+                    let code = match name.strip_prefix("#x") {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => name.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                },
+            };
+            Some((character, end))
+        });
+
+        match decoded {
+            Some((character, end)) => {
+                output.push(character);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+// Makes text from elsewhere, such as a window title, safe to put into
+// markup.
+pub fn escape(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '&' => output.push_str("&amp;"),
+            _ => output.push(character),
+        }
+    }
+
+    output
+}
+
+pub fn parse(markup: &str) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    let mut stack: Vec<(String, Style)> = Vec::new();
+    let mut style = Style::default();
+    let mut text = String::new();
+    let mut rest = markup;
+
+    let mut flush = |text: &mut String, style: Style| {
+        if !text.is_empty() {
+            runs.push(Run { text: unescape(text), style });
+            text.clear();
+        }
+    };
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let next = match tag(rest) {
+            Some((Tag::Open(name, attributes), length)) => open(&name, &attributes, style).map(|opened| {
+                stack.push((name, style));
+                (opened, length)
+            }),
+            Some((Tag::Close(name), length)) if stack.last().is_some_and(|(open, _)| *open == name) => {
+                stack.pop().map(|(_, enclosing)| (enclosing, length))
+            },
+            _ => None,
+        };
+
+        match next {
+            Some((next, length)) => {
+                flush(&mut text, style);
+                style = next;
+                rest = &rest[length..];
+            },
+            None => {
+                text.push('<');
+                rest = &rest[1..];
+            },
+        }
+    }
+
+    text.push_str(rest);
+    flush(&mut text, style);
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str, style: Style) -> Run {
+        Run { text: text.to_string(), style }
+    }
+
+    #[test]
+    fn plain_text_is_one_run() {
+        assert_eq!(parse("plain text"), vec![run("plain text", Style::default())]);
+        assert_eq!(parse(""), Vec::new());
+    }
+
+    #[test]
+    fn spans_style_their_text() {
+        let red = Style { color: Color::from_str("#f00").ok(), ..Style::default() };
+        assert_eq!(parse("<span foreground=\"#f00\">red</span> rest"), vec![
+            run("red", red),
+            run(" rest", Style::default()),
+        ]);
+        assert_eq!(parse("<span size='200%' alpha='50%' underline='single'>x</span>"), vec![
+            run("x", Style { scale: 2.0, alpha: 0.5, underline: true, ..Style::default() }),
+        ]);
+        assert_eq!(parse("<u><s>x</s></u>"), vec![
+            run("x", Style { underline: true, strikethrough: true, ..Style::default() }),
+        ]);
+    }
+
+    #[test]
+    fn nested_tags_restore_the_enclosing_style() {
+        let big = Style { scale: SIZE_STEP, ..Style::default() };
+        assert_eq!(parse("<big>a<u>b</u>c</big>"), vec![
+            run("a", big),
+            run("b", Style { underline: true, ..big }),
+            run("c", big),
+        ]);
+    }
+
+    #[test]
+    fn malformed_markup_is_kept_as_text() {
+        for markup in ["a < b", "<b>bold</b>", "<span weight=\"bold\">x</span>", "<span foreground=red>x</span>",
+            "<span foreground=\"nocolor\">x</span>", "x</u>", "<u"] {
+            assert_eq!(parse(markup), vec![run(markup, Style::default())], "{}", markup);
+        }
+        assert_eq!(parse("<u>x</s>"), vec![run("x</s>", Style { underline: true, ..Style::default() })]);
+    }
+
+    #[test]
+    fn entities_are_replaced() {
+        assert_eq!(parse("a &lt; b &amp;amp; &#x41;&#66; &bogus; &"), vec![
+            run("a < b &amp; AB &bogus; &", Style::default()),
+        ]);
+        assert_eq!(parse(&escape("<b>Tom & Jerry</b>")), vec![run("<b>Tom & Jerry</b>", Style::default())]);
+    }
+}
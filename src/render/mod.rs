@@ -0,0 +1,533 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::Vector2;
+use crate::color::Color;
+
+#[cfg(not(feature = "wgpu"))]
+mod gl;
+pub mod markup;
+mod text;
+#[cfg(feature = "wgpu")]
+mod wgpu;
+
+#[cfg(not(feature = "wgpu"))]
+pub use gl::{Context, Renderer, Window};
+#[cfg(feature = "wgpu")]
+pub use self::wgpu::{Context, Renderer, Window};
+pub use text::Fonts;
+
+// Graphs are drawn from vertices in screen space, each with its own
+// premultiplied color so the fill and the line go in one draw call.
+#[derive(Copy, Clone)]
+struct GraphVertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+// The part of the canvas a frame is drawn into, in pixels from the top
+// left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scissor {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// An outline drawn inside the edge of a rect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Border {
+    pub width: f32,
+    pub color: Color,
+}
+
+static NEXT_IMAGE_ID: AtomicU64 = AtomicU64::new(0);
+
+// Premultiplied RGBA pixels, row by row from the top. Renderers upload an
+// image to a texture the first time it's drawn and keep it while the image
+// is alive.
+pub struct Image {
+    id: u64,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Image {
+        Image { id: NEXT_IMAGE_ID.fetch_add(1, Ordering::Relaxed), width, height, pixels }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl PartialEq for Image {
+    fn eq(&self, other: &Image) -> bool {
+        self.width == other.width && self.height == other.height && self.pixels == other.pixels
+    }
+}
+
+impl std::fmt::Debug for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Image({}x{})", self.width, self.height)
+    }
+}
+
+// Where the two colors of a transition meet. Arrows and round ends point
+// away from the side they're named after being drawn in the left color,
+// `ArrowLeft` and `RoundLeft` are the mirrored versions filled from the
+// right.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Arrow,
+    ArrowLeft,
+    Slant,
+    SlantBack,
+    Round,
+    RoundLeft,
+}
+
+impl Shape {
+    fn index(&self) -> i32 {
+        match self {
+            Shape::Arrow => 0,
+            Shape::ArrowLeft => 1,
+            Shape::Slant => 2,
+            Shape::SlantBack => 3,
+            Shape::Round => 4,
+            Shape::RoundLeft => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Primitive {
+    Rect { x: f32, y: f32, width: f32, height: f32, radius: f32, color: Color, border: Option<Border> },
+    // Scale is relative to the configured font size.
+    Text { x: f32, y: f32, text: String, color: Color, scale: f32 },
+    // Colors are spread evenly along the direction given by angle, in
+    // degrees clockwise from left to right.
+    Gradient { x: f32, y: f32, width: f32, height: f32, radius: f32, angle: f32, colors: Vec<Color> },
+    // The image is stretched to fill the given size. Tinted images keep only
+    // their alpha, drawn in the tint color.
+    Image { x: f32, y: f32, width: f32, height: f32, image: Arc<Image>, tint: Option<Color> },
+    // Powerline-style boundary between the left and right color.
+    Transition { x: f32, y: f32, width: f32, height: f32, shape: Shape, left: Color, right: Color },
+    Progress(Progress),
+    Ring(Ring),
+    Graph(Graph),
+    Frame(Frame),
+    Shader(Shader),
+}
+
+// A rect filled by the user's background shader, if one was set, with the
+// time in seconds and the pointer position it's drawn for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shader {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub radius: f32,
+    pub time: f32,
+    pub mouse: Option<Vector2<f32>>,
+}
+
+// An outline inside the edges of a rect with a width and color of its own
+// for each edge, in the order top, right, bottom and left. Adjacent edges
+// meet at the corners along the line between their inner and outer corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub radius: f32,
+    pub edges: [Border; 4],
+}
+
+// A bar filled up to value, between 0 and 1, from the left or, when
+// vertical, from the bottom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub radius: f32,
+    pub value: f32,
+    pub vertical: bool,
+    pub filled: Color,
+    pub empty: Color,
+    pub border: Option<Border>,
+}
+
+// A ring filled clockwise from the top up to value, between 0 and 1, drawn
+// into the square at x and y.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ring {
+    pub x: f32,
+    pub y: f32,
+    pub size: f32,
+    pub thickness: f32,
+    pub value: f32,
+    pub filled: Color,
+    pub empty: Color,
+}
+
+// A line through values between 0 and 1, oldest first, with the newest at
+// the right edge and `slots` values spread across the width. The area under
+// the line is filled with `fill`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Graph {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub values: Vec<f32>,
+    pub slots: usize,
+    pub line: Option<Border>,
+    pub fill: Option<Color>,
+}
+
+impl Graph {
+    fn vertices(&self) -> Vec<GraphVertex> {
+        let gl = |color: Color| [color.gl_red(), color.gl_green(), color.gl_blue(), color.gl_alpha()];
+        let step = self.width / self.slots.saturating_sub(1).max(1) as f32;
+        let count = self.values.len();
+        let points: Vec<(f32, f32)> = self.values.iter().enumerate()
+            .map(|(index, value)| (
+                self.x + self.width - (count - 1 - index) as f32 * step,
+                self.y + self.height * (1.0 - value.clamp(0.0, 1.0)),
+            ))
+            .collect();
+
+        let mut vertices = Vec::new();
+        let mut triangle = |corners: [(f32, f32); 3], color: [f32; 4]| {
+            vertices.extend(corners.iter().map(|(x, y)| GraphVertex { position: [*x, *y], color }));
+        };
+        let bottom = self.y + self.height;
+
+        for segment in points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+            if let Some(fill) = self.fill {
+                triangle([(x0, bottom), (x0, y0), (x1, y1)], gl(fill));
+                triangle([(x0, bottom), (x1, y1), (x1, bottom)], gl(fill));
+            }
+
+            // Segments are stretched by half the line width at both ends so
+            // the joints between them don't show gaps.
+            if let Some(line) = self.line {
+                let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt().max(0.0001);
+                let half = line.width / 2.0;
+                let (dx, dy) = ((x1 - x0) / length * half, (y1 - y0) / length * half);
+                let (start, end) = ((x0 - dx, y0 - dy), (x1 + dx, y1 + dy));
+                let (nx, ny) = (-dy, dx);
+
+                let color = gl(line.color);
+                triangle([(start.0 + nx, start.1 + ny), (start.0 - nx, start.1 - ny), (end.0 + nx, end.1 + ny)], color);
+                triangle([(end.0 + nx, end.1 + ny), (start.0 - nx, start.1 - ny), (end.0 - nx, end.1 - ny)], color);
+            }
+        }
+
+        vertices
+    }
+}
+
+impl Primitive {
+    pub fn translate(&mut self, dx: f32, dy: f32) {
+        match self {
+            Primitive::Rect { x, y, .. }
+            | Primitive::Text { x, y, .. }
+            | Primitive::Gradient { x, y, .. }
+            | Primitive::Image { x, y, .. }
+            | Primitive::Transition { x, y, .. }
+            | Primitive::Progress(Progress { x, y, .. })
+            | Primitive::Ring(Ring { x, y, .. })
+            | Primitive::Graph(Graph { x, y, .. })
+            | Primitive::Frame(Frame { x, y, .. })
+            | Primitive::Shader(Shader { x, y, .. }) => {
+                *x += dx;
+                *y += dy;
+            },
+        }
+    }
+
+    // The same primitive with every color's opacity scaled by alpha. Images
+    // without a tint are drawn as they are.
+    pub fn faded(&self, alpha: f32) -> Primitive {
+        let border = |border: &Option<Border>| border.map(|border| Border { color: border.color.faded(alpha), ..border });
+        match self.clone() {
+            Primitive::Rect { x, y, width, height, radius, color, border: outline } => Primitive::Rect {
+                x, y, width, height, radius, color: color.faded(alpha), border: border(&outline),
+            },
+            Primitive::Text { x, y, text, color, scale } => Primitive::Text { x, y, text, color: color.faded(alpha), scale },
+            Primitive::Gradient { x, y, width, height, radius, angle, colors } => Primitive::Gradient {
+                x, y, width, height, radius, angle, colors: colors.iter().map(|color| color.faded(alpha)).collect(),
+            },
+            Primitive::Image { x, y, width, height, image, tint } => Primitive::Image {
+                x, y, width, height, image, tint: tint.map(|tint| tint.faded(alpha)),
+            },
+            Primitive::Transition { x, y, width, height, shape, left, right } => Primitive::Transition {
+                x, y, width, height, shape, left: left.faded(alpha), right: right.faded(alpha),
+            },
+            Primitive::Progress(progress) => Primitive::Progress(Progress {
+                filled: progress.filled.faded(alpha),
+                empty: progress.empty.faded(alpha),
+                border: border(&progress.border),
+                ..progress
+            }),
+            Primitive::Ring(ring) => Primitive::Ring(Ring {
+                filled: ring.filled.faded(alpha),
+                empty: ring.empty.faded(alpha),
+                ..ring
+            }),
+            Primitive::Graph(graph) => Primitive::Graph(Graph {
+                line: border(&graph.line),
+                fill: graph.fill.map(|fill| fill.faded(alpha)),
+                ..graph
+            }),
+            Primitive::Frame(frame) => Primitive::Frame(Frame {
+                edges: frame.edges.map(|edge| Border { color: edge.color.faded(alpha), ..edge }),
+                ..frame
+            }),
+            // Whatever the shader outputs is drawn as is.
+            Primitive::Shader(shader) => Primitive::Shader(shader),
+        }
+    }
+
+    // The same primitive with every position and length multiplied by
+    // factor, which takes it from logical to physical pixels. Text is laid
+    // out at the scaled font size rather than stretched, so it stays sharp.
+    pub fn scaled(&self, factor: f32) -> Primitive {
+        let border = |border: Option<Border>| border.map(|border| Border { width: border.width * factor, ..border });
+        match self.clone() {
+            Primitive::Rect { x, y, width, height, radius, color, border: outline } => Primitive::Rect {
+                x: x * factor,
+                y: y * factor,
+                width: width * factor,
+                height: height * factor,
+                radius: radius * factor,
+                color,
+                border: border(outline),
+            },
+            Primitive::Text { x, y, text, color, scale } => Primitive::Text {
+                x: x * factor, y: y * factor, text, color, scale: scale * factor,
+            },
+            Primitive::Gradient { x, y, width, height, radius, angle, colors } => Primitive::Gradient {
+                x: x * factor, y: y * factor, width: width * factor, height: height * factor, radius: radius * factor,
+                angle, colors,
+            },
+            Primitive::Image { x, y, width, height, image, tint } => Primitive::Image {
+                x: x * factor, y: y * factor, width: width * factor, height: height * factor, image, tint,
+            },
+            Primitive::Transition { x, y, width, height, shape, left, right } => Primitive::Transition {
+                x: x * factor, y: y * factor, width: width * factor, height: height * factor, shape, left, right,
+            },
+            Primitive::Progress(progress) => Primitive::Progress(Progress {
+                x: progress.x * factor,
+                y: progress.y * factor,
+                width: progress.width * factor,
+                height: progress.height * factor,
+                radius: progress.radius * factor,
+                border: border(progress.border),
+                ..progress
+            }),
+            Primitive::Ring(ring) => Primitive::Ring(Ring {
+                x: ring.x * factor,
+                y: ring.y * factor,
+                size: ring.size * factor,
+                thickness: ring.thickness * factor,
+                ..ring
+            }),
+            Primitive::Graph(graph) => Primitive::Graph(Graph {
+                x: graph.x * factor,
+                y: graph.y * factor,
+                width: graph.width * factor,
+                height: graph.height * factor,
+                line: border(graph.line),
+                ..graph
+            }),
+            Primitive::Frame(frame) => Primitive::Frame(Frame {
+                x: frame.x * factor,
+                y: frame.y * factor,
+                width: frame.width * factor,
+                height: frame.height * factor,
+                radius: frame.radius * factor,
+                edges: frame.edges.map(|edge| Border { width: edge.width * factor, ..edge }),
+            }),
+            Primitive::Shader(shader) => Primitive::Shader(Shader {
+                x: shader.x * factor,
+                y: shader.y * factor,
+                width: shader.width * factor,
+                height: shader.height * factor,
+                radius: shader.radius * factor,
+                mouse: shader.mouse.map(|(x, y)| (x * factor, y * factor)),
+                ..shader
+            }),
+        }
+    }
+}
+
+// Widgets draw into a RenderCtx using coordinates relative to their own
+// block; the bar positions the finished block during layout.
+pub struct RenderCtx<'a> {
+    fonts: &'a Fonts,
+    foreground: Color,
+    height: f32,
+    width: f32,
+    // Whether text is parsed as Pango-style markup.
+    markup: bool,
+    // Backgrounds of the modules drawn before and after this one.
+    neighbours: (Option<Color>, Option<Color>),
+    primitives: Vec<Primitive>,
+}
+
+impl<'a> RenderCtx<'a> {
+    pub fn new(fonts: &'a Fonts, foreground: Color, height: f32) -> RenderCtx<'a> {
+        RenderCtx {
+            fonts,
+            foreground,
+            height,
+            width: 0.0,
+            markup: false,
+            neighbours: (None, None),
+            primitives: Vec::new(),
+        }
+    }
+
+    pub fn neighbours(&self) -> (Option<Color>, Option<Color>) {
+        self.neighbours
+    }
+
+    pub fn set_neighbours(&mut self, left: Option<Color>, right: Option<Color>) {
+        self.neighbours = (left, right);
+    }
+
+    pub fn set_markup(&mut self, markup: bool) {
+        self.markup = markup;
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn foreground(&self) -> Color {
+        self.foreground
+    }
+
+    pub fn font_size(&self) -> f32 {
+        self.fonts.size()
+    }
+
+    pub fn set_foreground(&mut self, foreground: Color) {
+        self.foreground = foreground;
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Color) {
+        self.rounded_rect(x, y, width, height, 0.0, color);
+    }
+
+    pub fn rounded_rect(&mut self, x: f32, y: f32, width: f32, height: f32, radius: f32, color: Color) {
+        self.width = self.width.max(x + width);
+        self.primitives.push(Primitive::Rect { x, y, width, height, radius, color, border: None });
+    }
+
+    // Adds any primitive, for shapes the helpers above don't cover.
+    pub fn push(&mut self, primitive: Primitive) {
+        let right = match &primitive {
+            Primitive::Rect { x, width, .. }
+            | Primitive::Gradient { x, width, .. }
+            | Primitive::Image { x, width, .. }
+            | Primitive::Transition { x, width, .. }
+            | Primitive::Progress(Progress { x, width, .. })
+            | Primitive::Ring(Ring { x, size: width, .. })
+            | Primitive::Graph(Graph { x, width, .. })
+            | Primitive::Frame(Frame { x, width, .. })
+            | Primitive::Shader(Shader { x, width, .. }) => x + width,
+            Primitive::Text { x, text, scale, .. } => x + self.fonts.measure_scaled(text, *scale),
+        };
+
+        self.width = self.width.max(right);
+        self.primitives.push(primitive);
+    }
+
+    pub fn measure(&self, text: &str) -> f32 {
+        self.fonts.measure(text)
+    }
+
+    // Appends a line of text after the content drawn so far.
+    pub fn text(&mut self, text: &str, color: Color) {
+        self.text_at(self.width, text, color);
+    }
+
+    pub fn text_at(&mut self, x: f32, text: &str, color: Color) {
+        let y = self.fonts.baseline(self.height);
+        if !self.markup {
+            self.width = self.width.max(x + self.fonts.measure(text));
+            self.primitives.push(Primitive::Text { x, y, text: text.to_string(), color, scale: 1.0 });
+            return;
+        }
+
+        // Every run sits on the same baseline, whatever its size.
+        let mut x = x;
+        for run in markup::parse(text) {
+            let style = run.style;
+            let width = self.fonts.measure_scaled(&run.text, style.scale);
+            let color = style.color.unwrap_or(color).faded(style.alpha);
+            let size = self.fonts.size() * style.scale;
+            let thickness = (size / 14.0).round().max(1.0);
+
+            if let Some(background) = style.background {
+                self.rect(x, 0.0, width, self.height, background);
+            }
+            self.primitives.push(Primitive::Text { x, y, text: run.text, color, scale: style.scale });
+            if style.underline {
+                self.rect(x, y + thickness, width, thickness, color);
+            }
+            if style.strikethrough {
+                self.rect(x, y - (size * 0.3).round(), width, thickness, color);
+            }
+
+            x += width;
+            self.width = self.width.max(x);
+        }
+    }
+
+    pub fn advance(&mut self, width: f32) {
+        self.width += width;
+    }
+
+    // A context of the given height for content that is placed into this
+    // one with `append`.
+    pub fn child(&self, height: f32) -> RenderCtx<'a> {
+        let mut child = RenderCtx::new(self.fonts, self.foreground, height);
+        child.markup = self.markup;
+        child.neighbours = self.neighbours;
+        child
+    }
+
+    pub fn append(&mut self, child: RenderCtx<'a>, x: f32, y: f32) {
+        self.width = self.width.max(x + child.width);
+        self.primitives.extend(child.into_primitives(x, y));
+    }
+
+    pub fn into_primitives(self, x: f32, y: f32) -> Vec<Primitive> {
+        let mut primitives = self.primitives;
+        for primitive in &mut primitives {
+            primitive.translate(x, y);
+        }
+
+        primitives
+    }
+}
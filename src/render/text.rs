@@ -0,0 +1,113 @@
+use rusttype::{point, Font, PositionedGlyph, Scale};
+use tracing::info;
+use crate::Error;
+
+#[derive(Debug)]
+pub struct FontNotFoundError(String);
+
+impl std::fmt::Display for FontNotFoundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No font matching '{}' could be found", self.0)
+    }
+}
+
+impl std::error::Error for FontNotFoundError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// An ordered chain of fonts sharing one size. Every character is drawn
+// with the first font that has a glyph for it.
+pub struct Fonts {
+    fonts: Vec<Font<'static>>,
+    scale: Scale,
+}
+
+fn load_font(fontconfig: &fontconfig::Fontconfig, family: &str) -> Result<Font<'static>, Error> {
+    let not_found = || FontNotFoundError(family.to_string());
+    let found = fontconfig.find(family, None).ok_or_else(not_found)?;
+
+    let data = std::fs::read(&found.path)?;
+    let index = found.index.unwrap_or(0) as u32;
+    Ok(Font::try_from_vec_and_index(data, index).ok_or_else(not_found)?)
+}
+
+impl Fonts {
+    // Only the first family is required, fallbacks that can't be found are
+    // skipped.
+    pub fn load(families: &[String], size: f32) -> Result<Fonts, Error> {
+        let not_found = || FontNotFoundError(families.join(", "));
+        let fontconfig = fontconfig::Fontconfig::new().ok_or_else(not_found)?;
+
+        let mut fonts = Vec::new();
+        for (index, family) in families.iter().enumerate() {
+            match load_font(&fontconfig, family) {
+                Ok(font) => fonts.push(font),
+                Err(err) if index > 0 => info!("Skipping fallback font: {}", err),
+                Err(err) => return Err(err),
+            }
+        }
+
+        if fonts.is_empty() {
+            return Err(Box::new(not_found()));
+        }
+
+        Ok(Fonts { fonts, scale: Scale::uniform(size) })
+    }
+
+    pub fn size(&self) -> f32 {
+        self.scale.y
+    }
+
+    pub fn measure(&self, text: &str) -> f32 {
+        self.measure_scaled(text, 1.0)
+    }
+
+    // Measures text drawn at a multiple of the font size.
+    pub fn measure_scaled(&self, text: &str, scale: f32) -> f32 {
+        self.layout(text, 0.0, 0.0, scale).last()
+            .map(|(_, glyph)| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+            .unwrap_or(0.0)
+    }
+
+    // Returns the baseline that vertically centers a line of text in a box
+    // of the given height. Only the primary font is taken into account.
+    pub fn baseline(&self, height: f32) -> f32 {
+        let metrics = self.fonts[0].v_metrics(self.scale);
+        (height - (metrics.ascent - metrics.descent)) / 2.0 + metrics.ascent
+    }
+
+    // Characters no font has are drawn with the primary font's placeholder
+    // glyph.
+    fn font_for(&self, character: char) -> usize {
+        self.fonts.iter().position(|font| font.glyph(character).id().0 != 0).unwrap_or(0)
+    }
+
+    // Lays out the glyphs along with the index of the font each one comes
+    // from.
+    pub(super) fn layout(&self, text: &str, x: f32, y: f32, scale: f32) -> Vec<(usize, PositionedGlyph<'static>)> {
+        let scale = Scale { x: self.scale.x * scale, y: self.scale.y * scale };
+        let mut glyphs = Vec::new();
+        let mut caret = x;
+        let mut previous = None;
+
+        for character in text.chars() {
+            let index = self.font_for(character);
+            let font = &self.fonts[index];
+            let glyph = font.glyph(character).scaled(scale);
+            if let Some((previous_index, previous)) = previous {
+                if previous_index == index {
+                    caret += font.pair_kerning(scale, previous, glyph.id());
+                }
+            }
+
+            let advance = glyph.h_metrics().advance_width;
+            previous = Some((index, glyph.id()));
+            glyphs.push((index, glyph.positioned(point(caret, y))));
+            caret += advance;
+        }
+
+        glyphs
+    }
+}
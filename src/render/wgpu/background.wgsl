@@ -0,0 +1,21 @@
+// Follows common.wgsl and comes before the user's background shader, whose
+// fragment entry point is called background and takes a Quad. These are
+// what it gets to work with besides the position in pixels from the top
+// left of the rect, in.local, like the GL renderer's uniforms.
+fn resolution() -> vec2<f32> {
+    return u.rect.zw;
+}
+
+fn radius() -> f32 {
+    return u.params.x;
+}
+
+// In seconds.
+fn time() -> f32 {
+    return u.params.y;
+}
+
+// The pointer's position on the rect, or -1 when it's elsewhere.
+fn mouse() -> vec2<f32> {
+    return u.params.zw;
+}
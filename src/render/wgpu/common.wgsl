@@ -0,0 +1,45 @@
+// What every shader of the wgpu renderer starts with, background shaders
+// included. Quads get the shape's rect and the size of the target in
+// pixels, along with up to five colors and four parameters whose meaning is
+// up to the fragment shader. Colors are premultiplied.
+struct Uniforms {
+    screen: vec4<f32>,
+    rect: vec4<f32>,
+    params: vec4<f32>,
+    colors: array<vec4<f32>, 5>,
+}
+
+@group(0) @binding(0) var<uniform> u: Uniforms;
+@group(1) @binding(0) var source: texture_2d<f32>;
+@group(1) @binding(1) var source_sampler: sampler;
+
+struct Quad {
+    @builtin(position) position: vec4<f32>,
+    @location(0) local: vec2<f32>,
+}
+
+fn to_clip(pixel: vec2<f32>) -> vec4<f32> {
+    let ndc = pixel / u.screen.xy * 2.0 - 1.0;
+    return vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+}
+
+// Four vertices drawn as a strip, no vertex buffer needed.
+fn corner(index: u32) -> vec2<f32> {
+    return vec2<f32>(f32(index & 1u), f32(index >> 1u));
+}
+
+fn rounded_box(p: vec2<f32>, start: vec2<f32>, end: vec2<f32>, radius: f32) -> f32 {
+    let half_size = max((end - start) / 2.0, vec2<f32>(0.0));
+    let r = min(radius, min(half_size.x, half_size.y));
+    let q = abs(p - (start + end) / 2.0) - (half_size - vec2<f32>(r));
+    return length(max(q, vec2<f32>(0.0))) + min(max(q.x, q.y), 0.0) - r;
+}
+
+@vertex
+fn quad(@builtin(vertex_index) index: u32) -> Quad {
+    var out: Quad;
+    out.position = to_clip(u.rect.xy + corner(index) * u.rect.zw);
+    out.local = corner(index) * u.rect.zw;
+    return out;
+}
+
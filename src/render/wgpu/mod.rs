@@ -0,0 +1,1056 @@
+use glium::glutin;
+use glutin::event_loop::EventLoopWindowTarget;
+use glutin::platform::unix::WindowExtUnix;
+use glutin::window::WindowBuilder;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::num::NonZeroU64;
+use std::os::raw::c_void;
+use std::ptr::NonNull;
+use std::rc::Rc;
+use std::sync::{Arc, Weak};
+use rusttype::gpu_cache::Cache;
+use wgpu::rwh;
+use crate::Error;
+use crate::color::Color;
+use super::{Border, Fonts, GraphVertex, Image, Primitive, Progress, Ring, Scissor};
+
+const COMMON: &str = include_str!("common.wgsl");
+const SHADERS: &str = include_str!("shaders.wgsl");
+const BACKGROUND: &str = include_str!("background.wgsl");
+
+const GLYPH_CACHE_SIZE: u32 = 1024;
+
+// The size of Uniforms in shaders.wgsl: three vec4s and five colors.
+const UNIFORMS_SIZE: u64 = 8 * 16;
+
+// Everything is drawn to one of these first, see the GL renderer's Canvas.
+const CANVAS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+#[derive(Debug)]
+pub struct NoAdapterError;
+
+impl std::fmt::Display for NoAdapterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No graphics adapter can draw to the window")
+    }
+}
+
+impl std::error::Error for NoAdapterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub struct UnsupportedError(&'static str);
+
+impl std::fmt::Display for UnsupportedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} the wgpu renderer", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    // Taken once the window or Wayland surface it draws to goes away.
+    surface: RefCell<Option<wgpu::Surface<'static>>>,
+    config: RefCell<wgpu::SurfaceConfiguration>,
+    // Views of the surface's textures never encode to sRGB themselves, the
+    // copy shader does, like it does for GL.
+    view_format: wgpu::TextureFormat,
+}
+
+// What a renderer draws to the screen through, shared with the window or
+// surface it belongs to. Every one has a device of its own.
+#[derive(Clone)]
+pub struct Context(Rc<Gpu>);
+
+impl Context {
+    unsafe fn new(display: rwh::RawDisplayHandle, window: rwh::RawWindowHandle, (width, height): (u32, u32),
+        vsync: bool) -> Result<Context, Error> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let surface = instance.create_surface_unsafe(wgpu::SurfaceTargetUnsafe::RawHandle {
+            raw_display_handle: display,
+            raw_window_handle: window,
+        })?;
+
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface: Some(&surface),
+            ..Default::default()
+        })).ok_or(NoAdapterError)?;
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("rustybar"),
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_webgl2_defaults()
+                .using_resolution(adapter.limits())
+                .using_alignment(adapter.limits()),
+            memory_hints: wgpu::MemoryHints::MemoryUsage,
+        }, None))?;
+
+        let capabilities = surface.get_capabilities(&adapter);
+        let format = capabilities.formats.first().copied().ok_or(NoAdapterError)?;
+        let view_format = format.remove_srgb_suffix();
+        let alpha_mode = [wgpu::CompositeAlphaMode::PreMultiplied, wgpu::CompositeAlphaMode::Inherit].iter().copied()
+            .find(|mode| capabilities.alpha_modes.contains(mode))
+            .unwrap_or(wgpu::CompositeAlphaMode::Auto);
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: if vsync { wgpu::PresentMode::AutoVsync } else { wgpu::PresentMode::AutoNoVsync },
+            desired_maximum_frame_latency: 2,
+            alpha_mode,
+            view_formats: if view_format == format { Vec::new() } else { vec![view_format] },
+        };
+        surface.configure(&device, &config);
+
+        Ok(Context(Rc::new(Gpu {
+            device,
+            queue,
+            surface: RefCell::new(Some(surface)),
+            config: RefCell::new(config),
+            view_format,
+        })))
+    }
+
+    // Creates a context on a Wayland surface, given the wl_display and
+    // wl_surface pointers, which have to outlive it.
+    pub(crate) unsafe fn wayland(display: *mut c_void, surface: *mut c_void, dimensions: (u32, u32), vsync: bool)
+        -> Result<Context, Error> {
+        let (display, surface) = match (NonNull::new(display), NonNull::new(surface)) {
+            (Some(display), Some(surface)) => (display, surface),
+            _ => return Err(Box::new(UnsupportedError("Null Wayland surfaces aren't supported by"))),
+        };
+
+        Context::new(
+            rwh::RawDisplayHandle::Wayland(rwh::WaylandDisplayHandle::new(display)),
+            rwh::RawWindowHandle::Wayland(rwh::WaylandWindowHandle::new(surface)),
+            dimensions,
+            vsync,
+        )
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        let config = self.0.config.borrow();
+        (config.width, config.height)
+    }
+
+    pub fn resize(&self, (width, height): (u32, u32)) {
+        let mut config = self.0.config.borrow_mut();
+        if (config.width, config.height) == (width.max(1), height.max(1)) {
+            return;
+        }
+
+        config.width = width.max(1);
+        config.height = height.max(1);
+        if let Some(surface) = self.0.surface.borrow().as_ref() {
+            surface.configure(&self.0.device, &config);
+        }
+    }
+
+    // Lets go of the surface ahead of whatever it draws to.
+    pub fn release(&self) {
+        self.0.surface.borrow_mut().take();
+    }
+}
+
+// A winit window along with the context drawing into it.
+pub struct Window {
+    window: glutin::window::Window,
+    context: Context,
+}
+
+impl Window {
+    pub fn new<T>(target: &EventLoopWindowTarget<T>, builder: WindowBuilder, vsync: bool) -> Result<Window, Error> {
+        let window = builder.build(target)?;
+        let size = window.inner_size();
+
+        let handles = match (window.xlib_display().and_then(NonNull::new), window.xlib_window()) {
+            (Some(display), Some(handle)) => Some((
+                rwh::RawDisplayHandle::Xlib(rwh::XlibDisplayHandle::new(Some(display), window.xlib_screen_id().unwrap_or(0))),
+                rwh::RawWindowHandle::Xlib(rwh::XlibWindowHandle::new(handle)),
+            )),
+            _ => match (window.wayland_display().and_then(NonNull::new), window.wayland_surface().and_then(NonNull::new)) {
+                (Some(display), Some(surface)) => Some((
+                    rwh::RawDisplayHandle::Wayland(rwh::WaylandDisplayHandle::new(display)),
+                    rwh::RawWindowHandle::Wayland(rwh::WaylandWindowHandle::new(surface)),
+                )),
+                _ => None,
+            },
+        };
+        let (display, handle) = handles.ok_or(UnsupportedError("Windows outside X11 and Wayland aren't supported by"))?;
+
+        let context = unsafe { Context::new(display, handle, (size.width, size.height), vsync)? };
+        Ok(Window { window, context })
+    }
+
+    pub fn window(&self) -> &glutin::window::Window {
+        &self.window
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        let size = self.window.inner_size();
+        (size.width, size.height)
+    }
+}
+
+// Renderers hold on to the context too, the surface mustn't outlive the
+// window however long they're around.
+impl Drop for Window {
+    fn drop(&mut self) {
+        self.context.release();
+    }
+}
+
+struct Pipelines {
+    clear: wgpu::RenderPipeline,
+    rect: wgpu::RenderPipeline,
+    gradient: wgpu::RenderPipeline,
+    image: wgpu::RenderPipeline,
+    transition: wgpu::RenderPipeline,
+    frame: wgpu::RenderPipeline,
+    progress: wgpu::RenderPipeline,
+    ring: wgpu::RenderPipeline,
+    glyph: wgpu::RenderPipeline,
+    graph: wgpu::RenderPipeline,
+    copy: wgpu::RenderPipeline,
+    // The user's background shader, if one was set and it compiled.
+    background: Option<wgpu::RenderPipeline>,
+}
+
+// A texture along with the bind group shaders sample it through.
+struct Bound {
+    texture: wgpu::Texture,
+    group: wgpu::BindGroup,
+}
+
+// The textures a draw samples from.
+#[derive(Clone, Copy)]
+enum Source {
+    None,
+    Gradient(usize),
+    Image(u64),
+    Glyphs,
+}
+
+// One draw call, with its uniforms at the given offset into the uniform
+// buffer and, for text and graphs, a range of their vertex buffer.
+struct Draw<'a> {
+    pipeline: &'a wgpu::RenderPipeline,
+    offset: u32,
+    source: Source,
+    vertices: Option<std::ops::Range<u32>>,
+}
+
+#[derive(Default)]
+struct Uniforms {
+    rect: [f32; 4],
+    params: [f32; 4],
+    colors: [[f32; 4]; 5],
+}
+
+fn gl(color: Color) -> [f32; 4] {
+    [color.gl_red(), color.gl_green(), color.gl_blue(), color.gl_alpha()]
+}
+
+fn bytes(values: &[f32]) -> impl Iterator<Item = u8> + '_ {
+    values.iter().flat_map(|value| value.to_ne_bytes())
+}
+
+// Turns a linear channel back into the sRGB encoding textures store.
+fn encode(linear: f32) -> u8 {
+    let linear = linear.clamp(0.0, 1.0);
+    let encoded = if linear <= 0.0031308 { linear * 12.92 } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 };
+    (encoded * 255.0).round() as u8
+}
+
+pub struct Renderer {
+    context: Context,
+    pipelines: Pipelines,
+    // What pipelines drawing quads without a texture are laid out like.
+    plain: wgpu::PipelineLayout,
+    uniform_layout: wgpu::BindGroupLayout,
+    texture_layout: wgpu::BindGroupLayout,
+    linear: wgpu::Sampler,
+    nearest: wgpu::Sampler,
+    // Every draw's uniforms go into one buffer, each at a multiple of the
+    // stride, and it only grows.
+    stride: u64,
+    uniforms: Option<(wgpu::Buffer, wgpu::BindGroup, u64)>,
+    glyph_vertices: Option<wgpu::Buffer>,
+    graph_vertices: Option<wgpu::Buffer>,
+    canvas: Option<(Bound, wgpu::TextureView)>,
+    // The copy to the window gets uniforms of its own, drawn after the
+    // canvas's are long written.
+    copy_uniforms: (wgpu::Buffer, wgpu::BindGroup),
+    // Gradients drawn in the last frame.
+    gradients: Vec<(Vec<Color>, Bound)>,
+    images: HashMap<u64, (Weak<Image>, Bound)>,
+    glyphs: (Cache<'static>, Bound),
+    fonts: Fonts,
+}
+
+impl Renderer {
+    pub fn new(context: &Context, fonts: Fonts) -> Result<Renderer, Error> {
+        let device = &context.0.device;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("rustybar shaders"),
+            source: wgpu::ShaderSource::Wgsl([COMMON, SHADERS].concat().into()),
+        });
+
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("uniforms"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(UNIFORMS_SIZE),
+                },
+                count: None,
+            }],
+        });
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("texture"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let plain = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&uniform_layout],
+            push_constant_ranges: &[],
+        });
+        let textured = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&uniform_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+
+        let glyph_buffers = [wgpu::VertexBufferLayout {
+            array_stride: 4 * 4,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2],
+        }];
+        let graph_buffers = [wgpu::VertexBufferLayout {
+            array_stride: 6 * 4,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+        }];
+
+        let pipeline = |layout: &wgpu::PipelineLayout, vertex: &str, buffers: &[wgpu::VertexBufferLayout],
+            fragment: &str, format: wgpu::TextureFormat, blend: Option<wgpu::BlendState>| {
+            let strip = buffers.is_empty();
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(fragment),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: Some(vertex),
+                    buffers,
+                    compilation_options: Default::default(),
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: if strip { wgpu::PrimitiveTopology::TriangleStrip } else { wgpu::PrimitiveTopology::TriangleList },
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: Default::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: Some(fragment),
+                    targets: &[Some(wgpu::ColorTargetState { format, blend, write_mask: wgpu::ColorWrites::ALL })],
+                    compilation_options: Default::default(),
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        let blend = Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING);
+        let pipelines = Pipelines {
+            clear: pipeline(&plain, "quad", &[], "clear", CANVAS_FORMAT, None),
+            rect: pipeline(&plain, "quad", &[], "rect", CANVAS_FORMAT, blend),
+            gradient: pipeline(&textured, "quad", &[], "gradient", CANVAS_FORMAT, blend),
+            image: pipeline(&textured, "quad", &[], "image", CANVAS_FORMAT, blend),
+            transition: pipeline(&plain, "quad", &[], "transition", CANVAS_FORMAT, blend),
+            frame: pipeline(&plain, "quad", &[], "frame", CANVAS_FORMAT, blend),
+            progress: pipeline(&plain, "quad", &[], "progress", CANVAS_FORMAT, blend),
+            ring: pipeline(&plain, "quad", &[], "ring", CANVAS_FORMAT, blend),
+            glyph: pipeline(&textured, "glyph_vertex", &glyph_buffers, "glyph", CANVAS_FORMAT, blend),
+            graph: pipeline(&plain, "graph_vertex", &graph_buffers, "graph", CANVAS_FORMAT, blend),
+            copy: pipeline(&textured, "copy_vertex", &[], "copy", context.0.view_format, None),
+            background: None,
+        };
+
+        let sampler = |filter: wgpu::FilterMode| device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: filter,
+            min_filter: filter,
+            ..Default::default()
+        });
+        let (linear, nearest) = (sampler(wgpu::FilterMode::Linear), sampler(wgpu::FilterMode::Nearest));
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = UNIFORMS_SIZE.div_ceil(alignment) * alignment;
+
+        let copy_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("copy uniforms"),
+            size: stride,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let copy_group = Renderer::uniform_group(device, &uniform_layout, &copy_buffer);
+
+        let glyph_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("glyphs"),
+            size: wgpu::Extent3d { width: GLYPH_CACHE_SIZE, height: GLYPH_CACHE_SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let glyphs = Renderer::bind(device, &texture_layout, glyph_texture, &nearest);
+        let cache = Cache::builder().dimensions(GLYPH_CACHE_SIZE, GLYPH_CACHE_SIZE).build();
+
+        Ok(Renderer {
+            context: context.clone(),
+            pipelines,
+            plain,
+            uniform_layout,
+            texture_layout,
+            linear,
+            nearest,
+            stride,
+            uniforms: None,
+            glyph_vertices: None,
+            graph_vertices: None,
+            canvas: None,
+            copy_uniforms: (copy_buffer, copy_group),
+            gradients: Vec::new(),
+            images: HashMap::new(),
+            glyphs: (cache, glyphs),
+            fonts,
+        })
+    }
+
+    fn uniform_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(UNIFORMS_SIZE),
+                }),
+            }],
+        })
+    }
+
+    fn bind(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, texture: wgpu::Texture, sampler: &wgpu::Sampler)
+        -> Bound {
+        let view = texture.create_view(&Default::default());
+        let group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(sampler) },
+            ],
+        });
+        Bound { texture, group }
+    }
+
+    // Uploads premultiplied sRGB pixels, row by row from the top.
+    fn upload(&self, (width, height): (u32, u32), format: wgpu::TextureFormat, pixels: &[u8], sampler: &wgpu::Sampler)
+        -> Bound {
+        let gpu = &self.context.0;
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        gpu.queue.write_texture(
+            texture.as_image_copy(),
+            pixels,
+            wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(4 * width), rows_per_image: None },
+            size,
+        );
+        Renderer::bind(&gpu.device, &self.texture_layout, texture, sampler)
+    }
+
+    pub fn fonts(&self) -> &Fonts {
+        &self.fonts
+    }
+
+    // Compiles the fragment shader Shader primitives are drawn with, which
+    // is written in WGSL here rather than GLSL. It follows common.wgsl and
+    // background.wgsl, which tell what it gets to work with, and the
+    // fragment entry point is called background. Nothing is drawn for
+    // Shader primitives while there is no shader or it didn't compile.
+    pub fn set_background_shader(&mut self, source: &str) -> Result<(), Error> {
+        self.pipelines.background = None;
+
+        // Mistakes in the shader would otherwise only reach the device's
+        // handler for uncaught errors, which panics.
+        let device = &self.context.0.device;
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("background shader"),
+            source: wgpu::ShaderSource::Wgsl([COMMON, BACKGROUND, source].join("\n").into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("background"),
+            layout: Some(&self.plain),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: Some("quad"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            primitive: wgpu::PrimitiveState { topology: wgpu::PrimitiveTopology::TriangleStrip, ..Default::default() },
+            depth_stencil: None,
+            multisample: Default::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: Some("background"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: CANVAS_FORMAT,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+        if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+            return Err(Box::new(err));
+        }
+
+        self.pipelines.background = Some(pipeline);
+        Ok(())
+    }
+
+    pub fn has_background_shader(&self) -> bool {
+        self.pipelines.background.is_some()
+    }
+
+    // Makes the canvas the given size. Returns true when that took a new
+    // one, which starts out blank.
+    pub fn prepare(&mut self, (width, height): (u32, u32)) -> Result<bool, Error> {
+        let current = self.canvas.as_ref().map(|(canvas, _)| (canvas.texture.width(), canvas.texture.height()));
+        if current == Some((width.max(1), height.max(1))) {
+            return Ok(false);
+        }
+
+        let device = &self.context.0.device;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("canvas"),
+            size: wgpu::Extent3d { width: width.max(1), height: height.max(1), depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: CANVAS_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        self.canvas = Some((Renderer::bind(device, &self.texture_layout, texture, &self.nearest), view));
+        Ok(true)
+    }
+
+    // Copies the canvas onto the whole window. A rotated canvas is as high
+    // as the window is wide and turned a quarter clockwise.
+    pub fn present(&mut self, rotated: bool) -> Result<(), Error> {
+        let (canvas, _) = match &self.canvas {
+            Some(canvas) => canvas,
+            None => return Ok(()),
+        };
+        let (width, height) = (canvas.texture.width(), canvas.texture.height());
+        self.context.resize(if rotated { (height, width) } else { (width, height) });
+
+        let gpu = &self.context.0;
+        let surface = gpu.surface.borrow();
+        let surface = match surface.as_ref() {
+            Some(surface) => surface,
+            None => return Ok(()),
+        };
+        let frame = match surface.get_current_texture() {
+            Err(wgpu::SurfaceError::Outdated) | Err(wgpu::SurfaceError::Lost) => {
+                surface.configure(&gpu.device, &gpu.config.borrow());
+                surface.get_current_texture()?
+            },
+            frame => frame?,
+        };
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(gpu.view_format),
+            ..Default::default()
+        });
+
+        let uniforms = Uniforms { params: [if rotated { 1.0 } else { 0.0 }, 0.0, 0.0, 0.0], ..Default::default() };
+        let (buffer, group) = &self.copy_uniforms;
+        gpu.queue.write_buffer(buffer, 0, &self.uniform_bytes(&uniforms, (width, height)));
+
+        let mut encoder = gpu.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("copy"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: wgpu::StoreOp::Store },
+                })],
+                ..Default::default()
+            });
+            pass.set_pipeline(&self.pipelines.copy);
+            pass.set_bind_group(0, group, &[0]);
+            pass.set_bind_group(1, &canvas.group, &[]);
+            pass.draw(0..4, 0..1);
+        }
+        gpu.queue.submit(Some(encoder.finish()));
+        frame.present();
+        Ok(())
+    }
+
+    // Draws a whole window of the given size.
+    pub fn draw(&mut self, dimensions: (u32, u32), background: Color, primitives: &[Primitive]) -> Result<(), Error> {
+        self.prepare(dimensions)?;
+        self.draw_clipped(background, primitives, None)?;
+        self.present(false)
+    }
+
+    fn uniform_bytes(&self, uniforms: &Uniforms, (width, height): (u32, u32)) -> Vec<u8> {
+        let mut data: Vec<u8> = bytes(&[width as f32, height as f32, 0.0, 0.0])
+            .chain(bytes(&uniforms.rect))
+            .chain(bytes(&uniforms.params))
+            .chain(uniforms.colors.iter().flat_map(|color| bytes(color)))
+            .collect();
+        data.resize(self.stride as usize, 0);
+        data
+    }
+
+    // Puts the glyphs of every line of text into the cache, then returns the
+    // vertices for each line in order.
+    fn glyph_vertices(&mut self, primitives: &[Primitive]) -> Result<Vec<Vec<f32>>, Error> {
+        let lines: Vec<_> = primitives.iter()
+            .filter_map(|primitive| match primitive {
+                Primitive::Text { x, y, text, scale, .. } => Some(self.fonts.layout(text, *x, *y, *scale)),
+                _ => None,
+            })
+            .collect();
+
+        let (cache, bound) = &mut self.glyphs;
+        for (font, glyph) in lines.iter().flatten() {
+            cache.queue_glyph(*font, glyph.clone());
+        }
+
+        let queue = &self.context.0.queue;
+        cache.cache_queued(|rect, data| {
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &bound.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: rect.min.x, y: rect.min.y, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                data,
+                wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(rect.width()), rows_per_image: None },
+                wgpu::Extent3d { width: rect.width(), height: rect.height(), depth_or_array_layers: 1 },
+            );
+        })?;
+
+        Ok(lines.iter()
+            .map(|glyphs| {
+                let mut vertices = Vec::new();
+                for (font, glyph) in glyphs {
+                    if let Ok(Some((uv, screen))) = cache.rect_for(*font, glyph) {
+                        let (left, top) = (screen.min.x as f32, screen.min.y as f32);
+                        let (right, bottom) = (screen.max.x as f32, screen.max.y as f32);
+                        vertices.extend_from_slice(&[
+                            left, top, uv.min.x, uv.min.y,
+                            right, top, uv.max.x, uv.min.y,
+                            left, bottom, uv.min.x, uv.max.y,
+                            right, top, uv.max.x, uv.min.y,
+                            right, bottom, uv.max.x, uv.max.y,
+                            left, bottom, uv.min.x, uv.max.y,
+                        ]);
+                    }
+                }
+                vertices
+            })
+            .collect())
+    }
+
+    // Writes vertices into one of the vertex buffers, growing it as needed.
+    fn write_vertices(&self, slot: &mut Option<wgpu::Buffer>, vertices: &[f32]) {
+        if vertices.is_empty() {
+            return;
+        }
+
+        let data: Vec<u8> = bytes(vertices).collect();
+        let size = (data.len() as u64).next_power_of_two();
+        let gpu = &self.context.0;
+        if slot.as_ref().is_none_or(|buffer| buffer.size() < size) {
+            *slot = Some(gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("vertices"),
+                size,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if let Some(buffer) = slot {
+            gpu.queue.write_buffer(buffer, 0, &data);
+        }
+    }
+
+    // Draws into the canvas, only touching the pixels inside the scissor
+    // rectangle.
+    pub fn draw_clipped(&mut self, background: Color, primitives: &[Primitive], scissor: Option<Scissor>)
+        -> Result<(), Error> {
+        let dimensions = match &self.canvas {
+            Some((canvas, _)) => (canvas.texture.width(), canvas.texture.height()),
+            None => return Ok(()),
+        };
+        let scissor = scissor.unwrap_or(Scissor { x: 0, y: 0, width: dimensions.0, height: dimensions.1 });
+        let (x, y) = (scissor.x.min(dimensions.0), scissor.y.min(dimensions.1));
+        let (width, height) = (scissor.width.min(dimensions.0 - x), scissor.height.min(dimensions.1 - y));
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        let mut lines = self.glyph_vertices(primitives)?.into_iter();
+        let mut glyph_vertices = Vec::new();
+        let mut graph_vertices = Vec::new();
+        let mut uniforms = Vec::new();
+        let mut planned = Vec::new();
+
+        // Textures are uploaded before anything is drawn, the draws only say
+        // which one they need.
+        let mut gradients = Vec::new();
+        let clear = Uniforms {
+            rect: [0.0, 0.0, dimensions.0 as f32, dimensions.1 as f32],
+            colors: [gl(background), [0.0; 4], [0.0; 4], [0.0; 4], [0.0; 4]],
+            ..Default::default()
+        };
+        uniforms.push(clear);
+        planned.push((Kind::Clear, Source::None, None));
+
+        for primitive in primitives {
+            let mut colors = [[0.0; 4]; 5];
+            let (kind, source, rect, params) = match primitive {
+                Primitive::Rect { x, y, width, height, radius, color, border } => {
+                    let border = border.unwrap_or(Border { width: 0.0, color: Color::TRANSPARENT });
+                    colors[0] = gl(*color);
+                    colors[1] = gl(border.color);
+                    (Kind::Rect, Source::None, [*x, *y, *width, *height], [*radius, border.width, 0.0, 0.0])
+                },
+                Primitive::Text { color, .. } => {
+                    let vertices = lines.next().unwrap_or_default();
+                    if vertices.is_empty() {
+                        continue;
+                    }
+
+                    let start = (glyph_vertices.len() / 4) as u32;
+                    glyph_vertices.extend(vertices);
+                    let range = start..(glyph_vertices.len() / 4) as u32;
+                    colors[0] = gl(*color);
+                    uniforms.push(Uniforms { colors, ..Default::default() });
+                    planned.push((Kind::Glyph, Source::Glyphs, Some(range)));
+                    continue;
+                },
+                Primitive::Gradient { x, y, width, height, radius, angle, colors: stops } => {
+                    if stops.is_empty() {
+                        continue;
+                    }
+
+                    let index = match gradients.iter().position(|known: &Vec<Color>| known == stops) {
+                        Some(index) => index,
+                        None => {
+                            gradients.push(stops.clone());
+                            gradients.len() - 1
+                        },
+                    };
+                    let angle = angle.to_radians();
+                    (Kind::Gradient, Source::Gradient(index), [*x, *y, *width, *height],
+                        [*radius, stops.len() as f32, angle.cos(), angle.sin()])
+                },
+                Primitive::Image { x, y, width, height, image, tint } => {
+                    if image.width == 0 || image.height == 0 {
+                        continue;
+                    }
+
+                    if let Entry::Vacant(entry) = self.images.entry(image.id) {
+                        let bound = Renderer::upload_image(&self.context, &self.texture_layout, &self.linear, image);
+                        entry.insert((Arc::downgrade(image), bound));
+                    }
+                    colors[0] = tint.map_or([0.0; 4], gl);
+                    (Kind::Image, Source::Image(image.id), [*x, *y, *width, *height],
+                        [if tint.is_some() { 1.0 } else { 0.0 }, 0.0, 0.0, 0.0])
+                },
+                Primitive::Transition { x, y, width, height, shape, left, right } => {
+                    colors[0] = gl(*left);
+                    colors[1] = gl(*right);
+                    (Kind::Transition, Source::None, [*x, *y, *width, *height], [shape.index() as f32, 0.0, 0.0, 0.0])
+                },
+                Primitive::Progress(Progress { x, y, width, height, radius, value, vertical, filled, empty, border }) => {
+                    let border = border.unwrap_or(Border { width: 0.0, color: Color::TRANSPARENT });
+                    colors[0] = gl(*filled);
+                    colors[1] = gl(*empty);
+                    colors[2] = gl(border.color);
+                    (Kind::Progress, Source::None, [*x, *y, *width, *height],
+                        [*radius, border.width, value.clamp(0.0, 1.0), if *vertical { 1.0 } else { 0.0 }])
+                },
+                Primitive::Ring(Ring { x, y, size, thickness, value, filled, empty }) => {
+                    colors[0] = gl(*filled);
+                    colors[1] = gl(*empty);
+                    (Kind::Ring, Source::None, [*x, *y, *size, *size],
+                        [thickness.clamp(0.0, *size / 2.0), value.clamp(0.0, 1.0), 0.0, 0.0])
+                },
+                Primitive::Graph(graph) => {
+                    let vertices = graph.vertices();
+                    if vertices.is_empty() {
+                        continue;
+                    }
+
+                    let start = (graph_vertices.len() / 6) as u32;
+                    for GraphVertex { position, color } in vertices {
+                        graph_vertices.extend_from_slice(&position);
+                        graph_vertices.extend_from_slice(&color);
+                    }
+                    let range = start..(graph_vertices.len() / 6) as u32;
+                    uniforms.push(Uniforms::default());
+                    planned.push((Kind::Graph, Source::None, Some(range)));
+                    continue;
+                },
+                Primitive::Frame(frame) => {
+                    let [top, right, bottom, left] = frame.edges;
+                    colors = [gl(top.color), gl(right.color), gl(bottom.color), gl(left.color),
+                        [top.width, right.width, bottom.width, left.width]];
+                    (Kind::Frame, Source::None, [frame.x, frame.y, frame.width, frame.height], [frame.radius, 0.0, 0.0, 0.0])
+                },
+                Primitive::Shader(shader) => {
+                    if self.pipelines.background.is_none() {
+                        continue;
+                    }
+
+                    let mouse = shader.mouse.map_or([-1.0, -1.0], |(x, y)| [x - shader.x, y - shader.y]);
+                    (Kind::Background, Source::None, [shader.x, shader.y, shader.width, shader.height],
+                        [shader.radius, shader.time, mouse[0], mouse[1]])
+                },
+            };
+
+            uniforms.push(Uniforms { rect, params, colors });
+            planned.push((kind, source, None));
+        }
+
+        self.upload_gradients(&gradients);
+        self.images.retain(|_, (image, _)| image.strong_count() > 0);
+
+        let mut glyph_buffer = self.glyph_vertices.take();
+        self.write_vertices(&mut glyph_buffer, &glyph_vertices);
+        self.glyph_vertices = glyph_buffer;
+        let mut graph_buffer = self.graph_vertices.take();
+        self.write_vertices(&mut graph_buffer, &graph_vertices);
+        self.graph_vertices = graph_buffer;
+
+        let data: Vec<u8> = uniforms.iter().flat_map(|uniforms| self.uniform_bytes(uniforms, dimensions)).collect();
+        let gpu = self.context.0.clone();
+        let size = (uniforms.len() as u64 * self.stride).next_power_of_two();
+        if self.uniforms.as_ref().is_none_or(|(_, _, capacity)| *capacity < size) {
+            let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("uniforms"),
+                size,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let group = Renderer::uniform_group(&gpu.device, &self.uniform_layout, &buffer);
+            self.uniforms = Some((buffer, group, size));
+        }
+
+        let (buffer, group, _) = match &self.uniforms {
+            Some(uniforms) => uniforms,
+            None => return Ok(()),
+        };
+        gpu.queue.write_buffer(buffer, 0, &data);
+
+        let draws: Vec<_> = planned.into_iter().enumerate()
+            .filter_map(|(index, (kind, source, vertices))| Some(Draw {
+                pipeline: self.pipelines.get(kind)?,
+                offset: (index as u64 * self.stride) as u32,
+                source,
+                vertices,
+            }))
+            .collect();
+
+        let view = match &self.canvas {
+            Some((_, view)) => view,
+            None => return Ok(()),
+        };
+        let mut encoder = gpu.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("canvas"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                })],
+                ..Default::default()
+            });
+            pass.set_scissor_rect(x, y, width, height);
+
+            for draw in &draws {
+                let texture = match draw.source {
+                    Source::None => None,
+                    Source::Gradient(index) => self.gradients.get(index).map(|(_, bound)| &bound.group),
+                    Source::Image(id) => self.images.get(&id).map(|(_, bound)| &bound.group),
+                    Source::Glyphs => Some(&self.glyphs.1.group),
+                };
+
+                pass.set_pipeline(draw.pipeline);
+                pass.set_bind_group(0, group, &[draw.offset]);
+                if let Some(texture) = texture {
+                    pass.set_bind_group(1, texture, &[]);
+                }
+
+                match (&draw.vertices, draw.source) {
+                    (Some(range), Source::Glyphs) => {
+                        if let Some(buffer) = &self.glyph_vertices {
+                            pass.set_vertex_buffer(0, buffer.slice(..));
+                            pass.draw(range.clone(), 0..1);
+                        }
+                    },
+                    (Some(range), _) => {
+                        if let Some(buffer) = &self.graph_vertices {
+                            pass.set_vertex_buffer(0, buffer.slice(..));
+                            pass.draw(range.clone(), 0..1);
+                        }
+                    },
+                    (None, _) => pass.draw(0..4, 0..1),
+                }
+            }
+        }
+        gpu.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+
+    fn upload_image(context: &Context, layout: &wgpu::BindGroupLayout, sampler: &wgpu::Sampler, image: &Image) -> Bound {
+        let gpu = &context.0;
+        let size = wgpu::Extent3d { width: image.width, height: image.height, depth_or_array_layers: 1 };
+        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("image"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        gpu.queue.write_texture(
+            texture.as_image_copy(),
+            &image.pixels,
+            wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(4 * image.width), rows_per_image: None },
+            size,
+        );
+        Renderer::bind(&gpu.device, layout, texture, sampler)
+    }
+
+    // Keeps the textures of the gradients drawn this frame, in that order,
+    // making the ones that are new. Their texels are premultiplied like the
+    // colors, then encoded for an sRGB texture so filtering blends them in
+    // linear space.
+    fn upload_gradients(&mut self, gradients: &[Vec<Color>]) {
+        let mut known = std::mem::take(&mut self.gradients);
+        for colors in gradients {
+            let bound = match known.iter().position(|(cached, _)| cached == colors) {
+                Some(index) => known.swap_remove(index).1,
+                None => {
+                    let texels: Vec<u8> = colors.iter()
+                        .flat_map(|color| {
+                            let [red, green, blue, alpha] = gl(*color);
+                            [encode(red), encode(green), encode(blue), (alpha.clamp(0.0, 1.0) * 255.0).round() as u8]
+                        })
+                        .collect();
+                    self.upload((colors.len() as u32, 1), wgpu::TextureFormat::Rgba8UnormSrgb, &texels, &self.linear)
+                },
+            };
+            self.gradients.push((colors.clone(), bound));
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Clear,
+    Rect,
+    Gradient,
+    Image,
+    Transition,
+    Frame,
+    Progress,
+    Ring,
+    Glyph,
+    Graph,
+    Background,
+}
+
+impl Pipelines {
+    fn get(&self, kind: Kind) -> Option<&wgpu::RenderPipeline> {
+        Some(match kind {
+            Kind::Clear => &self.clear,
+            Kind::Rect => &self.rect,
+            Kind::Gradient => &self.gradient,
+            Kind::Image => &self.image,
+            Kind::Transition => &self.transition,
+            Kind::Frame => &self.frame,
+            Kind::Progress => &self.progress,
+            Kind::Ring => &self.ring,
+            Kind::Glyph => &self.glyph,
+            Kind::Graph => &self.graph,
+            Kind::Background => self.background.as_ref()?,
+        })
+    }
+}
@@ -0,0 +1,188 @@
+// Every shader of the wgpu renderer, ported from the GL ones, which have
+// the comments on how they work. They follow common.wgsl.
+
+@fragment
+fn clear(in: Quad) -> @location(0) vec4<f32> {
+    return u.colors[0];
+}
+
+// params: radius, border width. colors: fill, border.
+@fragment
+fn rect(in: Quad) -> @location(0) vec4<f32> {
+    let distance = rounded_box(in.local, vec2<f32>(0.0), u.rect.zw, u.params.x);
+    let outside = clamp(0.5 - distance, 0.0, 1.0);
+    let inside = clamp(0.5 - distance - u.params.y, 0.0, 1.0);
+    return (u.colors[0] * inside + u.colors[1] * (1.0 - inside)) * outside;
+}
+
+// params: radius, color count, direction. The colors are in the texture.
+@fragment
+fn gradient(in: Quad) -> @location(0) vec4<f32> {
+    let size = u.rect.zw;
+    let count = u.params.y;
+    let direction = u.params.zw;
+    let distance = rounded_box(in.local, vec2<f32>(0.0), size, u.params.x);
+
+    let extent = dot(abs(direction), size);
+    let t = clamp(0.5 + dot(in.local - size / 2.0, direction) / extent, 0.0, 1.0);
+    let fill = textureSample(source, source_sampler, vec2<f32>((t * (count - 1.0) + 0.5) / count, 0.5));
+
+    return fill * clamp(0.5 - distance, 0.0, 1.0);
+}
+
+// params: whether it's tinted. colors: tint.
+@fragment
+fn image(in: Quad) -> @location(0) vec4<f32> {
+    let texel = textureSample(source, source_sampler, in.local / u.rect.zw);
+    return select(texel, u.colors[0] * texel.a, u.params.x > 0.5);
+}
+
+// params: radius. colors: top, right, bottom and left edge, then the
+// widths in that order.
+@fragment
+fn frame(in: Quad) -> @location(0) vec4<f32> {
+    let size = u.rect.zw;
+    let radius = u.params.x;
+    let widths = u.colors[4];
+    let outer = rounded_box(in.local, vec2<f32>(0.0), size, radius);
+    let widest = max(max(widths.x, widths.y), max(widths.z, widths.w));
+    let inner = rounded_box(in.local, vec2<f32>(widths.w, widths.x), size - vec2<f32>(widths.y, widths.z),
+        max(radius - widest, 0.0));
+    let coverage = clamp(0.5 - outer, 0.0, 1.0) * clamp(0.5 + inner, 0.0, 1.0);
+
+    let distances = vec4<f32>(in.local.y, size.x - in.local.x, size.y - in.local.y, in.local.x)
+        / max(widths, vec4<f32>(1e-6));
+    let nearest = min(min(distances.x, distances.y), min(distances.z, distances.w));
+    var edge = u.colors[3];
+    if nearest == distances.x {
+        edge = u.colors[0];
+    } else if nearest == distances.y {
+        edge = u.colors[1];
+    } else if nearest == distances.z {
+        edge = u.colors[2];
+    }
+
+    return edge * coverage;
+}
+
+// params: shape. colors: left, right.
+@fragment
+fn transition(in: Quad) -> @location(0) vec4<f32> {
+    let p = in.local / u.rect.zw;
+    let shape = i32(u.params.x);
+    var d: f32;
+    if shape == 0 {
+        d = 1.0 - abs(2.0 * p.y - 1.0) - p.x;
+    } else if shape == 1 {
+        d = abs(2.0 * p.y - 1.0) - p.x;
+    } else if shape == 2 {
+        d = 1.0 - p.y - p.x;
+    } else if shape == 3 {
+        d = p.y - p.x;
+    } else if shape == 4 {
+        d = 1.0 - length(vec2<f32>(p.x, 2.0 * p.y - 1.0));
+    } else {
+        d = length(vec2<f32>(1.0 - p.x, 2.0 * p.y - 1.0)) - 1.0;
+    }
+
+    let coverage = clamp(d / max(fwidth(d), 0.0001) + 0.5, 0.0, 1.0);
+    return mix(u.colors[1], u.colors[0], coverage);
+}
+
+// params: radius, border width, value, whether it's vertical. colors:
+// filled, empty, border.
+@fragment
+fn progress(in: Quad) -> @location(0) vec4<f32> {
+    let size = u.rect.zw;
+    let vertical = u.params.w > 0.5;
+    let distance = rounded_box(in.local, vec2<f32>(0.0), size, u.params.x);
+
+    let along = select(in.local.x, size.y - in.local.y, vertical);
+    let extent = select(size.x, size.y, vertical);
+    let fill = mix(u.colors[1], u.colors[0], clamp(u.params.z * extent - along + 0.5, 0.0, 1.0));
+
+    let outside = clamp(0.5 - distance, 0.0, 1.0);
+    let inside = clamp(0.5 - distance - u.params.y, 0.0, 1.0);
+    return (fill * inside + u.colors[2] * (1.0 - inside)) * outside;
+}
+
+const TAU: f32 = 6.28318530718;
+
+// params: thickness, value. colors: filled, empty.
+@fragment
+fn ring(in: Quad) -> @location(0) vec4<f32> {
+    let size = u.rect.zw;
+    let thickness = u.params.x;
+    let value = u.params.y;
+    let p = in.local - size / 2.0;
+    let radius = size.x / 2.0 - thickness / 2.0;
+    let distance = abs(length(p) - radius) - thickness / 2.0;
+
+    let angle = atan2(p.x, -p.y);
+    let t = angle / TAU + select(0.0, 1.0, angle < 0.0);
+    let along = (value - t) * TAU * radius;
+    let fill = select(mix(u.colors[1], u.colors[0], clamp(along + 0.5, 0.0, 1.0)), u.colors[0], value >= 1.0);
+
+    return fill * clamp(0.5 - distance, 0.0, 1.0);
+}
+
+struct Glyph {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn glyph_vertex(@location(0) position: vec2<f32>, @location(1) uv: vec2<f32>) -> Glyph {
+    var out: Glyph;
+    out.position = to_clip(position);
+    out.uv = uv;
+    return out;
+}
+
+// colors: fill. The glyph cache is the texture.
+@fragment
+fn glyph(in: Glyph) -> @location(0) vec4<f32> {
+    return u.colors[0] * textureSample(source, source_sampler, in.uv).r;
+}
+
+struct Colored {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn graph_vertex(@location(0) position: vec2<f32>, @location(1) color: vec4<f32>) -> Colored {
+    var out: Colored;
+    out.position = to_clip(position);
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn graph(in: Colored) -> @location(0) vec4<f32> {
+    return in.color;
+}
+
+struct Copied {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// params: whether the canvas is turned a quarter clockwise.
+@vertex
+fn copy_vertex(@builtin(vertex_index) index: u32) -> Copied {
+    let p = corner(index);
+    var out: Copied;
+    out.position = vec4<f32>(p.x * 2.0 - 1.0, 1.0 - p.y * 2.0, 0.0, 1.0);
+    out.uv = select(p, vec2<f32>(p.y, 1.0 - p.x), u.params.x > 0.5);
+    return out;
+}
+
+@fragment
+fn copy(in: Copied) -> @location(0) vec4<f32> {
+    let texel = textureSample(source, source_sampler, in.uv);
+    let straight = select(vec3<f32>(0.0), texel.rgb / texel.a, texel.a > 0.0);
+    let encoded = mix(straight * 12.92, 1.055 * pow(straight, vec3<f32>(1.0 / 2.4)) - 0.055,
+        step(vec3<f32>(0.0031308), straight));
+    return vec4<f32>(encoded * texel.a, texel.a);
+}
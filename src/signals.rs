@@ -0,0 +1,22 @@
+use signal_hook::consts::{SIGUSR1, SIGUSR2};
+use signal_hook::iterator::Signals;
+use crate::Error;
+use crate::ipc::Command;
+
+// SIGUSR1 reloads the config, and with it the colorscheme, and SIGUSR2 toggles the bar, for window
+// managers whose key bindings can send signals but not talk to a socket.
+pub fn listen<F: Fn(Command) + Send + 'static>(on_command: F) -> Result<(), Error> {
+    let mut signals = Signals::new([SIGUSR1, SIGUSR2])?;
+
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            match signal {
+                SIGUSR1 => on_command(Command::Reload),
+                SIGUSR2 => on_command(Command::Toggle),
+                _ => (),
+            }
+        }
+    });
+
+    Ok(())
+}
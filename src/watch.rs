@@ -0,0 +1,46 @@
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use crate::Error;
+
+// Editors commonly save by writing a temporary file and renaming it over the
+// original, so the parent directories are watched rather than the files
+// themselves.
+pub fn watch_files<F: Fn() + Send + 'static>(paths: &[PathBuf], on_change: F) -> Result<RecommendedWatcher, Error> {
+    let file_names: HashSet<_> = paths.iter().filter_map(|path| path.file_name().map(|name| name.to_owned())).collect();
+
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        let event = match result {
+            Ok(event) => event,
+            Err(_) => return,
+        };
+
+        let relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_))
+            && event.paths.iter().any(|changed| changed.file_name().is_some_and(|name| file_names.contains(name)));
+
+        if relevant {
+            on_change();
+        }
+    })?;
+
+    // A directory that doesn't exist yet, such as pywal's cache before it
+    // first ran, only keeps its own files from being watched.
+    let directories: HashSet<_> = paths.iter().map(|path| path.parent().unwrap_or_else(|| Path::new("."))).collect();
+    let mut failure = None;
+    let mut watching = false;
+    for directory in directories {
+        match watcher.watch(directory, RecursiveMode::NonRecursive) {
+            Ok(()) => watching = true,
+            Err(err) => {
+                warn!("Not watching {} for changes: {}", directory.display(), err);
+                failure = Some(err);
+            },
+        }
+    }
+
+    match (watching, failure) {
+        (false, Some(err)) => Err(Box::new(err)),
+        _ => Ok(watcher),
+    }
+}
@@ -0,0 +1,853 @@
+use smithay_client_toolkit::{
+    default_environment,
+    environment::{Environment, SimpleGlobal},
+    new_default_environment,
+    output::with_output_info,
+    seat::with_seat_data,
+    reexports::{
+        calloop,
+        client::protocol::{
+            wl_compositor::WlCompositor, wl_output::{Transform, WlOutput}, wl_pointer, wl_surface::WlSurface,
+        },
+        client::{Attached, Display, EventQueue, Main},
+        protocols::unstable::xdg_output::v1::client::{
+            zxdg_output_manager_v1::ZxdgOutputManagerV1,
+            zxdg_output_v1,
+        },
+        protocols::viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+        protocols::wlr::unstable::layer_shell::v1::client::{
+            zwlr_layer_shell_v1::{self, ZwlrLayerShellV1},
+            zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+        },
+    },
+    WaylandSource,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::{error, info, warn};
+use crate::{cli, Error};
+use crate::bar::{self, Anchor, Bar, BarEvent, NoMatchingOutputError, OutputSelection, Surface};
+use crate::config::Config;
+use crate::error::BarError;
+use crate::fractional_scale::wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1;
+use crate::fractional_scale::wp_fractional_scale_v1::{self, WpFractionalScaleV1};
+use crate::ipc::Command;
+use crate::kde_blur::org_kde_kwin_blur::OrgKdeKwinBlur;
+use crate::kde_blur::org_kde_kwin_blur_manager::OrgKdeKwinBlurManager;
+use crate::popup::Placement;
+use crate::render::Context;
+use crate::widgets::{Button, Registry, Scroll, Waker};
+
+default_environment!(Env,
+    fields = [
+        layer_shell: SimpleGlobal<ZwlrLayerShellV1>,
+        blur_manager: SimpleGlobal<OrgKdeKwinBlurManager>,
+        fractional_scale: SimpleGlobal<WpFractionalScaleManagerV1>,
+        viewporter: SimpleGlobal<WpViewporter>,
+        xdg_output: SimpleGlobal<ZxdgOutputManagerV1>,
+    ],
+    singles = [
+        ZwlrLayerShellV1 => layer_shell,
+        OrgKdeKwinBlurManager => blur_manager,
+        WpFractionalScaleManagerV1 => fractional_scale,
+        WpViewporter => viewporter,
+        ZxdgOutputManagerV1 => xdg_output,
+    ],
+);
+
+#[derive(Debug)]
+struct LayerShellUnsupportedError;
+
+impl std::fmt::Display for LayerShellUnsupportedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The compositor does not support wlr-layer-shell")
+    }
+}
+
+impl std::error::Error for LayerShellUnsupportedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LayerEvent {
+    Configure { width: u32, height: u32 },
+    Closed,
+}
+
+pub struct LayerSurface {
+    surface: WlSurface,
+    layer_surface: Main<ZwlrLayerSurfaceV1>,
+    context: Context,
+    event: Rc<Cell<Option<LayerEvent>>>,
+    configured: Cell<bool>,
+    // None when the compositor picked the output.
+    output: Option<WlOutput>,
+    anchor: Anchor,
+    // Top, right, bottom and left, as configured through the gaps.
+    margin: (i32, i32, i32, i32),
+    // Creates the regions for blurring and for input.
+    compositor: Attached<WlCompositor>,
+    blur: Option<Box<Blur>>,
+    // The size in logical pixels as of the last configure event, and how
+    // many physical pixels make up a logical one: the scale the compositor
+    // prefers and the one the context was last sized for.
+    size: Cell<(u32, u32)>,
+    preferred: Rc<Cell<f64>>,
+    scale: Cell<f64>,
+    viewport: Option<Box<Viewport>>,
+}
+
+// Fractional scales are drawn at the size the compositor asks for and the
+// buffer shown at the surface's logical size through a viewport. Without
+// one buffers are scaled by the largest integer scale of the outputs the
+// surface is on.
+struct Viewport {
+    fractional: Main<WpFractionalScaleV1>,
+    viewport: Main<WpViewport>,
+}
+
+// The region behind the surface the compositor blurs, which follows its
+// size.
+struct Blur {
+    blur: Main<OrgKdeKwinBlur>,
+    radius: f32,
+}
+
+fn layer_anchor(anchor: Anchor) -> zwlr_layer_surface_v1::Anchor {
+    use zwlr_layer_surface_v1::Anchor as Edge;
+
+    match anchor {
+        Anchor::Top => Edge::Top | Edge::Left | Edge::Right,
+        Anchor::Bottom => Edge::Bottom | Edge::Left | Edge::Right,
+        Anchor::Left => Edge::Left | Edge::Top | Edge::Bottom,
+        Anchor::Right => Edge::Right | Edge::Top | Edge::Bottom,
+    }
+}
+
+// How a layer surface is placed on its output.
+struct Layout {
+    layer: zwlr_layer_shell_v1::Layer,
+    edges: zwlr_layer_surface_v1::Anchor,
+    size: (u32, u32),
+    exclusive_zone: i32,
+    anchor: Anchor,
+    // Top, right, bottom and left.
+    margin: (i32, i32, i32, i32),
+    vsync: bool,
+    // The corner radius of a surface to blur behind.
+    blur: Option<f32>,
+    // Whether the surface takes the keyboard.
+    keyboard: bool,
+}
+
+impl LayerSurface {
+    fn new(env: &Environment<Env>, layer_shell: &Attached<ZwlrLayerShellV1>, display: &Display, config: &Config,
+        output: Option<&WlOutput>, outputs: &XdgOutputs) -> Result<LayerSurface, Error> {
+        use zwlr_layer_surface_v1::Anchor as Edge;
+
+        // Sizes relative to the output and shorter bars need its size. The
+        // compositor picks the output when none is selected, the first one
+        // is measured then.
+        let measured = output.cloned().or_else(|| env.get_all_outputs().into_iter().next())
+            .and_then(|output| logical_size(&output, outputs));
+        let geometry = bar::Geometry::new(config, measured.unwrap_or((0.0, 0.0)));
+
+        // A zero size along the anchored edges lets the compositor stretch
+        // the bar across the whole output.
+        let size = geometry.size.round() as u32;
+        let mut requested = match config.anchor {
+            Anchor::Top | Anchor::Bottom => (0, size),
+            Anchor::Left | Anchor::Right => (size, 0),
+        };
+        let mut edges = layer_anchor(config.anchor);
+        let mut margin = (
+            geometry.gap_v.0.round() as i32,
+            geometry.gap_h.1.round() as i32,
+            geometry.gap_v.1.round() as i32,
+            geometry.gap_h.0.round() as i32,
+        );
+
+        // Shorter bars are pinned to the left or top end of their edge and
+        // moved into place by the margin there.
+        match (config.length, measured) {
+            (Some(_), Some((width, height))) => {
+                let along = match config.anchor {
+                    Anchor::Top | Anchor::Bottom => width,
+                    Anchor::Left | Anchor::Right => height,
+                };
+                let (start, length) = bar::edge_span(config, &geometry, along);
+                let (start, length) = (start.round() as i32, length.round().max(1.0) as u32);
+                match config.anchor {
+                    Anchor::Top | Anchor::Bottom => {
+                        requested.0 = length;
+                        edges -= Edge::Right;
+                        margin = (margin.0, 0, margin.2, start);
+                    },
+                    Anchor::Left | Anchor::Right => {
+                        requested.1 = length;
+                        edges -= Edge::Bottom;
+                        margin = (start, margin.1, 0, margin.3);
+                    },
+                }
+            },
+            (Some(_), None) => info!("The size of the output isn't known, the bar covers its whole edge"),
+            (None, _) => (),
+        }
+
+        let layout = Layout {
+            layer: zwlr_layer_shell_v1::Layer::Top,
+            edges,
+            size: requested,
+            exclusive_zone: if config.autohide.is_some() { 0 } else { size as i32 },
+            anchor: config.anchor,
+            margin,
+            vsync: config.vsync,
+            blur: Some(config.corner_radius).filter(|_| config.blur),
+            keyboard: config.keyboard_focus,
+        };
+
+        LayerSurface::create(env, layer_shell, display, output, layout)
+    }
+
+    // Tooltip popups go in the overlay layer on the bar's output, pinned to
+    // the same edge and pushed past the bar by their margins. They ignore
+    // exclusive zones, the bar's included, so the margins are measured from
+    // the edge of the output.
+    fn popup(&self, env: &Environment<Env>, layer_shell: &Attached<ZwlrLayerShellV1>, display: &Display,
+        placement: &Placement) -> Result<LayerSurface, Error> {
+        use zwlr_layer_surface_v1::Anchor as Edge;
+
+        let (bar_width, bar_height) = self.size.get();
+        let (width, height) = (placement.size.0.max(1) as i32, placement.size.1.max(1) as i32);
+        let (top, right, bottom, left) = self.margin;
+        let gap = placement.gap.round() as i32;
+        let across = |start: i32, length: i32, size: i32| {
+            (start + placement.along.round() as i32 - size / 2).min(start + length - size).max(start)
+        };
+
+        let (bar_width, bar_height) = (bar_width as i32, bar_height as i32);
+        let (edges, margin) = match self.anchor {
+            Anchor::Top => (Edge::Top | Edge::Left, (top + bar_height + gap, 0, 0, across(left, bar_width, width))),
+            Anchor::Bottom => (Edge::Bottom | Edge::Left, (0, 0, bottom + bar_height + gap, across(left, bar_width, width))),
+            Anchor::Left => (Edge::Left | Edge::Top, (across(top, bar_height, height), 0, 0, left + bar_width + gap)),
+            Anchor::Right => (Edge::Right | Edge::Top, (across(top, bar_height, height), right + bar_width + gap, 0, 0)),
+        };
+
+        let layout = Layout {
+            layer: zwlr_layer_shell_v1::Layer::Overlay,
+            edges,
+            size: (width as u32, height as u32),
+            exclusive_zone: -1,
+            anchor: self.anchor,
+            margin,
+            vsync: false,
+            blur: None,
+            keyboard: false,
+        };
+
+        LayerSurface::create(env, layer_shell, display, self.output.as_ref(), layout)
+    }
+
+    fn create(env: &Environment<Env>, layer_shell: &Attached<ZwlrLayerShellV1>, display: &Display,
+        output: Option<&WlOutput>, layout: Layout) -> Result<LayerSurface, Error> {
+        // Until the compositor says otherwise the surface is drawn at the
+        // scale of its output.
+        let initial_scale = output.and_then(|output| with_output_info(output, |info| info.scale_factor)).unwrap_or(1);
+        let preferred = Rc::new(Cell::new(initial_scale.max(1) as f64));
+        let fractional = env.get_global::<WpFractionalScaleManagerV1>().zip(env.get_global::<WpViewporter>());
+
+        let surface = match fractional {
+            Some(_) => env.create_surface().detach(),
+            None => {
+                let handler = Rc::clone(&preferred);
+                env.create_surface_with_scale_callback(move |scale, _, _| handler.set(scale.max(1) as f64)).detach()
+            },
+        };
+        let viewport = fractional.map(|(manager, viewporter)| {
+            let fractional = manager.get_fractional_scale(&surface);
+            let handler = Rc::clone(&preferred);
+            fractional.quick_assign(move |_, event, _| {
+                let wp_fractional_scale_v1::Event::PreferredScale { scale } = event;
+                handler.set(scale as f64 / 120.0);
+            });
+            Box::new(Viewport { fractional, viewport: viewporter.get_viewport(&surface) })
+        });
+        let layer_surface = layer_shell.get_layer_surface(&surface, output, layout.layer, "rustybar".to_owned());
+
+        let Layout { size: requested, margin, .. } = layout;
+        layer_surface.set_size(requested.0, requested.1);
+        layer_surface.set_anchor(layout.edges);
+        layer_surface.set_exclusive_zone(layout.exclusive_zone);
+        layer_surface.set_margin(margin.0, margin.1, margin.2, margin.3);
+        layer_surface.set_keyboard_interactivity(layout.keyboard as u32);
+
+        let event = Rc::new(Cell::new(None));
+        let handler = Rc::clone(&event);
+        layer_surface.quick_assign(move |layer_surface, event, _| match event {
+            zwlr_layer_surface_v1::Event::Configure { serial, width, height } => {
+                layer_surface.ack_configure(serial);
+                if handler.get() != Some(LayerEvent::Closed) {
+                    handler.set(Some(LayerEvent::Configure { width, height }));
+                }
+            },
+            zwlr_layer_surface_v1::Event::Closed => handler.set(Some(LayerEvent::Closed)),
+            _ => (),
+        });
+
+        let blur = layout.blur.and_then(|radius| match env.get_global::<OrgKdeKwinBlurManager>() {
+            Some(manager) => Some(Box::new(Blur {
+                blur: manager.create(&surface),
+                radius,
+            })),
+            None => {
+                info!("Not blurring behind the bar, the compositor doesn't support KWin's blur protocol");
+                None
+            },
+        });
+
+        surface.commit();
+
+        // The real size only arrives with the first configure event, the
+        // context is resized to it before anything is drawn.
+        let initial = (requested.0.max(1), requested.1.max(1));
+        let context = unsafe {
+            Context::wayland(display.get_display_ptr() as *mut _, surface.as_ref().c_ptr() as *mut _, initial, layout.vsync)?
+        };
+
+        Ok(LayerSurface {
+            surface,
+            layer_surface,
+            context,
+            event,
+            configured: Cell::new(false),
+            output: output.cloned(),
+            anchor: layout.anchor,
+            margin,
+            compositor: env.require_global::<WlCompositor>(),
+            blur,
+            size: Cell::new(initial),
+            preferred,
+            scale: Cell::new(1.0),
+            viewport,
+        })
+    }
+
+    // A negative margin on the anchored edge moves the surface past it, the
+    // compositor clips what ends up outside the output.
+    pub fn set_offset(&self, offset: f64) {
+        let offset = offset.round() as i32;
+        let (mut top, mut right, mut bottom, mut left) = self.margin;
+        match self.anchor {
+            Anchor::Top => top -= offset,
+            Anchor::Bottom => bottom -= offset,
+            Anchor::Left => left -= offset,
+            Anchor::Right => right -= offset,
+        }
+
+        self.layer_surface.set_margin(top, right, bottom, left);
+        self.surface.commit();
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    // Only the given rectangles of the surface take pointer input, as x, y,
+    // width and height in logical pixels, the rest goes to what's beneath
+    // it. No rectangles let everything through.
+    pub fn set_input_region(&self, rectangles: &[(i32, i32, i32, i32)]) {
+        let region = self.compositor.create_region();
+        for &(x, y, width, height) in rectangles {
+            region.add(x, y, width, height);
+        }
+        self.surface.set_input_region(Some(&region));
+        self.surface.commit();
+        region.destroy();
+    }
+
+    // The size in logical pixels, the one asked for until the compositor
+    // picks one.
+    pub fn logical_size(&self) -> (u32, u32) {
+        self.size.get()
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.scale.get()
+    }
+
+    // Takes the logical size from a configure event, the context gets the
+    // physical one for the preferred scale.
+    fn resize(&self, width: u32, height: u32) {
+        let scale = self.preferred.get();
+        self.size.set((width, height));
+        self.scale.set(scale);
+        match &self.viewport {
+            Some(viewport) => viewport.viewport.set_destination(width.max(1) as i32, height.max(1) as i32),
+            None => self.surface.set_buffer_scale(scale as i32),
+        }
+
+        let physical = |value: u32| (value as f64 * scale).round().max(1.0) as u32;
+        self.context.resize((physical(width), physical(height)));
+
+        // Applied along with the next frame.
+        if let Some(blur) = &self.blur {
+            let region = self.compositor.create_region();
+            for (x, y, width, height) in bar::blur_region((width, height), blur.radius) {
+                region.add(x, y, width, height);
+            }
+            blur.blur.set_region(Some(&region));
+            blur.blur.commit();
+            region.destroy();
+        }
+    }
+
+    // Sizes a configured surface for a scale the compositor changed its mind
+    // about since. Returns whether it had to.
+    fn rescale(&self) -> bool {
+        if !self.configured.get() || self.preferred.get() == self.scale.get() {
+            return false;
+        }
+
+        let (width, height) = self.size.get();
+        self.resize(width, height);
+        true
+    }
+}
+
+impl Drop for LayerSurface {
+    fn drop(&mut self) {
+        self.context.release();
+        if let Some(blur) = &self.blur {
+            blur.blur.release();
+        }
+        if let Some(viewport) = &self.viewport {
+            viewport.fractional.destroy();
+            viewport.viewport.destroy();
+        }
+        self.layer_surface.destroy();
+        self.surface.destroy();
+    }
+}
+
+// What xdg-output reports about an output: its logical size, which unlike
+// the current mode divided by the integer scale factor accounts for
+// fractional scales, and its connector name such as "DP-1".
+#[derive(Debug, Clone, Default)]
+struct XdgOutput {
+    logical_size: Option<(f64, f64)>,
+    name: Option<String>,
+}
+
+// By the id of the output.
+type XdgOutputs = Rc<RefCell<HashMap<u32, XdgOutput>>>;
+
+fn track_xdg_output(manager: &Attached<ZxdgOutputManagerV1>, output: &WlOutput, outputs: &XdgOutputs) {
+    let id = match with_output_info(output, |info| info.id) {
+        Some(id) if !outputs.borrow().contains_key(&id) => id,
+        _ => return,
+    };
+
+    outputs.borrow_mut().insert(id, XdgOutput::default());
+    let handler = Rc::clone(outputs);
+    manager.get_xdg_output(output).quick_assign(move |_, event, _| {
+        let mut outputs = handler.borrow_mut();
+        let tracked = outputs.entry(id).or_default();
+        match event {
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                tracked.logical_size = Some((width as f64, height as f64));
+            },
+            zxdg_output_v1::Event::Name { name } => tracked.name = Some(name),
+            _ => (),
+        }
+    });
+}
+
+// The size of the output in logical pixels, once the compositor has told us
+// its current mode.
+fn logical_size(output: &WlOutput, outputs: &XdgOutputs) -> Option<(f64, f64)> {
+    let tracked = with_output_info(output, |info| info.id)
+        .and_then(|id| outputs.borrow().get(&id).and_then(|tracked| tracked.logical_size));
+    if tracked.is_some() {
+        return tracked;
+    }
+
+    with_output_info(output, |info| {
+        let mode = info.modes.iter().find(|mode| mode.is_current)?;
+        let scale = info.scale_factor.max(1) as f64;
+        let (width, height) = (mode.dimensions.0 as f64 / scale, mode.dimensions.1 as f64 / scale);
+        let turned = matches!(info.transform,
+            Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270);
+        Some(if turned { (height, width) } else { (width, height) })
+    }).flatten()
+}
+
+// Named outputs are matched against the connector names xdg-output
+// reports, the same ones X11 knows them by. wl_output has no names before
+// version 4, so without xdg-output the model the compositor reports is
+// matched instead. Without a selection the compositor places the bar on the
+// output it considers current.
+fn select_outputs(env: &Environment<Env>, selection: &OutputSelection, outputs: &XdgOutputs)
+    -> Result<Vec<Option<WlOutput>>, Error> {
+    let matches = |output: &WlOutput, name: &str| {
+        with_output_info(output, |info| {
+            let connector = outputs.borrow().get(&info.id).and_then(|tracked| tracked.name.clone());
+            !info.obsolete && (connector.as_deref() == Some(name) || info.model == name)
+        }).unwrap_or(false)
+    };
+
+    let outputs: Vec<_> = match selection {
+        OutputSelection::Primary => return Ok(vec![None]),
+        OutputSelection::All => env.get_all_outputs(),
+        OutputSelection::Named(name) => env.get_all_outputs().into_iter()
+            .filter(|output| matches(output, name))
+            .collect(),
+    };
+
+    match selection {
+        OutputSelection::Named(name) if outputs.is_empty() => Err(Box::new(NoMatchingOutputError(name.clone()))),
+        _ => Ok(outputs.into_iter().map(Some).collect()),
+    }
+}
+
+// Button codes from linux/input-event-codes.h.
+fn pointer_button(code: u32) -> Option<Button> {
+    match code {
+        0x110 => Some(Button::Left),
+        0x111 => Some(Button::Right),
+        0x112 => Some(Button::Middle),
+        _ => None,
+    }
+}
+
+// Axis events are in surface pixels, roughly this many make up one step of
+// a scroll wheel.
+const AXIS_PER_SCROLL_STEP: f64 = 10.0;
+
+enum PointerInput {
+    Click(Button),
+    Scroll(Scroll),
+    Hover(bool),
+    // The pointer moved to the queued position.
+    Motion,
+}
+
+pub struct Session {
+    env: Environment<Env>,
+    display: Display,
+    queue: EventQueue,
+    layer_shell: Attached<ZwlrLayerShellV1>,
+}
+
+impl Session {
+    // Fails when there is no Wayland compositor or it lacks layer-shell, in
+    // which case the caller falls back to X11.
+    pub fn connect() -> Result<Session, Error> {
+        let (env, display, queue) = new_default_environment!(Env,
+            fields = [
+                layer_shell: SimpleGlobal::new(),
+                blur_manager: SimpleGlobal::new(),
+                fractional_scale: SimpleGlobal::new(),
+                viewporter: SimpleGlobal::new(),
+                xdg_output: SimpleGlobal::new(),
+            ])?;
+        let layer_shell = env.get_global::<ZwlrLayerShellV1>().ok_or(LayerShellUnsupportedError)?;
+
+        Ok(Session { env, display, queue, layer_shell })
+    }
+
+    pub fn run(self, args: &cli::Args, mut config: Config, registry: &Registry) -> Result<(), Error> {
+        let mut event_loop = calloop::EventLoop::<Vec<BarEvent>>::new()?;
+
+        let (sender, channel) = calloop::channel::channel();
+        event_loop.handle().insert_source(channel, |event, _, pending: &mut Vec<BarEvent>| {
+            if let calloop::channel::Event::Msg(event) = event {
+                pending.push(event);
+            }
+        }).map_err(|err| err.error)?;
+
+        let waker_sender = Mutex::new(sender.clone());
+        let waker = Waker::new(move || {
+            let _ = waker_sender.lock().unwrap().send(BarEvent::Wake);
+        });
+
+        let ipc_sender = Mutex::new(sender.clone());
+        let listeners = crate::listen_commands(move |command| {
+            let _ = ipc_sender.lock().unwrap().send(BarEvent::Command(command));
+        });
+
+        let watch = |config: &Config| {
+            let watcher_sender = Mutex::new(sender.clone());
+            crate::watch_config_files(args, config, move || {
+                let _ = watcher_sender.lock().unwrap().send(BarEvent::ConfigChanged);
+            })
+        };
+        let mut _watcher = watch(&config);
+
+        let appearance_sender = Mutex::new(sender.clone());
+        let _ = crate::appearance::watch(move || {
+            let _ = appearance_sender.lock().unwrap().send(BarEvent::ConfigChanged);
+        });
+
+        let Session { env, display, mut queue, layer_shell } = self;
+
+        // Outputs are measured and named before any bar is placed on them.
+        let xdg_outputs = XdgOutputs::default();
+        let xdg_output = env.get_global::<ZxdgOutputManagerV1>();
+        if let Some(manager) = &xdg_output {
+            for output in env.get_all_outputs() {
+                track_xdg_output(manager, &output, &xdg_outputs);
+            }
+            queue.sync_roundtrip(&mut (), |_, _, _| ())?;
+        }
+
+        let create_surfaces = |config: &Config| -> Result<Vec<Surface>, Error> {
+            select_outputs(&env, &config.output, &xdg_outputs)?.iter()
+                .map(|output| LayerSurface::new(&env, &layer_shell, &display, config, output.as_ref(), &xdg_outputs))
+                .map(|surface| surface.map(Surface::Wayland))
+                .collect()
+        };
+        let create_bar = |config: &Config| -> Result<Bar, Error> {
+            let surfaces = create_surfaces(config).map_err(BarError::Window)?;
+            Ok(Bar::with_surfaces(surfaces, config, registry, &waker)?)
+        };
+
+        // Outputs coming and going, or changing mode, all end up here; the
+        // compositor also closes layer surfaces whose output disappeared.
+        let outputs_changed = Rc::new(Cell::new(false));
+        let listener_flag = Rc::clone(&outputs_changed);
+        let listener_outputs = Rc::clone(&xdg_outputs);
+        let _output_listener = env.listen_for_outputs(move |output, _, _| {
+            if let Some(manager) = &xdg_output {
+                track_xdg_output(manager, &output, &listener_outputs);
+            }
+            listener_flag.set(true);
+        });
+
+        // Pointer handlers only queue clicks, scrolling and the pointer
+        // entering, moving or leaving, they are routed to the widgets, the
+        // tooltips and the autohide slide once the event queue has been
+        // dispatched.
+        let clicks = Rc::new(RefCell::new(Vec::new()));
+        let _pointers: Vec<_> = env.get_all_seats().iter()
+            .filter(|seat| with_seat_data(seat, |data| data.has_pointer && !data.defunct).unwrap_or(false))
+            .map(|seat| {
+                let pointer = seat.get_pointer();
+                let clicks = Rc::clone(&clicks);
+                let mut focus: Option<(WlSurface, (f32, f32))> = None;
+                let mut scrolled = 0.0;
+
+                pointer.quick_assign(move |_, event, _| match event {
+                    wl_pointer::Event::Enter { surface, surface_x, surface_y, .. } => {
+                        let position = (surface_x as f32, surface_y as f32);
+                        let mut clicks = clicks.borrow_mut();
+                        clicks.push((surface.clone(), position, PointerInput::Hover(true)));
+                        clicks.push((surface.clone(), position, PointerInput::Motion));
+                        focus = Some((surface, position));
+                    },
+                    wl_pointer::Event::Motion { surface_x, surface_y, .. } => {
+                        if let Some((surface, position)) = &mut focus {
+                            *position = (surface_x as f32, surface_y as f32);
+                            clicks.borrow_mut().push((surface.clone(), *position, PointerInput::Motion));
+                        }
+                    },
+                    wl_pointer::Event::Leave { surface, .. } => {
+                        clicks.borrow_mut().push((surface, (0.0, 0.0), PointerInput::Hover(false)));
+                        focus = None;
+                    },
+                    wl_pointer::Event::Button { button, state: wl_pointer::ButtonState::Pressed, .. } => {
+                        if let (Some((surface, position)), Some(button)) = (&focus, pointer_button(button)) {
+                            clicks.borrow_mut().push((surface.clone(), *position, PointerInput::Click(button)));
+                        }
+                    },
+                    wl_pointer::Event::Axis { axis: wl_pointer::Axis::VerticalScroll, value, .. } => {
+                        // Positive values scroll down, towards the end of a page.
+                        scrolled += value / AXIS_PER_SCROLL_STEP;
+                        let direction = if scrolled > 0.0 { Scroll::Down } else { Scroll::Up };
+
+                        while scrolled.abs() >= 1.0 {
+                            scrolled -= scrolled.signum();
+                            if let Some((surface, position)) = &focus {
+                                clicks.borrow_mut().push((surface.clone(), *position, PointerInput::Scroll(direction)));
+                            }
+                        }
+                    },
+                    _ => (),
+                });
+
+                pointer
+            })
+            .collect();
+
+        let mut bar = create_bar(&config)?;
+        WaylandSource::new(queue).quick_insert(event_loop.handle()).map_err(|err| err.error)?;
+
+        let mut pending = Vec::new();
+        let mut update_due = true;
+        let mut force_redraw = false;
+        let mut mapped = true;
+        loop {
+            let mut reload = false;
+            for event in pending.drain(..) {
+                match event {
+                    BarEvent::Wake => update_due = true,
+                    BarEvent::ConfigChanged | BarEvent::Command(Command::Reload) => reload = true,
+                    BarEvent::OutputsChanged => outputs_changed.set(true),
+                    BarEvent::Command(Command::Set { module, text }) => {
+                        if bar.set_text(&module, &text) {
+                            force_redraw = true;
+                        } else {
+                            warn!("No module named '{}'", module);
+                        }
+                    },
+                    BarEvent::Command(Command::Action { module, action }) => match bar.run_action(&module, &action) {
+                        Some(changed) => force_redraw |= changed,
+                        None => warn!("No module named '{}'", module),
+                    },
+                    BarEvent::Command(Command::Debug) => {
+                        bar.toggle_debug();
+                        force_redraw = true;
+                    },
+                    BarEvent::Command(command) => {
+                        let visible = match command {
+                            Command::Show => true,
+                            Command::Hide => false,
+                            _ => !bar.is_visible(),
+                        };
+
+                        bar.set_visible(visible);
+                    },
+                }
+            }
+
+            if reload {
+                let reloaded = crate::load_config(args)
+                    .and_then(|new_config| Ok((create_bar(&new_config)?, new_config)));
+                match reloaded {
+                    Ok((new_bar, new_config)) => {
+                        update_due = true;
+                        mapped = true;
+                        bar = new_bar;
+                        config = new_config;
+                        _watcher = watch(&config);
+                    },
+                    Err(err) => error!("Failed to reload config: {}", err),
+                }
+            }
+
+            let mut resized = Vec::new();
+            for output in bar.outputs() {
+                if let Surface::Wayland(layer) = output.surface() {
+                    match layer.event.take() {
+                        Some(LayerEvent::Configure { width, height }) => {
+                            layer.resize(width, height);
+                            layer.configured.set(true);
+                            resized.push(true);
+                        },
+                        Some(LayerEvent::Closed) => {
+                            outputs_changed.set(true);
+                            resized.push(false);
+                        },
+                        None => resized.push(layer.rescale()),
+                    }
+                }
+            }
+
+            if outputs_changed.replace(false) && mapped {
+                if let Err(err) = create_surfaces(&config).and_then(|surfaces| bar.set_surfaces(surfaces, &config)) {
+                    warn!("Failed to move the bar to the new output layout: {}", err);
+                }
+            }
+
+            let mut changed = std::mem::take(&mut force_redraw);
+            for (surface, position, input) in clicks.borrow_mut().drain(..) {
+                let clicked = bar.outputs().iter().position(|output| match output.surface() {
+                    Surface::Wayland(layer) => layer.surface == surface,
+                    Surface::X11(..) => false,
+                });
+
+                let on_popup = match bar.popup_surface() {
+                    Some(Surface::Wayland(layer)) => layer.surface == surface,
+                    _ => false,
+                };
+
+                changed |= match (clicked, input) {
+                    (Some(index), PointerInput::Click(button)) => bar.click(index, position, button),
+                    (Some(index), PointerInput::Scroll(direction)) => bar.scroll(index, position, direction),
+                    (Some(_), PointerInput::Hover(hovered)) => {
+                        bar.set_hovered(hovered);
+                        if !hovered {
+                            bar.hover(None);
+                        }
+                        false
+                    },
+                    (Some(index), PointerInput::Motion) => {
+                        bar.hover(Some((index, position.0, position.1)));
+                        false
+                    },
+                    (None, PointerInput::Scroll(direction)) if on_popup => bar.scroll_popup(direction),
+                    (None, PointerInput::Click(button)) if on_popup => bar.click_popup(position, button),
+                    (None, _) => false,
+                };
+            }
+
+            if update_due {
+                changed |= bar.update();
+                update_due = false;
+            }
+            listeners.announce(&mut bar);
+
+            // Hidden bars have no surfaces at all, showing the bar again
+            // creates them the same way an output change does.
+            if bar.is_mapped() != mapped {
+                mapped = bar.is_mapped();
+                let surfaces = if mapped { create_surfaces(&config) } else { Ok(Vec::new()) };
+                if let Err(err) = surfaces.and_then(|surfaces| bar.set_surfaces(surfaces, &config)) {
+                    warn!("Failed to show the bar: {}", err);
+                }
+            }
+            let stale: Vec<usize> = bar.outputs().iter().zip(resized).enumerate()
+                .filter(|(_, (output, resized))| match output.surface() {
+                    Surface::Wayland(layer) => (changed || *resized) && layer.configured.get(),
+                    Surface::X11(..) => false,
+                })
+                .map(|(index, _)| index)
+                .collect();
+
+            for index in stale {
+                bar.draw(index);
+            }
+
+            let popup_changed = bar.sync_popup(|parent, placement| match parent {
+                Surface::Wayland(layer) => layer.popup(&env, &layer_shell, &display, placement).map(Surface::Wayland),
+                Surface::X11(..) => Err(Box::new(crate::popup::UnsupportedSurfaceError)),
+            });
+            // Popups are drawn once the compositor has configured them,
+            // and again whenever the bar was.
+            if let Some(Surface::Wayland(layer)) = bar.popup_surface() {
+                let configured = match layer.event.take() {
+                    Some(LayerEvent::Configure { width, height }) => {
+                        layer.resize(width, height);
+                        layer.configured.set(true);
+                        true
+                    },
+                    _ => layer.rescale(),
+                };
+                if layer.configured.get() && (configured || popup_changed || changed) {
+                    bar.draw_popup();
+                }
+            }
+
+            display.flush()?;
+
+            let deadline = bar.next_update();
+            let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            event_loop.dispatch(timeout, &mut pending)?;
+            update_due |= deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        }
+    }
+}
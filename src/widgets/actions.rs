@@ -0,0 +1,197 @@
+use serde::Deserialize;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{Button, Scroll, Widget};
+
+// A click action is either a shell command, or a table naming one of the
+// widget's built-in actions: `on-click = { action = "toggle-mute" }`. With
+// the lua feature it may also be a snippet: `on-click = { lua = "..." }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Action {
+    Command(String),
+    Builtin { action: String },
+    #[cfg(feature = "lua")]
+    Lua { lua: String },
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Actions {
+    on_click: Option<Action>,
+    on_middle_click: Option<Action>,
+    on_right_click: Option<Action>,
+    on_scroll_up: Option<Action>,
+    on_scroll_down: Option<Action>,
+    invert_scroll: bool,
+}
+
+impl Actions {
+    pub fn is_empty(&self) -> bool {
+        [&self.on_click, &self.on_middle_click, &self.on_right_click, &self.on_scroll_up, &self.on_scroll_down]
+            .iter()
+            .all(|action| action.is_none())
+            && !self.invert_scroll
+    }
+
+    fn for_button(&self, button: Button) -> Option<&Action> {
+        match button {
+            Button::Left => self.on_click.as_ref(),
+            Button::Middle => self.on_middle_click.as_ref(),
+            Button::Right => self.on_right_click.as_ref(),
+        }
+    }
+
+    fn for_scroll(&self, direction: Scroll) -> Option<&Action> {
+        match direction {
+            Scroll::Up => self.on_scroll_up.as_ref(),
+            Scroll::Down => self.on_scroll_down.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownActionError(pub String);
+
+impl std::fmt::Display for UnknownActionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown action '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownActionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// Runs a command through the shell without waiting for it; a thread reaps
+// the child so it doesn't linger as a zombie.
+pub fn spawn_command(command: &str) -> Result<(), Error> {
+    let mut child = Command::new("sh").arg("-c").arg(command)
+        .stdin(Stdio::null())
+        .spawn()?;
+
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+// Wraps a widget whose module section configures click or scroll actions.
+// Input without an action is still handed to the widget itself.
+pub struct WithActions {
+    widget: Box<dyn Widget>,
+    actions: Actions,
+    // Lua handlers of the module share one state, created on first use.
+    #[cfg(feature = "lua")]
+    script: Option<super::lua::Script>,
+}
+
+impl WithActions {
+    pub fn new(widget: Box<dyn Widget>, actions: Actions) -> WithActions {
+        WithActions {
+            widget,
+            actions,
+            #[cfg(feature = "lua")]
+            script: None,
+        }
+    }
+
+    #[cfg(feature = "lua")]
+    fn run_lua(&mut self, code: &str) -> Result<bool, Error> {
+        if self.script.is_none() {
+            self.script = Some(super::lua::Script::new("handlers", "")?);
+        }
+        self.script.as_ref().unwrap().run(code).map(|()| false)
+    }
+
+    fn run(&mut self, action: &Action) -> bool {
+        let result = match action {
+            Action::Command(command) => spawn_command(command).map(|()| false),
+            Action::Builtin { action } => self.widget.action(action),
+            #[cfg(feature = "lua")]
+            Action::Lua { lua } => self.run_lua(lua),
+        };
+
+        result.unwrap_or_else(|err| {
+            warn!("Action failed: {}", err);
+            false
+        })
+    }
+}
+
+impl Widget for WithActions {
+    fn update(&mut self) -> bool {
+        #[cfg(feature = "lua")]
+        if let Some(script) = &self.script {
+            script.fire_timers();
+        }
+
+        self.widget.update()
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        self.widget.render(ctx)
+    }
+
+    #[cfg(not(feature = "lua"))]
+    fn next_update(&self) -> Option<Instant> {
+        self.widget.next_update()
+    }
+
+    #[cfg(feature = "lua")]
+    fn next_update(&self) -> Option<Instant> {
+        let timer = self.script.as_ref().and_then(|script| script.next_timer());
+        self.widget.next_update().into_iter().chain(timer).min()
+    }
+
+    fn animating(&self) -> bool {
+        self.widget.animating()
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        match self.actions.for_button(button).cloned() {
+            Some(action) => self.run(&action),
+            None => self.widget.click(x, button),
+        }
+    }
+
+    fn scroll(&mut self, x: f32, direction: Scroll) -> bool {
+        let direction = if self.actions.invert_scroll { direction.inverted() } else { direction };
+        match self.actions.for_scroll(direction).cloned() {
+            Some(action) => self.run(&action),
+            None => self.widget.scroll(x, direction),
+        }
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        self.widget.action(name)
+    }
+
+    fn tooltip(&self, x: f32) -> Option<String> {
+        self.widget.tooltip(x)
+    }
+
+    fn popup(&self) -> Option<String> {
+        self.widget.popup()
+    }
+
+    fn popup_click(&mut self, line: usize, button: Button) -> bool {
+        self.widget.popup_click(line, button)
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.widget.value()
+    }
+
+    fn background(&self) -> Option<Color> {
+        self.widget.background()
+    }
+
+    fn click_through(&self) -> bool {
+        self.widget.click_through()
+    }
+}
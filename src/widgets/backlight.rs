@@ -0,0 +1,209 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, sysfs, Scroll, Waker, Widget};
+use super::worker::Poller;
+use super::meter::{self, BarStyle, Display, RingStyle};
+
+const BACKLIGHT: &str = "/sys/class/backlight";
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct BacklightConfig {
+    // Uses the first backlight device such as "intel_backlight" when unset.
+    device: Option<String>,
+    format: String,
+    format_missing: String,
+    interval: f64,
+    scroll_step: u32,
+    // Scrolling down stops here so the screen never goes fully dark.
+    min_brightness: u32,
+    color: Option<Color>,
+    display: Display,
+    bar: BarStyle,
+    ring: RingStyle,
+}
+
+impl Default for BacklightConfig {
+    fn default() -> BacklightConfig {
+        BacklightConfig {
+            device: None,
+            format: "BRI {percent}%".to_string(),
+            format_missing: String::new(),
+            interval: 1.0,
+            scroll_step: 5,
+            min_brightness: 1,
+            color: None,
+            display: Display::Text,
+            bar: BarStyle::default(),
+            ring: RingStyle::default(),
+        }
+    }
+}
+
+fn find_device(only: Option<&str>) -> Option<PathBuf> {
+    if let Some(name) = only {
+        return Some(Path::new(BACKLIGHT).join(name)).filter(|path| path.exists());
+    }
+
+    let mut devices: Vec<PathBuf> = std::fs::read_dir(BACKLIGHT).ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    devices.sort();
+
+    devices.into_iter().next()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Reading {
+    brightness: u32,
+    max: u32,
+}
+
+impl Reading {
+    fn percent(&self) -> u32 {
+        if self.max == 0 {
+            0
+        } else {
+            (self.brightness as f64 / self.max as f64 * 100.0).round() as u32
+        }
+    }
+}
+
+fn read_device(path: &Path) -> Option<Reading> {
+    // actual_brightness is what the hardware reports, brightness only what
+    // was last requested.
+    let brightness = sysfs::read_number(path.join("actual_brightness"))
+        .or_else(|| sysfs::read_number(path.join("brightness")))?;
+    let max = sysfs::read_number(path.join("max_brightness"))?;
+
+    Some(Reading { brightness, max })
+}
+
+// Writing sysfs directly needs the udev rules most distributions ship for
+// the video group, otherwise logind changes it on behalf of the session.
+fn set_brightness(path: &Path, brightness: u32) -> Result<(), Error> {
+    if std::fs::write(path.join("brightness"), brightness.to_string()).is_ok() {
+        return Ok(());
+    }
+
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let connection = zbus::blocking::Connection::system()?;
+    connection.call_method(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1/session/auto",
+        Some("org.freedesktop.login1.Session"),
+        "SetBrightness",
+        &("backlight", name.as_str(), brightness),
+    )?;
+
+    Ok(())
+}
+
+pub struct Backlight {
+    config: BacklightConfig,
+    poller: Poller<Option<Reading>>,
+    device: Option<PathBuf>,
+    reading: Option<Reading>,
+    text: String,
+}
+
+impl Backlight {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: BacklightConfig = section.clone().try_into()?;
+        let device = find_device(config.device.as_deref());
+
+        let sampled = device.clone();
+        let poller = Poller::spawn(Duration::from_secs_f64(config.interval.max(0.1)), waker.clone(), move || {
+            sampled.as_deref().and_then(read_device)
+        });
+
+        Ok(Box::new(Backlight { config, poller, device, reading: None, text: String::new() }))
+    }
+
+    fn format(&self, reading: Option<Reading>) -> String {
+        let reading = match reading {
+            Some(reading) => reading,
+            None => return self.config.format_missing.clone(),
+        };
+
+        format::placeholders(&self.config.format, |key| match key {
+            "percent" => Some(reading.percent().to_string()),
+            "brightness" => Some(reading.brightness.to_string()),
+            "max" => Some(reading.max.to_string()),
+            _ => None,
+        })
+    }
+
+    fn refresh(&mut self, reading: Option<Reading>) -> bool {
+        let text = self.format(reading);
+        if reading == self.reading && text == self.text {
+            return false;
+        }
+
+        self.reading = reading;
+        self.text = text;
+        true
+    }
+}
+
+impl Widget for Backlight {
+    fn update(&mut self) -> bool {
+        match self.poller.take() {
+            Some(reading) => self.refresh(reading),
+            None => false,
+        }
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.reading.map(|reading| reading.percent() as f64)
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = self.config.color.unwrap_or_else(|| ctx.foreground());
+        let value = self.reading.map(|reading| reading.percent() as f32 / 100.0);
+        match (self.config.display, value) {
+            (Display::Bar, Some(value)) => meter::draw_bar(ctx, value, color, &self.config.bar),
+            (Display::Ring, Some(value)) => meter::draw_ring(ctx, value, color, &self.config.ring),
+            _ => ctx.text(&self.text, color),
+        }
+    }
+
+    fn scroll(&mut self, _x: f32, direction: Scroll) -> bool {
+        let (device, reading) = match (&self.device, self.reading) {
+            (Some(device), Some(reading)) => (device, reading),
+            _ => return false,
+        };
+
+        let percent = match direction {
+            Scroll::Up => reading.percent() + self.config.scroll_step,
+            Scroll::Down => reading.percent().saturating_sub(self.config.scroll_step),
+        };
+        let percent = percent.clamp(self.config.min_brightness.min(100), 100);
+        let mut brightness = (reading.max as f64 * percent as f64 / 100.0).round() as u32;
+
+        // Devices with only a handful of levels would otherwise round back
+        // to the current one.
+        if brightness == reading.brightness && percent != reading.percent() {
+            brightness = match direction {
+                Scroll::Up => (brightness + 1).min(reading.max),
+                Scroll::Down => brightness.saturating_sub(1),
+            };
+        }
+        let lowest = (reading.max as f64 * self.config.min_brightness as f64 / 100.0).ceil() as u32;
+        let brightness = brightness.max(lowest.min(reading.max));
+
+        if let Err(err) = set_brightness(device, brightness) {
+            warn!("Failed to change the brightness: {}", err);
+        }
+
+        // The new brightness shows right away rather than with the next
+        // sample.
+        let reading = read_device(device);
+        self.refresh(reading)
+    }
+}
@@ -0,0 +1,349 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, sysfs, Waker, Widget};
+use super::worker::Poller;
+use super::meter::{self, BarStyle, Display, RingStyle};
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct BatteryConfig {
+    // Restricts the widget to one battery such as "BAT0", otherwise all
+    // batteries are combined.
+    battery: Option<String>,
+    format: String,
+    format_charging: Option<String>,
+    format_missing: String,
+    interval: f64,
+    warning: f64,
+    critical: f64,
+    color: Option<Color>,
+    warning_color: Color,
+    critical_color: Color,
+    display: Display,
+    bar: BarStyle,
+    ring: RingStyle,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> BatteryConfig {
+        BatteryConfig {
+            battery: None,
+            format: "{capacity}% {status}".to_string(),
+            format_charging: None,
+            format_missing: String::new(),
+            interval: 5.0,
+            warning: 30.0,
+            critical: 15.0,
+            color: None,
+            warning_color: Color::from_str("#ffaa00").unwrap(),
+            critical_color: Color::from_str("#ff3333").unwrap(),
+            display: Display::Text,
+            bar: BarStyle::default(),
+            ring: RingStyle::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Charging,
+    Discharging,
+    Full,
+    NotCharging,
+    Unknown,
+}
+
+impl Status {
+    fn from_sysfs(status: &str) -> Status {
+        match status {
+            "Charging" => Status::Charging,
+            "Discharging" => Status::Discharging,
+            "Full" => Status::Full,
+            "Not charging" => Status::NotCharging,
+            _ => Status::Unknown,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Status::Charging => "Charging",
+            Status::Discharging => "Discharging",
+            Status::Full => "Full",
+            Status::NotCharging => "Not charging",
+            Status::Unknown => "Unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Reading {
+    capacity: f64,
+    status: Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    // µWh.
+    Energy,
+    // µAh.
+    Charge,
+    // The driver's own rounded percentage.
+    Capacity,
+}
+
+struct Sample {
+    now: f64,
+    full: f64,
+    unit: Unit,
+    status: Status,
+}
+
+fn read_battery(path: &Path) -> Option<Sample> {
+    let status = Status::from_sysfs(&sysfs::read_string(path.join("status")).unwrap_or_default());
+
+    // Drivers expose either energy (µWh) or charge (µAh); capacity is the
+    // driver's own rounded percentage and only used as a last resort.
+    let energy = sysfs::read_number(path.join("energy_now")).zip(sysfs::read_number(path.join("energy_full")));
+    let charge = sysfs::read_number(path.join("charge_now")).zip(sysfs::read_number(path.join("charge_full")));
+
+    match (energy, charge) {
+        (Some((now, full)), _) => Some(Sample { now, full, unit: Unit::Energy, status }),
+        (None, Some((now, full))) => Some(Sample { now, full, unit: Unit::Charge, status }),
+        (None, None) => sysfs::read_number(path.join("capacity"))
+            .map(|capacity: f64| Sample { now: capacity, full: 100.0, unit: Unit::Capacity, status }),
+    }
+}
+
+fn batteries(only: Option<&str>) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(POWER_SUPPLY) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut batteries: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| sysfs::read_string(path.join("type")).as_deref() == Some("Battery"))
+        .filter(|path| only.is_none_or(|name| path.file_name().is_some_and(|file| file == name)))
+        .collect();
+    batteries.sort();
+
+    batteries
+}
+
+fn read(only: Option<&str>) -> Option<Reading> {
+    combine(batteries(only).iter().filter_map(|path| read_battery(path)).collect())
+}
+
+fn combine(samples: Vec<Sample>) -> Option<Reading> {
+    let samples: Vec<Sample> = samples.into_iter().filter(|sample| sample.full > 0.0).collect();
+    let first = samples.first()?;
+
+    // Batteries reporting in the same unit count by what they hold, so a
+    // small one weighs less. Otherwise there's nothing to add up and each
+    // battery counts the same.
+    let capacity = if samples.iter().all(|sample| sample.unit == first.unit) {
+        let now: f64 = samples.iter().map(|sample| sample.now).sum();
+        let full: f64 = samples.iter().map(|sample| sample.full).sum();
+        now / full * 100.0
+    } else {
+        samples.iter().map(|sample| sample.now / sample.full * 100.0).sum::<f64>() / samples.len() as f64
+    };
+
+    Some(Reading {
+        capacity: capacity.min(100.0),
+        status: combined_status(&samples),
+    })
+}
+
+fn combined_status(samples: &[Sample]) -> Status {
+    let statuses: Vec<Status> = samples.iter().map(|sample| sample.status).collect();
+
+    if statuses.contains(&Status::Charging) {
+        Status::Charging
+    } else if statuses.contains(&Status::Discharging) {
+        Status::Discharging
+    } else if statuses.iter().all(|status| *status == Status::Full) {
+        Status::Full
+    } else if statuses.contains(&Status::NotCharging) {
+        Status::NotCharging
+    } else {
+        Status::Unknown
+    }
+}
+
+pub struct Battery {
+    config: BatteryConfig,
+    poller: Poller<Option<Reading>>,
+    reading: Option<Reading>,
+    text: String,
+}
+
+impl Battery {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: BatteryConfig = section.clone().try_into()?;
+        let only = config.battery.clone();
+        let poller = Poller::spawn(Duration::from_secs_f64(config.interval.max(0.1)), waker.clone(), move || {
+            read(only.as_deref())
+        });
+
+        Ok(Box::new(Battery { config, poller, reading: None, text: String::new() }))
+    }
+
+    fn format(&self, reading: Option<Reading>) -> String {
+        let reading = match reading {
+            Some(reading) => reading,
+            None => return self.config.format_missing.clone(),
+        };
+
+        let template = match (&self.config.format_charging, reading.status) {
+            (Some(format), Status::Charging) => format,
+            _ => &self.config.format,
+        };
+
+        format::placeholders(template, |key| match key {
+            "capacity" => Some(format!("{:.0}", reading.capacity)),
+            "status" => Some(reading.status.label().to_string()),
+            _ => None,
+        })
+    }
+
+    fn color(&self, ctx: &RenderCtx) -> Color {
+        let default = self.config.color.unwrap_or_else(|| ctx.foreground());
+
+        match self.reading {
+            Some(reading) if reading.status != Status::Charging => {
+                if reading.capacity <= self.config.critical {
+                    self.config.critical_color
+                } else if reading.capacity <= self.config.warning {
+                    self.config.warning_color
+                } else {
+                    default
+                }
+            },
+            _ => default,
+        }
+    }
+}
+
+impl Widget for Battery {
+    fn update(&mut self) -> bool {
+        let reading = match self.poller.take() {
+            Some(reading) => reading,
+            None => return false,
+        };
+        let text = self.format(reading);
+        if reading == self.reading && text == self.text {
+            return false;
+        }
+
+        self.reading = reading;
+        self.text = text;
+        true
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.reading.map(|reading| reading.capacity)
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = self.color(ctx);
+        let value = self.reading.map(|reading| reading.capacity as f32 / 100.0);
+        match (self.config.display, value) {
+            (Display::Bar, Some(value)) => meter::draw_bar(ctx, value, color, &self.config.bar),
+            (Display::Ring, Some(value)) => meter::draw_ring(ctx, value, color, &self.config.ring),
+            _ if self.text.is_empty() => (),
+            _ => ctx.text(&self.text, color),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(now: f64, full: f64, unit: Unit, status: Status) -> Sample {
+        Sample { now, full, unit, status }
+    }
+
+    // A battery directory as the kernel lays it out under power_supply.
+    fn battery_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rustybar-battery-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&path).unwrap();
+        for (file, contents) in files {
+            std::fs::write(path.join(file), format!("{}\n", contents)).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn batteries_read_energy_then_charge_then_capacity() {
+        let energy = battery_dir("energy", &[("status", "Discharging"), ("energy_now", "30000000"),
+            ("energy_full", "60000000"), ("charge_now", "1"), ("charge_full", "2"), ("capacity", "49")]);
+        let charge = battery_dir("charge", &[("status", "Not charging"), ("charge_now", "1000"),
+            ("charge_full", "4000"), ("capacity", "25")]);
+        let capacity = battery_dir("capacity", &[("capacity", "80")]);
+        let empty = battery_dir("empty", &[("status", "Full")]);
+
+        let readings: Vec<_> = [&energy, &charge, &capacity, &empty].iter()
+            .map(|path| read_battery(path).map(|sample| (sample.now, sample.full, sample.unit, sample.status)))
+            .collect();
+        for path in [energy, charge, capacity, empty] {
+            std::fs::remove_dir_all(path).unwrap();
+        }
+
+        assert_eq!(readings, [
+            Some((30000000.0, 60000000.0, Unit::Energy, Status::Discharging)),
+            Some((1000.0, 4000.0, Unit::Charge, Status::NotCharging)),
+            Some((80.0, 100.0, Unit::Capacity, Status::Unknown)),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn batteries_in_the_same_unit_count_by_what_they_hold() {
+        let reading = combine(vec![
+            sample(10.0, 20.0, Unit::Energy, Status::Discharging),
+            sample(50.0, 60.0, Unit::Energy, Status::Discharging),
+        ]);
+        assert_eq!(reading, Some(Reading { capacity: 75.0, status: Status::Discharging }));
+    }
+
+    #[test]
+    fn batteries_in_different_units_count_the_same() {
+        let reading = combine(vec![
+            sample(10.0, 20.0, Unit::Energy, Status::Full),
+            sample(100.0, 100.0, Unit::Capacity, Status::Full),
+        ]);
+        assert_eq!(reading, Some(Reading { capacity: 75.0, status: Status::Full }));
+    }
+
+    #[test]
+    fn batteries_without_a_full_charge_are_left_out() {
+        assert_eq!(combine(vec![sample(10.0, 0.0, Unit::Charge, Status::Unknown)]), None);
+        assert_eq!(combine(Vec::new()), None);
+        let reading = combine(vec![sample(110.0, 100.0, Unit::Capacity, Status::Full)]);
+        assert_eq!(reading.map(|reading| reading.capacity), Some(100.0));
+    }
+
+    #[test]
+    fn charging_wins_over_discharging_over_the_rest() {
+        let status = |statuses: &[Status]| combined_status(&statuses.iter()
+            .map(|status| sample(1.0, 1.0, Unit::Energy, *status))
+            .collect::<Vec<_>>());
+
+        assert_eq!(status(&[Status::Discharging, Status::Charging]), Status::Charging);
+        assert_eq!(status(&[Status::Full, Status::Discharging]), Status::Discharging);
+        assert_eq!(status(&[Status::Full, Status::Full]), Status::Full);
+        assert_eq!(status(&[Status::Full, Status::NotCharging]), Status::NotCharging);
+        assert_eq!(status(&[Status::Full, Status::Unknown]), Status::Unknown);
+        assert_eq!(Status::from_sysfs("Not charging"), Status::NotCharging);
+        assert_eq!(Status::from_sysfs("Weird"), Status::Unknown);
+    }
+}
@@ -0,0 +1,304 @@
+use serde::Deserialize;
+use std::cell::Cell;
+use std::time::Instant;
+use crate::Error;
+use crate::bar::Length;
+use crate::color::Color;
+use crate::render::{Border, Primitive, RenderCtx};
+use super::{Button, Scroll, Widget};
+
+// Styling shared by every module, read from the `block` table of its
+// section so it can't clash with the widget's own options. The padding can
+// also be a percentage of the block's height or given in ems of the font
+// size, like "0.5em":
+//
+//     [module.clock.block]
+//     background = "#333333"
+//     padding = 6
+//     corner-radius = 4
+//     underline = { color = "#5588ff", width = 2 }
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct BlockStyle {
+    background: Option<Color>,
+    padding: Length,
+    margin: f32,
+    vertical_margin: f32,
+    border_width: f32,
+    border_color: Option<Color>,
+    corner_radius: f32,
+    underline: Option<Decoration>,
+    overline: Option<Decoration>,
+}
+
+// A line along the bottom or top edge of the block, in the foreground color
+// unless set.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct Decoration {
+    color: Option<Color>,
+    width: f32,
+}
+
+impl Default for Decoration {
+    fn default() -> Decoration {
+        Decoration { color: None, width: 2.0 }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Section {
+    pub block: Option<BlockStyle>,
+    states: Vec<State>,
+    smooth_states: bool,
+}
+
+impl Section {
+    pub fn states(&self) -> States {
+        States { states: self.states.clone(), smooth: self.smooth_states }
+    }
+}
+
+// Colors a module takes on while the value its widget shows is in a range,
+// both ends included. These are read from the module's own section:
+//
+//     [module.cpu]
+//     states = [
+//         { above = 50, color = "#ffaa00" },
+//         { above = 90, color = "#ff3333", underline = "#ff3333" },
+//     ]
+//
+// Later states override earlier ones. With `smooth-states = true` the
+// colors are instead blended between the `above` values of neighbouring
+// states.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+struct State {
+    above: Option<f64>,
+    below: Option<f64>,
+    color: Option<Color>,
+    background: Option<Color>,
+    underline: Option<Color>,
+}
+
+impl State {
+    fn matches(&self, value: f64) -> bool {
+        self.above.is_none_or(|above| value >= above) && self.below.is_none_or(|below| value <= below)
+    }
+
+    fn style(&self) -> StateStyle {
+        StateStyle { color: self.color, background: self.background, underline: self.underline }
+    }
+}
+
+// The colors a state changes, each one only if it's set.
+#[derive(Debug, Clone, Copy, Default)]
+struct StateStyle {
+    color: Option<Color>,
+    background: Option<Color>,
+    underline: Option<Color>,
+}
+
+impl StateStyle {
+    fn or(self, under: StateStyle) -> StateStyle {
+        StateStyle {
+            color: self.color.or(under.color),
+            background: self.background.or(under.background),
+            underline: self.underline.or(under.underline),
+        }
+    }
+
+    // Colors only one side sets are kept until the other side is reached.
+    fn mix(self, other: StateStyle, t: f32) -> StateStyle {
+        let mix = |a: Option<Color>, b: Option<Color>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.mix(&b, t)),
+            (a, _) => a,
+        };
+
+        StateStyle {
+            color: mix(self.color, other.color),
+            background: mix(self.background, other.background),
+            underline: mix(self.underline, other.underline),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct States {
+    states: Vec<State>,
+    smooth: bool,
+}
+
+impl States {
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    fn style(&self, value: Option<f64>) -> StateStyle {
+        let value = match value {
+            Some(value) => value,
+            None => return StateStyle::default(),
+        };
+
+        if self.smooth {
+            return self.blended(value);
+        }
+
+        self.states.iter()
+            .filter(|state| state.matches(value))
+            .fold(StateStyle::default(), |style, state| state.style().or(style))
+    }
+
+    // Below the first stop no state applies, past the last one it's used
+    // as is.
+    fn blended(&self, value: f64) -> StateStyle {
+        let mut stops: Vec<(f64, StateStyle)> = self.states.iter()
+            .filter_map(|state| Some((state.above?, state.style())))
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        match stops.iter().position(|(above, _)| *above > value) {
+            Some(0) => StateStyle::default(),
+            Some(index) => {
+                let (start, from) = stops[index - 1];
+                let (end, to) = stops[index];
+                from.mix(to, ((value - start) / (end - start)) as f32)
+            },
+            None => stops.last().map(|(_, style)| *style).unwrap_or_default(),
+        }
+    }
+}
+
+// Draws a widget on top of a background block, offsetting it by the margin,
+// border and padding around it.
+pub struct Block {
+    widget: Box<dyn Widget>,
+    style: BlockStyle,
+    states: States,
+    // The padding in logical pixels as of the last render, for passing on
+    // clicks.
+    padding: Cell<f32>,
+}
+
+impl Block {
+    pub fn new(widget: Box<dyn Widget>, style: BlockStyle, states: States) -> Block {
+        Block { widget, style, states, padding: Cell::new(0.0) }
+    }
+
+    fn border(&self) -> Option<Border> {
+        match self.style.border_color {
+            Some(color) if self.style.border_width > 0.0 => Some(Border { width: self.style.border_width, color }),
+            _ => None,
+        }
+    }
+
+    fn content_offset(&self) -> f32 {
+        let border = self.border().map(|border| border.width).unwrap_or(0.0);
+        self.style.margin + border + self.padding.get()
+    }
+}
+
+impl Widget for Block {
+    fn update(&mut self) -> bool {
+        self.widget.update()
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let style = &self.style;
+        let height = (ctx.height() - 2.0 * style.vertical_margin).max(0.0);
+
+        let state = self.states.style(self.widget.value());
+        let mut content = ctx.child(height);
+        if let Some(color) = state.color {
+            content.set_foreground(color);
+        }
+        self.widget.render(&mut content);
+
+        // Widgets that hide themselves by drawing nothing don't leave an
+        // empty block behind.
+        if content.width() <= 0.0 {
+            return;
+        }
+
+        self.padding.set(style.padding.resolve(height as f64, ctx.font_size() as f64) as f32);
+        let offset = self.content_offset();
+        let width = content.width() + 2.0 * (offset - style.margin);
+        let background = state.background.or(style.background).unwrap_or(Color::TRANSPARENT);
+        let (x, y) = (style.margin, style.vertical_margin);
+
+        ctx.push(Primitive::Rect {
+            x,
+            y,
+            width,
+            height,
+            radius: style.corner_radius,
+            color: background,
+            border: self.border(),
+        });
+
+        let foreground = ctx.foreground();
+        let underline = match (style.underline, state.underline) {
+            (Some(underline), Some(color)) => Some(Decoration { color: Some(color), ..underline }),
+            (None, Some(color)) => Some(Decoration { color: Some(color), ..Decoration::default() }),
+            (underline, None) => underline,
+        };
+        if let Some(underline) = underline {
+            ctx.rect(x, y + height - underline.width, width, underline.width, underline.color.unwrap_or(foreground));
+        }
+        if let Some(overline) = style.overline {
+            ctx.rect(x, y, width, overline.width, overline.color.unwrap_or(foreground));
+        }
+
+        ctx.append(content, offset, y);
+        ctx.advance(style.margin);
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        self.widget.next_update()
+    }
+
+    fn animating(&self) -> bool {
+        self.widget.animating()
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        let x = x - self.content_offset();
+        self.widget.click(x, button)
+    }
+
+    fn scroll(&mut self, x: f32, direction: Scroll) -> bool {
+        let x = x - self.content_offset();
+        self.widget.scroll(x, direction)
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        self.widget.action(name)
+    }
+
+    fn tooltip(&self, x: f32) -> Option<String> {
+        self.widget.tooltip(x - self.content_offset())
+    }
+
+    fn popup(&self) -> Option<String> {
+        self.widget.popup()
+    }
+
+    fn popup_click(&mut self, line: usize, button: Button) -> bool {
+        self.widget.popup_click(line, button)
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.widget.value()
+    }
+
+    fn background(&self) -> Option<Color> {
+        self.states.style(self.widget.value()).background
+            .or(self.style.background)
+            .or_else(|| self.widget.background())
+    }
+
+    fn click_through(&self) -> bool {
+        self.widget.click_through()
+    }
+}
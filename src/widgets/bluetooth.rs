@@ -0,0 +1,198 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, Button, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::bluez::{self, Control, Device, Status, Watcher};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct BluetoothConfig {
+    format: String,
+    format_connected: String,
+    format_off: String,
+    format_missing: String,
+    color: Option<Color>,
+    off_color: Color,
+}
+
+impl Default for BluetoothConfig {
+    fn default() -> BluetoothConfig {
+        BluetoothConfig {
+            format: "on".to_string(),
+            format_connected: "{devices}".to_string(),
+            format_off: "off".to_string(),
+            format_missing: String::new(),
+            color: None,
+            off_color: Color::from_str("#888888").unwrap(),
+        }
+    }
+}
+
+fn device_name(device: &Device) -> String {
+    match device.battery {
+        Some(battery) => format!("{} {}%", device.name, battery),
+        None => device.name.clone(),
+    }
+}
+
+pub struct Bluetooth {
+    config: BluetoothConfig,
+    watcher: Watcher,
+    status: Status,
+    // Whether the popup listing the paired devices is open.
+    devices: bool,
+    text: String,
+}
+
+impl Bluetooth {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        Ok(Box::new(Bluetooth {
+            config: section.clone().try_into()?,
+            watcher: Watcher::spawn(waker.clone()),
+            status: Status::default(),
+            devices: false,
+            text: String::new(),
+        }))
+    }
+
+    fn connected(&self) -> impl Iterator<Item = &Device> {
+        self.status.devices.iter().filter(|device| device.connected)
+    }
+
+    fn format(&self) -> String {
+        let adapter = match &self.status.adapter {
+            Some(adapter) => adapter,
+            None => return self.config.format_missing.clone(),
+        };
+
+        let connected: Vec<&Device> = self.connected().collect();
+        let template = match (adapter.powered, connected.is_empty()) {
+            (false, _) => &self.config.format_off,
+            (true, true) => &self.config.format,
+            (true, false) => &self.config.format_connected,
+        };
+
+        format::placeholders(template, |key| match key {
+            "adapter" => Some(adapter.name.clone()),
+            "devices" => Some(connected.iter().map(|device| device_name(device)).collect::<Vec<_>>().join(", ")),
+            "count" => Some(connected.len().to_string()),
+            "battery" => Some(self.battery().map(|battery| battery.to_string()).unwrap_or_default()),
+            _ => None,
+        })
+    }
+
+    // The lowest battery of the connected devices that report one.
+    fn battery(&self) -> Option<u8> {
+        self.connected().filter_map(|device| device.battery).min()
+    }
+
+    fn toggle_power(&self) {
+        if let Some(adapter) = &self.status.adapter {
+            bluez::control(&adapter.path, Control::Power(!adapter.powered));
+        }
+    }
+}
+
+impl Widget for Bluetooth {
+    fn update(&mut self) -> bool {
+        let status = match self.watcher.take() {
+            Some(status) => status,
+            None => return false,
+        };
+
+        if status == self.status {
+            return false;
+        }
+
+        self.status = status;
+        self.text = self.format();
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match &self.status.adapter {
+            Some(adapter) if !adapter.powered => self.config.off_color,
+            _ => self.config.color.unwrap_or_else(|| ctx.foreground()),
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        match button {
+            Button::Left => self.toggle_power(),
+            Button::Right => self.devices = !self.devices,
+            Button::Middle => (),
+        }
+
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "toggle-power" => self.toggle_power(),
+            "devices" => self.devices = !self.devices,
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(false)
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let adapter = self.status.adapter.as_ref()?;
+        let state = if adapter.powered { "on" } else { "off" };
+        let mut lines = vec![format!("<span foreground=\"#888888\">{} ({})</span>", markup::escape(&adapter.name), state)];
+        lines.extend(self.connected().map(|device| markup::escape(&device_name(device))));
+
+        Some(lines.join("\n"))
+    }
+
+    // The adapter's power switch on top, then every paired device, which a
+    // click connects or disconnects.
+    fn popup(&self) -> Option<String> {
+        let adapter = self.status.adapter.as_ref().filter(|_| self.devices)?;
+
+        let power = format!("{} Powered", if adapter.powered { "☑" } else { "☐" });
+        let mut lines = vec![power, "<span alpha=\"40%\">──────</span>".to_string()];
+        lines.extend(self.status.devices.iter().map(|device| {
+            let line = format!("{} {}", if device.connected { "◉" } else { "○" }, markup::escape(&device_name(device)));
+            match adapter.powered {
+                true => line,
+                false => format!("<span alpha=\"50%\">{}</span>", line),
+            }
+        }));
+        if self.status.devices.is_empty() {
+            lines.push("<span alpha=\"50%\">No paired devices</span>".to_string());
+        }
+
+        Some(lines.join("\n"))
+    }
+
+    fn popup_click(&mut self, line: usize, _button: Button) -> bool {
+        let adapter = match &self.status.adapter {
+            Some(adapter) => adapter,
+            None => return false,
+        };
+
+        if line == 0 {
+            self.toggle_power();
+        } else if let Some(device) = line.checked_sub(2).and_then(|index| self.status.devices.get(index)) {
+            if adapter.powered {
+                let control = match device.connected {
+                    true => Control::Disconnect(device.path.clone()),
+                    false => Control::Connect(device.path.clone()),
+                };
+                bluez::control(&adapter.path, control);
+            }
+        }
+
+        false
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.battery().map(f64::from)
+    }
+}
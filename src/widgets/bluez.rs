@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use zbus::MatchRule;
+use zbus::blocking::{Connection, Proxy};
+use zbus::message::Type as MessageType;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use crate::Error;
+use super::{worker, Waker};
+use super::worker::Signals;
+
+// BlueZ exports every adapter and device as an object below /org/bluez on
+// the system bus, all of them listed by its object manager.
+const BUS_NAME: &str = "org.bluez";
+const ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+const BATTERY_INTERFACE: &str = "org.bluez.Battery1";
+
+type Objects = HashMap<OwnedObjectPath, HashMap<String, HashMap<String, OwnedValue>>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adapter {
+    pub path: String,
+    pub name: String,
+    pub powered: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub path: String,
+    pub name: String,
+    pub connected: bool,
+    // Percent, for devices that report it.
+    pub battery: Option<u8>,
+}
+
+// The first adapter with its paired devices, sorted by name. Without an
+// adapter, or with BlueZ not running, there's nothing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Status {
+    pub adapter: Option<Adapter>,
+    pub devices: Vec<Device>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Control {
+    Power(bool),
+    Connect(String),
+    Disconnect(String),
+}
+
+fn proxy<'a>(connection: &Connection, path: &'a str, interface: &'a str) -> Result<Proxy<'a>, Error> {
+    Ok(zbus::blocking::proxy::Builder::new(connection)
+        .destination(BUS_NAME)?
+        .path(path)?
+        .interface(interface)?
+        .cache_properties(CacheProperties::No)
+        .build()?)
+}
+
+fn property<T: TryFrom<OwnedValue>>(properties: &HashMap<String, OwnedValue>, name: &str) -> Option<T> {
+    properties.get(name)
+        .and_then(|value| value.try_clone().ok())
+        .and_then(|value| T::try_from(value).ok())
+}
+
+async fn query(connection: &zbus::Connection) -> Result<Status, Error> {
+    let manager: zbus::Proxy = zbus::proxy::Builder::new(connection)
+        .destination(BUS_NAME)?
+        .path("/")?
+        .interface("org.freedesktop.DBus.ObjectManager")?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+    let objects: Objects = manager.call("GetManagedObjects", &()).await?;
+
+    Ok(status(&objects))
+}
+
+fn status(objects: &Objects) -> Status {
+    let mut adapters: Vec<Adapter> = objects.iter()
+        .filter_map(|(path, interfaces)| {
+            let adapter = interfaces.get(ADAPTER_INTERFACE)?;
+            Some(Adapter {
+                path: path.to_string(),
+                name: property(adapter, "Alias").or_else(|| property(adapter, "Name")).unwrap_or_default(),
+                powered: property(adapter, "Powered").unwrap_or(false),
+            })
+        })
+        .collect();
+    adapters.sort_by(|a, b| a.path.cmp(&b.path));
+    let adapter = match adapters.into_iter().next() {
+        Some(adapter) => adapter,
+        None => return Status::default(),
+    };
+
+    let mut devices: Vec<Device> = objects.iter()
+        .filter_map(|(path, interfaces)| {
+            let device = interfaces.get(DEVICE_INTERFACE)?;
+            let owner: OwnedObjectPath = property(device, "Adapter")?;
+            if owner.as_str() != adapter.path || !property(device, "Paired").unwrap_or(false) {
+                return None;
+            }
+
+            Some(Device {
+                path: path.to_string(),
+                name: property(device, "Alias").or_else(|| property(device, "Name")).unwrap_or_default(),
+                connected: property(device, "Connected").unwrap_or(false),
+                battery: interfaces.get(BATTERY_INTERFACE).and_then(|battery| property(battery, "Percentage")),
+            })
+        })
+        .collect();
+    devices.sort_by_key(|device| device.name.to_lowercase());
+
+    Status { adapter: Some(adapter), devices }
+}
+
+async fn watch(shared: Weak<Mutex<Option<Status>>>, waker: Waker) -> Result<(), Error> {
+    let connection = zbus::Connection::system().await?;
+
+    let signal = |interface, member| -> Result<MatchRule<'static>, Error> {
+        Ok(MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .sender(BUS_NAME)?
+            .interface(interface)?
+            .member(member)?
+            .build())
+    };
+    let owners = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender("org.freedesktop.DBus")?
+        .interface("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .arg(0, BUS_NAME)?
+        .build();
+    let rules = [
+        signal("org.freedesktop.DBus.Properties", "PropertiesChanged")?,
+        signal("org.freedesktop.DBus.ObjectManager", "InterfacesAdded")?,
+        signal("org.freedesktop.DBus.ObjectManager", "InterfacesRemoved")?,
+        owners,
+    ];
+
+    let mut signals = Signals::subscribe(&connection, rules, 16).await?;
+
+    // BlueZ going away leaves nothing to show until it's back.
+    loop {
+        let status = query(&connection).await.unwrap_or_default();
+        match shared.upgrade() {
+            Some(state) => *state.lock().unwrap() = Some(status),
+            None => return Ok(()),
+        }
+        waker.wake();
+
+        signals.next().await?;
+    }
+}
+
+// Keeps track of the adapter and its paired devices, following their
+// property changes and devices being added or removed, until it's dropped.
+pub struct Watcher {
+    state: Arc<Mutex<Option<Status>>>,
+    _watch: worker::Task,
+}
+
+impl Watcher {
+    pub fn spawn(waker: Waker) -> Watcher {
+        let state = Arc::new(Mutex::new(None));
+        let shared = Arc::downgrade(&state);
+
+        let watch = worker::watch(Duration::from_secs(5), "bluetooth".to_string(),
+            move || watch(shared.clone(), waker.clone()));
+
+        Watcher { state, _watch: watch }
+    }
+
+    // Returns the status if it changed since the last call.
+    pub fn take(&self) -> Option<Status> {
+        self.state.lock().unwrap().take()
+    }
+}
+
+fn send_control(adapter: &str, control: &Control) -> Result<(), Error> {
+    let connection = Connection::system()?;
+
+    match control {
+        Control::Power(powered) => proxy(&connection, adapter, ADAPTER_INTERFACE)?.set_property("Powered", *powered)?,
+        Control::Connect(device) => proxy(&connection, device, DEVICE_INTERFACE)?.call_method("Connect", &()).map(drop)?,
+        Control::Disconnect(device) => proxy(&connection, device, DEVICE_INTERFACE)?.call_method("Disconnect", &()).map(drop)?,
+    }
+
+    Ok(())
+}
+
+pub fn control(adapter: &str, control: Control) {
+    let adapter = adapter.to_string();
+    worker::spawn_control("control bluetooth".to_string(), move || send_control(&adapter, &control));
+}
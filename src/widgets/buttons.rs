@@ -0,0 +1,131 @@
+use std::cell::{Cell, RefCell};
+use std::time::Instant;
+use crate::animation::{Easing, Tween};
+use crate::color::Color;
+use crate::render::RenderCtx;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ButtonStyle {
+    pub padding: f32,
+    pub corner_radius: f32,
+    // Thickness of the underline and overline.
+    pub line_width: f32,
+}
+
+impl ButtonStyle {
+    pub fn width(&self, ctx: &RenderCtx, label: &str) -> f32 {
+        ctx.measure(label) + 2.0 * self.padding
+    }
+}
+
+// Lines drawn along the bottom and top edge of a single button, such as an
+// underline marking the focused workspace.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lines {
+    pub underline: Option<Color>,
+    pub overline: Option<Color>,
+}
+
+// A row of labelled buttons that remembers where each one was drawn, for
+// widgets that map clicks back to one of their entries.
+#[derive(Default)]
+pub struct Buttons {
+    extents: RefCell<Vec<(f32, f32)>>,
+}
+
+impl Buttons {
+    // Must be called before the first button of every render.
+    pub fn clear(&self) {
+        self.extents.borrow_mut().clear();
+    }
+
+    pub fn draw(&self, ctx: &mut RenderCtx, label: &str, color: Color, background: Option<Color>, lines: Lines,
+        style: &ButtonStyle) {
+        let start = ctx.width();
+        let width = style.width(ctx, label);
+
+        ctx.advance(width);
+        Buttons::highlight(ctx, (start, start + width), background, lines, style);
+        ctx.text_at(start + style.padding, label, color);
+        self.push(start, start + width);
+    }
+
+    // Draws the background and lines of a button across the given extent.
+    pub fn highlight(ctx: &mut RenderCtx, extent: (f32, f32), background: Option<Color>, lines: Lines,
+        style: &ButtonStyle) {
+        let (start, width) = (extent.0, extent.1 - extent.0);
+        if let Some(background) = background {
+            ctx.rounded_rect(start, 0.0, width, ctx.height(), style.corner_radius, background);
+        }
+        if let Some(underline) = lines.underline {
+            ctx.rect(start, ctx.height() - style.line_width, width, style.line_width, underline);
+        }
+        if let Some(overline) = lines.overline {
+            ctx.rect(start, 0.0, width, style.line_width, overline);
+        }
+    }
+
+    // Records a button for content the widget drew itself.
+    pub fn push(&self, start: f32, end: f32) {
+        self.extents.borrow_mut().push((start, end));
+    }
+
+    pub fn extent(&self, index: usize) -> (f32, f32) {
+        self.extents.borrow()[index]
+    }
+
+    // Index of the button drawn under x, in drawing order.
+    pub fn at(&self, x: f32) -> Option<usize> {
+        self.extents.borrow().iter().position(|(start, end)| x >= *start && x < *end)
+    }
+}
+
+// Slides the highlight of the active button over from where it was drawn
+// before, rather than having it jump there.
+pub struct Indicator {
+    duration: f64,
+    easing: Easing,
+    // The extent of the active button as of the last render, and where the
+    // highlight started from while it's moving.
+    active: Cell<Option<(f32, f32)>>,
+    slide: Cell<Option<((f32, f32), Tween)>>,
+}
+
+impl Indicator {
+    pub fn new(duration: f64, easing: Easing) -> Indicator {
+        Indicator { duration, easing, active: Cell::new(None), slide: Cell::new(None) }
+    }
+
+    fn at(from: (f32, f32), to: (f32, f32), tween: &Tween, now: Instant) -> (f32, f32) {
+        let t = tween.value(now) as f32;
+        (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+    }
+
+    // Where to draw the highlight of the active button, which is at the
+    // given extent, while it's still on its way there.
+    pub fn place(&self, extent: (f32, f32)) -> Option<(f32, f32)> {
+        let now = Instant::now();
+        let previous = self.active.replace(Some(extent));
+        if self.duration > 0.0 {
+            if let Some(previous) = previous.filter(|previous| *previous != extent) {
+                let from = match self.slide.get() {
+                    Some((from, tween)) => Indicator::at(from, previous, &tween, now),
+                    None => previous,
+                };
+                self.slide.set(Some((from, Tween::new(0.0, 1.0, self.duration, self.easing))));
+            }
+        }
+
+        match self.slide.get() {
+            Some((from, tween)) if !tween.is_done(now) => Some(Indicator::at(from, extent, &tween, now)),
+            _ => {
+                self.slide.set(None);
+                None
+            },
+        }
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.slide.get().is_some()
+    }
+}
@@ -0,0 +1,317 @@
+use chrono::{Datelike, Local, NaiveDate, Timelike, Weekday};
+use chrono::format::{Fixed, Item, Numeric, StrftimeItems};
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{Button, Scroll, Waker, Widget};
+use super::actions::UnknownActionError;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum WeekStart {
+    Monday,
+    Sunday,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct ClockConfig {
+    format: String,
+    // The tooltip, empty for none.
+    tooltip_format: String,
+    color: Option<Color>,
+    // Whether a left click opens the calendar.
+    calendar: bool,
+    week_start: WeekStart,
+    today_color: Color,
+    today_background: Color,
+}
+
+impl Default for ClockConfig {
+    fn default() -> ClockConfig {
+        ClockConfig {
+            format: "%H:%M".to_string(),
+            tooltip_format: "%A, %-d %B %Y".to_string(),
+            color: None,
+            calendar: true,
+            week_start: WeekStart::Monday,
+            today_color: Color::from_str("#222222").unwrap(),
+            today_background: Color::from_str("#dddddd").unwrap(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FormatError(String);
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid time format '{}'", self.0)
+    }
+}
+
+impl std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+pub struct Clock {
+    config: ClockConfig,
+    show_seconds: bool,
+    text: String,
+    date: String,
+    // The first day of the month the open calendar shows.
+    calendar: Option<NaiveDate>,
+    next_update: Instant,
+}
+
+impl Clock {
+    pub fn build(section: &toml::Value, _waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: ClockConfig = section.clone().try_into()?;
+        // chrono panics on specifiers it doesn't know once it formats.
+        let show_seconds = shows_seconds(&items(&config.format)?);
+        items(&config.tooltip_format)?;
+
+        Ok(Box::new(Clock {
+            config,
+            show_seconds,
+            text: String::new(),
+            date: String::new(),
+            calendar: None,
+            next_update: Instant::now(),
+        }))
+    }
+
+    fn toggle_calendar(&mut self) {
+        self.calendar = match self.calendar {
+            Some(_) => None,
+            None => month_of(Local::now().date_naive()),
+        };
+    }
+
+    // Moves the open calendar by a number of months.
+    fn turn(&mut self, months: i32) {
+        if let Some(first) = self.calendar {
+            let index = first.year() * 12 + first.month0() as i32 + months;
+            self.calendar = NaiveDate::from_ymd_opt(index.div_euclid(12), index.rem_euclid(12) as u32 + 1, 1)
+                .or(self.calendar);
+        }
+    }
+}
+
+fn items(format: &str) -> Result<Vec<Item<'_>>, Error> {
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    match items.contains(&Item::Error) {
+        true => Err(Box::new(FormatError(format.to_string()))),
+        false => Ok(items),
+    }
+}
+
+// Composite specifiers such as %T come apart into their fields, so only
+// the fields that change every second need looking for. The internal ones
+// are the fractions of %3f and friends.
+fn shows_seconds(items: &[Item]) -> bool {
+    items.iter().any(|item| matches!(item,
+        Item::Numeric(Numeric::Second | Numeric::Nanosecond | Numeric::Timestamp, _)
+        | Item::Fixed(Fixed::Nanosecond | Fixed::Nanosecond3 | Fixed::Nanosecond6 | Fixed::Nanosecond9
+            | Fixed::RFC2822 | Fixed::RFC3339 | Fixed::Internal(_))))
+}
+
+fn month_of(date: NaiveDate) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(date.year(), date.month(), 1)
+}
+
+// Digits are as wide as a figure space in nearly every font, padding with
+// it keeps the columns of the calendar lined up even in proportional fonts.
+const FIGURE_SPACE: char = '\u{2007}';
+
+// A week to a line under the month and the names of the days, with today
+// highlighted.
+fn calendar(first: NaiveDate, today: NaiveDate, config: &ClockConfig) -> String {
+    let start = match config.week_start {
+        WeekStart::Monday => Weekday::Mon,
+        WeekStart::Sunday => Weekday::Sun,
+    };
+    let names: Vec<String> = (0..7)
+        .map(|offset| {
+            let day = (0..offset).fold(start, |day, _| day.succ());
+            day.to_string()[..2].to_string()
+        })
+        .collect();
+
+    let title = first.format("%B %Y").to_string();
+    let width = names.len() * 3 - 1;
+    let indent = width.saturating_sub(title.chars().count()) / 2;
+    let mut lines = vec![
+        format!("{}{}", FIGURE_SPACE.to_string().repeat(indent), title),
+        format!("<span foreground=\"#888888\">{}</span>", names.join(&FIGURE_SPACE.to_string())),
+    ];
+
+    let leading = (first.weekday().num_days_from_monday() + 7 - start.num_days_from_monday()) % 7;
+    let mut week: Vec<String> = (0..leading).map(|_| FIGURE_SPACE.to_string().repeat(2)).collect();
+    let days = first.iter_days().take_while(|day| day.month() == first.month());
+    for day in days {
+        let number = format!("{:>2}", day.day()).replace(' ', &FIGURE_SPACE.to_string());
+        week.push(if day == today {
+            format!("<span foreground=\"{}\" background=\"{}\">{}</span>",
+                config.today_color, config.today_background, number)
+        } else {
+            number
+        });
+
+        if week.len() == 7 {
+            lines.push(week.join(&FIGURE_SPACE.to_string()));
+            week.clear();
+        }
+    }
+    if !week.is_empty() {
+        lines.push(week.join(&FIGURE_SPACE.to_string()));
+    }
+
+    lines.join("\n")
+}
+
+impl Widget for Clock {
+    fn update(&mut self) -> bool {
+        if Instant::now() < self.next_update {
+            return false;
+        }
+
+        let now = Local::now();
+        let into_second = Duration::from_nanos(now.nanosecond() as u64 % 1_000_000_000);
+        let until_next = if self.show_seconds {
+            Duration::from_secs(1) - into_second
+        } else {
+            Duration::from_secs(60 - now.second() as u64) - into_second
+        };
+        self.next_update = Instant::now() + until_next;
+
+        self.date = now.format(&self.config.tooltip_format).to_string();
+        let text = now.format(&self.config.format).to_string();
+        if text == self.text {
+            return false;
+        }
+
+        self.text = text;
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = self.config.color.unwrap_or_else(|| ctx.foreground());
+        ctx.text(&self.text, color);
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        Some(self.next_update)
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        if button == Button::Left && self.config.calendar {
+            self.toggle_calendar();
+        }
+
+        false
+    }
+
+    // Scrolling up goes back in time.
+    fn scroll(&mut self, _x: f32, direction: Scroll) -> bool {
+        self.turn(if direction == Scroll::Up { -1 } else { 1 });
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "calendar" => self.toggle_calendar(),
+            "next-month" => self.turn(1),
+            "previous-month" => self.turn(-1),
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(false)
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        Some(markup::escape(&self.date))
+    }
+
+    fn popup(&self) -> Option<String> {
+        Some(calendar(self.calendar?, Local::now().date_naive(), &self.config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(format: &str) -> bool {
+        shows_seconds(&items(format).unwrap())
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    // The calendar with figure spaces made visible and the names of the
+    // days and today in brackets.
+    fn plain(first: NaiveDate, today: NaiveDate, week_start: WeekStart) -> Vec<String> {
+        let config = ClockConfig { week_start, ..ClockConfig::default() };
+        let highlight = format!("<span foreground=\"{}\" background=\"{}\">", config.today_color, config.today_background);
+        calendar(first, today, &config)
+            .replace(&highlight, "[")
+            .replace("<span foreground=\"#888888\">", "[")
+            .replace("</span>", "]")
+            .replace(FIGURE_SPACE, "_")
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn formats_with_seconds_update_every_second() {
+        for format in ["%S", "%T", "%H:%M:%S", "%s", "%.3f", "%+", "%c"] {
+            assert!(seconds(format), "{}", format);
+        }
+        for format in ["%H:%M", "%R", "%A, %-d %B %Y", "%I:%M %p", "no time"] {
+            assert!(!seconds(format), "{}", format);
+        }
+    }
+
+    #[test]
+    fn unknown_specifiers_are_errors() {
+        assert!(items("%H:%M").is_ok());
+        assert!(items("%Q").is_err());
+        assert!(items("%").is_err());
+    }
+
+    #[test]
+    fn calendars_start_the_week_as_configured() {
+        assert_eq!(plain(date(2026, 2, 1), date(2026, 2, 11), WeekStart::Monday), [
+            "___February 2026",
+            "[Mo_Tu_We_Th_Fr_Sa_Su]",
+            "___________________1",
+            "_2__3__4__5__6__7__8",
+            "_9_10_[11]_12_13_14_15",
+            "16_17_18_19_20_21_22",
+            "23_24_25_26_27_28",
+        ]);
+
+        assert_eq!(plain(date(2026, 2, 1), date(2026, 3, 1), WeekStart::Sunday), [
+            "___February 2026",
+            "[Su_Mo_Tu_We_Th_Fr_Sa]",
+            "_1__2__3__4__5__6__7",
+            "_8__9_10_11_12_13_14",
+            "15_16_17_18_19_20_21",
+            "22_23_24_25_26_27_28",
+        ]);
+    }
+
+    #[test]
+    fn months_start_on_their_first_day() {
+        assert_eq!(month_of(date(2024, 2, 29)), Some(date(2024, 2, 1)));
+        assert_eq!(month_of(date(2026, 12, 31)), Some(date(2026, 12, 1)));
+    }
+}
@@ -0,0 +1,216 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, Waker, Widget};
+use super::meter::{self, GraphStyle, History};
+use super::worker::Poller;
+
+const FIRST_SAMPLE_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct CpuConfig {
+    format: String,
+    interval: f64,
+    per_core: bool,
+    bar_width: f32,
+    bar_spacing: f32,
+    color: Option<Color>,
+    bar_color: Option<Color>,
+    bar_background: Color,
+    graph: Option<GraphStyle>,
+}
+
+impl Default for CpuConfig {
+    fn default() -> CpuConfig {
+        CpuConfig {
+            format: "CPU {usage}%".to_string(),
+            interval: 2.0,
+            per_core: false,
+            bar_width: 4.0,
+            bar_spacing: 1.0,
+            color: None,
+            bar_color: None,
+            bar_background: Color::from_str("#444444").unwrap(),
+            graph: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Times {
+    idle: u64,
+    total: u64,
+}
+
+impl Times {
+    fn parse(line: &str) -> Option<Times> {
+        let fields: Vec<u64> = line.split_whitespace().skip(1)
+            .map(|field| field.parse())
+            .collect::<Result<_, _>>()
+            .ok()?;
+
+        // user nice system idle iowait irq softirq steal; guest time is
+        // already included in user and nice.
+        let idle = fields.get(3)? + fields.get(4).unwrap_or(&0);
+        let total = fields.iter().take(8).sum();
+
+        Some(Times { idle, total })
+    }
+
+    fn usage_since(&self, previous: &Times) -> f64 {
+        let total = self.total.saturating_sub(previous.total);
+        let idle = self.idle.saturating_sub(previous.idle);
+
+        if total == 0 {
+            0.0
+        } else {
+            (total - idle.min(total)) as f64 / total as f64 * 100.0
+        }
+    }
+}
+
+fn read_times() -> Option<(Times, Vec<Times>)> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let mut lines = stat.lines().filter(|line| line.starts_with("cpu"));
+
+    let overall = Times::parse(lines.next()?)?;
+    let cores = lines.filter_map(Times::parse).collect();
+
+    Some((overall, cores))
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Usage {
+    overall: f64,
+    cores: Vec<f64>,
+}
+
+pub struct Cpu {
+    config: CpuConfig,
+    poller: Poller<Usage>,
+    usage: Usage,
+    history: Option<History>,
+    text: String,
+}
+
+impl Cpu {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: CpuConfig = section.clone().try_into()?;
+        let period = Duration::from_secs_f64(config.interval.max(0.1));
+
+        let mut previous = None;
+        let poller = Poller::spawn(period, waker.clone(), move || {
+            // Usage is a delta between two readings, so the first sample
+            // needs a short baseline before it means anything.
+            if previous.is_none() {
+                previous = read_times();
+                std::thread::sleep(FIRST_SAMPLE_DELAY);
+            }
+
+            let current = read_times();
+            let usage = match (&previous, &current) {
+                (Some((last, last_cores)), Some((now, cores))) => Usage {
+                    overall: now.usage_since(last),
+                    cores: cores.iter().zip(last_cores).map(|(now, last)| now.usage_since(last)).collect(),
+                },
+                _ => Usage::default(),
+            };
+
+            previous = current;
+            usage
+        });
+
+        let history = config.graph.as_ref().map(History::new);
+        Ok(Box::new(Cpu { config, poller, usage: Usage::default(), history, text: String::new() }))
+    }
+}
+
+impl Widget for Cpu {
+    fn update(&mut self) -> bool {
+        let usage = match self.poller.take() {
+            Some(usage) => usage,
+            None => return false,
+        };
+
+        let text = format::placeholders(&self.config.format, |key| match key {
+            "usage" => Some(format!("{:.0}", usage.overall)),
+            _ => key.strip_prefix("core")
+                .and_then(|index| index.parse::<usize>().ok())
+                .and_then(|index| usage.cores.get(index))
+                .map(|core| format!("{:.0}", core)),
+        });
+
+        if let Some(history) = &mut self.history {
+            history.push(usage.overall);
+        }
+
+        let changed = text != self.text || (self.config.per_core && usage != self.usage) || self.history.is_some();
+        self.text = text;
+        self.usage = usage;
+
+        changed
+    }
+
+    fn value(&self) -> Option<f64> {
+        Some(self.usage.overall)
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = self.config.color.unwrap_or_else(|| ctx.foreground());
+        ctx.text(&self.text, color);
+
+        if let (Some(history), Some(style)) = (&self.history, &self.config.graph) {
+            meter::draw_graph(ctx, history, Some(100.0), color, style);
+        }
+
+        if !self.config.per_core {
+            return;
+        }
+
+        let height = ctx.height();
+        let bar_color = self.config.bar_color.unwrap_or(color);
+        for core in &self.usage.cores {
+            ctx.advance(self.config.bar_spacing);
+
+            let x = ctx.width();
+            let filled = height * (*core as f32 / 100.0);
+            ctx.rect(x, 0.0, self.config.bar_width, height - filled, self.config.bar_background);
+            ctx.rect(x, height - filled, self.config.bar_width, filled, bar_color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_count_iowait_as_idle_and_skip_guest_time() {
+        let times = Times::parse("cpu  100 20 30 400 50 6 7 8 90 10").unwrap();
+        assert_eq!((times.idle, times.total), (450, 621));
+
+        let times = Times::parse("cpu3 1 2 3 4").unwrap();
+        assert_eq!((times.idle, times.total), (4, 10));
+    }
+
+    #[test]
+    fn malformed_times_are_skipped() {
+        for line in ["cpu", "cpu 1 2 3", "cpu 1 2 x 4", "cpu 1 2 -3 4"] {
+            assert!(Times::parse(line).is_none(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn usage_is_the_share_of_time_not_idle() {
+        let previous = Times { idle: 100, total: 200 };
+        assert_eq!(Times { idle: 130, total: 300 }.usage_since(&previous), 70.0);
+        assert_eq!(Times { idle: 200, total: 300 }.usage_since(&previous), 0.0);
+        assert_eq!(previous.usage_since(&previous), 0.0);
+        // The counters went backwards, as after a core went offline.
+        assert_eq!(Times { idle: 50, total: 100 }.usage_since(&previous), 0.0);
+    }
+}
@@ -0,0 +1,267 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, Waker, Widget};
+use super::worker::Poller;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ReturnType {
+    Text,
+    // A JSON object per update, as understood by waybar's custom modules.
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct CustomConfig {
+    command: String,
+    return_type: ReturnType,
+    format: String,
+    interval: f64,
+    timeout: f64,
+    color: Option<Color>,
+    // Colors keyed by exit code, e.g. `state-colors = { 33 = "#ff3333" }`.
+    state_colors: HashMap<String, Color>,
+    // Colors keyed by the class a JSON script reports, these take
+    // precedence over state colors.
+    class_colors: HashMap<String, Color>,
+}
+
+impl Default for CustomConfig {
+    fn default() -> CustomConfig {
+        CustomConfig {
+            command: String::new(),
+            return_type: ReturnType::Text,
+            format: "{output}".to_string(),
+            interval: 5.0,
+            timeout: 10.0,
+            color: None,
+            state_colors: HashMap::new(),
+            class_colors: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Output {
+    text: String,
+    alt: String,
+    tooltip: String,
+    classes: Vec<String>,
+    percentage: Option<f64>,
+    code: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Classes {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonOutput {
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    alt: String,
+    #[serde(default)]
+    tooltip: String,
+    class: Option<Classes>,
+    percentage: Option<f64>,
+}
+
+impl Output {
+    // Scripts usually print a single line of JSON per update, but pretty
+    // printed output is accepted as well.
+    fn parse_json(output: &str) -> Result<Output, Error> {
+        let json: JsonOutput = match serde_json::from_str(output.trim()) {
+            Ok(json) => json,
+            Err(_) => serde_json::from_str(output.lines().next().unwrap_or(""))?,
+        };
+
+        let classes = match json.class {
+            Some(Classes::One(class)) => vec![class],
+            Some(Classes::Many(classes)) => classes,
+            None => Vec::new(),
+        };
+
+        Ok(Output {
+            text: json.text,
+            alt: json.alt,
+            tooltip: json.tooltip,
+            classes,
+            percentage: json.percentage,
+            code: None,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct TimeoutError(String);
+
+impl std::fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' took too long and was killed", self.0)
+    }
+}
+
+impl std::error::Error for TimeoutError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// Runs the command through the shell and parses what it prints, killing it
+// when it doesn't finish within the timeout.
+// The command runs in a process group of its own, so that whatever it
+// started goes along with the shell.
+fn kill(child: &mut Child) {
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.wait();
+}
+
+fn run(command: &str, return_type: ReturnType, timeout: Duration) -> Result<Output, Error> {
+    let mut child = Command::new("sh").arg("-c").arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+
+    // Reading on another thread keeps a chatty script from blocking on a
+    // full pipe while it is being waited on.
+    let mut stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        let _ = sender.send(output);
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if Instant::now() >= deadline {
+            kill(&mut child);
+            return Err(Box::new(TimeoutError(command.to_string())));
+        }
+
+        std::thread::sleep(Duration::from_millis(50));
+    };
+
+    // Something the shell left running in the background may still hold
+    // the pipe open, which counts against the timeout as well.
+    let output = match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(output) => output,
+        Err(_) => {
+            kill(&mut child);
+            return Err(Box::new(TimeoutError(command.to_string())));
+        },
+    };
+    let mut parsed = match return_type {
+        ReturnType::Text => Output {
+            text: output.lines().next().unwrap_or("").trim().to_string(),
+            ..Default::default()
+        },
+        ReturnType::Json => Output::parse_json(&output)?,
+    };
+
+    parsed.code = status.code();
+    Ok(parsed)
+}
+
+pub struct Custom {
+    config: CustomConfig,
+    poller: Poller<Option<Output>>,
+    output: Option<Output>,
+    text: String,
+}
+
+impl Custom {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: CustomConfig = section.clone().try_into()?;
+        let (command, return_type) = (config.command.clone(), config.return_type);
+        let timeout = Duration::from_secs_f64(config.timeout.max(0.1));
+
+        let poller = Poller::spawn(Duration::from_secs_f64(config.interval.max(0.1)), waker.clone(), move || {
+            run(&command, return_type, timeout)
+                .map_err(|err| warn!("Custom command failed: {}", err))
+                .ok()
+        });
+
+        Ok(Box::new(Custom { config, poller, output: None, text: String::new() }))
+    }
+}
+
+impl Widget for Custom {
+    fn update(&mut self) -> bool {
+        let output = match self.poller.take() {
+            Some(output) if output != self.output => output,
+            _ => return false,
+        };
+
+        self.text = match &output {
+            Some(output) if !output.text.is_empty() => format::placeholders(&self.config.format, |key| match key {
+                "output" | "text" => Some(output.text.clone()),
+                "alt" => Some(output.alt.clone()),
+                "tooltip" => Some(output.tooltip.clone()),
+                "percentage" => output.percentage.map(|percentage| format!("{:.0}", percentage)),
+                "code" => output.code.map(|code| code.to_string()),
+                _ => None,
+            }),
+            _ => String::new(),
+        };
+        self.output = output;
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let class = self.output.iter()
+            .flat_map(|output| &output.classes)
+            .find_map(|class| self.config.class_colors.get(class));
+        let state = self.output.as_ref()
+            .and_then(|output| output.code)
+            .and_then(|code| self.config.state_colors.get(&code.to_string()));
+
+        let color = class.or(state).copied().or(self.config.color).unwrap_or_else(|| ctx.foreground());
+        ctx.text(&self.text, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_give_their_first_line() {
+        let output = run("echo ' hello '; echo world", ReturnType::Text, Duration::from_secs(5)).unwrap();
+        assert_eq!((output.text.as_str(), output.code), ("hello", Some(0)));
+        assert_eq!(run("exit 3", ReturnType::Text, Duration::from_secs(5)).unwrap().code, Some(3));
+    }
+
+    #[test]
+    fn commands_running_over_time_are_killed_with_what_they_started() {
+        let marker = std::env::temp_dir().join(format!("rustybar-custom-{}", std::process::id()));
+        let command = format!("(sleep 1; touch '{}') & echo early", marker.display());
+        let started = Instant::now();
+        assert!(run(&command, ReturnType::Text, Duration::from_millis(300)).is_err());
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        std::thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+}
@@ -0,0 +1,473 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use zbus::{Connection, MatchRule, Proxy};
+use zbus::message::Type as MessageType;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{OwnedValue, Structure, Value};
+use tracing::debug;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, worker, Waker, Widget};
+use super::worker::Signals;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Bus {
+    Session,
+    System,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct DbusConfig {
+    bus: Bus,
+    // The bus name owning the object, such as "org.freedesktop.UPower".
+    // Signals may come from anyone when it's left out.
+    service: Option<String>,
+    path: Option<String>,
+    interface: String,
+    // Either a property to follow, or a signal whose arguments are shown
+    // every time it's sent.
+    property: Option<String>,
+    signal: Option<String>,
+    // Seconds between reads of properties that don't announce their
+    // changes.
+    interval: Option<f64>,
+    format: String,
+    // Shown until the property can be read or the signal came.
+    format_missing: String,
+    // Digits after the point of fractional numbers.
+    precision: Option<usize>,
+    // The text and color for particular values, such as
+    // `values = { "1" = "charging" }` for UPower's State.
+    values: HashMap<String, String>,
+    value_colors: HashMap<String, Color>,
+    color: Option<Color>,
+    missing_color: Color,
+}
+
+impl Default for DbusConfig {
+    fn default() -> DbusConfig {
+        DbusConfig {
+            bus: Bus::Session,
+            service: None,
+            path: None,
+            interface: String::new(),
+            property: None,
+            signal: None,
+            interval: None,
+            format: "{value}".to_string(),
+            format_missing: String::new(),
+            precision: None,
+            values: HashMap::new(),
+            value_colors: HashMap::new(),
+            color: None,
+            missing_color: Color::from_str("#888888").unwrap(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct WatchError(&'static str);
+
+impl std::fmt::Display for WatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "D-Bus module {}", self.0)
+    }
+}
+
+impl std::error::Error for WatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Property {
+    service: String,
+    path: String,
+    name: String,
+    interval: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Signal {
+    service: Option<String>,
+    path: Option<String>,
+    name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Watch {
+    Property(Property),
+    Signal(Signal),
+}
+
+impl Watch {
+    fn from_config(config: &DbusConfig) -> Result<Watch, Error> {
+        if config.interface.is_empty() {
+            return Err(Box::new(WatchError("needs an interface")));
+        }
+
+        match (&config.property, &config.signal) {
+            (Some(name), None) => match (&config.service, &config.path) {
+                (Some(service), Some(path)) => Ok(Watch::Property(Property {
+                    service: service.clone(),
+                    path: path.clone(),
+                    name: name.clone(),
+                    interval: config.interval.map(|interval| Duration::from_secs_f64(interval.max(0.1))),
+                })),
+                _ => Err(Box::new(WatchError("needs a service and a path to read a property from"))),
+            },
+            (None, Some(name)) => Ok(Watch::Signal(Signal {
+                service: config.service.clone(),
+                path: config.path.clone(),
+                name: name.clone(),
+            })),
+            _ => Err(Box::new(WatchError("needs either a property or a signal"))),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Watch::Property(Property { name, .. }) | Watch::Signal(Signal { name, .. }) => name,
+        }
+    }
+}
+
+// A value turned into text, along with the parts of it that placeholders
+// can pick: the fields of structures and arrays by their index, and the
+// entries of dictionaries by their key.
+#[derive(Debug, Clone, PartialEq)]
+struct Reading {
+    value: String,
+    number: Option<f64>,
+    fields: Vec<(String, String)>,
+}
+
+impl Reading {
+    fn of(value: &Value, precision: Option<usize>) -> Reading {
+        Reading { value: text(value, precision), number: number(value), fields: fields(value, precision) }
+    }
+
+    // The first argument stands for the signal, and dictionaries among
+    // the arguments have their entries picked out as well, which makes
+    // PropertiesChanged as easy to show as a property.
+    fn of_signal(arguments: &[Value], precision: Option<usize>) -> Reading {
+        let mut reading = arguments.first()
+            .map(|first| Reading { fields: Vec::new(), ..Reading::of(first, precision) })
+            .unwrap_or(Reading { value: String::new(), number: None, fields: Vec::new() });
+
+        for (index, argument) in arguments.iter().enumerate() {
+            reading.fields.push((index.to_string(), text(argument, precision)));
+            if let Value::Dict(_) = argument {
+                reading.fields.extend(fields(argument, precision));
+            }
+        }
+
+        reading
+    }
+
+    fn field(&self, key: &str) -> Option<&str> {
+        self.fields.iter().find(|(name, _)| name == key).map(|(_, text)| text.as_str())
+    }
+}
+
+fn text(value: &Value, precision: Option<usize>) -> String {
+    let join = |values: &mut dyn Iterator<Item = String>| values.collect::<Vec<String>>().join(", ");
+    match value {
+        Value::Bool(value) => value.to_string(),
+        Value::U8(value) => value.to_string(),
+        Value::I16(value) => value.to_string(),
+        Value::U16(value) => value.to_string(),
+        Value::I32(value) => value.to_string(),
+        Value::U32(value) => value.to_string(),
+        Value::I64(value) => value.to_string(),
+        Value::U64(value) => value.to_string(),
+        Value::F64(value) => match precision {
+            Some(precision) => format!("{:.*}", precision, value),
+            None => value.to_string(),
+        },
+        Value::Str(value) => value.to_string(),
+        Value::Signature(value) => value.to_string(),
+        Value::ObjectPath(value) => value.to_string(),
+        Value::Value(value) => text(value, precision),
+        Value::Array(array) => join(&mut array.inner().iter().map(|value| text(value, precision))),
+        Value::Dict(dict) => join(&mut dict.iter()
+            .map(|(key, value)| format!("{}: {}", text(key, precision), text(value, precision)))),
+        Value::Structure(structure) => join(&mut structure.fields().iter().map(|value| text(value, precision))),
+        value => value.to_string(),
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+        Value::U8(value) => Some(*value as f64),
+        Value::I16(value) => Some(*value as f64),
+        Value::U16(value) => Some(*value as f64),
+        Value::I32(value) => Some(*value as f64),
+        Value::U32(value) => Some(*value as f64),
+        Value::I64(value) => Some(*value as f64),
+        Value::U64(value) => Some(*value as f64),
+        Value::F64(value) => Some(*value),
+        Value::Str(value) => value.parse().ok(),
+        Value::Value(value) => number(value),
+        _ => None,
+    }
+}
+
+fn fields(value: &Value, precision: Option<usize>) -> Vec<(String, String)> {
+    let indexed = |values: &[Value]| values.iter().enumerate()
+        .map(|(index, value)| (index.to_string(), text(value, precision)))
+        .collect();
+
+    match value {
+        Value::Value(value) => fields(value, precision),
+        Value::Array(array) => indexed(array.inner()),
+        Value::Structure(structure) => indexed(structure.fields()),
+        Value::Dict(dict) => dict.iter().map(|(key, value)| (text(key, precision), text(value, precision))).collect(),
+        _ => Vec::new(),
+    }
+}
+
+async fn connect(bus: Bus) -> Result<Connection, Error> {
+    Ok(match bus {
+        Bus::Session => Connection::session().await?,
+        Bus::System => Connection::system().await?,
+    })
+}
+
+async fn proxy<'a>(connection: &Connection, service: &'a str, path: &'a str, interface: &'a str)
+    -> Result<Proxy<'a>, Error>
+{
+    Ok(zbus::proxy::Builder::new(connection)
+        .destination(service)?
+        .path(path)?
+        .interface(interface)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?)
+}
+
+type Shared = Weak<Mutex<Option<Option<Reading>>>>;
+
+fn publish(shared: &Shared, waker: &Waker, reading: Option<Reading>) -> bool {
+    match shared.upgrade() {
+        Some(state) => {
+            *state.lock().unwrap() = Some(reading);
+            waker.wake();
+            true
+        },
+        None => false,
+    }
+}
+
+// A property is read again whenever the object says it changed and when
+// the service starts or stops, until then it's missing.
+async fn watch_property(shared: &Shared, waker: &Waker, connection: &Connection, config: &Watched, property: &Property)
+    -> Result<(), Error>
+{
+    let Property { service, path, name, interval } = property;
+    let changes = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender(service.clone())?
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?
+        .path(path.clone())?
+        .arg(0, config.interface.clone())?
+        .build();
+    let owners = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender("org.freedesktop.DBus")?
+        .interface("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .arg(0, service.clone())?
+        .build();
+    let mut signals = Signals::subscribe(connection, [changes, owners], 16).await?;
+
+    let object = proxy(connection, service, path, &config.interface).await?;
+    loop {
+        let reading = match object.get_property::<OwnedValue>(name).await {
+            Ok(value) => Some(Reading::of(&value, config.precision)),
+            Err(err) => {
+                debug!("Failed to read {} of {}: {}", name, service, err);
+                None
+            },
+        };
+        if !publish(shared, waker, reading) {
+            return Ok(());
+        }
+
+        match interval {
+            Some(interval) => if let Ok(Err(err)) = tokio::time::timeout(*interval, signals.next()).await {
+                return Err(err);
+            },
+            None => drop(signals.next().await?),
+        }
+    }
+}
+
+// Dictionary entries a signal lacks keep the text an earlier one gave
+// them, since signals like PropertiesChanged only carry what changed.
+async fn watch_signal(shared: &Shared, waker: &Waker, connection: &Connection, config: &Watched, signal: &Signal)
+    -> Result<(), Error>
+{
+    let Signal { service, path, name } = signal;
+    let mut rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .interface(config.interface.clone())?
+        .member(name.clone())?;
+    if let Some(service) = service {
+        rule = rule.sender(service.clone())?;
+    }
+    if let Some(path) = path {
+        rule = rule.path(path.clone())?;
+    }
+
+    let mut signals = Signals::subscribe(connection, [rule.build()], 16).await?;
+    let mut last: Option<Reading> = None;
+    loop {
+        for message in signals.next().await? {
+            let body = message.body();
+            let mut reading = match body.deserialize::<Structure>() {
+                Ok(arguments) => Reading::of_signal(arguments.fields(), config.precision),
+                Err(_) => Reading::of_signal(&[], config.precision),
+            };
+
+            if let Some(last) = last {
+                for (key, text) in last.fields {
+                    if reading.field(&key).is_none() {
+                        reading.fields.push((key, text));
+                    }
+                }
+            }
+            if !publish(shared, waker, Some(reading.clone())) {
+                return Ok(());
+            }
+            last = Some(reading);
+        }
+    }
+}
+
+// What the watch needs of the config.
+struct Watched {
+    bus: Bus,
+    interface: String,
+    precision: Option<usize>,
+    watch: Watch,
+}
+
+async fn watch(shared: Shared, waker: Waker, config: Arc<Watched>) -> Result<(), Error> {
+    let connection = connect(config.bus).await?;
+    match &config.watch {
+        Watch::Property(property) => watch_property(&shared, &waker, &connection, &config, property).await,
+        Watch::Signal(signal) => watch_signal(&shared, &waker, &connection, &config, signal).await,
+    }
+}
+
+// Keeps the reading of a property or the latest signal up to date, until
+// it's dropped along with the module.
+struct Watcher {
+    state: Arc<Mutex<Option<Option<Reading>>>>,
+    _watch: worker::Task,
+}
+
+impl Watcher {
+    fn spawn(waker: Waker, config: Watched) -> Watcher {
+        let state = Arc::new(Mutex::new(None));
+        let shared = Arc::downgrade(&state);
+
+        let what = format!("{}.{} on D-Bus", config.interface, config.watch.name());
+        let config = Arc::new(config);
+        let watch = worker::watch(Duration::from_secs(5), what,
+            move || watch(shared.clone(), waker.clone(), Arc::clone(&config)));
+
+        Watcher { state, _watch: watch }
+    }
+
+    // Returns the reading if it changed since the last call, the inner
+    // None means the property can't be read.
+    fn take(&self) -> Option<Option<Reading>> {
+        self.state.lock().unwrap().take()
+    }
+}
+
+pub struct DbusWatch {
+    config: DbusConfig,
+    watch: Watch,
+    watcher: Watcher,
+    reading: Option<Reading>,
+    text: String,
+}
+
+impl DbusWatch {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: DbusConfig = section.clone().try_into()?;
+        let watch = Watch::from_config(&config)?;
+        let watcher = Watcher::spawn(waker.clone(), Watched {
+            bus: config.bus,
+            interface: config.interface.clone(),
+            precision: config.precision,
+            watch: watch.clone(),
+        });
+
+        Ok(Box::new(DbusWatch { config, watch, watcher, reading: None, text: String::new() }))
+    }
+
+    fn format(&self) -> String {
+        let reading = match &self.reading {
+            Some(reading) => reading,
+            None => return self.config.format_missing.clone(),
+        };
+
+        format::placeholders(&self.config.format, |key| match key {
+            "value" => Some(self.config.values.get(&reading.value).unwrap_or(&reading.value).clone()),
+            _ => reading.field(key).map(str::to_string),
+        })
+    }
+}
+
+impl Widget for DbusWatch {
+    fn update(&mut self) -> bool {
+        let reading = match self.watcher.take() {
+            Some(reading) => reading,
+            None => return false,
+        };
+
+        self.reading = reading;
+        let text = self.format();
+        if text == self.text {
+            return false;
+        }
+
+        self.text = text;
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match &self.reading {
+            Some(reading) => match self.config.value_colors.get(&reading.value) {
+                Some(color) => *color,
+                None => self.config.color.unwrap_or_else(|| ctx.foreground()),
+            },
+            None => self.config.missing_color,
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let reading = self.reading.as_ref()?;
+        Some(format!("{} <span foreground=\"#888888\">{}</span>",
+            markup::escape(self.watch.name()), markup::escape(&reading.value)))
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.reading.as_ref().and_then(|reading| reading.number)
+    }
+}
@@ -0,0 +1,199 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use tracing::warn;
+use crate::Error;
+use crate::animation::Easing;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use crate::x11::X11;
+use super::{format, Button, Scroll, Waker, Widget};
+use super::buttons::{ButtonStyle, Buttons, Indicator, Lines};
+use super::ewmh::Watcher;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct DesktopsConfig {
+    format: String,
+    padding: f32,
+    corner_radius: f32,
+    color: Option<Color>,
+    background: Option<Color>,
+    current_color: Option<Color>,
+    current_background: Option<Color>,
+    underline: Option<Color>,
+    overline: Option<Color>,
+    current_underline: Option<Color>,
+    current_overline: Option<Color>,
+    line_width: f32,
+    // Seconds the current desktop's highlight takes to move over to the
+    // next one, 0 to have it jump.
+    indicator_duration: f64,
+    indicator_easing: Easing,
+}
+
+impl Default for DesktopsConfig {
+    fn default() -> DesktopsConfig {
+        DesktopsConfig {
+            format: "{name}".to_string(),
+            padding: 8.0,
+            corner_radius: 0.0,
+            color: None,
+            background: None,
+            current_color: None,
+            current_background: Some(Color::from_str("#444444").unwrap()),
+            underline: None,
+            overline: None,
+            current_underline: None,
+            current_overline: None,
+            line_width: 2.0,
+            indicator_duration: 0.0,
+            indicator_easing: Easing::EaseInOut,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DesktopState {
+    names: Vec<String>,
+    current: Option<u32>,
+}
+
+fn query(x11: &X11) -> Result<DesktopState, Error> {
+    let (root, atoms) = (x11.root(), x11.atoms());
+    let count = x11.cardinal(root, atoms._NET_NUMBER_OF_DESKTOPS)?.unwrap_or(0);
+    let mut names = x11.utf8_strings(root, atoms._NET_DESKTOP_NAMES)?;
+
+    // Window managers may name fewer desktops than they have, the rest are
+    // shown by number.
+    names.truncate(count as usize);
+    for index in names.len()..count as usize {
+        names.push((index + 1).to_string());
+    }
+
+    Ok(DesktopState { names, current: x11.cardinal(root, atoms._NET_CURRENT_DESKTOP)? })
+}
+
+fn switch(index: u32) {
+    let result = X11::connect().and_then(|x11| {
+        let message = x11.atoms()._NET_CURRENT_DESKTOP;
+        x11.send_root_message(x11.root(), message, [index, 0, 0, 0, 0])
+    });
+
+    if let Err(err) = result {
+        warn!("Failed to switch desktop: {}", err);
+    }
+}
+
+pub struct Desktops {
+    config: DesktopsConfig,
+    watcher: Watcher<DesktopState>,
+    state: DesktopState,
+    buttons: Buttons,
+    indicator: Indicator,
+}
+
+impl Desktops {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: DesktopsConfig = section.clone().try_into()?;
+        Ok(Box::new(Desktops {
+            indicator: Indicator::new(config.indicator_duration, config.indicator_easing),
+            config,
+            watcher: Watcher::spawn(waker.clone(), query),
+            state: DesktopState { names: Vec::new(), current: None },
+            buttons: Buttons::default(),
+        }))
+    }
+}
+
+impl Widget for Desktops {
+    fn update(&mut self) -> bool {
+        match self.watcher.take() {
+            Some(state) => {
+                self.state = state;
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        self.buttons.clear();
+        let config = &self.config;
+        let style = ButtonStyle { padding: config.padding, corner_radius: config.corner_radius, line_width: config.line_width };
+
+        let labels: Vec<String> = self.state.names.iter().enumerate()
+            .map(|(index, name)| format::placeholders(&self.config.format, |key| match key {
+                "name" => Some(name.clone()),
+                "index" => Some((index + 1).to_string()),
+                _ => None,
+            }))
+            .collect();
+
+        // While the current desktop changes its highlight is drawn on its
+        // own, under a row of buttons drawn into a context of their own.
+        let active = self.state.current.map(|current| current as usize).filter(|active| *active < labels.len());
+        let slide = active.and_then(|index| {
+            let start = ctx.width() + labels[..index].iter().map(|label| style.width(ctx, label)).sum::<f32>();
+            self.indicator.place((start, start + style.width(ctx, &labels[index])))
+        });
+        let mut row = ctx.child(ctx.height());
+        row.advance(ctx.width());
+
+        for (index, label) in labels.iter().enumerate() {
+            let current = self.state.current == Some(index as u32);
+            let (color, background) = if current {
+                (config.current_color, config.current_background)
+            } else {
+                (None, config.background)
+            };
+            let lines = if current {
+                Lines {
+                    underline: config.current_underline.or(config.underline),
+                    overline: config.current_overline.or(config.overline),
+                }
+            } else {
+                Lines { underline: config.underline, overline: config.overline }
+            };
+
+            let color = color.or(config.color).unwrap_or_else(|| ctx.foreground());
+            match slide.filter(|_| current) {
+                Some(extent) => {
+                    Buttons::highlight(ctx, extent, background, lines, &style);
+                    self.buttons.draw(&mut row, label, color, None, Lines::default(), &style);
+                },
+                None => self.buttons.draw(&mut row, label, color, background, lines, &style),
+            }
+        }
+
+        ctx.append(row, 0.0, 0.0);
+    }
+
+    fn animating(&self) -> bool {
+        self.indicator.is_moving()
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        let index = match self.buttons.at(x) {
+            Some(index) if button == Button::Left => index as u32,
+            _ => return false,
+        };
+
+        switch(index);
+        false
+    }
+
+    // Cycles through the desktops, wrapping around at either end.
+    fn scroll(&mut self, _x: f32, direction: Scroll) -> bool {
+        let count = self.state.names.len() as u32;
+        let current = match self.state.current {
+            Some(current) if count > 0 => current,
+            _ => return false,
+        };
+
+        switch(match direction {
+            Scroll::Up => (current + count - 1) % count,
+            Scroll::Down => (current + 1) % count,
+        });
+        false
+    }
+}
@@ -0,0 +1,159 @@
+use serde::Deserialize;
+use std::ffi::CString;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, Waker, Widget};
+use super::worker::Poller;
+
+const SIZE_UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct DiskConfig {
+    mounts: Vec<String>,
+    // Applied to every mount point, the results are joined by separator.
+    format: String,
+    format_missing: String,
+    separator: String,
+    interval: f64,
+    // Percent used, the warning color is shown once any mount reaches it.
+    warning: f64,
+    color: Option<Color>,
+    warning_color: Color,
+}
+
+impl Default for DiskConfig {
+    fn default() -> DiskConfig {
+        DiskConfig {
+            mounts: vec!["/".to_string()],
+            format: "{mount} {free}".to_string(),
+            format_missing: String::new(),
+            separator: " ".to_string(),
+            interval: 30.0,
+            warning: 90.0,
+            color: None,
+            warning_color: Color::from_str("#ffaa00").unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Usage {
+    total: u64,
+    used: u64,
+    // Space available to unprivileged users, which excludes the blocks
+    // reserved for root.
+    free: u64,
+}
+
+impl Usage {
+    // Percent of the space usable by normal users, the way df reports it.
+    fn percent(&self) -> f64 {
+        let usable = self.used + self.free;
+        if usable == 0 {
+            0.0
+        } else {
+            self.used as f64 / usable as f64 * 100.0
+        }
+    }
+}
+
+fn statvfs(mount: &str) -> Option<Usage> {
+    let path = CString::new(mount).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+
+    let block = stats.f_frsize as u64;
+    Some(Usage {
+        total: stats.f_blocks as u64 * block,
+        used: (stats.f_blocks as u64).saturating_sub(stats.f_bfree as u64) * block,
+        free: stats.f_bavail as u64 * block,
+    })
+}
+
+pub struct Disk {
+    config: DiskConfig,
+    poller: Poller<Vec<(String, Option<Usage>)>>,
+    usage: Vec<(String, Option<Usage>)>,
+    text: String,
+}
+
+impl Disk {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: DiskConfig = section.clone().try_into()?;
+        let period = Duration::from_secs_f64(config.interval.max(0.1));
+
+        // Network filesystems can block statvfs for a long time, so it runs
+        // off the event loop.
+        let mounts = config.mounts.clone();
+        let poller = Poller::spawn(period, waker.clone(), move || {
+            mounts.iter().map(|mount| (mount.clone(), statvfs(mount))).collect()
+        });
+
+        Ok(Box::new(Disk { config, poller, usage: Vec::new(), text: String::new() }))
+    }
+
+    fn format(&self, mount: &str, usage: Option<Usage>) -> String {
+        let usage = match usage {
+            Some(usage) => usage,
+            None => return self.config.format_missing.clone(),
+        };
+
+        let size = |bytes: u64| format::scaled(bytes as f64, 1024.0, &SIZE_UNITS);
+        format::placeholders(&self.config.format, |key| match key {
+            "mount" => Some(mount.to_string()),
+            "used" => Some(size(usage.used)),
+            "free" => Some(size(usage.free)),
+            "total" => Some(size(usage.total)),
+            "percent" => Some(format!("{:.0}", usage.percent())),
+            _ => None,
+        })
+    }
+}
+
+impl Widget for Disk {
+    fn update(&mut self) -> bool {
+        let usage = match self.poller.take() {
+            Some(usage) => usage,
+            None => return false,
+        };
+
+        let text = usage.iter()
+            .map(|(mount, usage)| self.format(mount, *usage))
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(&self.config.separator);
+
+        self.usage = usage;
+        if text == self.text {
+            return false;
+        }
+
+        self.text = text;
+        true
+    }
+
+    // The fullest of the mounts.
+    fn value(&self) -> Option<f64> {
+        self.usage.iter()
+            .filter_map(|(_, usage)| usage.as_ref().map(Usage::percent))
+            .max_by(|a, b| a.total_cmp(b))
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let warning = self.usage.iter()
+            .any(|(_, usage)| usage.is_some_and(|usage| usage.percent() >= self.config.warning));
+        let color = if warning {
+            self.config.warning_color
+        } else {
+            self.config.color.unwrap_or_else(|| ctx.foreground())
+        };
+
+        ctx.text(&self.text, color);
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::Error;
+use crate::x11::X11;
+use super::Waker;
+
+// Re-runs `query` on its own X connection whenever a watched property or,
+// once selected, the keyboard state changes, publishing the result when it
+// differs from the previous one.
+// The query selects the windows it wants to hear about, by default only the
+// root window is watched.
+//
+// The thread notices the widget was dropped the next time a property
+// changes, X gives no way to interrupt a blocking wait from another thread.
+pub struct Watcher<T> {
+    state: Arc<Mutex<Option<T>>>,
+}
+
+impl<T: Clone + PartialEq + Send + 'static> Watcher<T> {
+    pub fn spawn<F>(waker: Waker, mut query: F) -> Watcher<T>
+    where F: FnMut(&X11) -> Result<T, Error> + Send + 'static {
+        let state = Arc::new(Mutex::new(None));
+        let shared_state = Arc::downgrade(&state);
+
+        std::thread::spawn(move || loop {
+            let mut follow = || -> Result<(), Error> {
+                let x11 = X11::connect()?;
+                x11.select_property_changes(x11.root())?;
+
+                let mut previous = None;
+                loop {
+                    let value = query(&x11)?;
+                    if previous.as_ref() != Some(&value) {
+                        match shared_state.upgrade() {
+                            Some(state) => *state.lock().unwrap() = Some(value.clone()),
+                            None => return Ok(()),
+                        }
+                        waker.wake();
+                        previous = Some(value);
+                    }
+
+                    x11.wait_for_change()?;
+                }
+            };
+
+            let _ = follow();
+            if shared_state.strong_count() == 0 {
+                return;
+            }
+
+            std::thread::sleep(Duration::from_secs(5));
+        });
+
+        Watcher { state }
+    }
+
+    pub fn take(&self) -> Option<T> {
+        self.state.lock().unwrap().take()
+    }
+}
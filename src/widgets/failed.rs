@@ -0,0 +1,49 @@
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::Widget;
+
+// Errors are cut to this many characters on the bar, the tooltip has all
+// of it.
+const SHOWN_LENGTH: usize = 60;
+
+const BACKGROUND: Color = Color::rgb(0xcc, 0x22, 0x22);
+const FOREGROUND: Color = Color::rgb(0xff, 0xff, 0xff);
+
+// Takes the place of a module that couldn't be built, so a mistake in one
+// module section leaves the rest of the bar working.
+pub struct Failed {
+    text: String,
+    message: String,
+}
+
+impl Failed {
+    pub fn new(name: &str, err: &Error) -> Failed {
+        let message = err.to_string();
+        let first_line = message.lines().next().unwrap_or("");
+        let mut text: String = first_line.chars().take(SHOWN_LENGTH).collect();
+        if text.len() < message.len() {
+            text.push('…');
+        }
+
+        Failed { text: format!(" {}: {} ", name, text), message }
+    }
+}
+
+impl Widget for Failed {
+    fn update(&mut self) -> bool {
+        false
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        ctx.text(&self.text, FOREGROUND);
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        Some(self.message.clone())
+    }
+
+    fn background(&self) -> Option<Color> {
+        Some(BACKGROUND)
+    }
+}
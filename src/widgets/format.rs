@@ -0,0 +1,42 @@
+// Expands `{name}` placeholders in a format string. Placeholders the lookup
+// doesn't know are left untouched so typos stay visible on the bar.
+pub fn placeholders<F: Fn(&str) -> Option<String>>(template: &str, lookup: F) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        match rest.find('}') {
+            Some(end) => {
+                match lookup(&rest[1..end]) {
+                    Some(value) => output.push_str(&value),
+                    None => output.push_str(&rest[..=end]),
+                }
+                rest = &rest[end + 1..];
+            },
+            None => break,
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+// Scales a value down by powers of base until it fits the largest unit it
+// reaches, e.g. 1536 with base 1024 and ["B", "KiB"] is "1.5 KiB".
+pub fn scaled(value: f64, base: f64, units: &[&str]) -> String {
+    let mut value = value;
+    let mut unit = 0;
+    while value >= base && unit < units.len() - 1 {
+        value /= base;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{:.0} {}", value, units[unit])
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
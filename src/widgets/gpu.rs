@@ -0,0 +1,363 @@
+use serde::Deserialize;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, sysfs, Waker, Widget};
+use super::worker::Poller;
+
+const DRM: &str = "/sys/class/drm";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Backend {
+    // amdgpu when a card uses it, then nvidia-smi and intel_gpu_top if
+    // they're installed.
+    Auto,
+    Amdgpu,
+    Nvidia,
+    Intel,
+}
+
+impl Backend {
+    fn name(self) -> &'static str {
+        match self {
+            Backend::Auto => "auto",
+            Backend::Amdgpu => "amdgpu",
+            Backend::Nvidia => "nvidia",
+            Backend::Intel => "intel",
+        }
+    }
+
+    fn detect(self, amdgpu: &AmdgpuConfig) -> Option<Backend> {
+        if self != Backend::Auto {
+            return Some(self);
+        }
+
+        if amdgpu_card(amdgpu.card.as_deref()).is_some() {
+            return Some(Backend::Amdgpu);
+        }
+
+        let path = std::env::var_os("PATH")?;
+        let installed = |program: &str| std::env::split_paths(&path).any(|dir| dir.join(program).is_file());
+        match (installed("nvidia-smi"), installed("intel_gpu_top")) {
+            (true, _) => Some(Backend::Nvidia),
+            (false, true) => Some(Backend::Intel),
+            (false, false) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct AmdgpuConfig {
+    // Such as "card1", defaults to the first card amdgpu drives.
+    card: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct NvidiaConfig {
+    // The index, UUID or PCI bus id nvidia-smi knows the GPU by.
+    gpu: String,
+}
+
+impl Default for NvidiaConfig {
+    fn default() -> NvidiaConfig {
+        NvidiaConfig { gpu: "0".to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct IntelConfig {
+    // An intel_gpu_top device filter such as "drm:/dev/dri/card0".
+    device: Option<String>,
+    // Seconds intel_gpu_top measures for, which every poll waits out.
+    sample: f64,
+}
+
+impl Default for IntelConfig {
+    fn default() -> IntelConfig {
+        IntelConfig { device: None, sample: 0.5 }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct GpuConfig {
+    backend: Backend,
+    amdgpu: AmdgpuConfig,
+    nvidia: NvidiaConfig,
+    intel: IntelConfig,
+    format: String,
+    format_missing: String,
+    interval: f64,
+    // Utilization in percent.
+    warning: f64,
+    critical: f64,
+    color: Option<Color>,
+    warning_color: Color,
+    critical_color: Color,
+}
+
+impl Default for GpuConfig {
+    fn default() -> GpuConfig {
+        GpuConfig {
+            backend: Backend::Auto,
+            amdgpu: AmdgpuConfig::default(),
+            nvidia: NvidiaConfig::default(),
+            intel: IntelConfig::default(),
+            format: "GPU {utilization}%".to_string(),
+            format_missing: String::new(),
+            interval: 2.0,
+            warning: 70.0,
+            critical: 90.0,
+            color: None,
+            warning_color: Color::from_str("#ffaa00").unwrap(),
+            critical_color: Color::from_str("#ff3333").unwrap(),
+        }
+    }
+}
+
+// Integrated GPUs have no memory of their own and not every backend reads
+// the temperature, so anything but the utilization may be missing.
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    // Percent.
+    utilization: f64,
+    // Bytes.
+    vram_used: Option<u64>,
+    vram_total: Option<u64>,
+    // Degrees Celsius.
+    temperature: Option<f64>,
+}
+
+fn amdgpu_card(name: Option<&str>) -> Option<PathBuf> {
+    let mut cards: Vec<PathBuf> = std::fs::read_dir(DRM).ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.file_name().and_then(|file| file.to_str()).is_some_and(|file| {
+            name.map_or(file.starts_with("card") && !file.contains('-'), |name| file == name)
+        }))
+        .collect();
+    cards.sort();
+
+    cards.into_iter().find(|card| {
+        std::fs::read_link(card.join("device/driver"))
+            .is_ok_and(|driver| driver.file_name().is_some_and(|driver| driver == "amdgpu"))
+    })
+}
+
+// The driver reports through the card's device directory, the temperature
+// through its hwmon chip, in millidegrees.
+fn amdgpu(card: &Path) -> Option<Sample> {
+    let device = card.join("device");
+    let temperature = std::fs::read_dir(device.join("hwmon")).ok()
+        .and_then(|mut chips| chips.find_map(|chip| {
+            sysfs::read_number::<f64, _>(chip.ok()?.path().join("temp1_input"))
+        }))
+        .map(|millidegrees| millidegrees / 1000.0);
+
+    Some(Sample {
+        utilization: sysfs::read_number(device.join("gpu_busy_percent"))?,
+        vram_used: sysfs::read_number(device.join("mem_info_vram_used")),
+        vram_total: sysfs::read_number(device.join("mem_info_vram_total")),
+        temperature,
+    })
+}
+
+// nvidia-smi asks NVML, which keeps the bar from linking the driver's
+// library. Memory comes in MiB.
+fn nvidia(config: &NvidiaConfig) -> Option<Sample> {
+    let output = Command::new("nvidia-smi")
+        .args(["--query-gpu=utilization.gpu,memory.used,memory.total,temperature.gpu", "--format=csv,noheader,nounits"])
+        .args(["-i", &config.gpu])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = output.lines().next()?.split(',').map(str::trim).collect();
+    let mib = |field: &str| field.parse::<u64>().ok().map(|mib| mib * 1024 * 1024);
+
+    Some(Sample {
+        utilization: fields.first()?.parse().ok()?,
+        vram_used: fields.get(1).and_then(|field| mib(field)),
+        vram_total: fields.get(2).and_then(|field| mib(field)),
+        temperature: fields.get(3).and_then(|field| field.parse().ok()),
+    })
+}
+
+// intel_gpu_top prints a JSON object per sample for as long as it runs, so
+// it's stopped after the first one. The busiest engine stands for the GPU.
+fn intel(config: &IntelConfig) -> Option<Sample> {
+    let period = ((config.sample * 1000.0).max(100.0) as u64).to_string();
+    let mut command = Command::new("intel_gpu_top");
+    command.args(["-J", "-s", &period]);
+    if let Some(device) = &config.device {
+        command.args(["-d", device]);
+    }
+
+    let mut child = command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn().ok()?;
+    let stdout = child.stdout.take()?;
+
+    // Objects are read line by line until their braces are balanced.
+    let (mut object, mut depth) = (String::new(), 0i32);
+    let mut sample = None;
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        if depth == 0 {
+            object.clear();
+            match line.find('{') {
+                Some(start) => object.push_str(&line[start..]),
+                None => continue,
+            }
+        } else {
+            object.push_str(&line);
+        }
+        object.push('\n');
+
+        depth += line.matches('{').count() as i32 - line.matches('}').count() as i32;
+        if depth <= 0 {
+            let json: serde_json::Value = match serde_json::from_str(object.trim_end().trim_end_matches(',')) {
+                Ok(json) => json,
+                Err(err) => {
+                    warn!("Failed to read intel_gpu_top's output: {}", err);
+                    break;
+                },
+            };
+            sample = json["engines"].as_object().map(|engines| Sample {
+                utilization: engines.values().filter_map(|engine| engine["busy"].as_f64()).fold(0.0, f64::max),
+                vram_used: None,
+                vram_total: None,
+                temperature: None,
+            });
+            break;
+        }
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+    sample
+}
+
+// Which of the module's colors the utilization calls for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Normal,
+    Warning,
+    Critical,
+}
+
+pub struct Gpu {
+    config: GpuConfig,
+    backend: Option<Backend>,
+    poller: Poller<Option<Sample>>,
+    sample: Option<Sample>,
+    text: String,
+    level: Level,
+}
+
+impl Gpu {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: GpuConfig = section.clone().try_into()?;
+        let backend = config.backend.detect(&config.amdgpu);
+        if backend.is_none() {
+            warn!("No GPU found to show the usage of");
+        }
+
+        // DRM numbering may change once the driver reloads, like hwmon's,
+        // so the card is looked up again when it can't be read.
+        let (amdgpu_config, nvidia_config, intel_config) =
+            (config.amdgpu.clone(), config.nvidia.clone(), config.intel.clone());
+        let mut card = None;
+        let period = Duration::from_secs_f64(config.interval.max(0.1));
+        let poller = Poller::spawn(period, waker.clone(), move || match backend? {
+            Backend::Auto => None,
+            Backend::Amdgpu => card.as_deref().and_then(amdgpu).or_else(|| {
+                card = amdgpu_card(amdgpu_config.card.as_deref());
+                amdgpu(card.as_ref()?)
+            }),
+            Backend::Nvidia => nvidia(&nvidia_config),
+            Backend::Intel => intel(&intel_config),
+        });
+
+        Ok(Box::new(Gpu { config, backend, poller, sample: None, text: String::new(), level: Level::Normal }))
+    }
+
+    // The format needn't show the utilization, so the level is compared
+    // along with the text.
+    fn level(&self) -> Level {
+        match self.sample.as_ref().map(|sample| sample.utilization) {
+            Some(utilization) if utilization >= self.config.critical => Level::Critical,
+            Some(utilization) if utilization >= self.config.warning => Level::Warning,
+            _ => Level::Normal,
+        }
+    }
+
+    fn format(&self) -> String {
+        let sample = match &self.sample {
+            Some(sample) => sample,
+            None => return self.config.format_missing.clone(),
+        };
+
+        // Readings the backend doesn't have show as a question mark.
+        let reading = |value: Option<String>| Some(value.unwrap_or_else(|| "?".to_string()));
+        let bytes = |bytes: Option<u64>| bytes.map(|bytes| format::scaled(bytes as f64, 1024.0, &["B", "KiB", "MiB", "GiB"]));
+        let vram_percent = match (sample.vram_used, sample.vram_total) {
+            (Some(used), Some(total)) if total > 0 => Some(format!("{:.0}", used as f64 / total as f64 * 100.0)),
+            _ => None,
+        };
+
+        format::placeholders(&self.config.format, |key| match key {
+            "utilization" => Some(format!("{:.0}", sample.utilization)),
+            "vram_used" => reading(bytes(sample.vram_used)),
+            "vram_total" => reading(bytes(sample.vram_total)),
+            "vram_percent" => reading(vram_percent.clone()),
+            "temperature" => reading(sample.temperature.map(|celsius| format!("{:.0}", celsius))),
+            "backend" => self.backend.map(|backend| backend.name().to_string()),
+            _ => None,
+        })
+    }
+}
+
+impl Widget for Gpu {
+    fn update(&mut self) -> bool {
+        let sample = match self.poller.take() {
+            Some(sample) => sample,
+            None => return false,
+        };
+
+        self.sample = sample;
+        let (text, level) = (self.format(), self.level());
+        if text == self.text && level == self.level {
+            return false;
+        }
+
+        self.text = text;
+        self.level = level;
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match self.level {
+            Level::Normal => self.config.color.unwrap_or_else(|| ctx.foreground()),
+            Level::Warning => self.config.warning_color,
+            Level::Critical => self.config.critical_color,
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.sample.as_ref().map(|sample| sample.utilization)
+    }
+}
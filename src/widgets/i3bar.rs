@@ -0,0 +1,393 @@
+use serde::Deserialize;
+use std::cell::Cell;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{Button, Scroll, Waker, Widget};
+use super::buttons::Buttons;
+
+// Shows the output of status commands speaking the i3bar protocol, such as
+// i3status, i3status-rust or bumblebee-status:
+//
+//     [module.i3bar]
+//     command = "i3status"
+//
+// Clicks are sent back to commands asking for them in their header.
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct I3barConfig {
+    command: String,
+    color: Option<Color>,
+    separator_color: Color,
+    urgent_color: Color,
+    urgent_background: Color,
+}
+
+impl Default for I3barConfig {
+    fn default() -> I3barConfig {
+        I3barConfig {
+            command: "i3status".to_string(),
+            color: None,
+            separator_color: "#888888".parse().unwrap(),
+            urgent_color: "#ffffff".parse().unwrap(),
+            urgent_background: "#900000".parse().unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    version: u32,
+    #[serde(default)]
+    click_events: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum MinWidth {
+    Pixels(f32),
+    // As wide as this text would be.
+    Text(String),
+}
+
+fn default_separator_width() -> f32 {
+    9.0
+}
+
+fn default_border_width() -> f32 {
+    1.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// Colors are kept as strings, a block with a color that doesn't parse is
+// still shown in the default one.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Block {
+    full_text: String,
+    name: Option<String>,
+    instance: Option<String>,
+    color: Option<String>,
+    background: Option<String>,
+    border: Option<String>,
+    #[serde(default = "default_border_width")]
+    border_top: f32,
+    #[serde(default = "default_border_width")]
+    border_right: f32,
+    #[serde(default = "default_border_width")]
+    border_bottom: f32,
+    #[serde(default = "default_border_width")]
+    border_left: f32,
+    min_width: Option<MinWidth>,
+    #[serde(default)]
+    align: String,
+    #[serde(default)]
+    urgent: bool,
+    #[serde(default = "default_true")]
+    separator: bool,
+    #[serde(default = "default_separator_width")]
+    separator_block_width: f32,
+    #[serde(default)]
+    markup: String,
+}
+
+fn color(code: &Option<String>) -> Option<Color> {
+    code.as_ref().and_then(|code| code.parse().ok())
+}
+
+#[derive(Default)]
+struct State {
+    blocks: Option<Vec<Block>>,
+    // Only kept while the running command reads click events.
+    stdin: Option<ChildStdin>,
+}
+
+// Status lines come one per line as elements of an endless JSON array, so
+// the brackets and commas around them are stripped before parsing.
+fn parse_line(line: &str) -> Option<Vec<Block>> {
+    let line = line.trim().trim_start_matches(',').trim_end_matches(',').trim();
+    if line.is_empty() || line == "[" {
+        return None;
+    }
+
+    serde_json::from_str(line)
+        .map_err(|err| warn!("Ignoring unreadable i3bar status line: {}", err))
+        .ok()
+}
+
+fn follow(command: &str, state: &Arc<Mutex<State>>, child: &Arc<Mutex<Option<Child>>>, waker: &Waker)
+    -> Result<(), Error> {
+    let mut process = Command::new("sh").arg("-c").arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut lines = BufReader::new(process.stdout.take().unwrap()).lines();
+    let stdin = process.stdin.take();
+    *child.lock().unwrap() = Some(process);
+
+    let header: Header = serde_json::from_str(&lines.next().transpose()?.unwrap_or_default())?;
+    if header.version < 1 {
+        warn!("Unknown i3bar protocol version {}", header.version);
+    }
+
+    {
+        let mut state = state.lock().unwrap();
+        state.stdin = stdin.filter(|_| header.click_events);
+        if let Some(stdin) = &mut state.stdin {
+            let _ = writeln!(stdin, "[");
+        }
+    }
+
+    for line in lines.map_while(Result::ok) {
+        if let Some(blocks) = parse_line(&line) {
+            state.lock().unwrap().blocks = Some(blocks);
+            waker.wake();
+        }
+    }
+
+    Ok(())
+}
+
+struct Status {
+    state: Arc<Mutex<State>>,
+    child: Arc<Mutex<Option<Child>>>,
+}
+
+impl Status {
+    // Commands that exit are started again after a while.
+    fn spawn(command: String, waker: Waker) -> Status {
+        let state = Arc::new(Mutex::new(State::default()));
+        let child = Arc::new(Mutex::new(None));
+        let (shared_state, shared_child) = (Arc::downgrade(&state), Arc::downgrade(&child));
+
+        std::thread::spawn(move || loop {
+            let (state, child) = match (shared_state.upgrade(), shared_child.upgrade()) {
+                (Some(state), Some(child)) => (state, child),
+                _ => return,
+            };
+
+            if let Err(err) = follow(&command, &state, &child, &waker) {
+                warn!("i3bar status command failed: {}", err);
+            }
+
+            state.lock().unwrap().stdin = None;
+            if let Some(mut process) = child.lock().unwrap().take() {
+                let _ = process.wait();
+            }
+            drop((state, child));
+
+            std::thread::sleep(Duration::from_secs(5));
+        });
+
+        Status { state, child }
+    }
+
+    fn take(&self) -> Option<Vec<Block>> {
+        self.state.lock().unwrap().blocks.take()
+    }
+
+    fn send_click(&self, block: &Block, button: u32, x: f32, width: f32, height: f32) {
+        let mut state = self.state.lock().unwrap();
+        let event = serde_json::json!({
+            "name": block.name,
+            "instance": block.instance,
+            "button": button,
+            "modifiers": [],
+            "relative_x": x.round(),
+            "relative_y": (height / 2.0).round(),
+            "width": width.round(),
+            "height": height.round(),
+        });
+        if let Some(stdin) = &mut state.stdin {
+            if let Err(err) = writeln!(stdin, "{},", event) {
+                warn!("Failed to send a click to the i3bar status command: {}", err);
+                state.stdin = None;
+            }
+        }
+    }
+}
+
+impl Drop for Status {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.lock().unwrap().take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+pub struct I3bar {
+    config: I3barConfig,
+    status: Status,
+    blocks: Vec<Block>,
+    buttons: Buttons,
+    height: Cell<f32>,
+}
+
+impl I3bar {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: I3barConfig = section.clone().try_into()?;
+
+        Ok(Box::new(I3bar {
+            status: Status::spawn(config.command.clone(), waker.clone()),
+            config,
+            blocks: Vec::new(),
+            buttons: Buttons::default(),
+            height: Cell::new(0.0),
+        }))
+    }
+
+    // Buttons are numbered as in X11, scrolling counts as buttons 4 and 5.
+    fn send(&self, x: f32, button: u32) -> bool {
+        if let Some(index) = self.buttons.at(x) {
+            let (start, end) = self.buttons.extent(index);
+            self.status.send_click(&self.blocks[index], button, x - start, end - start, self.height.get());
+        }
+        false
+    }
+
+    fn draw_block(&self, ctx: &mut RenderCtx, block: &Block, start: f32) -> f32 {
+        let foreground = self.config.color.unwrap_or_else(|| ctx.foreground());
+        let (text_color, background) = match block.urgent {
+            true => (self.config.urgent_color, Some(self.config.urgent_background)),
+            false => (color(&block.color).unwrap_or(foreground), color(&block.background)),
+        };
+        let border = color(&block.border);
+
+        let mut content = ctx.child(ctx.height());
+        content.set_markup(block.markup == "pango");
+        content.text(&block.full_text, text_color);
+
+        let (left, right) = match border {
+            Some(_) => (block.border_left, block.border_right),
+            None => (0.0, 0.0),
+        };
+        let minimum = match &block.min_width {
+            Some(MinWidth::Pixels(width)) => *width,
+            Some(MinWidth::Text(text)) => ctx.measure(text),
+            None => 0.0,
+        };
+        let inner = content.width().max(minimum);
+        let offset = match block.align.as_str() {
+            "center" => ((inner - content.width()) / 2.0).round(),
+            "right" => inner - content.width(),
+            _ => 0.0,
+        };
+        let width = left + inner + right;
+
+        if let Some(background) = background {
+            ctx.rect(start, 0.0, width, ctx.height(), background);
+        }
+        if let Some(border) = border {
+            let (top, bottom) = (block.border_top, block.border_bottom);
+            let height = ctx.height();
+            ctx.rect(start, 0.0, width, top, border);
+            ctx.rect(start, height - bottom, width, bottom, border);
+            ctx.rect(start, 0.0, left, height, border);
+            ctx.rect(start + width - right, 0.0, right, height, border);
+        }
+        ctx.append(content, start + left + offset, 0.0);
+
+        width
+    }
+}
+
+impl Widget for I3bar {
+    fn update(&mut self) -> bool {
+        // Empty blocks aren't shown at all, separator included.
+        let blocks = self.status.take().map(|mut blocks| {
+            blocks.retain(|block| !block.full_text.is_empty());
+            blocks
+        });
+
+        match blocks {
+            Some(blocks) if blocks != self.blocks => {
+                self.blocks = blocks;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        self.buttons.clear();
+        self.height.set(ctx.height());
+
+        // Every block sets the gap and separator that follow it.
+        let mut x = ctx.width();
+        for (index, block) in self.blocks.iter().enumerate() {
+            if let Some(previous) = index.checked_sub(1).map(|previous| &self.blocks[previous]) {
+                let gap = previous.separator_block_width.max(0.0);
+                if previous.separator && gap > 0.0 {
+                    let height = (ctx.height() * 0.6).round();
+                    ctx.rect(x + (gap / 2.0).floor(), ((ctx.height() - height) / 2.0).round(), 1.0, height,
+                        self.config.separator_color);
+                }
+                x += gap;
+            }
+
+            let width = self.draw_block(ctx, block, x);
+            self.buttons.push(x, x + width);
+            x += width;
+        }
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        let button = match button {
+            Button::Left => 1,
+            Button::Middle => 2,
+            Button::Right => 3,
+        };
+        self.send(x, button)
+    }
+
+    fn scroll(&mut self, x: f32, direction: Scroll) -> bool {
+        let button = match direction {
+            Scroll::Up => 4,
+            Scroll::Down => 5,
+        };
+        self.send(x, button)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_outside_the_blocks_are_skipped() {
+        for line in ["", "[", " [ ", ",", "{\"version\":1}garbage"] {
+            assert_eq!(parse_line(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn blocks_take_the_protocol_defaults() {
+        let line = r##",[{"full_text":"one","color":"#ff0000"},
+            {"full_text":"two","separator":false,"min_width":"00:00"}],"##;
+        let blocks = parse_line(line).unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].full_text, "one");
+        assert_eq!(color(&blocks[0].color), Some(Color::rgb(255, 0, 0)));
+        assert!(blocks[0].separator);
+        assert_eq!(blocks[0].separator_block_width, 9.0);
+        assert_eq!((blocks[0].border_top, blocks[0].border_left), (1.0, 1.0));
+        assert!(!blocks[1].separator);
+        assert_eq!(blocks[1].min_width, Some(MinWidth::Text("00:00".to_string())));
+    }
+
+    #[test]
+    fn unreadable_colors_are_left_out() {
+        let blocks = parse_line(r#"[{"full_text":"x","color":"sparkly","min_width":40}]"#).unwrap();
+        assert_eq!(color(&blocks[0].color), None);
+        assert_eq!(blocks[0].min_width, Some(MinWidth::Pixels(40.0)));
+    }
+}
@@ -0,0 +1,269 @@
+use serde::Deserialize;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::Error;
+use super::Waker;
+
+// Minimal client for the IPC protocol shared by i3 and sway: every message
+// is the magic string, the payload length and the message type, followed by
+// a JSON payload.
+
+const MAGIC: &[u8] = b"i3-ipc";
+
+pub const RUN_COMMAND: u32 = 0;
+pub const GET_WORKSPACES: u32 = 1;
+pub const SUBSCRIBE: u32 = 2;
+pub const GET_TREE: u32 = 4;
+pub const GET_INPUTS: u32 = 100;
+
+#[derive(Debug)]
+struct SocketNotFoundError;
+
+impl std::fmt::Display for SocketNotFoundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Neither sway nor i3 is running")
+    }
+}
+
+impl std::error::Error for SocketNotFoundError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Workspace {
+    pub num: i32,
+    pub name: String,
+    pub output: String,
+    pub focused: bool,
+    pub visible: bool,
+    pub urgent: bool,
+}
+
+// Only sway reports inputs, the keyboard fields are missing for other
+// devices.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Input {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub xkb_layout_names: Vec<String>,
+    pub xkb_active_layout_index: Option<usize>,
+}
+
+fn socket_path() -> Result<PathBuf, Error> {
+    for variable in &["SWAYSOCK", "I3SOCK"] {
+        if let Some(path) = std::env::var_os(variable) {
+            return Ok(PathBuf::from(path));
+        }
+    }
+
+    let output = Command::new("i3").arg("--get-socketpath").stderr(Stdio::null()).output()?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || path.is_empty() {
+        return Err(Box::new(SocketNotFoundError));
+    }
+
+    Ok(PathBuf::from(path))
+}
+
+// Depth first search for the focused container of a layout tree.
+fn focused_node(node: &serde_json::Value) -> Option<&serde_json::Value> {
+    if node["focused"].as_bool() == Some(true) {
+        return Some(node);
+    }
+
+    ["nodes", "floating_nodes"].iter()
+        .filter_map(|children| node[*children].as_array())
+        .flatten()
+        .find_map(focused_node)
+}
+
+// A window in the layout tree, as listed by a taskbar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub id: u64,
+    pub title: String,
+    // The Wayland app id, or the X11 class of Xwayland and i3 windows.
+    pub app_id: Option<String>,
+    pub focused: bool,
+}
+
+// Windows are the leaves of the tree below the workspaces, the scratchpad
+// hides its windows on purpose.
+fn collect_windows(node: &serde_json::Value, windows: &mut Vec<Window>) {
+    let kind = node["type"].as_str();
+    if kind == Some("workspace") && node["name"].as_str() == Some("__i3_scratch") {
+        return;
+    }
+
+    let children: Vec<&serde_json::Value> = ["nodes", "floating_nodes"].iter()
+        .filter_map(|children| node[*children].as_array())
+        .flatten()
+        .collect();
+    if children.is_empty() && matches!(kind, Some("con") | Some("floating_con")) {
+        let app_id = node["app_id"].as_str().or_else(|| node["window_properties"]["class"].as_str());
+        windows.push(Window {
+            id: node["id"].as_u64().unwrap_or(0),
+            title: node["name"].as_str().unwrap_or_default().to_string(),
+            app_id: app_id.map(str::to_string),
+            focused: node["focused"].as_bool() == Some(true),
+        });
+    }
+
+    for child in children {
+        collect_windows(child, windows);
+    }
+}
+
+pub struct Connection {
+    stream: UnixStream,
+}
+
+impl Connection {
+    pub fn connect() -> Result<Connection, Error> {
+        Ok(Connection { stream: UnixStream::connect(socket_path()?)? })
+    }
+
+    pub fn send(&mut self, kind: u32, payload: &str) -> Result<(), Error> {
+        let mut message = MAGIC.to_vec();
+        message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&kind.to_ne_bytes());
+        message.extend_from_slice(payload.as_bytes());
+
+        self.stream.write_all(&message)?;
+        Ok(())
+    }
+
+    // Returns the type and payload of the next message; events have the
+    // high bit of their type set.
+    pub fn receive(&mut self) -> Result<(u32, Vec<u8>), Error> {
+        let mut header = [0; 14];
+        self.stream.read_exact(&mut header)?;
+
+        let length = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+        let kind = u32::from_ne_bytes([header[10], header[11], header[12], header[13]]);
+
+        let mut payload = vec![0; length as usize];
+        self.stream.read_exact(&mut payload)?;
+
+        Ok((kind, payload))
+    }
+
+    pub fn request(&mut self, kind: u32, payload: &str) -> Result<Vec<u8>, Error> {
+        self.send(kind, payload)?;
+        loop {
+            let (reply, payload) = self.receive()?;
+            if reply == kind {
+                return Ok(payload);
+            }
+        }
+    }
+
+    pub fn workspaces(&mut self) -> Result<Vec<Workspace>, Error> {
+        Ok(serde_json::from_slice(&self.request(GET_WORKSPACES, "")?)?)
+    }
+
+    pub fn inputs(&mut self) -> Result<Vec<Input>, Error> {
+        Ok(serde_json::from_slice(&self.request(GET_INPUTS, "")?)?)
+    }
+
+    // Name of the focused window, None when a workspace without windows has
+    // the focus.
+    pub fn focused_title(&mut self) -> Result<Option<String>, Error> {
+        let tree: serde_json::Value = serde_json::from_slice(&self.request(GET_TREE, "")?)?;
+        let focused = match focused_node(&tree) {
+            Some(node) if matches!(node["type"].as_str(), Some("con") | Some("floating_con")) => node,
+            _ => return Ok(None),
+        };
+
+        Ok(focused["name"].as_str().map(|name| name.to_string()))
+    }
+
+    // Every window in tree order, which is how they're laid out on the
+    // outputs and workspaces.
+    pub fn windows(&mut self) -> Result<Vec<Window>, Error> {
+        let tree: serde_json::Value = serde_json::from_slice(&self.request(GET_TREE, "")?)?;
+        let mut windows = Vec::new();
+        collect_windows(&tree, &mut windows);
+        Ok(windows)
+    }
+
+    // Whether the focused window covers its workspace or every output.
+    pub fn focused_fullscreen(&mut self) -> Result<bool, Error> {
+        let tree: serde_json::Value = serde_json::from_slice(&self.request(GET_TREE, "")?)?;
+        Ok(focused_node(&tree).and_then(|node| node["fullscreen_mode"].as_u64()).is_some_and(|mode| mode > 0))
+    }
+}
+
+pub fn run_command(command: &str) -> Result<(), Error> {
+    Connection::connect()?.request(RUN_COMMAND, command)?;
+    Ok(())
+}
+
+// Follows the given event types and re-queries the state with `query` every
+// time one of them fires. Reconnects when the window manager restarts.
+pub struct Subscription<T> {
+    state: Arc<Mutex<Option<T>>>,
+    stream: Arc<Mutex<Option<UnixStream>>>,
+}
+
+impl<T: Send + 'static> Subscription<T> {
+    pub fn spawn<F>(events: &[&str], waker: Waker, mut query: F) -> Subscription<T>
+    where F: FnMut(&mut Connection) -> Result<T, Error> + Send + 'static {
+        let state = Arc::new(Mutex::new(None));
+        let stream = Arc::new(Mutex::new(None));
+        let (shared_state, shared_stream) = (Arc::downgrade(&state), Arc::downgrade(&stream));
+        let subscribe = serde_json::to_string(events).unwrap();
+
+        std::thread::spawn(move || loop {
+            let mut follow = || -> Result<(), Error> {
+                let mut connection = Connection::connect()?;
+                connection.request(SUBSCRIBE, &subscribe)?;
+
+                match shared_stream.upgrade() {
+                    Some(stream) => *stream.lock().unwrap() = Some(connection.stream.try_clone()?),
+                    None => return Ok(()),
+                }
+
+                loop {
+                    let value = query(&mut connection)?;
+                    match shared_state.upgrade() {
+                        Some(state) => *state.lock().unwrap() = Some(value),
+                        None => return Ok(()),
+                    }
+                    waker.wake();
+
+                    while connection.receive()?.0 & 0x8000_0000 == 0 {}
+                }
+            };
+
+            let _ = follow();
+            if shared_state.strong_count() == 0 {
+                return;
+            }
+
+            std::thread::sleep(Duration::from_secs(5));
+        });
+
+        Subscription { state, stream }
+    }
+
+    pub fn take(&self) -> Option<T> {
+        self.state.lock().unwrap().take()
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        if let Some(stream) = self.stream.lock().unwrap().take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
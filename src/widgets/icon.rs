@@ -0,0 +1,214 @@
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{Image, Primitive, RenderCtx};
+use super::{icons, Button, Scroll, Widget};
+
+// `icon` in a module section draws a PNG, JPEG or SVG file in front of the
+// widget, scaled to icon-size pixels high or most of the bar's height:
+//
+//     [module.custom]
+//     icon = "~/.icons/arch.png"
+//
+// Values without a slash are icon names looked up in icon-theme, symbolic
+// icons are drawn in icon-color or the widget's foreground:
+//
+//     [module.battery]
+//     icon = "battery-good-symbolic"
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Section {
+    pub icon: Option<String>,
+    pub icon_size: Option<f32>,
+    pub icon_spacing: Option<f32>,
+    pub icon_theme: Option<String>,
+    pub icon_color: Option<Color>,
+}
+
+#[derive(Debug)]
+struct IconNotFoundError(String);
+
+impl std::fmt::Display for IconNotFoundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No icon named '{}' could be found", self.0)
+    }
+}
+
+impl std::error::Error for IconNotFoundError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+const DEFAULT_SPACING: f32 = 4.0;
+
+// SVGs and named icons are first loaded at this height to check they can
+// be, and again once the bar's height is known.
+const INITIAL_SIZE: u32 = 22;
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+enum Source {
+    File(PathBuf),
+    Named { name: String, theme: Option<String> },
+}
+
+impl Source {
+    fn parse(icon: &str, section: &Section) -> Source {
+        match icon.contains('/') {
+            true => Source::File(expand_home(icon)),
+            false => Source::Named { name: icon.to_string(), theme: section.icon_theme.clone() },
+        }
+    }
+
+    // Themes may hold a different file for every size.
+    fn resolve(&self, size: u32) -> Result<PathBuf, Error> {
+        match self {
+            Source::File(path) => Ok(path.clone()),
+            Source::Named { name, theme } => icons::find(name, theme.as_deref(), size, None)
+                .ok_or_else(|| Box::new(IconNotFoundError(name.clone())) as Error),
+        }
+    }
+}
+
+pub struct WithIcon {
+    widget: Box<dyn Widget>,
+    source: Source,
+    // The file shown, its image and the size it was loaded for.
+    path: RefCell<PathBuf>,
+    image: RefCell<Arc<Image>>,
+    loaded: Cell<u32>,
+    size: Option<f32>,
+    color: Option<Color>,
+    spacing: f32,
+    // Where the widget starts as of the last render, the icon's width
+    // depends on the height of the bar.
+    offset: Cell<f32>,
+}
+
+impl WithIcon {
+    pub fn new(widget: Box<dyn Widget>, path: &str, section: &Section) -> Result<WithIcon, Error> {
+        let source = Source::parse(path, section);
+        let initial = section.icon_size.map(|size| size.round() as u32).unwrap_or(INITIAL_SIZE);
+        let path = source.resolve(initial)?;
+        Ok(WithIcon {
+            widget,
+            image: RefCell::new(icons::open(&path, initial)?),
+            path: RefCell::new(path),
+            source,
+            loaded: Cell::new(initial),
+            size: section.icon_size,
+            color: section.icon_color,
+            spacing: section.icon_spacing.unwrap_or(DEFAULT_SPACING),
+            offset: Cell::new(0.0),
+        })
+    }
+
+    // Looks up named icons and rasterizes scalable ones again whenever the
+    // size they're drawn at changes. If that fails the previous image is
+    // stretched instead.
+    fn image_at(&self, size: u32) -> Arc<Image> {
+        let mut image = self.image.borrow_mut();
+        let reloads = matches!(self.source, Source::Named { .. }) || icons::is_scalable(&self.path.borrow());
+        if reloads && self.loaded.get() != size {
+            self.loaded.set(size);
+            let reloaded = self.source.resolve(size)
+                .and_then(|path| Ok((icons::open(&path, size)?, path)));
+            match reloaded {
+                Ok((resized, path)) => {
+                    *image = resized;
+                    *self.path.borrow_mut() = path;
+                },
+                Err(err) => warn!("Failed to load {} at {}px: {}", self.path.borrow().display(), size, err),
+            }
+        }
+
+        Arc::clone(&image)
+    }
+}
+
+impl Widget for WithIcon {
+    fn update(&mut self) -> bool {
+        self.widget.update()
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let mut content = ctx.child(ctx.height());
+        self.widget.render(&mut content);
+        if content.width() <= 0.0 {
+            return;
+        }
+
+        // Icons keep their aspect ratio.
+        let height = self.size.unwrap_or_else(|| (ctx.height() * 0.7).round()).min(ctx.height());
+        let image = self.image_at(height.round() as u32);
+        let width = height * image.width() as f32 / image.height().max(1) as f32;
+        let x = ctx.width();
+        ctx.push(Primitive::Image {
+            x,
+            y: ((ctx.height() - height) / 2.0).round(),
+            width,
+            height,
+            image,
+            tint: icons::is_symbolic(&self.path.borrow()).then(|| self.color.unwrap_or_else(|| ctx.foreground())),
+        });
+
+        let offset = x + width + self.spacing;
+        self.offset.set(offset);
+        ctx.append(content, offset, 0.0);
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        self.widget.next_update()
+    }
+
+    fn animating(&self) -> bool {
+        self.widget.animating()
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        self.widget.click(x - self.offset.get(), button)
+    }
+
+    fn scroll(&mut self, x: f32, direction: Scroll) -> bool {
+        self.widget.scroll(x - self.offset.get(), direction)
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        self.widget.action(name)
+    }
+
+    fn tooltip(&self, x: f32) -> Option<String> {
+        self.widget.tooltip(x - self.offset.get())
+    }
+
+    fn popup(&self) -> Option<String> {
+        self.widget.popup()
+    }
+
+    fn popup_click(&mut self, line: usize, button: Button) -> bool {
+        self.widget.popup_click(line, button)
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.widget.value()
+    }
+
+    fn background(&self) -> Option<Color> {
+        self.widget.background()
+    }
+
+    fn click_through(&self) -> bool {
+        self.widget.click_through()
+    }
+}
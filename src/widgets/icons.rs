@@ -0,0 +1,307 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use crate::Error;
+use crate::render::Image;
+
+// Icon lookup following the freedesktop icon theme layout, for PNG and SVG
+// icons. Icon files given by path may also be JPEG. SVGs are rasterized at
+// the size they're shown at, so they stay crisp at any bar height.
+//
+// Without a configured theme the one picked in the GTK settings is used.
+// Icons named `-symbolic` are single-color outlines meant to be drawn in the
+// color of the surrounding text.
+
+// Extensions icon themes use, in order of preference.
+const THEME_EXTENSIONS: [&str; 2] = ["png", "svg"];
+
+#[derive(Debug)]
+struct UnsupportedImageError(PathBuf);
+
+impl std::fmt::Display for UnsupportedImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is not an image format that can be shown", self.0.display())
+    }
+}
+
+impl std::error::Error for UnsupportedImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+fn base_directories() -> Vec<PathBuf> {
+    let mut bases = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+        bases.push(home.join(".icons"));
+    }
+    if let Some(data) = dirs::data_dir() {
+        bases.push(data.join("icons"));
+    }
+
+    let data_dirs = std::env::var("XDG_DATA_DIRS").unwrap_or_else(|_| "/usr/local/share:/usr/share".to_string());
+    bases.extend(data_dirs.split(':').filter(|dir| !dir.is_empty()).map(|dir| Path::new(dir).join("icons")));
+
+    bases
+}
+
+#[derive(Debug, Default)]
+struct Theme {
+    // Subdirectories, the nominal icon size each of them holds and whether
+    // its icons scale to any size.
+    directories: Vec<(String, u32, bool)>,
+    inherits: Vec<String>,
+}
+
+impl Theme {
+    fn parse(index: &str) -> Theme {
+        let mut section = String::new();
+        let mut listed = Vec::new();
+        let mut inherits = Vec::new();
+        let mut sizes = HashMap::new();
+        let mut scalable = Vec::new();
+
+        for line in index.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = name.to_string();
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+
+            let list = || value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(str::to_string);
+            match (section.as_str(), key) {
+                ("Icon Theme", "Directories") => listed = list().collect(),
+                ("Icon Theme", "Inherits") => inherits = list().collect(),
+                (directory, "Size") => {
+                    if let Ok(size) = value.parse() {
+                        sizes.insert(directory.to_string(), size);
+                    }
+                },
+                (directory, "Type") if value == "Scalable" => scalable.push(directory.to_string()),
+                _ => (),
+            }
+        }
+
+        let directories = listed.into_iter()
+            .filter_map(|directory| {
+                let size = *sizes.get(&directory)?;
+                let scales = scalable.contains(&directory);
+                Some((directory, size, scales))
+            })
+            .collect();
+
+        Theme { directories, inherits }
+    }
+
+    fn load(bases: &[PathBuf], name: &str) -> Option<Theme> {
+        bases.iter().find_map(|base| std::fs::read_to_string(base.join(name).join("index.theme")).ok())
+            .map(|index| Theme::parse(&index))
+    }
+}
+
+// The icon closest to the given size: the smallest one at least that large,
+// otherwise the largest one available.
+fn best_match(candidates: Vec<(u32, PathBuf)>, size: u32) -> Option<PathBuf> {
+    let larger = candidates.iter().filter(|(found, _)| *found >= size).min_by_key(|(found, _)| *found);
+    let smaller = candidates.iter().max_by_key(|(found, _)| *found);
+
+    larger.or(smaller).map(|(_, path)| path.clone())
+}
+
+// Scalable icons match any size.
+fn find_in_theme(bases: &[PathBuf], theme_name: &str, theme: &Theme, name: &str, size: u32) -> Option<PathBuf> {
+    let candidates = theme.directories.iter()
+        .flat_map(|(directory, found, scales)| {
+            let found = if *scales { size } else { *found };
+            bases.iter().map(move |base| (found, base.join(theme_name).join(directory)))
+        })
+        .flat_map(|(found, directory)| {
+            THEME_EXTENSIONS.iter().map(move |extension| (found, directory.join(format!("{}.{}", name, extension))))
+        })
+        .filter(|(_, path)| path.is_file())
+        .collect();
+
+    best_match(candidates, size)
+}
+
+// Looks for the icon directly in a directory without theme structure.
+fn find_flat(directory: &Path, name: &str) -> Option<PathBuf> {
+    THEME_EXTENSIONS.iter()
+        .map(|extension| directory.join(format!("{}.{}", name, extension)))
+        .find(|path| path.is_file())
+}
+
+// Finds the file for an icon name in the given theme, the themes it
+// inherits from and hicolor, then in the extra directory an application
+// may ship its own icons in.
+fn user_theme() -> Option<String> {
+    let config = dirs::config_dir()?;
+    ["gtk-4.0", "gtk-3.0"].iter()
+        .filter_map(|version| std::fs::read_to_string(config.join(version).join("settings.ini")).ok())
+        .flat_map(|settings| settings.lines().map(str::to_string).collect::<Vec<_>>())
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            Some(value.trim().to_string()).filter(|_| key.trim() == "gtk-icon-theme-name")
+        })
+        .find(|theme| !theme.is_empty())
+}
+
+pub fn is_symbolic(path: &Path) -> bool {
+    path.file_stem().and_then(|stem| stem.to_str()).is_some_and(|stem| stem.ends_with("-symbolic"))
+}
+
+pub fn find(name: &str, theme: Option<&str>, size: u32, extra: Option<&Path>) -> Option<PathBuf> {
+    if Path::new(name).is_absolute() {
+        return Some(PathBuf::from(name)).filter(|path| path.is_file());
+    }
+
+    let mut bases = base_directories();
+    if let Some(extra) = extra {
+        if let Some(flat) = find_flat(extra, name) {
+            return Some(flat);
+        }
+        bases.insert(0, extra.to_path_buf());
+    }
+
+    // Inherited themes are searched breadth first, hicolor always comes last.
+    let theme = theme.map(str::to_string).or_else(user_theme);
+    let mut pending: VecDeque<String> = theme.into_iter().collect();
+    let mut visited: Vec<String> = Vec::new();
+    loop {
+        let theme_name = match pending.pop_front() {
+            Some(theme_name) => theme_name,
+            None if !visited.iter().any(|visited| visited == "hicolor") => "hicolor".to_string(),
+            None => break,
+        };
+
+        if visited.contains(&theme_name) {
+            continue;
+        }
+
+        if let Some(theme) = Theme::load(&bases, &theme_name) {
+            if let Some(path) = find_in_theme(&bases, &theme_name, &theme, name, size) {
+                return Some(path);
+            }
+            pending.extend(theme.inherits.into_iter().filter(|inherited| inherited != "hicolor"));
+        }
+        visited.push(theme_name);
+    }
+
+    find_flat(Path::new("/usr/share/pixmaps"), name)
+}
+
+// Scales the color channels of RGBA pixels by their alpha.
+pub fn premultiply(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel[..3] {
+            *channel = (*channel as u32 * alpha / 255) as u8;
+        }
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().and_then(|extension| extension.to_str()).map(str::to_lowercase)
+}
+
+pub fn is_scalable(path: &Path) -> bool {
+    matches!(extension(path).as_deref(), Some("svg") | Some("svgz"))
+}
+
+// Loads an image file, rasterizing scalable images at the given height in
+// pixels. Other images come at their own size.
+pub fn load(path: &Path, size: u32) -> Result<Image, Error> {
+    match extension(path).as_deref() {
+        Some("png") => load_png(path),
+        Some("jpg") | Some("jpeg") => load_jpeg(path),
+        Some("svg") | Some("svgz") => load_svg(path, size),
+        _ => Err(Box::new(UnsupportedImageError(path.to_path_buf()))),
+    }
+}
+
+// Loaded images by path and the size scalable ones were rasterized at.
+type Cache = HashMap<(PathBuf, u32), Weak<Image>>;
+
+// Images stay loaded while anything holds on to them, so modules showing
+// the same file share one image and with it one texture per renderer.
+// Scalable images are cached for every size they're rasterized at.
+pub fn open(path: &Path, size: u32) -> Result<Arc<Image>, Error> {
+    static LOADED: OnceLock<Mutex<Cache>> = OnceLock::new();
+    let mut loaded = LOADED.get_or_init(Default::default).lock().unwrap();
+
+    let key = (path.to_path_buf(), if is_scalable(path) { size } else { 0 });
+    if let Some(image) = loaded.get(&key).and_then(Weak::upgrade) {
+        return Ok(image);
+    }
+
+    let image = Arc::new(load(path, size)?);
+    loaded.retain(|_, image| image.strong_count() > 0);
+    loaded.insert(key, Arc::downgrade(&image));
+
+    Ok(image)
+}
+
+// tiny-skia renders premultiplied RGBA already.
+fn load_svg(path: &Path, size: u32) -> Result<Image, Error> {
+    use resvg::{tiny_skia, usvg};
+
+    let tree = usvg::Tree::from_data(&std::fs::read(path)?, &usvg::Options::default())?;
+    let natural = tree.size();
+    let scale = size.max(1) as f32 / natural.height();
+    let width = (natural.width() * scale).ceil().max(1.0) as u32;
+    let height = size.max(1);
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or_else(|| UnsupportedImageError(path.to_path_buf()))?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    Ok(Image::new(width, height, pixmap.take()))
+}
+
+fn load_jpeg(path: &Path) -> Result<Image, Error> {
+    let mut decoder = jpeg_decoder::Decoder::new(BufReader::new(File::open(path)?));
+    let buffer = decoder.decode()?;
+    let info = decoder.info().ok_or_else(|| UnsupportedImageError(path.to_path_buf()))?;
+
+    // JPEGs have no alpha, so there is nothing to premultiply.
+    let pixels: Vec<u8> = match info.pixel_format {
+        jpeg_decoder::PixelFormat::RGB24 => buffer.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+        jpeg_decoder::PixelFormat::L8 => buffer.iter().flat_map(|g| [*g, *g, *g, 255]).collect(),
+        jpeg_decoder::PixelFormat::L16 => buffer.chunks_exact(2).flat_map(|g| [g[0], g[0], g[0], 255]).collect(),
+        jpeg_decoder::PixelFormat::CMYK32 => buffer.chunks_exact(4)
+            .flat_map(|cmyk| {
+                let k = 255 - cmyk[3] as u32;
+                let channel = |value: u8| ((255 - value as u32) * k / 255) as u8;
+                [channel(cmyk[0]), channel(cmyk[1]), channel(cmyk[2]), 255]
+            })
+            .collect(),
+    };
+
+    Ok(Image::new(info.width as u32, info.height as u32, pixels))
+}
+
+fn load_png(path: &Path) -> Result<Image, Error> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap_or(0)];
+    let info = reader.next_frame(&mut buffer)?;
+    let buffer = &buffer[..info.buffer_size()];
+
+    let mut pixels: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => buffer.to_vec(),
+        png::ColorType::Rgb => buffer.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer.chunks_exact(2).flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]]).collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|g| [*g, *g, *g, 255]).collect(),
+        png::ColorType::Indexed => return Err(Box::new(UnsupportedImageError(path.to_path_buf()))),
+    };
+    premultiply(&mut pixels);
+
+    Ok(Image::new(info.width, info.height, pixels))
+}
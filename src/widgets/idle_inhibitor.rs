@@ -0,0 +1,124 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use tracing::warn;
+use zbus::zvariant::OwnedFd;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{Button, Waker, Widget};
+use super::actions::UnknownActionError;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct IdleInhibitorConfig {
+    format_active: String,
+    format_inactive: String,
+    // What logind holds off, "idle" or a colon separated list such as
+    // "idle:sleep".
+    what: String,
+    start_active: bool,
+    color: Option<Color>,
+    inactive_color: Color,
+}
+
+impl Default for IdleInhibitorConfig {
+    fn default() -> IdleInhibitorConfig {
+        IdleInhibitorConfig {
+            format_active: "awake".to_string(),
+            format_inactive: "idle".to_string(),
+            what: "idle".to_string(),
+            start_active: false,
+            color: None,
+            inactive_color: Color::from_str("#888888").unwrap(),
+        }
+    }
+}
+
+// logind keeps the lock for as long as the descriptor it hands out stays
+// open. Screen lockers and compositors that follow logind's idle hint don't
+// blank the screen meanwhile.
+fn inhibit(what: &str) -> Result<OwnedFd, Error> {
+    let connection = zbus::blocking::Connection::system()?;
+    let reply = connection.call_method(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1",
+        Some("org.freedesktop.login1.Manager"),
+        "Inhibit",
+        &(what, "rustybar", "Idle inhibitor toggled on the bar", "block"),
+    )?;
+
+    Ok(reply.body().deserialize()?)
+}
+
+pub struct IdleInhibitor {
+    config: IdleInhibitorConfig,
+    lock: Option<OwnedFd>,
+}
+
+impl IdleInhibitor {
+    pub fn build(section: &toml::Value, _waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let mut widget = IdleInhibitor { config: section.clone().try_into()?, lock: None };
+        if widget.config.start_active {
+            widget.toggle();
+        }
+
+        Ok(Box::new(widget))
+    }
+
+    // Asking logind is quick enough to not need a thread, and the bar has
+    // to know whether it got the lock to show it.
+    fn toggle(&mut self) {
+        if self.lock.take().is_some() {
+            return;
+        }
+
+        match inhibit(&self.config.what) {
+            Ok(lock) => self.lock = Some(lock),
+            Err(err) => warn!("Failed to inhibit idling: {}", err),
+        }
+    }
+
+    fn text(&self) -> &str {
+        match self.lock {
+            Some(_) => &self.config.format_active,
+            None => &self.config.format_inactive,
+        }
+    }
+}
+
+impl Widget for IdleInhibitor {
+    fn update(&mut self) -> bool {
+        false
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match self.lock {
+            Some(_) => self.config.color.unwrap_or_else(|| ctx.foreground()),
+            None => self.config.inactive_color,
+        };
+
+        ctx.text(self.text(), color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        if button == Button::Left {
+            self.toggle();
+            return true;
+        }
+
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "toggle" => self.toggle(),
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(true)
+    }
+
+    fn value(&self) -> Option<f64> {
+        Some(if self.lock.is_some() { 1.0 } else { 0.0 })
+    }
+}
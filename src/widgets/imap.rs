@@ -0,0 +1,325 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Shutdown, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::cell::Cell;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+use crate::Error;
+use super::Waker;
+
+// Just enough IMAP to count unseen messages and wait for new ones with
+// IDLE. TLS goes through `openssl s_client`, the same way HTTP goes through
+// curl elsewhere, which keeps a TLS stack out of the bar.
+
+// Servers drop IDLE after 30 minutes, so it's renewed well before that.
+const IDLE_RENEWAL: Duration = Duration::from_secs(25 * 60);
+// How soon a server has to answer anything but IDLE.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub struct ImapError(String);
+
+impl std::fmt::Display for ImapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IMAP server said: {}", self.0)
+    }
+}
+
+impl std::error::Error for ImapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Login {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub user: String,
+    // Either the password, or a command printing it such as `pass mail`.
+    pub password: Option<String>,
+    pub password_command: Option<String>,
+    pub mailbox: String,
+}
+
+impl Login {
+    // Asked for on every connection, so password managers that lock again
+    // after a while still work.
+    fn password(&self) -> Result<String, Error> {
+        let command = match (&self.password, &self.password_command) {
+            (Some(password), _) => return Ok(password.clone()),
+            (None, Some(command)) => command,
+            (None, None) => return Ok(String::new()),
+        };
+
+        let output = Command::new("sh").arg("-c").arg(command).stdin(Stdio::null()).output()?;
+        if !output.status.success() {
+            return Err(Box::new(ImapError(format!("'{}' failed with {}", command, output.status))));
+        }
+
+        let password = String::from_utf8_lossy(&output.stdout);
+        Ok(password.lines().next().unwrap_or_default().to_string())
+    }
+}
+
+// Closes the connection from another thread, which wakes up a reader
+// waiting on it.
+#[derive(Clone)]
+enum Closer {
+    Plain(Arc<TcpStream>),
+    Tls(Arc<Mutex<Child>>),
+}
+
+impl Closer {
+    fn close(&self) {
+        match self {
+            Closer::Plain(stream) => {
+                let _ = stream.shutdown(Shutdown::Both);
+            },
+            Closer::Tls(child) => {
+                let mut child = child.lock().unwrap();
+                let _ = child.kill();
+                let _ = child.wait();
+            },
+        }
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn received_line(received: Result<String, mpsc::RecvTimeoutError>) -> Result<String, Error> {
+    match received {
+        Ok(line) => Ok(line),
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(Box::new(ImapError("nothing in time".to_string()))),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(Box::new(ImapError("connection closed".to_string()))),
+    }
+}
+
+struct Connection {
+    writer: Box<dyn Write + Send>,
+    // Lines the server sent, read by a thread of their own so waiting for
+    // them can time out.
+    lines: mpsc::Receiver<String>,
+    closer: Closer,
+    tag: u32,
+}
+
+impl Connection {
+    fn open(login: &Login) -> Result<Connection, Error> {
+        let (reader, writer, closer): (Box<dyn Read + Send>, Box<dyn Write + Send>, Closer) = if login.tls {
+            // s_client only checks the chain unless it's told whose
+            // certificate to expect, and addresses have no server name.
+            let mut command = Command::new("openssl");
+            command.args(["s_client", "-quiet", "-verify_return_error"]);
+            match login.host.parse::<IpAddr>() {
+                Ok(_) => command.args(["-verify_ip", &login.host]),
+                Err(_) => command.args(["-verify_hostname", &login.host, "-servername", &login.host]),
+            };
+            let mut child = command
+                .args(["-connect", &format!("{}:{}", login.host, login.port)])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()?;
+            let (stdin, stdout) = (child.stdin.take().unwrap(), child.stdout.take().unwrap());
+            (Box::new(stdout), Box::new(stdin), Closer::Tls(Arc::new(Mutex::new(child))))
+        } else {
+            let stream = TcpStream::connect((login.host.as_str(), login.port))?;
+            (Box::new(stream.try_clone()?), Box::new(stream.try_clone()?), Closer::Plain(Arc::new(stream)))
+        };
+
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                if sender.send(line.trim_end_matches('\r').to_string()).is_err() {
+                    return;
+                }
+            }
+        });
+
+        // A certificate that fails verification makes s_client hang up
+        // before the server could greet.
+        let connection = Connection { writer, lines, closer, tag: 0 };
+        let greeting = match connection.lines.recv_timeout(RESPONSE_TIMEOUT) {
+            Err(mpsc::RecvTimeoutError::Disconnected) if login.tls => {
+                return Err(Box::new(ImapError(format!("no TLS connection to {}, its certificate may not be valid", login.host))));
+            },
+            received => received_line(received)?,
+        };
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            return Err(Box::new(ImapError(greeting)));
+        }
+
+        Ok(connection)
+    }
+
+    fn line(&self, timeout: Duration) -> Result<String, Error> {
+        received_line(self.lines.recv_timeout(timeout))
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), Error> {
+        self.writer.write_all(format!("{}\r\n", line).as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    // Runs a command and returns the untagged lines that came before its
+    // completion.
+    fn command(&mut self, command: &str) -> Result<Vec<String>, Error> {
+        self.tag += 1;
+        let tag = format!("a{}", self.tag);
+        self.send(&format!("{} {}", tag, command))?;
+        self.complete(&tag)
+    }
+
+    fn complete(&mut self, tag: &str) -> Result<Vec<String>, Error> {
+        let mut untagged = Vec::new();
+        loop {
+            let line = self.line(RESPONSE_TIMEOUT)?;
+            match line.strip_prefix(tag).map(str::trim_start) {
+                Some(status) if status.starts_with("OK") => return Ok(untagged),
+                Some(status) => return Err(Box::new(ImapError(status.to_string()))),
+                None => untagged.push(line),
+            }
+        }
+    }
+
+    fn unseen(&mut self) -> Result<usize, Error> {
+        let lines = self.command("SEARCH UNSEEN")?;
+        Ok(lines.iter()
+            .filter_map(|line| line.strip_prefix("* SEARCH"))
+            .map(|numbers| numbers.split_whitespace().count())
+            .sum())
+    }
+
+    // Waits until the mailbox changes or the IDLE needs renewing. Returns
+    // false when the server can't IDLE.
+    fn idle(&mut self) -> Result<bool, Error> {
+        self.tag += 1;
+        let tag = format!("a{}", self.tag);
+        self.send(&format!("{} IDLE", tag))?;
+
+        // New, removed and flagged messages are what can change the count,
+        // news may come in before the server confirms the IDLE.
+        let changes = |line: &str| ["EXISTS", "EXPUNGE", "FETCH"].iter().any(|change| line.contains(change));
+        let mut changed = false;
+        loop {
+            let answer = self.line(RESPONSE_TIMEOUT)?;
+            if answer.starts_with('+') {
+                break;
+            }
+            if answer.starts_with(&tag) {
+                return Ok(false);
+            }
+            changed |= changes(&answer);
+        }
+
+        while !changed {
+            match self.lines.recv_timeout(IDLE_RENEWAL) {
+                Ok(line) => changed = changes(&line),
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(Box::new(ImapError("connection closed".to_string())));
+                },
+            }
+        }
+
+        self.send("DONE")?;
+        self.complete(&tag)?;
+        Ok(true)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.closer.close();
+    }
+}
+
+// Keeps the unseen count of one mailbox up to date, waking the bar the
+// moment it changes. Servers without IDLE are asked every `interval`.
+pub struct Watcher {
+    state: Arc<Mutex<Option<Result<usize, String>>>>,
+    closer: Arc<Mutex<Option<Closer>>>,
+}
+
+impl Watcher {
+    pub fn spawn(login: Login, interval: Duration, waker: Waker) -> Watcher {
+        let state = Arc::new(Mutex::new(None));
+        let closer = Arc::new(Mutex::new(None));
+        let (shared_state, shared_closer) = (Arc::downgrade(&state), Arc::downgrade(&closer));
+
+        // Failing to log in or to reach the server is only worth a warning
+        // when it starts, not on every retry.
+        let failing = Cell::new(false);
+        std::thread::spawn(move || loop {
+            let publish = |count: Result<usize, String>| match shared_state.upgrade() {
+                Some(state) => {
+                    failing.set(count.is_err());
+                    *state.lock().unwrap() = Some(count);
+                    waker.wake();
+                    true
+                },
+                None => false,
+            };
+
+            let follow = || -> Result<(), Error> {
+                let mut connection = Connection::open(&login)?;
+                match shared_closer.upgrade() {
+                    Some(closer) => *closer.lock().unwrap() = Some(connection.closer.clone()),
+                    None => return Ok(()),
+                }
+
+                connection.command(&format!("LOGIN {} {}", quote(&login.user), quote(&login.password()?)))?;
+                connection.command(&format!("EXAMINE {}", quote(&login.mailbox)))?;
+                let mut can_idle = true;
+                loop {
+                    if !publish(Ok(connection.unseen()?)) {
+                        return Ok(());
+                    }
+
+                    can_idle = can_idle && connection.idle()?;
+                    if !can_idle {
+                        std::thread::sleep(interval);
+                        connection.command("NOOP")?;
+                    }
+                }
+            };
+
+            if let Err(err) = follow() {
+                match failing.get() {
+                    false => warn!("Failed to check mail on {}: {}", login.host, err),
+                    true => debug!("Failed to check mail on {} again: {}", login.host, err),
+                }
+                if !publish(Err(err.to_string())) {
+                    return;
+                }
+            }
+            if shared_state.strong_count() == 0 {
+                return;
+            }
+
+            std::thread::sleep(Duration::from_secs(30));
+        });
+
+        Watcher { state, closer }
+    }
+
+    // The newest count if it changed since the last call, or why the
+    // server couldn't be reached or logged in to.
+    pub fn take(&self) -> Option<Result<usize, String>> {
+        self.state.lock().unwrap().take()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        if let Some(closer) = self.closer.lock().unwrap().take() {
+            closer.close();
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use crate::x11::X11;
+use super::{format, i3ipc, Button, Scroll, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::ewmh::Watcher;
+use super::i3ipc::{Connection, Subscription};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct KeyboardConfig {
+    format: String,
+    // Labels to show instead of the layout names, such as "English (US)" =
+    // "US" under sway.
+    aliases: HashMap<String, String>,
+    color: Option<Color>,
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> KeyboardConfig {
+        KeyboardConfig {
+            format: "{short}".to_string(),
+            aliases: HashMap::new(),
+            color: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Layouts {
+    names: Vec<String>,
+    active: usize,
+}
+
+// Same split as the title widget: sway keeps its own keyboard state that X
+// clients never see, everything else uses XKB.
+enum Source {
+    Ipc(Subscription<Option<Layouts>>),
+    X11(Watcher<Option<Layouts>>),
+}
+
+impl Source {
+    fn take(&self) -> Option<Option<Layouts>> {
+        match self {
+            Source::Ipc(subscription) => subscription.take(),
+            Source::X11(watcher) => watcher.take(),
+        }
+    }
+
+    fn switch(&self, layouts: &Layouts, direction: Scroll) {
+        let count = layouts.names.len();
+        let result = match self {
+            Source::Ipc(_) => i3ipc::run_command(match direction {
+                Scroll::Up => "input type:keyboard xkb_switch_layout prev",
+                Scroll::Down => "input type:keyboard xkb_switch_layout next",
+            }),
+            Source::X11(_) => {
+                let group = match direction {
+                    Scroll::Up => (layouts.active + count - 1) % count,
+                    Scroll::Down => (layouts.active + 1) % count,
+                };
+                X11::connect().and_then(|x11| x11.lock_keyboard_group(group as u8))
+            },
+        };
+
+        if let Err(err) = result {
+            warn!("Failed to switch keyboard layout: {}", err);
+        }
+    }
+}
+
+fn query_x11(x11: &X11) -> Result<Option<Layouts>, Error> {
+    x11.select_keyboard_changes()?;
+
+    let names = x11.keyboard_layouts()?;
+    if names.is_empty() {
+        return Ok(None);
+    }
+
+    let active = (x11.keyboard_group()? as usize).min(names.len() - 1);
+    Ok(Some(Layouts { names, active }))
+}
+
+// All keyboards usually share one configuration, the first one that has
+// layouts stands in for the rest.
+fn query_ipc(connection: &mut Connection) -> Result<Option<Layouts>, Error> {
+    let keyboard = connection.inputs()?.into_iter()
+        .find(|input| input.kind == "keyboard" && !input.xkb_layout_names.is_empty());
+
+    Ok(keyboard.map(|input| {
+        let active = input.xkb_active_layout_index.unwrap_or(0).min(input.xkb_layout_names.len() - 1);
+        Layouts { names: input.xkb_layout_names, active }
+    }))
+}
+
+pub struct Keyboard {
+    config: KeyboardConfig,
+    source: Source,
+    layouts: Option<Layouts>,
+}
+
+impl Keyboard {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let source = if std::env::var_os("SWAYSOCK").is_some() {
+            Source::Ipc(Subscription::spawn(&["input"], waker.clone(), query_ipc))
+        } else {
+            Source::X11(Watcher::spawn(waker.clone(), query_x11))
+        };
+
+        Ok(Box::new(Keyboard {
+            config: section.clone().try_into()?,
+            source,
+            layouts: None,
+        }))
+    }
+
+    fn switch(&self, direction: Scroll) {
+        if let Some(layouts) = &self.layouts {
+            self.source.switch(layouts, direction);
+        }
+    }
+
+    // XKB names layouts by short codes already, sway by their full
+    // description, which is cut down to two letters.
+    fn short(&self, name: &str) -> String {
+        if let Some(alias) = self.config.aliases.get(name) {
+            return alias.clone();
+        }
+
+        match self.source {
+            Source::Ipc(_) => name.chars().take(2).collect::<String>().to_lowercase(),
+            Source::X11(_) => name.to_string(),
+        }
+    }
+}
+
+impl Widget for Keyboard {
+    fn update(&mut self) -> bool {
+        match self.source.take() {
+            Some(layouts) => {
+                let changed = layouts != self.layouts;
+                self.layouts = layouts;
+                changed
+            },
+            None => false,
+        }
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let layouts = match &self.layouts {
+            Some(layouts) => layouts,
+            None => return,
+        };
+
+        let name = &layouts.names[layouts.active];
+        let text = format::placeholders(&self.config.format, |key| match key {
+            "short" => Some(self.short(name)),
+            "name" => Some(name.clone()),
+            "index" => Some((layouts.active + 1).to_string()),
+            "count" => Some(layouts.names.len().to_string()),
+            _ => None,
+        });
+
+        let color = self.config.color.unwrap_or_else(|| ctx.foreground());
+        ctx.text(&text, color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        match button {
+            Button::Left => self.switch(Scroll::Down),
+            Button::Right => self.switch(Scroll::Up),
+            Button::Middle => (),
+        }
+        false
+    }
+
+    fn scroll(&mut self, _x: f32, direction: Scroll) -> bool {
+        self.switch(direction);
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "next" => self.switch(Scroll::Down),
+            "previous" => self.switch(Scroll::Up),
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+        Ok(false)
+    }
+}
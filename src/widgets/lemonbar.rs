@@ -0,0 +1,445 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::iter::Peekable;
+use std::process::{Child, Command, Stdio};
+use std::str::Chars;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{Button, Scroll, Waker, Widget};
+use super::actions::spawn_command;
+use super::buttons::Buttons;
+
+// Shows lemonbar formatted lines read from a command, or from stdin when no
+// command is set, so scripts written for lemonbar keep working:
+//
+//     [module.lemonbar]
+//     command = "~/.config/lemonbar/status.sh"
+//
+// Text after %{l}, %{c} and %{r} can go to modules in the matching group by
+// giving each of them a `part`, modules showing the same command share it.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Part {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct LemonbarConfig {
+    command: String,
+    // Without a part every section is shown one after the other.
+    part: Option<Part>,
+    color: Option<Color>,
+    // Reversed text with no background is drawn in this color.
+    background: Color,
+    line_width: f32,
+}
+
+impl Default for LemonbarConfig {
+    fn default() -> LemonbarConfig {
+        LemonbarConfig {
+            command: String::new(),
+            part: None,
+            color: None,
+            background: "#000000".parse().unwrap(),
+            line_width: 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Style {
+    foreground: Option<Color>,
+    background: Option<Color>,
+    // Shared by underlines and overlines, as in lemonbar.
+    line_color: Option<Color>,
+    underline: bool,
+    overline: bool,
+    reversed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    // Clickable areas may be nested, the innermost one with an action for
+    // the button wins.
+    Text { text: String, style: Style, actions: Vec<(u8, String)> },
+    Offset(f32),
+}
+
+// Colors that don't parse and unknown attributes are ignored, like lemonbar
+// does. The alpha comes first, as in #AARRGGBB.
+fn color(value: &str) -> Option<Option<Color>> {
+    let digits = value.strip_prefix('#').filter(|digits| digits.is_ascii() && matches!(digits.len(), 4 | 8));
+    match (value, digits) {
+        ("-", _) => Some(None),
+        (_, Some(digits)) => {
+            let alpha = digits.len() / 4;
+            format!("#{}{}", &digits[alpha..], &digits[..alpha]).parse().ok().map(Some)
+        },
+        (_, None) => value.parse().ok().map(Some),
+    }
+}
+
+fn word(chars: &mut Peekable<Chars>) -> String {
+    let mut word = String::new();
+    while let Some(&c) = chars.peek() {
+        if c == ' ' || c == '}' {
+            break;
+        }
+        word.push(c);
+        chars.next();
+    }
+    word
+}
+
+fn digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(c);
+        chars.next();
+    }
+    digits
+}
+
+// Commands end at the next colon not escaped with a backslash.
+fn command(chars: &mut Peekable<Chars>) -> String {
+    let mut command = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&':') => command.push(chars.next().unwrap()),
+            ':' => break,
+            c => command.push(c),
+        }
+    }
+    command
+}
+
+#[derive(Default)]
+struct Parser {
+    parts: [Vec<Piece>; 3],
+    part: usize,
+    style: Style,
+    actions: Vec<(u8, String)>,
+    text: String,
+}
+
+impl Parser {
+    fn flush(&mut self) {
+        if !self.text.is_empty() {
+            let text = std::mem::take(&mut self.text);
+            self.parts[self.part].push(Piece::Text { text, style: self.style, actions: self.actions.clone() });
+        }
+    }
+
+    fn attributes(&mut self, chars: &mut Peekable<Chars>) {
+        while let Some(c) = chars.next() {
+            match c {
+                '}' => return,
+                ' ' => (),
+                'l' => self.part = 0,
+                'c' => self.part = 1,
+                'r' => self.part = 2,
+                'R' => self.style.reversed = !self.style.reversed,
+                'F' => self.style.foreground = color(&word(chars)).unwrap_or(self.style.foreground),
+                'B' => self.style.background = color(&word(chars)).unwrap_or(self.style.background),
+                'U' => self.style.line_color = color(&word(chars)).unwrap_or(self.style.line_color),
+                '+' | '-' | '!' => {
+                    let line = match chars.next() {
+                        Some('u') => &mut self.style.underline,
+                        Some('o') => &mut self.style.overline,
+                        _ => continue,
+                    };
+                    *line = match c {
+                        '+' => true,
+                        '-' => false,
+                        _ => !*line,
+                    };
+                },
+                'A' => {
+                    let button = digits(chars);
+                    if chars.peek() == Some(&':') {
+                        chars.next();
+                        let button = button.parse().unwrap_or(1);
+                        let command = command(chars);
+                        self.actions.push((button, command));
+                    } else {
+                        self.actions.pop();
+                    }
+                },
+                'O' => {
+                    if let Ok(offset) = word(chars).parse() {
+                        self.parts[self.part].push(Piece::Offset(offset));
+                    }
+                },
+                _ => {
+                    word(chars);
+                },
+            }
+        }
+    }
+}
+
+fn parse(line: &str) -> [Vec<Piece>; 3] {
+    let mut parser = Parser::default();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '%' && chars.peek() == Some(&'{') {
+            chars.next();
+            parser.flush();
+            parser.attributes(&mut chars);
+        } else {
+            parser.text.push(c);
+        }
+    }
+    parser.flush();
+
+    parser.parts
+}
+
+// The newest line of a command along with a count of lines read, so every
+// module showing it can tell when it changed.
+struct Feed {
+    line: Mutex<(u64, String)>,
+    // All modules of a bar share one waker, the newest one is used.
+    waker: Mutex<Waker>,
+    child: Mutex<Option<Child>>,
+}
+
+impl Feed {
+    fn read<R: std::io::Read>(feed: Weak<Feed>, reader: R) {
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            let feed = match feed.upgrade() {
+                Some(feed) => feed,
+                None => return,
+            };
+
+            let mut latest = feed.line.lock().unwrap();
+            *latest = (latest.0 + 1, line);
+            drop(latest);
+            feed.waker.lock().unwrap().wake();
+        }
+    }
+
+    // Stdin can only be read once, so its feed lives as long as the bar.
+    fn open(command: &str, waker: &Waker) -> Result<Arc<Feed>, Error> {
+        static FEEDS: OnceLock<Mutex<HashMap<String, Weak<Feed>>>> = OnceLock::new();
+        let mut feeds = FEEDS.get_or_init(Default::default).lock().unwrap();
+
+        if let Some(feed) = feeds.get(command).and_then(Weak::upgrade) {
+            *feed.waker.lock().unwrap() = waker.clone();
+            return Ok(feed);
+        }
+
+        let feed = Arc::new(Feed {
+            line: Mutex::new((0, String::new())),
+            waker: Mutex::new(waker.clone()),
+            child: Mutex::new(None),
+        });
+
+        if command.is_empty() {
+            let stdin = Arc::clone(&feed);
+            std::thread::spawn(move || Feed::read(Arc::downgrade(&stdin), std::io::stdin()));
+        } else {
+            let mut child = Command::new("sh").arg("-c").arg(command)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .spawn()?;
+            let stdout = child.stdout.take().unwrap();
+            *feed.child.lock().unwrap() = Some(child);
+
+            let shared = Arc::downgrade(&feed);
+            std::thread::spawn(move || Feed::read(shared, stdout));
+        }
+
+        feeds.retain(|_, feed| feed.strong_count() > 0);
+        feeds.insert(command.to_string(), Arc::downgrade(&feed));
+
+        Ok(feed)
+    }
+}
+
+impl Drop for Feed {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.lock().unwrap().take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+pub struct Lemonbar {
+    config: LemonbarConfig,
+    feed: Arc<Feed>,
+    seen: u64,
+    pieces: Vec<Piece>,
+    buttons: Buttons,
+}
+
+impl Lemonbar {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: LemonbarConfig = section.clone().try_into()?;
+
+        Ok(Box::new(Lemonbar {
+            feed: Feed::open(&config.command, waker)?,
+            config,
+            seen: 0,
+            pieces: Vec::new(),
+            buttons: Buttons::default(),
+        }))
+    }
+
+    // Buttons 4 and 5 are scrolling up and down, as in X11.
+    fn run(&self, x: f32, button: u8) -> bool {
+        let actions = self.buttons.at(x).and_then(|index| match &self.pieces[index] {
+            Piece::Text { actions, .. } => Some(actions),
+            Piece::Offset(_) => None,
+        });
+
+        if let Some((_, command)) = actions.and_then(|actions| actions.iter().rev().find(|(known, _)| *known == button)) {
+            if let Err(err) = spawn_command(command) {
+                warn!("Failed to run '{}': {}", command, err);
+            }
+        }
+        false
+    }
+}
+
+impl Widget for Lemonbar {
+    fn update(&mut self) -> bool {
+        let (count, line) = self.feed.line.lock().unwrap().clone();
+        if count == self.seen {
+            return false;
+        }
+        self.seen = count;
+
+        let [left, center, right] = parse(&line);
+        let pieces = match self.config.part {
+            Some(Part::Left) => left,
+            Some(Part::Center) => center,
+            Some(Part::Right) => right,
+            None => [left, center, right].concat(),
+        };
+
+        let changed = pieces != self.pieces;
+        self.pieces = pieces;
+        changed
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        self.buttons.clear();
+        let default = self.config.color.unwrap_or_else(|| ctx.foreground());
+        let line_width = self.config.line_width;
+
+        for piece in &self.pieces {
+            let start = ctx.width();
+            let (text, style) = match piece {
+                Piece::Text { text, style, .. } => (text, style),
+                Piece::Offset(offset) => {
+                    ctx.advance(*offset);
+                    self.buttons.push(start, start);
+                    continue;
+                },
+            };
+
+            let (foreground, background) = match style.reversed {
+                true => (style.background.unwrap_or(self.config.background), Some(style.foreground.unwrap_or(default))),
+                false => (style.foreground.unwrap_or(default), style.background),
+            };
+            let width = ctx.measure(text);
+
+            if let Some(background) = background {
+                ctx.rect(start, 0.0, width, ctx.height(), background);
+            }
+            ctx.text_at(start, text, foreground);
+
+            let line = style.line_color.unwrap_or(foreground);
+            if style.underline {
+                ctx.rect(start, ctx.height() - line_width, width, line_width, line);
+            }
+            if style.overline {
+                ctx.rect(start, 0.0, width, line_width, line);
+            }
+
+            self.buttons.push(start, start + width);
+        }
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        let button = match button {
+            Button::Left => 1,
+            Button::Middle => 2,
+            Button::Right => 3,
+        };
+        self.run(x, button)
+    }
+
+    fn scroll(&mut self, x: f32, direction: Scroll) -> bool {
+        let button = match direction {
+            Scroll::Up => 4,
+            Scroll::Down => 5,
+        };
+        self.run(x, button)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str, style: Style, actions: &[(u8, &str)]) -> Piece {
+        let actions = actions.iter().map(|(button, command)| (*button, command.to_string())).collect();
+        Piece::Text { text: text.to_string(), style, actions }
+    }
+
+    #[test]
+    fn colors_take_the_alpha_first() {
+        let parsed = |value: &str| color(value).map(|color| color.map(|color| color.to_string()));
+        assert_eq!(parsed("#FF282828"), Some(Some("#282828ff".to_string())));
+        assert_eq!(parsed("#80282828"), Some(Some("#28282880".to_string())));
+        assert_eq!(parsed("#8f00"), Some(Some("#ff000088".to_string())));
+        assert_eq!(parsed("#282828"), Some(Some("#282828ff".to_string())));
+        assert_eq!(parsed("red"), Some(Some("#ff0000ff".to_string())));
+        assert_eq!(parsed("-"), Some(None));
+        assert_eq!(parsed("#zzzz"), None);
+        assert_eq!(parsed("#éé"), None);
+    }
+
+    #[test]
+    fn lines_split_into_parts() {
+        let red = Style { foreground: "#f00".parse().ok(), ..Style::default() };
+        let [left, center, right] = parse("%{l}a%{c}%{F#f00}b%{F-} c%{r}d");
+        assert_eq!(left, vec![text("a", Style::default(), &[])]);
+        assert_eq!(center, vec![text("b", red, &[]), text(" c", Style::default(), &[])]);
+        assert_eq!(right, vec![text("d", Style::default(), &[])]);
+    }
+
+    #[test]
+    fn attributes_style_the_text_after_them() {
+        let lined = Style { underline: true, overline: true, line_color: "#0f0".parse().ok(), ..Style::default() };
+        let [left, _, _] = parse("50% %{+u!o U#0f0}x%{-u -o R}y%{T2 O12}z");
+        assert_eq!(left, vec![
+            text("50% ", Style::default(), &[]),
+            text("x", lined, &[]),
+            text("y", Style { underline: false, overline: false, reversed: true, ..lined }, &[]),
+            Piece::Offset(12.0),
+            text("z", Style { underline: false, overline: false, reversed: true, ..lined }, &[]),
+        ]);
+    }
+
+    #[test]
+    fn clickable_areas_nest() {
+        let [left, _, _] = parse("%{A:outer:}a%{A3:echo\\: hi:}b%{A}c%{A}d");
+        assert_eq!(left, vec![
+            text("a", Style::default(), &[(1, "outer")]),
+            text("b", Style::default(), &[(1, "outer"), (3, "echo: hi")]),
+            text("c", Style::default(), &[(1, "outer")]),
+            text("d", Style::default(), &[]),
+        ]);
+    }
+}
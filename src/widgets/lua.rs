@@ -0,0 +1,259 @@
+use mlua::{Function, Lua, Table};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{actions, Button, Scroll, Waker, Widget};
+
+// A module written in Lua, right in the config:
+//
+//     [module.counter]
+//     type = "lua"
+//     script = '''
+//     count = 0
+//     function update() return "clicked " .. count .. " times" end
+//     function click(button, x) count = count + 1 end
+//     '''
+//
+// `update` is called every `interval` seconds and what it returns is shown,
+// `click` receives X11 button numbers with 4 and 5 for scrolling. Other
+// global functions can be bound with `on-click = { action = "name" }`.
+// Scripts talk to the bar through the `bar` table:
+//
+//     bar.text(text), bar.color("#rrggbb")    set what the module shows
+//     bar.every(seconds, f), bar.after(seconds, f)
+//     bar.exec(command)                       runs it, returns stdout and code
+//     bar.spawn(command)                      runs it in the background
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct LuaConfig {
+    script: String,
+    interval: f64,
+    color: Option<Color>,
+}
+
+impl Default for LuaConfig {
+    fn default() -> LuaConfig {
+        LuaConfig { script: String::new(), interval: 1.0, color: None }
+    }
+}
+
+#[derive(Default)]
+struct Output {
+    text: String,
+    color: Option<Color>,
+    changed: bool,
+}
+
+struct Timer {
+    due: Instant,
+    period: Option<Duration>,
+    callback: Function,
+}
+
+// A Lua state with the bar API installed. Inline click handlers get one of
+// their own as well, the text they set just isn't shown anywhere.
+pub struct Script {
+    lua: Lua,
+    output: Rc<RefCell<Output>>,
+    timers: Rc<RefCell<Vec<Timer>>>,
+}
+
+// Runs through the shell and waits for it, so scripts shouldn't exec
+// anything slow.
+fn exec(command: &str) -> Result<(String, Option<i32>), Error> {
+    let output = Command::new("sh").arg("-c").arg(command)
+        .stdin(Stdio::null())
+        .output()?;
+
+    Ok((String::from_utf8_lossy(&output.stdout).to_string(), output.status.code()))
+}
+
+impl Script {
+    pub fn new(name: &str, code: &str) -> Result<Script, Error> {
+        let script = Script {
+            lua: Lua::new(),
+            output: Rc::new(RefCell::new(Output::default())),
+            timers: Rc::new(RefCell::new(Vec::new())),
+        };
+        script.install()?;
+        script.lua.load(code).set_name(name).exec()?;
+
+        Ok(script)
+    }
+
+    fn install(&self) -> mlua::Result<()> {
+        let bar = self.lua.create_table()?;
+
+        let output = self.output.clone();
+        bar.set("text", self.lua.create_function(move |_, text: String| {
+            let mut output = output.borrow_mut();
+            output.changed |= output.text != text;
+            output.text = text;
+            Ok(())
+        })?)?;
+
+        let output = self.output.clone();
+        bar.set("color", self.lua.create_function(move |_, color: Option<String>| {
+            let color = color.map(|color| color.parse::<Color>()).transpose().map_err(mlua::Error::external)?;
+            let mut output = output.borrow_mut();
+            output.changed |= output.color != color;
+            output.color = color;
+            Ok(())
+        })?)?;
+
+        for (key, repeat) in [("every", true), ("after", false)] {
+            let timers = self.timers.clone();
+            bar.set(key, self.lua.create_function(move |_, (seconds, callback): (f64, Function)| {
+                let period = Duration::from_secs_f64(seconds.max(0.1));
+                timers.borrow_mut().push(Timer {
+                    due: Instant::now() + period,
+                    period: Some(period).filter(|_| repeat),
+                    callback,
+                });
+                Ok(())
+            })?)?;
+        }
+
+        bar.set("exec", self.lua.create_function(|_, command: String| {
+            exec(&command).map_err(|err| mlua::Error::external(err.to_string()))
+        })?)?;
+        bar.set("spawn", self.lua.create_function(|_, command: String| {
+            actions::spawn_command(&command).map_err(|err| mlua::Error::external(err.to_string()))
+        })?)?;
+
+        self.lua.globals().set("bar", bar)
+    }
+
+    fn globals(&self) -> Table {
+        self.lua.globals()
+    }
+
+    pub fn run(&self, code: &str) -> Result<(), Error> {
+        Ok(self.lua.load(code).set_name("handler").exec()?)
+    }
+
+    // Calls the timers that are due, returning true when one changed the
+    // output.
+    pub fn fire_timers(&self) -> bool {
+        let now = Instant::now();
+        let due: Vec<Function> = {
+            let mut timers = self.timers.borrow_mut();
+            let due = timers.iter().filter(|timer| timer.due <= now).map(|timer| timer.callback.clone()).collect();
+            timers.retain(|timer| timer.due > now || timer.period.is_some());
+            for timer in timers.iter_mut().filter(|timer| timer.due <= now) {
+                timer.due = now + timer.period.unwrap_or_default();
+            }
+            due
+        };
+
+        for callback in due {
+            if let Err(err) = callback.call::<()>(()) {
+                warn!("Lua timer failed: {}", err);
+            }
+        }
+        self.take_changed()
+    }
+
+    pub fn next_timer(&self) -> Option<Instant> {
+        self.timers.borrow().iter().map(|timer| timer.due).min()
+    }
+
+    fn take_changed(&self) -> bool {
+        std::mem::take(&mut self.output.borrow_mut().changed)
+    }
+}
+
+pub struct LuaWidget {
+    config: LuaConfig,
+    script: Script,
+    next: Instant,
+}
+
+impl LuaWidget {
+    pub fn build(section: &toml::Value, _waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: LuaConfig = section.clone().try_into()?;
+        let script = Script::new("script", &config.script)?;
+
+        Ok(Box::new(LuaWidget { config, script, next: Instant::now() }))
+    }
+
+    // Calls a global function, showing what it returns when that is text.
+    fn call<A: mlua::IntoLuaMulti>(&mut self, name: &str, args: A) -> Result<bool, Error> {
+        let function: Option<Function> = self.script.globals().get(name)?;
+        let function = match function {
+            Some(function) => function,
+            None => return Ok(false),
+        };
+
+        if let Some(text) = function.call::<Option<String>>(args)? {
+            let mut output = self.script.output.borrow_mut();
+            output.changed |= output.text != text;
+            output.text = text;
+        }
+        Ok(self.script.take_changed())
+    }
+
+    fn click_button(&mut self, x: f32, button: u8) -> bool {
+        self.call("click", (button, x)).unwrap_or_else(|err| {
+            warn!("Lua click handler failed: {}", err);
+            false
+        })
+    }
+}
+
+impl Widget for LuaWidget {
+    fn update(&mut self) -> bool {
+        let mut changed = self.script.fire_timers();
+
+        let now = Instant::now();
+        if now >= self.next {
+            self.next = now + Duration::from_secs_f64(self.config.interval.max(0.1));
+            changed |= self.call("update", ()).unwrap_or_else(|err| {
+                warn!("Lua update failed: {}", err);
+                false
+            });
+        }
+        changed
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let output = self.script.output.borrow();
+        let color = output.color.or(self.config.color).unwrap_or_else(|| ctx.foreground());
+        ctx.text(&output.text, color);
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        self.script.next_timer().into_iter().chain(Some(self.next)).min()
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        let button = match button {
+            Button::Left => 1,
+            Button::Middle => 2,
+            Button::Right => 3,
+        };
+        self.click_button(x, button)
+    }
+
+    fn scroll(&mut self, x: f32, direction: Scroll) -> bool {
+        let button = match direction {
+            Scroll::Up => 4,
+            Scroll::Down => 5,
+        };
+        self.click_button(x, button)
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match self.script.globals().get::<Option<Function>>(name)? {
+            Some(_) => self.call(name, ()),
+            None => Err(Box::new(actions::UnknownActionError(name.to_string()))),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, Waker, Widget};
+use super::imap::{self, Login};
+use super::worker::Poller;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct AccountConfig {
+    // Names the account's own placeholder, such as {work}.
+    name: String,
+    // A maildir folder, or the IMAP server holding the mailbox.
+    maildir: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    // Plain connections are for servers on the same machine, such as mail
+    // bridges.
+    tls: bool,
+    user: String,
+    password: Option<String>,
+    password_command: Option<String>,
+    mailbox: String,
+}
+
+impl Default for AccountConfig {
+    fn default() -> AccountConfig {
+        AccountConfig {
+            name: "mail".to_string(),
+            maildir: None,
+            host: None,
+            port: None,
+            tls: true,
+            user: String::new(),
+            password: None,
+            password_command: None,
+            mailbox: "INBOX".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct MailConfig {
+    accounts: Vec<AccountConfig>,
+    format: String,
+    format_none: String,
+    // Shown in place of the others while an account can't be counted,
+    // with the same placeholders.
+    format_unreachable: String,
+    // Seconds between counts of maildirs, and of IMAP mailboxes on servers
+    // that can't tell about new mail by themselves.
+    interval: f64,
+    color: Option<Color>,
+    none_color: Color,
+    unreachable_color: Color,
+}
+
+impl Default for MailConfig {
+    fn default() -> MailConfig {
+        MailConfig {
+            accounts: Vec::new(),
+            format: "MAIL {unread}".to_string(),
+            format_none: String::new(),
+            format_unreachable: "MAIL ?".to_string(),
+            interval: 10.0,
+            color: None,
+            none_color: Color::from_str("#888888").unwrap(),
+            unreachable_color: Color::from_str("#ff3333").unwrap(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AccountError(String);
+
+impl std::fmt::Display for AccountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Mail account '{}' needs either a maildir or an IMAP host", self.0)
+    }
+}
+
+impl std::error::Error for AccountError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+// Messages nobody has looked at yet wait in new, the ones in cur are unread
+// until their info carries the seen flag, as in "1234.host:2,S".
+fn count_maildir(path: &Path) -> Option<usize> {
+    let new = std::fs::read_dir(path.join("new")).ok()?.filter_map(|entry| entry.ok()).count();
+    let unseen = std::fs::read_dir(path.join("cur")).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            let flags = name.to_str().and_then(|name| name.rsplit_once(":2,")).map(|(_, flags)| flags);
+            !flags.is_some_and(|flags| flags.contains('S'))
+        })
+        .count();
+
+    Some(new + unseen)
+}
+
+enum Source {
+    Maildir(Poller<Option<usize>>),
+    Imap(imap::Watcher),
+}
+
+impl Source {
+    // The newest count if there is one, or why there's none.
+    fn take(&self) -> Option<Result<usize, String>> {
+        match self {
+            Source::Maildir(poller) => poller.take()
+                .map(|count| count.ok_or_else(|| "the maildir can't be read".to_string())),
+            Source::Imap(watcher) => watcher.take(),
+        }
+    }
+}
+
+struct Account {
+    name: String,
+    source: Source,
+    // Nothing until counted, or why the mailbox can't be counted.
+    unread: Option<Result<usize, String>>,
+}
+
+impl Account {
+    fn count(&self) -> Option<usize> {
+        self.unread.as_ref().and_then(|unread| unread.as_ref().ok().copied())
+    }
+
+    fn unreachable(&self) -> bool {
+        self.unread.as_ref().is_some_and(Result::is_err)
+    }
+}
+
+pub struct Mail {
+    config: MailConfig,
+    accounts: Vec<Account>,
+    text: String,
+}
+
+impl Mail {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: MailConfig = section.clone().try_into()?;
+        let interval = Duration::from_secs_f64(config.interval.max(1.0));
+
+        let accounts = config.accounts.iter()
+            .map(|account| {
+                let source = match (&account.maildir, &account.host) {
+                    (Some(maildir), _) => {
+                        let path = expand_home(maildir);
+                        Source::Maildir(Poller::spawn(interval, waker.clone(), move || count_maildir(&path)))
+                    },
+                    (None, Some(host)) => Source::Imap(imap::Watcher::spawn(Login {
+                        host: host.clone(),
+                        port: account.port.unwrap_or(if account.tls { 993 } else { 143 }),
+                        tls: account.tls,
+                        user: account.user.clone(),
+                        password: account.password.clone(),
+                        password_command: account.password_command.clone(),
+                        mailbox: account.mailbox.clone(),
+                    }, interval, waker.clone())),
+                    (None, None) => return Err(Box::new(AccountError(account.name.clone())) as Error),
+                };
+
+                Ok(Account { name: account.name.clone(), source, unread: None })
+            })
+            .collect::<Result<Vec<Account>, Error>>()?;
+
+        Ok(Box::new(Mail { config, accounts, text: String::new() }))
+    }
+
+    fn unread(&self) -> usize {
+        self.accounts.iter().filter_map(Account::count).sum()
+    }
+
+    fn unreachable(&self) -> bool {
+        self.accounts.iter().any(Account::unreachable)
+    }
+
+    // Accounts that can't be counted show a question mark in their own
+    // placeholder and are left out of the total.
+    fn format(&self) -> String {
+        let unread = self.unread();
+        let template = match (self.unreachable(), unread) {
+            (true, _) => &self.config.format_unreachable,
+            (false, 0) => &self.config.format_none,
+            (false, _) => &self.config.format,
+        };
+
+        format::placeholders(template, |key| match key {
+            "unread" => Some(unread.to_string()),
+            _ => self.accounts.iter().find(|account| account.name == key)
+                .map(|account| account.count().map(|unread| unread.to_string()).unwrap_or_else(|| "?".to_string())),
+        })
+    }
+}
+
+impl Widget for Mail {
+    fn update(&mut self) -> bool {
+        let mut counted = false;
+        for account in &mut self.accounts {
+            if let Some(unread) = account.source.take() {
+                account.unread = Some(unread);
+                counted = true;
+            }
+        }
+        if !counted {
+            return false;
+        }
+
+        let text = self.format();
+        let changed = text != self.text;
+        self.text = text;
+        changed
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match (self.unreachable(), self.unread()) {
+            (true, _) => self.config.unreachable_color,
+            (false, 0) => self.config.none_color,
+            (false, _) => self.config.color.unwrap_or_else(|| ctx.foreground()),
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let lines: Vec<String> = self.accounts.iter()
+            .map(|account| match &account.unread {
+                Some(Ok(unread)) => format!("{} <span foreground=\"#888888\">{} unread</span>", markup::escape(&account.name), unread),
+                Some(Err(err)) => format!("{} <span foreground=\"#888888\">unreachable: {}</span>",
+                    markup::escape(&account.name), markup::escape(err)),
+                None => format!("{} <span foreground=\"#888888\">not counted yet</span>", markup::escape(&account.name)),
+            })
+            .collect();
+
+        Some(lines.join("\n")).filter(|text| !text.is_empty())
+    }
+
+    fn value(&self) -> Option<f64> {
+        Some(self.unread() as f64)
+    }
+}
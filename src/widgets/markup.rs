@@ -0,0 +1,82 @@
+use serde::Deserialize;
+use std::time::Instant;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{Button, Scroll, Widget};
+
+// `markup = true` in a module section has the widget's text parsed as
+// Pango-style markup, so format strings can style parts of it:
+//
+//     format = "<span foreground='#888888'>CPU</span> {usage}%"
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Section {
+    pub markup: bool,
+}
+
+pub struct WithMarkup {
+    widget: Box<dyn Widget>,
+}
+
+impl WithMarkup {
+    pub fn new(widget: Box<dyn Widget>) -> WithMarkup {
+        WithMarkup { widget }
+    }
+}
+
+impl Widget for WithMarkup {
+    fn update(&mut self) -> bool {
+        self.widget.update()
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        ctx.set_markup(true);
+        self.widget.render(ctx);
+        ctx.set_markup(false);
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        self.widget.next_update()
+    }
+
+    fn animating(&self) -> bool {
+        self.widget.animating()
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        self.widget.click(x, button)
+    }
+
+    fn scroll(&mut self, x: f32, direction: Scroll) -> bool {
+        self.widget.scroll(x, direction)
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        self.widget.action(name)
+    }
+
+    fn tooltip(&self, x: f32) -> Option<String> {
+        self.widget.tooltip(x)
+    }
+
+    fn popup(&self) -> Option<String> {
+        self.widget.popup()
+    }
+
+    fn popup_click(&mut self, line: usize, button: Button) -> bool {
+        self.widget.popup_click(line, button)
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.widget.value()
+    }
+
+    fn background(&self) -> Option<Color> {
+        self.widget.background()
+    }
+
+    fn click_through(&self) -> bool {
+        self.widget.click_through()
+    }
+}
@@ -0,0 +1,200 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, Button, Scroll, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::mpris::{self, Control, Player, Status, Watcher};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ScrollAction {
+    Seek,
+    Player,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct MediaConfig {
+    format: String,
+    format_paused: String,
+    format_stopped: String,
+    format_missing: String,
+    color: Option<Color>,
+    paused_color: Color,
+    scroll: ScrollAction,
+    seek_step: u32,
+}
+
+impl Default for MediaConfig {
+    fn default() -> MediaConfig {
+        MediaConfig {
+            format: "{artist} - {title}".to_string(),
+            format_paused: "{artist} - {title}".to_string(),
+            format_stopped: String::new(),
+            format_missing: String::new(),
+            color: None,
+            paused_color: Color::from_str("#888888").unwrap(),
+            scroll: ScrollAction::Seek,
+            seek_step: 5,
+        }
+    }
+}
+
+pub struct Media {
+    config: MediaConfig,
+    watcher: Watcher,
+    players: Vec<Player>,
+    // The bus name of the player picked by scrolling, if it's still around.
+    selected: Option<String>,
+    text: String,
+}
+
+impl Media {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        Ok(Box::new(Media {
+            config: section.clone().try_into()?,
+            watcher: Watcher::spawn(waker.clone()),
+            players: Vec::new(),
+            selected: None,
+            text: String::new(),
+        }))
+    }
+
+    // Without a selection the first playing player is shown, so whatever
+    // started playing last doesn't need to be picked by hand.
+    fn current(&self) -> Option<&Player> {
+        self.selected.as_ref()
+            .and_then(|bus| self.players.iter().find(|player| &player.bus == bus))
+            .or_else(|| self.players.iter().find(|player| player.status == Status::Playing))
+            .or_else(|| self.players.first())
+    }
+
+    fn format(&self) -> String {
+        let player = match self.current() {
+            Some(player) => player,
+            None => return self.config.format_missing.clone(),
+        };
+
+        let template = match player.status {
+            Status::Playing => &self.config.format,
+            Status::Paused => &self.config.format_paused,
+            Status::Stopped => &self.config.format_stopped,
+        };
+
+        format::placeholders(template, |key| match key {
+            "artist" => Some(player.artist.clone()),
+            "title" => Some(player.title.clone()),
+            "album" => Some(player.album.clone()),
+            "player" => Some(player.name().to_string()),
+            "status" => Some(player.status.name().to_string()),
+            _ => None,
+        })
+    }
+
+    fn control(&self, control: Control) {
+        if let Some(player) = self.current() {
+            mpris::control(&player.bus, control);
+        }
+    }
+
+    fn cycle(&mut self, direction: Scroll) {
+        let count = self.players.len();
+        if count == 0 {
+            return;
+        }
+
+        let current = self.current().and_then(|current| self.players.iter().position(|player| player == current));
+        let index = match (current, direction) {
+            (Some(index), Scroll::Up) => (index + count - 1) % count,
+            (Some(index), Scroll::Down) => (index + 1) % count,
+            (None, _) => 0,
+        };
+
+        self.selected = Some(self.players[index].bus.clone());
+        self.text = self.format();
+    }
+}
+
+impl Widget for Media {
+    fn update(&mut self) -> bool {
+        let players = match self.watcher.take() {
+            Some(players) => players,
+            None => return false,
+        };
+
+        if players == self.players {
+            return false;
+        }
+
+        self.players = players;
+        self.text = self.format();
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match self.current() {
+            Some(player) if player.status != Status::Playing => self.config.paused_color,
+            _ => self.config.color.unwrap_or_else(|| ctx.foreground()),
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        if button == Button::Left {
+            self.control(Control::PlayPause);
+        }
+
+        false
+    }
+
+    fn scroll(&mut self, _x: f32, direction: Scroll) -> bool {
+        match self.config.scroll {
+            ScrollAction::Seek => {
+                let step = self.config.seek_step as i64 * 1_000_000;
+                self.control(Control::Seek(if direction == Scroll::Up { step } else { -step }));
+                false
+            },
+            ScrollAction::Player => {
+                self.cycle(direction);
+                true
+            },
+        }
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "play-pause" => self.control(Control::PlayPause),
+            "next" => self.control(Control::Next),
+            "previous" => self.control(Control::Previous),
+            "next-player" => {
+                self.cycle(Scroll::Down);
+                return Ok(true);
+            },
+            "previous-player" => {
+                self.cycle(Scroll::Up);
+                return Ok(true);
+            },
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(false)
+    }
+
+    // Everything known about the track, which the bar may only have room
+    // for part of.
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let player = self.current()?;
+        let lines = [&player.title, &player.artist, &player.album];
+        let mut text: Vec<String> = lines.iter()
+            .filter(|line| !line.is_empty())
+            .map(|line| markup::escape(line))
+            .collect();
+        text.push(format!("<span foreground=\"#888888\">{} ({})</span>",
+            markup::escape(player.name()), player.status.name()));
+
+        Some(text.join("\n"))
+    }
+}
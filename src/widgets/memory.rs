@@ -0,0 +1,209 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, Waker, Widget};
+use super::worker::Poller;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+enum Unit {
+    KiB,
+    MiB,
+    GiB,
+    #[serde(rename = "KB")]
+    Kb,
+    #[serde(rename = "MB")]
+    Mb,
+    #[serde(rename = "GB")]
+    Gb,
+}
+
+impl Unit {
+    fn bytes(&self) -> f64 {
+        match self {
+            Unit::KiB => 1024.0,
+            Unit::MiB => 1024.0 * 1024.0,
+            Unit::GiB => 1024.0 * 1024.0 * 1024.0,
+            Unit::Kb => 1e3,
+            Unit::Mb => 1e6,
+            Unit::Gb => 1e9,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Unit::KiB => "KiB",
+            Unit::MiB => "MiB",
+            Unit::GiB => "GiB",
+            Unit::Kb => "KB",
+            Unit::Mb => "MB",
+            Unit::Gb => "GB",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct MemoryConfig {
+    format: String,
+    unit: Unit,
+    precision: usize,
+    interval: f64,
+    color: Option<Color>,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> MemoryConfig {
+        MemoryConfig {
+            format: "MEM {used}/{total} {unit}".to_string(),
+            unit: Unit::GiB,
+            precision: 1,
+            interval: 5.0,
+            color: None,
+        }
+    }
+}
+
+// Values from /proc/meminfo, in bytes.
+struct MemInfo(HashMap<String, u64>);
+
+impl MemInfo {
+    fn read() -> Option<MemInfo> {
+        std::fs::read_to_string("/proc/meminfo").ok().map(|contents| MemInfo::parse(&contents))
+    }
+
+    fn parse(contents: &str) -> MemInfo {
+        let values = contents.lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                let kib: u64 = value.split_whitespace().next()?.parse().ok()?;
+                Some((key.to_string(), kib * 1024))
+            })
+            .collect();
+
+        MemInfo(values)
+    }
+
+    fn get(&self, key: &str) -> u64 {
+        self.0.get(key).copied().unwrap_or(0)
+    }
+
+    fn used(&self) -> u64 {
+        self.get("MemTotal").saturating_sub(self.get("MemAvailable"))
+    }
+
+    fn swap_used(&self) -> u64 {
+        self.get("SwapTotal").saturating_sub(self.get("SwapFree"))
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64 * 100.0
+    }
+}
+
+pub struct Memory {
+    config: MemoryConfig,
+    poller: Poller<Option<MemInfo>>,
+    percent: Option<f64>,
+    text: String,
+}
+
+impl Memory {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: MemoryConfig = section.clone().try_into()?;
+        let poller = Poller::spawn(Duration::from_secs_f64(config.interval.max(0.1)), waker.clone(), MemInfo::read);
+
+        Ok(Box::new(Memory { config, poller, percent: None, text: String::new() }))
+    }
+
+    fn format(&self, info: &MemInfo) -> String {
+        let scaled = |bytes: u64| format!("{:.*}", self.config.precision, bytes as f64 / self.config.unit.bytes());
+
+        format::placeholders(&self.config.format, |key| match key {
+            "used" => Some(scaled(info.used())),
+            "total" => Some(scaled(info.get("MemTotal"))),
+            "free" => Some(scaled(info.get("MemFree"))),
+            "available" => Some(scaled(info.get("MemAvailable"))),
+            "percent" => Some(format!("{:.0}", percent(info.used(), info.get("MemTotal")))),
+            "swap_used" => Some(scaled(info.swap_used())),
+            "swap_total" => Some(scaled(info.get("SwapTotal"))),
+            "swap_percent" => Some(format!("{:.0}", percent(info.swap_used(), info.get("SwapTotal")))),
+            "unit" => Some(self.config.unit.label().to_string()),
+            _ => None,
+        })
+    }
+}
+
+impl Widget for Memory {
+    fn update(&mut self) -> bool {
+        let (text, percent) = match self.poller.take() {
+            Some(Some(info)) => (self.format(&info), Some(percent(info.used(), info.get("MemTotal")))),
+            Some(None) => (String::new(), None),
+            None => return false,
+        };
+
+        self.percent = percent;
+        if text == self.text {
+            return false;
+        }
+
+        self.text = text;
+        true
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.percent
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = self.config.color.unwrap_or_else(|| ctx.foreground());
+        ctx.text(&self.text, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMINFO: &str = "MemTotal:       16000000 kB
+MemFree:         2000000 kB
+MemAvailable:    4000000 kB
+HugePages_Total:       0
+SwapTotal:       8000000 kB
+SwapFree:        6000000 kB
+Broken line
+Bad:           lots kB
+";
+
+    #[test]
+    fn meminfo_values_are_in_bytes() {
+        let info = MemInfo::parse(MEMINFO);
+        assert_eq!(info.get("MemTotal"), 16_000_000 * 1024);
+        assert_eq!(info.get("HugePages_Total"), 0);
+        assert_eq!(info.get("Bad"), 0);
+        assert_eq!(info.get("Missing"), 0);
+    }
+
+    #[test]
+    fn used_memory_is_what_isnt_available() {
+        let info = MemInfo::parse(MEMINFO);
+        assert_eq!(info.used(), 12_000_000 * 1024);
+        assert_eq!(info.swap_used(), 2_000_000 * 1024);
+        assert_eq!(percent(info.used(), info.get("MemTotal")), 75.0);
+        assert_eq!(percent(info.swap_used(), info.get("SwapTotal")), 25.0);
+    }
+
+    #[test]
+    fn missing_values_count_as_nothing_used() {
+        let info = MemInfo::parse("MemAvailable: 100 kB\n");
+        assert_eq!(info.used(), 0);
+        assert_eq!(info.swap_used(), 0);
+        assert_eq!(percent(info.used(), info.get("MemTotal")), 0.0);
+    }
+}
@@ -0,0 +1,206 @@
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use crate::color::Color;
+use crate::render::{Border, Graph, Primitive, Progress, RenderCtx, Ring};
+
+// How widgets with a percentage show it, `display = "bar"` draws a bar in
+// place of the text and `display = "ring"` a dial, which also fits into
+// the narrow modules of vertical bars:
+//
+//     [module.volume]
+//     display = "bar"
+//     bar = { length = 60, thickness = 6, empty = "#444444" }
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Display {
+    #[default]
+    Text,
+    Bar,
+    Ring,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct BarStyle {
+    // Along and across the direction the bar fills in, vertical bars are
+    // cut short to fit the height of the bar.
+    length: f32,
+    thickness: f32,
+    vertical: bool,
+    empty: Color,
+    border_color: Option<Color>,
+    border_width: f32,
+    corner_radius: f32,
+}
+
+impl Default for BarStyle {
+    fn default() -> BarStyle {
+        BarStyle {
+            length: 48.0,
+            thickness: 6.0,
+            vertical: false,
+            empty: Color::from_str("#444444").unwrap(),
+            border_color: None,
+            border_width: 1.0,
+            corner_radius: 0.0,
+        }
+    }
+}
+
+// Draws a bar filled up to value, between 0 and 1, after the content drawn
+// so far and vertically centered.
+pub fn draw_bar(ctx: &mut RenderCtx, value: f32, color: Color, style: &BarStyle) {
+    let (width, height) = if style.vertical {
+        (style.thickness, style.length.min(ctx.height()))
+    } else {
+        (style.length, style.thickness.min(ctx.height()))
+    };
+
+    let border = match style.border_color {
+        Some(color) if style.border_width > 0.0 => Some(Border { width: style.border_width, color }),
+        _ => None,
+    };
+
+    ctx.push(Primitive::Progress(Progress {
+        x: ctx.width(),
+        y: ((ctx.height() - height) / 2.0).round(),
+        width,
+        height,
+        radius: style.corner_radius,
+        value,
+        vertical: style.vertical,
+        filled: color,
+        empty: style.empty,
+        border,
+    }));
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RingStyle {
+    // The diameter, by default most of the height of the bar.
+    size: Option<f32>,
+    thickness: f32,
+    empty: Color,
+}
+
+impl Default for RingStyle {
+    fn default() -> RingStyle {
+        RingStyle {
+            size: None,
+            thickness: 3.0,
+            empty: Color::from_str("#444444").unwrap(),
+        }
+    }
+}
+
+pub fn draw_ring(ctx: &mut RenderCtx, value: f32, color: Color, style: &RingStyle) {
+    let size = style.size.unwrap_or_else(|| (ctx.height() * 0.7).round()).min(ctx.height());
+
+    ctx.push(Primitive::Ring(Ring {
+        x: ctx.width(),
+        y: ((ctx.height() - size) / 2.0).round(),
+        size,
+        thickness: style.thickness,
+        value,
+        filled: color,
+        empty: style.empty,
+    }));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Plot {
+    Line,
+    Fill,
+    Both,
+}
+
+// A graph of the recent history of a value, drawn after the widget's text
+// when its section has a `graph` table:
+//
+//     [module.cpu]
+//     graph = { length = 30, width = 60, plot = "fill" }
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GraphStyle {
+    // How many samples the graph spans.
+    length: usize,
+    width: f32,
+    // By default most of the height of the bar.
+    height: Option<f32>,
+    spacing: f32,
+    plot: Plot,
+    line_width: f32,
+    // The widget's color unless set, the fill defaults to a faded line.
+    color: Option<Color>,
+    fill: Option<Color>,
+    background: Option<Color>,
+}
+
+impl Default for GraphStyle {
+    fn default() -> GraphStyle {
+        GraphStyle {
+            length: 30,
+            width: 48.0,
+            height: None,
+            spacing: 4.0,
+            plot: Plot::Both,
+            line_width: 1.5,
+            color: None,
+            fill: None,
+            background: None,
+        }
+    }
+}
+
+// The last few samples of a value, oldest first.
+pub struct History {
+    values: VecDeque<f64>,
+    length: usize,
+}
+
+impl History {
+    pub fn new(style: &GraphStyle) -> History {
+        let length = style.length.max(2);
+        History { values: VecDeque::with_capacity(length), length }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.values.len() == self.length {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+}
+
+// Draws the history scaled to max, or to its largest value when there is
+// no fixed maximum.
+pub fn draw_graph(ctx: &mut RenderCtx, history: &History, max: Option<f64>, color: Color, style: &GraphStyle) {
+    let height = style.height.unwrap_or_else(|| (ctx.height() * 0.7).round()).min(ctx.height());
+    let (x, y) = (ctx.width() + style.spacing, ((ctx.height() - height) / 2.0).round());
+    if let Some(background) = style.background {
+        ctx.rect(x, y, style.width, height, background);
+    }
+
+    let max = max.unwrap_or_else(|| history.values.iter().copied().fold(0.0, f64::max));
+    let values = history.values.iter()
+        .map(|value| if max > 0.0 { (value / max) as f32 } else { 0.0 })
+        .collect();
+
+    let color = style.color.unwrap_or(color);
+    let line = Some(Border { width: style.line_width, color }).filter(|_| style.plot != Plot::Fill);
+    let fill = Some(style.fill.unwrap_or_else(|| color.faded(0.3))).filter(|_| style.plot != Plot::Line);
+
+    ctx.push(Primitive::Graph(Graph {
+        x,
+        y,
+        width: style.width,
+        height,
+        values,
+        slots: history.length,
+        line,
+        fill,
+    }));
+}
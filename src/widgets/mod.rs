@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+
+mod actions;
+mod backlight;
+mod battery;
+mod block;
+mod bluetooth;
+mod bluez;
+mod buttons;
+mod clock;
+mod cpu;
+mod custom;
+mod dbus_watch;
+mod desktops;
+mod disk;
+pub mod ewmh;
+mod failed;
+mod format;
+mod gpu;
+mod i3bar;
+pub mod i3ipc;
+mod icon;
+mod icons;
+mod idle_inhibitor;
+mod imap;
+mod keyboard;
+mod lemonbar;
+#[cfg(feature = "lua")]
+mod lua;
+mod mail;
+mod markup;
+mod media;
+mod memory;
+mod meter;
+mod mpris;
+mod network;
+#[cfg(feature = "notification-daemon")]
+mod notification_popups;
+#[cfg(feature = "notification-daemon")]
+mod notification_server;
+mod notifications;
+mod notify;
+mod ping;
+pub mod plugin;
+mod pulse;
+mod separator;
+mod source_mute;
+mod sni;
+mod spacer;
+mod stopwatch;
+mod sysfs;
+mod systemd;
+mod systemd1;
+mod taskbar;
+mod temperature;
+mod timer;
+mod title;
+mod tray;
+mod updates;
+mod uptime;
+mod volume;
+mod vpn;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod weather;
+mod wireless;
+mod worker;
+mod workspaces;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scroll {
+    Up,
+    Down,
+}
+
+impl Scroll {
+    pub fn inverted(self) -> Scroll {
+        match self {
+            Scroll::Up => Scroll::Down,
+            Scroll::Down => Scroll::Up,
+        }
+    }
+}
+
+pub trait Widget {
+    // Refreshes the widget's state, returning true when it needs to be redrawn.
+    fn update(&mut self) -> bool;
+
+    fn render(&self, ctx: &mut RenderCtx);
+
+    // The next moment this widget wants `update` to be called, if it relies
+    // on a timer rather than being woken externally.
+    fn next_update(&self) -> Option<Instant> {
+        None
+    }
+
+    // Whether the widget is in the middle of an animation. The bar redraws
+    // it every frame until it's done, without counting that as an update.
+    fn animating(&self) -> bool {
+        false
+    }
+
+    // Called when the widget is clicked, with x relative to the left edge of
+    // the widget. Returns true when the click changed what is shown.
+    fn click(&mut self, _x: f32, _button: Button) -> bool {
+        false
+    }
+
+    // Called once per step of the scroll wheel while the cursor is over the
+    // widget. Returns true when the widget needs to be redrawn.
+    fn scroll(&mut self, _x: f32, _direction: Scroll) -> bool {
+        false
+    }
+
+    // Runs one of the widget's built-in actions by name, as configured
+    // through `on-click` and friends. Returns true when the widget needs to
+    // be redrawn.
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        Err(actions::UnknownActionError(name.to_string()))?
+    }
+
+    // Text for a popup shown while the pointer rests on the widget, with x
+    // relative to the widget's left edge. Lines are separated by newlines
+    // and may use markup.
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        None
+    }
+
+    // Text for a popup the widget keeps open by itself, such as after a
+    // click, until it returns None again. Takes precedence over tooltips.
+    fn popup(&self) -> Option<String> {
+        None
+    }
+
+    // Called when a line of the popup the widget keeps open is clicked,
+    // counting from 0 at the top. Returns true when the widget needs to be
+    // redrawn.
+    fn popup_click(&mut self, _line: usize, _button: Button) -> bool {
+        false
+    }
+
+    // The number the widget shows, such as a percentage or a temperature,
+    // which picks the module's states.
+    fn value(&self) -> Option<f64> {
+        None
+    }
+
+    // The color the widget fills its area with, which separators next to it
+    // blend into.
+    fn background(&self) -> Option<Color> {
+        None
+    }
+
+    // Whether clicks on the widget go through to the windows beneath the
+    // bar.
+    fn click_through(&self) -> bool {
+        false
+    }
+}
+
+// Lets widgets that gather data off the event loop thread schedule an
+// update as soon as new data is available.
+#[derive(Clone)]
+pub struct Waker(Arc<dyn Fn() + Send + Sync>);
+
+impl Waker {
+    pub fn new<F: Fn() + Send + Sync + 'static>(wake: F) -> Waker {
+        Waker(Arc::new(wake))
+    }
+
+    pub fn wake(&self) {
+        (self.0)()
+    }
+}
+
+pub type Constructor = Box<dyn Fn(&toml::Value, &Waker) -> Result<Box<dyn Widget>, Error>>;
+
+#[derive(Debug)]
+pub struct UnknownWidgetError(String);
+
+impl std::fmt::Display for UnknownWidgetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown widget type '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownWidgetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// Shown in place of a module whose widget couldn't be built.
+pub fn failed(name: &str, err: &Error) -> Box<dyn Widget> {
+    Box::new(failed::Failed::new(name, err))
+}
+
+pub struct Registry {
+    constructors: HashMap<String, Constructor>,
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        let mut registry = Registry { constructors: HashMap::new() };
+        registry.register("backlight", backlight::Backlight::build);
+        registry.register("battery", battery::Battery::build);
+        registry.register("bluetooth", bluetooth::Bluetooth::build);
+        registry.register("clock", clock::Clock::build);
+        registry.register("cpu", cpu::Cpu::build);
+        registry.register("custom", custom::Custom::build);
+        registry.register("dbus", dbus_watch::DbusWatch::build);
+        registry.register("desktops", desktops::Desktops::build);
+        registry.register("disk", disk::Disk::build);
+        registry.register("gpu", gpu::Gpu::build);
+        registry.register("i3bar", i3bar::I3bar::build);
+        registry.register("idle-inhibitor", idle_inhibitor::IdleInhibitor::build);
+        registry.register("keyboard", keyboard::Keyboard::build);
+        registry.register("lemonbar", lemonbar::Lemonbar::build);
+        #[cfg(feature = "lua")]
+        registry.register("lua", lua::LuaWidget::build);
+        registry.register("mail", mail::Mail::build);
+        registry.register("media", media::Media::build);
+        registry.register("memory", memory::Memory::build);
+        registry.register("network", network::Network::build);
+        #[cfg(feature = "notification-daemon")]
+        registry.register("notification-popups", notification_popups::NotificationPopups::build);
+        registry.register("notifications", notifications::Notifications::build);
+        registry.register("ping", ping::Ping::build);
+        registry.register("separator", separator::Separator::build);
+        registry.register("source-mute", source_mute::SourceMute::build);
+        registry.register("spacer", spacer::Spacer::build);
+        registry.register("stopwatch", stopwatch::Stopwatch::build);
+        registry.register("systemd", systemd::Systemd::build);
+        registry.register("taskbar", taskbar::Taskbar::build);
+        registry.register("temperature", temperature::Temperature::build);
+        registry.register("timer", timer::Timer::build);
+        registry.register("title", title::Title::build);
+        registry.register("tray", tray::Tray::build);
+        registry.register("updates", updates::Updates::build);
+        registry.register("uptime", uptime::Uptime::build);
+        registry.register("volume", volume::Volume::build);
+        registry.register("vpn", vpn::Vpn::build);
+        registry.register("weather", weather::Weather::build);
+        registry.register("wireless", wireless::Wireless::build);
+        registry.register("workspaces", workspaces::Workspaces::build);
+
+        registry
+    }
+}
+
+impl Registry {
+    pub fn register<F>(&mut self, kind: &str, constructor: F)
+    where F: Fn(&toml::Value, &Waker) -> Result<Box<dyn Widget>, Error> + 'static {
+        self.constructors.insert(kind.to_string(), Box::new(constructor));
+    }
+
+    pub fn has(&self, kind: &str) -> bool {
+        self.constructors.contains_key(kind)
+    }
+
+    // Module sections may set `type` to reuse a widget kind under another
+    // name, otherwise the module name itself is the widget kind.
+    pub fn build(&self, name: &str, section: Option<&toml::Value>, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let empty = toml::Value::Table(Default::default());
+        let section = section.unwrap_or(&empty);
+        let kind = section.get("type").and_then(|kind| kind.as_str()).unwrap_or(name);
+
+        let mut widget = match self.constructors.get(kind) {
+            Some(constructor) => constructor(section, waker)?,
+            None => Err(UnknownWidgetError(kind.to_string()))?,
+        };
+
+        // Markup goes innermost, so that it only applies to the widget's own
+        // text and not to whatever wraps it.
+        let markup: markup::Section = section.clone().try_into()?;
+        if markup.markup {
+            widget = Box::new(markup::WithMarkup::new(widget));
+        }
+
+        // Icons go inside the block, next to the widget's content.
+        let icon: icon::Section = section.clone().try_into()?;
+        if let Some(path) = &icon.icon {
+            widget = Box::new(icon::WithIcon::new(widget, path, &icon)?);
+        }
+
+        // States need a block to draw their background and underline in, so
+        // they get a plain one if the module has none.
+        let block: block::Section = section.clone().try_into()?;
+        let states = block.states();
+        if block.block.is_some() || !states.is_empty() {
+            widget = Box::new(block::Block::new(widget, block.block.unwrap_or_default(), states));
+        }
+
+        let actions: actions::Actions = section.clone().try_into()?;
+        if actions.is_empty() {
+            Ok(widget)
+        } else {
+            Ok(Box::new(actions::WithActions::new(widget, actions)))
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use zbus::MatchRule;
+use zbus::blocking::{Connection, Proxy};
+use zbus::fdo::DBusProxy;
+use zbus::message::Type as MessageType;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::OwnedValue;
+use crate::Error;
+use super::{worker, Waker};
+use super::worker::Signals;
+
+// Media players are found through the MPRIS D-Bus interface, every player
+// owns a name below this prefix and exports the same object path.
+const BUS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Playing => "playing",
+            Status::Paused => "paused",
+            Status::Stopped => "stopped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Player {
+    pub bus: String,
+    pub status: Status,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+}
+
+impl Player {
+    // The bus name without the MPRIS prefix, e.g. "spotify" or
+    // "firefox.instance_1_42".
+    pub fn name(&self) -> &str {
+        self.bus.strip_prefix(BUS_PREFIX).unwrap_or(&self.bus)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    PlayPause,
+    Next,
+    Previous,
+    // Relative to the current position, in microseconds.
+    Seek(i64),
+}
+
+fn player_proxy<'a>(connection: &Connection, bus: &'a str) -> Result<Proxy<'a>, Error> {
+    // Properties are read once per change signal, caching them would only
+    // add a second subscription per player.
+    Ok(zbus::blocking::proxy::Builder::new(connection)
+        .destination(bus)?
+        .path(OBJECT_PATH)?
+        .interface(PLAYER_INTERFACE)?
+        .cache_properties(CacheProperties::No)
+        .build()?)
+}
+
+async fn query_player(connection: &zbus::Connection, bus: &str) -> Result<Player, Error> {
+    let proxy: zbus::Proxy = zbus::proxy::Builder::new(connection)
+        .destination(bus)?
+        .path(OBJECT_PATH)?
+        .interface(PLAYER_INTERFACE)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+
+    let status = match proxy.get_property::<String>("PlaybackStatus").await?.as_str() {
+        "Playing" => Status::Playing,
+        "Paused" => Status::Paused,
+        _ => Status::Stopped,
+    };
+
+    let mut metadata: HashMap<String, OwnedValue> = proxy.get_property("Metadata").await.unwrap_or_default();
+    let mut text = |key: &str| metadata.remove(key)
+        .and_then(|value| String::try_from(value).ok())
+        .unwrap_or_default();
+
+    let title = text("xesam:title");
+    let album = text("xesam:album");
+    let artist = metadata.remove("xesam:artist")
+        .and_then(|value| Vec::<String>::try_from(value).ok())
+        .map(|artists| artists.join(", "))
+        .unwrap_or_default();
+
+    Ok(Player { bus: bus.to_string(), status, title, artist, album })
+}
+
+async fn query(connection: &zbus::Connection) -> Result<Vec<Player>, Error> {
+    let mut names: Vec<String> = DBusProxy::new(connection).await?.list_names().await?.into_iter()
+        .map(|name| name.to_string())
+        .filter(|name| name.starts_with(BUS_PREFIX))
+        .collect();
+    names.sort();
+
+    // A player that quits while being queried is simply left out, the
+    // signal for its name going away follows right after.
+    let mut players = Vec::new();
+    for bus in &names {
+        if let Ok(player) = query_player(connection, bus).await {
+            players.push(player);
+        }
+    }
+
+    Ok(players)
+}
+
+async fn watch(shared: Weak<Mutex<Option<Vec<Player>>>>, waker: Waker) -> Result<(), Error> {
+    let connection = zbus::Connection::session().await?;
+
+    let properties = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?
+        .path(OBJECT_PATH)?
+        .build();
+    let owners = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender("org.freedesktop.DBus")?
+        .interface("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .arg0ns("org.mpris.MediaPlayer2")?
+        .build();
+
+    // Both kinds of signal funnel into one stream so the players are
+    // queried from a single place, with bursts of changes coalesced.
+    let mut signals = Signals::subscribe(&connection, [properties, owners], 16).await?;
+
+    loop {
+        let players = query(&connection).await?;
+        match shared.upgrade() {
+            Some(state) => *state.lock().unwrap() = Some(players),
+            None => return Ok(()),
+        }
+        waker.wake();
+
+        signals.next().await?;
+    }
+}
+
+// Keeps the list of running players up to date, following their property
+// changes and players appearing or quitting on the session bus, until it's
+// dropped.
+pub struct Watcher {
+    state: Arc<Mutex<Option<Vec<Player>>>>,
+    _watch: worker::Task,
+}
+
+impl Watcher {
+    pub fn spawn(waker: Waker) -> Watcher {
+        let state = Arc::new(Mutex::new(None));
+        let shared = Arc::downgrade(&state);
+
+        let watch = worker::watch(Duration::from_secs(5), "media players".to_string(),
+            move || watch(shared.clone(), waker.clone()));
+
+        Watcher { state, _watch: watch }
+    }
+
+    // Returns the players if they changed since the last call.
+    pub fn take(&self) -> Option<Vec<Player>> {
+        self.state.lock().unwrap().take()
+    }
+}
+
+fn send_control(bus: &str, control: Control) -> Result<(), Error> {
+    let connection = Connection::session()?;
+    let proxy = player_proxy(&connection, bus)?;
+
+    match control {
+        Control::PlayPause => proxy.call_method("PlayPause", &())?,
+        Control::Next => proxy.call_method("Next", &())?,
+        Control::Previous => proxy.call_method("Previous", &())?,
+        Control::Seek(offset) => proxy.call_method("Seek", &(offset,))?,
+    };
+
+    Ok(())
+}
+
+pub fn control(bus: &str, control: Control) {
+    let bus = bus.to_string();
+    worker::spawn_control(format!("control {}", bus), move || send_control(&bus, control));
+}
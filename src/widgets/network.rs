@@ -0,0 +1,300 @@
+use serde::Deserialize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, sysfs, Waker, Widget};
+use super::meter::{self, GraphStyle, History};
+use super::worker::Poller;
+
+const FIRST_SAMPLE_DELAY: Duration = Duration::from_millis(250);
+
+const RATE_UNITS: [&str; 5] = ["B/s", "KB/s", "MB/s", "GB/s", "TB/s"];
+
+// Which rate the graph follows.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Rate {
+    Down,
+    Up,
+    Total,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct NetworkConfig {
+    // Follows the interface of the default route when unset.
+    interface: Option<String>,
+    format: String,
+    format_down: String,
+    format_missing: String,
+    interval: f64,
+    color: Option<Color>,
+    down_color: Color,
+    graph: Option<GraphStyle>,
+    graph_rate: Rate,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> NetworkConfig {
+        NetworkConfig {
+            interface: None,
+            format: "{interface} {ip} D {down} U {up}".to_string(),
+            format_down: "{interface} down".to_string(),
+            format_missing: String::new(),
+            interval: 2.0,
+            color: None,
+            down_color: Color::from_str("#888888").unwrap(),
+            graph: None,
+            graph_rate: Rate::Down,
+        }
+    }
+}
+
+// The interface used by the IPv4 default route with the lowest metric,
+// falling back to the IPv6 default route.
+fn default_interface() -> Option<String> {
+    let routes = std::fs::read_to_string("/proc/net/route").unwrap_or_default();
+    let ipv4 = routes.lines().skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let flags = u32::from_str_radix(fields.get(3)?, 16).ok()?;
+            let metric: u32 = fields.get(6)?.parse().ok()?;
+
+            let default = fields.get(1) == Some(&"00000000") && fields.get(7) == Some(&"00000000");
+            if default && flags & 1 != 0 {
+                Some((metric, fields[0].to_string()))
+            } else {
+                None
+            }
+        })
+        .min();
+
+    if let Some((_, interface)) = ipv4 {
+        return Some(interface);
+    }
+
+    let routes = std::fs::read_to_string("/proc/net/ipv6_route").ok()?;
+    routes.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let default = fields.get(1) == Some(&"00") && fields.first()?.bytes().all(|digit| digit == b'0');
+            let metric = u32::from_str_radix(fields.get(5)?, 16).ok()?;
+            let interface = fields.get(9)?;
+
+            if default && *interface != "lo" {
+                Some((metric, interface.to_string()))
+            } else {
+                None
+            }
+        })
+        .min()
+        .map(|(_, interface)| interface)
+}
+
+// The first IPv4 and IPv6 address of the interface, as listed by iproute2.
+// Global IPv6 addresses are preferred over link-local ones.
+fn addresses(interface: &str) -> (Option<String>, Option<String>) {
+    let output = Command::new("ip").args(["-o", "addr", "show", "dev", interface])
+        .stderr(Stdio::null())
+        .output();
+    let listing = match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).to_string(),
+        _ => return (None, None),
+    };
+
+    let mut ipv4 = None;
+    let mut ipv6 = None;
+    let mut link_local = None;
+    for line in listing.lines() {
+        let mut fields = line.split_whitespace().skip(2);
+        let (family, address) = match (fields.next(), fields.next()) {
+            (Some(family), Some(address)) => (family, address.split('/').next().unwrap_or(address).to_string()),
+            _ => continue,
+        };
+
+        match family {
+            "inet" if ipv4.is_none() => ipv4 = Some(address),
+            "inet6" if line.contains("scope link") => link_local = link_local.or(Some(address)),
+            "inet6" if ipv6.is_none() => ipv6 = Some(address),
+            _ => (),
+        }
+    }
+
+    (ipv4, ipv6.or(link_local))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Counters {
+    received: u64,
+    transmitted: u64,
+    at: Instant,
+}
+
+impl Counters {
+    fn read(interface: &str) -> Option<Counters> {
+        let statistics = Path::new("/sys/class/net").join(interface).join("statistics");
+
+        Some(Counters {
+            received: sysfs::read_number(statistics.join("rx_bytes"))?,
+            transmitted: sysfs::read_number(statistics.join("tx_bytes"))?,
+            at: Instant::now(),
+        })
+    }
+
+    // Bytes per second received and transmitted since the earlier reading.
+    fn rates_since(&self, previous: &Counters) -> (f64, f64) {
+        let elapsed = self.at.duration_since(previous.at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        (
+            self.received.saturating_sub(previous.received) as f64 / elapsed,
+            self.transmitted.saturating_sub(previous.transmitted) as f64 / elapsed,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    interface: String,
+    state: String,
+    ipv4: Option<String>,
+    ipv6: Option<String>,
+    down: f64,
+    up: f64,
+}
+
+impl Sample {
+    fn is_up(&self) -> bool {
+        self.state == "up" || (self.state == "unknown" && self.ipv4.is_some())
+    }
+}
+
+fn sample(interface: &str, previous: &mut Option<(String, Counters)>) -> Option<Sample> {
+    let state = sysfs::read_string(Path::new("/sys/class/net").join(interface).join("operstate"))?;
+    let (ipv4, ipv6) = addresses(interface);
+
+    // Rates are a delta between two readings, so they need a short
+    // baseline the first time and whenever the interface changes.
+    let stale = previous.as_ref().map(|(name, _)| name != interface).unwrap_or(true);
+    if stale {
+        *previous = Counters::read(interface).map(|counters| (interface.to_string(), counters));
+        std::thread::sleep(FIRST_SAMPLE_DELAY);
+    }
+
+    let current = Counters::read(interface);
+    let (down, up) = match (previous.as_ref(), &current) {
+        (Some((_, last)), Some(now)) => now.rates_since(last),
+        _ => (0.0, 0.0),
+    };
+    *previous = current.map(|counters| (interface.to_string(), counters));
+
+    Some(Sample { interface: interface.to_string(), state, ipv4, ipv6, down, up })
+}
+
+pub struct Network {
+    config: NetworkConfig,
+    poller: Poller<Option<Sample>>,
+    sample: Option<Sample>,
+    history: Option<History>,
+    text: String,
+}
+
+impl Network {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: NetworkConfig = section.clone().try_into()?;
+        let period = Duration::from_secs_f64(config.interval.max(0.1));
+
+        let interface = config.interface.clone();
+        let mut previous = None;
+        let poller = Poller::spawn(period, waker.clone(), move || {
+            let interface = interface.clone().or_else(default_interface)?;
+            sample(&interface, &mut previous)
+        });
+
+        let history = config.graph.as_ref().map(History::new);
+        Ok(Box::new(Network { config, poller, sample: None, history, text: String::new() }))
+    }
+
+    fn format(&self) -> String {
+        let sample = match &self.sample {
+            Some(sample) => sample,
+            None => return self.config.format_missing.clone(),
+        };
+
+        let template = if sample.is_up() { &self.config.format } else { &self.config.format_down };
+        format::placeholders(template, |key| match key {
+            "interface" => Some(sample.interface.clone()),
+            "state" => Some(sample.state.clone()),
+            "ip" => Some(sample.ipv4.clone().unwrap_or_default()),
+            "ipv6" => Some(sample.ipv6.clone().unwrap_or_default()),
+            "down" => Some(format::scaled(sample.down, 1000.0, &RATE_UNITS)),
+            "up" => Some(format::scaled(sample.up, 1000.0, &RATE_UNITS)),
+            _ => None,
+        })
+    }
+}
+
+impl Widget for Network {
+    fn update(&mut self) -> bool {
+        let sample = match self.poller.take() {
+            Some(sample) => sample,
+            None => return false,
+        };
+
+        let was_up = self.sample.as_ref().map(Sample::is_up);
+        self.sample = sample;
+
+        // Missing samples count as no traffic so the graph keeps moving.
+        if let Some(history) = &mut self.history {
+            let (down, up) = self.sample.as_ref().map_or((0.0, 0.0), |sample| (sample.down, sample.up));
+            history.push(match self.config.graph_rate {
+                Rate::Down => down,
+                Rate::Up => up,
+                Rate::Total => down + up,
+            });
+        }
+
+        let text = self.format();
+        if text == self.text && self.sample.as_ref().map(Sample::is_up) == was_up && self.history.is_none() {
+            return false;
+        }
+
+        self.text = text;
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match &self.sample {
+            Some(sample) if !sample.is_up() => self.config.down_color,
+            _ => self.config.color.unwrap_or_else(|| ctx.foreground()),
+        };
+
+        ctx.text(&self.text, color);
+
+        // Throughput has no upper bound, the graph is scaled to the busiest
+        // sample it shows.
+        if let (Some(history), Some(style)) = (&self.history, &self.config.graph) {
+            meter::draw_graph(ctx, history, None, color, style);
+        }
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let sample = self.sample.as_ref()?;
+        let mut lines = vec![format!("{} ({})", markup::escape(&sample.interface), sample.state)];
+        lines.extend(sample.ipv4.iter().map(|address| format!("IPv4 {}", address)));
+        lines.extend(sample.ipv6.iter().map(|address| format!("IPv6 {}", address)));
+        lines.push(format!(
+            "Down {} Up {}",
+            format::scaled(sample.down, 1000.0, &RATE_UNITS),
+            format::scaled(sample.up, 1000.0, &RATE_UNITS),
+        ));
+
+        Some(lines.join("\n"))
+    }
+}
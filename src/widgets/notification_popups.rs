@@ -0,0 +1,210 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, Button, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::notification_server::{Expiry, Notification, Reason, Server, Urgency};
+
+// Notifications are shown in the popup the module keeps open, so they take
+// the bar's fonts and the tooltip style. Like any popup a module opens on
+// its own, it goes next to the module.
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct PopupsConfig {
+    format: String,
+    // Seconds a notification stays when its sender leaves it to the bar, 0
+    // keeps it until it's dismissed.
+    timeout: f64,
+    critical_timeout: f64,
+    max_shown: usize,
+    body_lines: usize,
+    low_color: Color,
+    normal_color: Option<Color>,
+    critical_color: Color,
+}
+
+impl Default for PopupsConfig {
+    fn default() -> PopupsConfig {
+        PopupsConfig {
+            format: String::new(),
+            timeout: 5.0,
+            critical_timeout: 0.0,
+            max_shown: 5,
+            body_lines: 3,
+            low_color: Color::from_str("#888888").unwrap(),
+            normal_color: None,
+            critical_color: Color::from_str("#ff5555").unwrap(),
+        }
+    }
+}
+
+pub struct NotificationPopups {
+    config: PopupsConfig,
+    server: Arc<Server>,
+    generation: Option<u64>,
+    notifications: Vec<Notification>,
+    popup: Option<String>,
+    // The notification each line of the popup belongs to, with the action
+    // clicking the line picks. Lines between notifications are None.
+    lines: Vec<Option<(u32, Option<String>)>>,
+    text: String,
+}
+
+impl NotificationPopups {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        Ok(Box::new(NotificationPopups {
+            config: section.clone().try_into()?,
+            server: Server::open(waker)?,
+            generation: None,
+            notifications: Vec::new(),
+            popup: None,
+            lines: Vec::new(),
+            text: String::new(),
+        }))
+    }
+
+    fn deadline(&self, notification: &Notification) -> Option<Instant> {
+        let timeout = match notification.expiry {
+            Expiry::Never => return None,
+            Expiry::After(timeout) => timeout,
+            Expiry::Default => {
+                let seconds = match notification.urgency {
+                    Urgency::Critical => self.config.critical_timeout,
+                    _ => self.config.timeout,
+                };
+                Some(seconds).filter(|seconds| *seconds > 0.0).map(Duration::from_secs_f64)?
+            },
+        };
+
+        Some(notification.received + timeout)
+    }
+
+    fn color(&self, urgency: Urgency) -> Option<Color> {
+        match urgency {
+            Urgency::Low => Some(self.config.low_color),
+            Urgency::Normal => self.config.normal_color,
+            Urgency::Critical => Some(self.config.critical_color),
+        }
+    }
+
+    // The newest notifications go on top.
+    fn layout(&mut self) {
+        let mut text = Vec::new();
+        self.lines.clear();
+
+        for notification in self.notifications.iter().rev().take(self.config.max_shown) {
+            if !text.is_empty() {
+                text.push(String::new());
+                self.lines.push(None);
+            }
+
+            let default = notification.actions.iter()
+                .find(|(key, _)| key == "default")
+                .map(|(key, _)| key.clone());
+
+            let summary = format!("<b>{}</b>", markup::escape(&notification.summary));
+            let mut header = match self.color(notification.urgency) {
+                Some(color) => format!("<span foreground=\"{}\">{}</span>", color, summary),
+                None => summary,
+            };
+            if !notification.app.is_empty() {
+                header.push_str(&format!(" <span foreground=\"#888888\">{}</span>", markup::escape(&notification.app)));
+            }
+            text.push(header);
+            self.lines.push(Some((notification.id, default.clone())));
+
+            for line in notification.body.lines().filter(|line| !line.trim().is_empty()).take(self.config.body_lines) {
+                text.push(markup::escape(line));
+                self.lines.push(Some((notification.id, default.clone())));
+            }
+
+            for (key, label) in notification.actions.iter().filter(|(key, _)| key != "default") {
+                text.push(format!("  › {}", markup::escape(label)));
+                self.lines.push(Some((notification.id, Some(key.clone()))));
+            }
+        }
+
+        self.popup = Some(text.join("\n")).filter(|text| !text.is_empty());
+    }
+
+    fn dismiss(&self, all: bool) {
+        let newest = self.notifications.iter().rev().take(if all { usize::MAX } else { 1 });
+        for notification in newest {
+            self.server.close(notification.id, Reason::Dismissed);
+        }
+    }
+}
+
+impl Widget for NotificationPopups {
+    fn update(&mut self) -> bool {
+        let now = Instant::now();
+        let (mut generation, mut notifications) = self.server.notifications();
+        let expired: Vec<u32> = notifications.iter()
+            .filter(|notification| self.deadline(notification).is_some_and(|deadline| deadline <= now))
+            .map(|notification| notification.id)
+            .collect();
+        if !expired.is_empty() {
+            for id in expired {
+                self.server.close(id, Reason::Expired);
+            }
+            (generation, notifications) = self.server.notifications();
+        }
+
+        if self.generation == Some(generation) {
+            return false;
+        }
+
+        self.generation = Some(generation);
+        self.notifications = notifications;
+        self.layout();
+        self.text = format::placeholders(&self.config.format, |key| match key {
+            "count" => Some(self.notifications.len().to_string()),
+            _ => None,
+        });
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = ctx.foreground();
+        ctx.text(&self.text, color);
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        self.notifications.iter().filter_map(|notification| self.deadline(notification)).min()
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "dismiss" => self.dismiss(false),
+            "dismiss-all" => self.dismiss(true),
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(false)
+    }
+
+    fn popup(&self) -> Option<String> {
+        self.popup.clone()
+    }
+
+    // Left clicks pick the line's action, if there is one, anything else
+    // dismisses the notification.
+    fn popup_click(&mut self, line: usize, button: Button) -> bool {
+        if let Some(Some((id, action))) = self.lines.get(line) {
+            match (button, action) {
+                (Button::Left, Some(key)) => self.server.invoke(*id, key),
+                _ => self.server.close(*id, Reason::Dismissed),
+            }
+        }
+
+        false
+    }
+
+    fn value(&self) -> Option<f64> {
+        Some(self.notifications.len() as f64)
+    }
+}
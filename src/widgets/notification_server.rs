@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::{Duration, Instant};
+use zbus::blocking::Connection;
+use zbus::fdo::{RequestNameFlags, RequestNameReply};
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::OwnedValue;
+use tracing::warn;
+use crate::Error;
+use super::Waker;
+
+// The bar taking the place of dunst or mako: applications hand their
+// notifications to whoever owns this name, following the desktop
+// notifications spec.
+const BUS_NAME: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+// How long the sender asked for the notification to stay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry {
+    Default,
+    Never,
+    After(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub id: u32,
+    pub app: String,
+    pub summary: String,
+    pub body: String,
+    // Action keys with their labels, "default" being the one for clicking
+    // the notification itself.
+    pub actions: Vec<(String, String)>,
+    pub urgency: Urgency,
+    pub expiry: Expiry,
+    pub received: Instant,
+}
+
+// Why a notification went away, as told to its sender.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    Expired = 1,
+    Dismissed = 2,
+    Closed = 3,
+}
+
+#[derive(Default)]
+struct State {
+    notifications: Vec<Notification>,
+    last_id: u32,
+    // Goes up whenever the notifications change.
+    generation: u64,
+}
+
+impl State {
+    fn remove(&mut self, id: u32) -> bool {
+        let count = self.notifications.len();
+        self.notifications.retain(|notification| notification.id != id);
+        let removed = self.notifications.len() != count;
+        if removed {
+            self.generation += 1;
+        }
+        removed
+    }
+}
+
+struct Daemon {
+    state: Arc<Mutex<State>>,
+    waker: Arc<Mutex<Waker>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl Daemon {
+    // The arguments are the ones the spec gives Notify.
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: String,
+        replaces_id: u32,
+        _app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    ) -> u32 {
+        let urgency = match hints.get("urgency").and_then(|urgency| u8::try_from(urgency).ok()) {
+            Some(0) => Urgency::Low,
+            Some(2) => Urgency::Critical,
+            _ => Urgency::Normal,
+        };
+        let expiry = match expire_timeout {
+            0 => Expiry::Never,
+            timeout if timeout < 0 => Expiry::Default,
+            timeout => Expiry::After(Duration::from_millis(timeout as u64)),
+        };
+        // Actions come as one list of keys each followed by its label.
+        let actions = actions.chunks_exact(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+
+        let mut state = self.state.lock().unwrap();
+        let id = if replaces_id != 0 && state.notifications.iter().any(|notification| notification.id == replaces_id) {
+            replaces_id
+        } else {
+            state.last_id = state.last_id.wrapping_add(1).max(1);
+            state.last_id
+        };
+
+        let notification = Notification {
+            id,
+            app: app_name,
+            summary,
+            body,
+            actions,
+            urgency,
+            expiry,
+            received: Instant::now(),
+        };
+        match state.notifications.iter_mut().find(|shown| shown.id == id) {
+            Some(shown) => *shown = notification,
+            None => state.notifications.push(notification),
+        }
+        state.generation += 1;
+        drop(state);
+
+        self.waker.lock().unwrap().wake();
+        id
+    }
+
+    async fn close_notification(&self, id: u32, #[zbus(signal_emitter)] emitter: SignalEmitter<'_>)
+        -> zbus::fdo::Result<()> {
+        let removed = self.state.lock().unwrap().remove(id);
+        if removed {
+            self.waker.lock().unwrap().wake();
+            Daemon::notification_closed(&emitter, id, Reason::Closed as u32).await?;
+        }
+
+        Ok(())
+    }
+
+    // Bodies are shown as plain text.
+    fn get_capabilities(&self) -> Vec<String> {
+        vec!["actions".to_string(), "body".to_string()]
+    }
+
+    fn get_server_information(&self) -> (String, String, String, String) {
+        ("rustybar".to_string(), "rustybar".to_string(), env!("CARGO_PKG_VERSION").to_string(), "1.2".to_string())
+    }
+
+    #[zbus(signal)]
+    async fn notification_closed(emitter: &SignalEmitter<'_>, id: u32, reason: u32) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn action_invoked(emitter: &SignalEmitter<'_>, id: u32, action_key: &str) -> zbus::Result<()>;
+}
+
+#[derive(Debug)]
+pub struct DaemonRunningError;
+
+impl std::fmt::Display for DaemonRunningError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Another notification daemon owns {}", BUS_NAME)
+    }
+}
+
+impl std::error::Error for DaemonRunningError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// Owns the bus name while it's alive.
+pub struct Server {
+    connection: Connection,
+    state: Arc<Mutex<State>>,
+    waker: Arc<Mutex<Waker>>,
+}
+
+impl Server {
+    // Every module shares one server, so that a reloaded bar keeps the
+    // name and the notifications shown before. The newest waker is used.
+    pub fn open(waker: &Waker) -> Result<Arc<Server>, Error> {
+        static SERVER: OnceLock<Mutex<Weak<Server>>> = OnceLock::new();
+        let mut shared = SERVER.get_or_init(Default::default).lock().unwrap();
+
+        if let Some(server) = shared.upgrade() {
+            *server.waker.lock().unwrap() = waker.clone();
+            return Ok(server);
+        }
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let waker = Arc::new(Mutex::new(waker.clone()));
+        let connection = Connection::session()?;
+        connection.object_server().at(PATH, Daemon { state: Arc::clone(&state), waker: Arc::clone(&waker) })?;
+        if connection.request_name_with_flags(BUS_NAME, RequestNameFlags::DoNotQueue.into())?
+            != RequestNameReply::PrimaryOwner {
+            Err(DaemonRunningError)?;
+        }
+
+        let server = Arc::new(Server { connection, state, waker });
+        *shared = Arc::downgrade(&server);
+        Ok(server)
+    }
+
+    // The notifications being shown, oldest first, along with a count that
+    // goes up whenever they change.
+    pub fn notifications(&self) -> (u64, Vec<Notification>) {
+        let state = self.state.lock().unwrap();
+        (state.generation, state.notifications.clone())
+    }
+
+    pub fn close(&self, id: u32, reason: Reason) {
+        let removed = self.state.lock().unwrap().remove(id);
+        if !removed {
+            return;
+        }
+
+        self.waker.lock().unwrap().wake();
+        let emitted = SignalEmitter::new(self.connection.inner(), PATH)
+            .and_then(|emitter| zbus::block_on(Daemon::notification_closed(&emitter, id, reason as u32)));
+        if let Err(err) = emitted {
+            warn!("Failed to announce that notification {} closed: {}", id, err);
+        }
+    }
+
+    // Tells the sender the action was picked, which closes the
+    // notification.
+    pub fn invoke(&self, id: u32, key: &str) {
+        let emitted = SignalEmitter::new(self.connection.inner(), PATH)
+            .and_then(|emitter| zbus::block_on(Daemon::action_invoked(&emitter, id, key)));
+        if let Err(err) = emitted {
+            warn!("Failed to send action '{}' of notification {}: {}", key, id, err);
+        }
+
+        self.close(id, Reason::Dismissed);
+    }
+}
@@ -0,0 +1,170 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, Button, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::notify::{Control, Status, Watcher};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct NotificationsConfig {
+    format: String,
+    format_dnd: String,
+    format_missing: String,
+    color: Option<Color>,
+    dnd_color: Color,
+    // Seconds between polls of the count.
+    interval: f64,
+    // The mako mode that stands for do-not-disturb.
+    dnd_mode: String,
+    history_length: usize,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> NotificationsConfig {
+        NotificationsConfig {
+            format: "{count}".to_string(),
+            format_dnd: "{count} (dnd)".to_string(),
+            format_missing: String::new(),
+            color: None,
+            dnd_color: Color::from_str("#888888").unwrap(),
+            interval: 2.0,
+            dnd_mode: "do-not-disturb".to_string(),
+            history_length: 10,
+        }
+    }
+}
+
+pub struct Notifications {
+    config: NotificationsConfig,
+    watcher: Watcher,
+    status: Status,
+    // Whether the history popup is open.
+    history: bool,
+    text: String,
+}
+
+impl Notifications {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: NotificationsConfig = section.clone().try_into()?;
+        let interval = Duration::from_secs_f64(config.interval.max(0.1));
+        let watcher = Watcher::spawn(waker.clone(), config.dnd_mode.clone(), interval);
+
+        let widget = Notifications { config, watcher, status: Status::default(), history: false, text: String::new() };
+        Ok(Box::new(Notifications { text: widget.format(), ..widget }))
+    }
+
+    fn format(&self) -> String {
+        let daemon = match self.status.daemon {
+            Some(daemon) => daemon,
+            None => return self.config.format_missing.clone(),
+        };
+
+        let template = if self.status.dnd { &self.config.format_dnd } else { &self.config.format };
+        format::placeholders(template, |key| match key {
+            "count" => Some(self.status.count.to_string()),
+            "daemon" => Some(daemon.name().to_string()),
+            _ => None,
+        })
+    }
+
+    fn control(&self, control: Control) {
+        if let Some(daemon) = self.status.daemon {
+            self.watcher.control(daemon, control);
+        }
+    }
+}
+
+impl Widget for Notifications {
+    fn update(&mut self) -> bool {
+        let status = match self.watcher.take() {
+            Some(status) => status,
+            None => return false,
+        };
+
+        if status == self.status {
+            return false;
+        }
+
+        self.status = status;
+        let text = self.format();
+        if text == self.text {
+            return false;
+        }
+
+        self.text = text;
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = if self.status.dnd {
+            self.config.dnd_color
+        } else {
+            self.config.color.unwrap_or_else(|| ctx.foreground())
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        match button {
+            Button::Left => self.control(Control::ToggleDnd),
+            Button::Middle => self.control(Control::Restore),
+            Button::Right => self.history = !self.history,
+        }
+
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "toggle-dnd" => self.control(Control::ToggleDnd),
+            "restore" => self.control(Control::Restore),
+            "history" => self.history = !self.history,
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(false)
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let daemon = self.status.daemon?;
+        let state = if self.status.dnd { "do not disturb" } else { "on" };
+        Some(format!("{} notifications\n<span foreground=\"#888888\">{} ({})</span>",
+            self.status.count, daemon.name(), state))
+    }
+
+    // The latest notifications the daemon kept, newest first.
+    fn popup(&self) -> Option<String> {
+        if !self.history || self.status.daemon.is_none() {
+            return None;
+        }
+
+        let lines: Vec<String> = self.status.history.iter()
+            .take(self.config.history_length)
+            .map(|notification| {
+                let mut line = format!("<b>{}</b>", markup::escape(&notification.summary));
+                if !notification.body.is_empty() {
+                    line.push_str(&format!(" {}", markup::escape(&notification.body.replace('\n', " "))));
+                }
+                if !notification.app.is_empty() {
+                    line.push_str(&format!(" <span foreground=\"#888888\">{}</span>", markup::escape(&notification.app)));
+                }
+                line
+            })
+            .collect();
+
+        if lines.is_empty() {
+            Some("<span foreground=\"#888888\">No notifications</span>".to_string())
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.status.daemon.map(|_| self.status.count as f64)
+    }
+}
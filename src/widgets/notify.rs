@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use zbus::MatchRule;
+use zbus::blocking::{Connection, Proxy};
+use zbus::message::Type as MessageType;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::OwnedValue;
+use crate::Error;
+use super::{worker, Waker};
+use super::worker::Signals;
+
+// Whichever daemon owns the notifications name gets asked through its own
+// interface: dunst exports one next to the standard notifications object,
+// mako one at a path of its own.
+const BUS_NAME: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+const DUNST_PATH: &str = PATH;
+const DUNST_INTERFACE: &str = "org.dunstproject.cmd0";
+const MAKO_PATH: &str = "/fr/emersion/Mako";
+const MAKO_INTERFACE: &str = "fr.emersion.Mako";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Daemon {
+    Dunst,
+    Mako,
+}
+
+impl Daemon {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Daemon::Dunst => "dunst",
+            Daemon::Mako => "mako",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub app: String,
+    pub summary: String,
+    pub body: String,
+}
+
+// What the daemon reports, with no daemon when neither is running. The
+// history has the latest notification first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Status {
+    pub daemon: Option<Daemon>,
+    pub count: usize,
+    pub dnd: bool,
+    pub history: Vec<Notification>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    ToggleDnd,
+    // Shows the latest notification from the history again.
+    Restore,
+}
+
+fn proxy<'a>(connection: &Connection, path: &'a str, interface: &'a str) -> Result<Proxy<'a>, Error> {
+    Ok(zbus::blocking::proxy::Builder::new(connection)
+        .destination(BUS_NAME)?
+        .path(path)?
+        .interface(interface)?
+        .cache_properties(CacheProperties::No)
+        .build()?)
+}
+
+async fn async_proxy<'a>(connection: &zbus::Connection, path: &'a str, interface: &'a str)
+    -> Result<zbus::Proxy<'a>, Error>
+{
+    Ok(zbus::proxy::Builder::new(connection)
+        .destination(BUS_NAME)?
+        .path(path)?
+        .interface(interface)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?)
+}
+
+fn notifications(entries: Vec<HashMap<String, OwnedValue>>, app_key: &str) -> Vec<Notification> {
+    entries.into_iter()
+        .map(|mut entry| {
+            let mut text = |key: &str| entry.remove(key)
+                .and_then(|value| String::try_from(value).ok())
+                .unwrap_or_default();
+            Notification { app: text(app_key), summary: text("summary"), body: text("body") }
+        })
+        .collect()
+}
+
+async fn query_dunst(connection: &zbus::Connection) -> Result<Status, Error> {
+    let dunst = async_proxy(connection, DUNST_PATH, DUNST_INTERFACE).await?;
+    let dnd = dunst.get_property::<bool>("paused").await?;
+    // Notifications wait while dunst is paused.
+    let count = dunst.get_property::<u32>("displayedLength").await? + dunst.get_property::<u32>("waitingLength").await?;
+    let history: Vec<HashMap<String, OwnedValue>> = dunst.call("NotificationListHistory", &()).await?;
+
+    Ok(Status { daemon: Some(Daemon::Dunst), count: count as usize, dnd, history: notifications(history, "appname") })
+}
+
+async fn query_mako(connection: &zbus::Connection, dnd_mode: &str) -> Result<Status, Error> {
+    let mako = async_proxy(connection, MAKO_PATH, MAKO_INTERFACE).await?;
+    let modes: Vec<String> = mako.call("ListModes", &()).await?;
+    let shown: Vec<HashMap<String, OwnedValue>> = mako.call("ListNotifications", &()).await?;
+    let history: Vec<HashMap<String, OwnedValue>> = mako.call("ListHistory", &()).await?;
+
+    Ok(Status {
+        daemon: Some(Daemon::Mako),
+        count: shown.len(),
+        dnd: modes.iter().any(|mode| mode == dnd_mode),
+        history: notifications(history, "app-name"),
+    })
+}
+
+async fn query(connection: &zbus::Connection, dnd_mode: &str) -> Status {
+    if let Ok(status) = query_dunst(connection).await {
+        return status;
+    }
+    query_mako(connection, dnd_mode).await.unwrap_or_default()
+}
+
+async fn watch(shared: Weak<Mutex<Option<Status>>>, waker: Waker, dnd_mode: String, interval: Duration)
+    -> Result<(), Error> {
+    let connection = zbus::Connection::session().await?;
+
+    let properties = |path| -> Result<MatchRule<'static>, Error> {
+        Ok(MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .path(path)?
+            .build())
+    };
+    let closed = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .interface(BUS_NAME)?
+        .member("NotificationClosed")?
+        .build();
+    let owners = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender("org.freedesktop.DBus")?
+        .interface("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .arg(0, BUS_NAME)?
+        .build();
+
+    let rules = [properties(DUNST_PATH)?, properties(MAKO_PATH)?, closed, owners];
+    let mut signals = Signals::subscribe(&connection, rules, 16).await?;
+
+    // New notifications come with no signal anyone but the daemon sees, so
+    // the count is also read every interval.
+    let mut known = None;
+    loop {
+        let status = query(&connection, &dnd_mode).await;
+        if known.as_ref() != Some(&status) {
+            match shared.upgrade() {
+                Some(state) => *state.lock().unwrap() = Some(status.clone()),
+                None => return Ok(()),
+            }
+            waker.wake();
+            known = Some(status);
+        }
+
+        if let Ok(Err(err)) = tokio::time::timeout(interval, signals.next()).await {
+            return Err(err);
+        }
+    }
+}
+
+// Keeps track of the notification daemon's count, do-not-disturb state and
+// history, until it's dropped.
+pub struct Watcher {
+    state: Arc<Mutex<Option<Status>>>,
+    dnd_mode: String,
+    _watch: worker::Task,
+}
+
+impl Watcher {
+    // Mako has no do-not-disturb state of its own, it's whether the given
+    // mode is on.
+    pub fn spawn(waker: Waker, dnd_mode: String, interval: Duration) -> Watcher {
+        let state = Arc::new(Mutex::new(None));
+        let shared = Arc::downgrade(&state);
+
+        let mode = dnd_mode.clone();
+        let watch = worker::watch(Duration::from_secs(5), "notifications".to_string(),
+            move || watch(shared.clone(), waker.clone(), mode.clone(), interval));
+
+        Watcher { state, dnd_mode, _watch: watch }
+    }
+
+    // Returns the status if it changed since the last call.
+    pub fn take(&self) -> Option<Status> {
+        self.state.lock().unwrap().take()
+    }
+
+    pub fn control(&self, daemon: Daemon, control: Control) {
+        let dnd_mode = self.dnd_mode.clone();
+        worker::spawn_control(format!("control {}", daemon.name()),
+            move || send_control(daemon, control, &dnd_mode));
+    }
+}
+
+fn send_control(daemon: Daemon, control: Control, dnd_mode: &str) -> Result<(), Error> {
+    let connection = Connection::session()?;
+
+    match (daemon, control) {
+        (Daemon::Dunst, Control::ToggleDnd) => {
+            let dunst = proxy(&connection, DUNST_PATH, DUNST_INTERFACE)?;
+            let paused = dunst.get_property::<bool>("paused")?;
+            dunst.set_property("paused", !paused)?;
+        },
+        (Daemon::Dunst, Control::Restore) => {
+            proxy(&connection, DUNST_PATH, DUNST_INTERFACE)?.call_method("NotificationShow", &())?;
+        },
+        (Daemon::Mako, Control::ToggleDnd) => {
+            let mako = proxy(&connection, MAKO_PATH, MAKO_INTERFACE)?;
+            let mut modes: Vec<String> = mako.call("ListModes", &())?;
+            match modes.iter().position(|mode| mode == dnd_mode) {
+                Some(index) => {
+                    modes.remove(index);
+                },
+                None => modes.push(dnd_mode.to_string()),
+            }
+            mako.call_method("SetModes", &(modes,))?;
+        },
+        (Daemon::Mako, Control::Restore) => {
+            proxy(&connection, MAKO_PATH, MAKO_INTERFACE)?.call_method("RestoreNotification", &())?;
+        },
+    }
+
+    Ok(())
+}
+
+// Shows a notification through whichever daemon is running.
+pub fn show(summary: String, body: String) {
+    worker::spawn_control("show a notification".to_string(), move || send_notification(&summary, &body));
+}
+
+fn send_notification(summary: &str, body: &str) -> Result<(), Error> {
+    let connection = Connection::session()?;
+    let (actions, hints): (Vec<&str>, HashMap<&str, OwnedValue>) = (Vec::new(), HashMap::new());
+    connection.call_method(Some(BUS_NAME), PATH, Some(BUS_NAME), "Notify",
+        &("rustybar", 0u32, "", summary, body, actions, hints, -1i32))?;
+
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::os::unix::io::FromRawFd;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, Waker, Widget};
+use super::worker::Poller;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct PingConfig {
+    host: String,
+    // Seconds between rounds of pings, and how many go out per round.
+    interval: f64,
+    count: u32,
+    // Seconds to wait for each reply.
+    timeout: f64,
+    format: String,
+    // Shown when no reply came back.
+    format_down: String,
+    // Shown when the host can't be resolved or pinged at all.
+    format_missing: String,
+    // Round trip times in milliseconds, and the share of pings lost in
+    // percent, above which the module turns to the warning or critical
+    // color.
+    warning: f64,
+    critical: f64,
+    loss_warning: f64,
+    loss_critical: f64,
+    color: Option<Color>,
+    warning_color: Color,
+    critical_color: Color,
+    down_color: Color,
+}
+
+impl Default for PingConfig {
+    fn default() -> PingConfig {
+        PingConfig {
+            host: "1.1.1.1".to_string(),
+            interval: 10.0,
+            count: 3,
+            timeout: 2.0,
+            format: "{rtt} ms".to_string(),
+            format_down: "offline".to_string(),
+            format_missing: String::new(),
+            warning: 100.0,
+            critical: 300.0,
+            loss_warning: 1.0,
+            loss_critical: 50.0,
+            color: None,
+            warning_color: Color::from_str("#ffaa00").unwrap(),
+            critical_color: Color::from_str("#ff3333").unwrap(),
+            down_color: Color::from_str("#888888").unwrap(),
+        }
+    }
+}
+
+// One round of pings.
+#[derive(Debug, Clone, PartialEq)]
+struct Round {
+    address: IpAddr,
+    sent: u32,
+    // Round trip times of the replies, in milliseconds.
+    replies: Vec<f64>,
+}
+
+impl Round {
+    fn loss(&self) -> f64 {
+        match self.sent {
+            0 => 0.0,
+            sent => (sent - self.replies.len() as u32) as f64 / sent as f64 * 100.0,
+        }
+    }
+
+    fn average(&self) -> Option<f64> {
+        match self.replies.len() {
+            0 => None,
+            count => Some(self.replies.iter().sum::<f64>() / count as f64),
+        }
+    }
+}
+
+// Unprivileged ICMP sockets, which Linux allows for the groups in the
+// net.ipv4.ping_group_range sysctl. The kernel fills in the identifier and
+// checksum and only hands over the replies to this socket's requests, with
+// the IP header stripped.
+fn socket(address: IpAddr) -> Result<UdpSocket, Error> {
+    let (domain, protocol) = match address {
+        IpAddr::V4(_) => (libc::AF_INET, libc::IPPROTO_ICMP),
+        IpAddr::V6(_) => (libc::AF_INET6, libc::IPPROTO_ICMPV6),
+    };
+
+    let fd = unsafe { libc::socket(domain, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, protocol) };
+    if fd < 0 {
+        return Err(Box::new(std::io::Error::last_os_error()));
+    }
+
+    Ok(unsafe { UdpSocket::from_raw_fd(fd) })
+}
+
+fn ping(host: &str, count: u32, timeout: Duration) -> Result<Round, Error> {
+    let address = (host, 0).to_socket_addrs()?.next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("'{}' has no address", host)))?
+        .ip();
+    let socket = socket(address)?;
+    let (request, reply) = match address {
+        IpAddr::V4(_) => (8, 0),
+        IpAddr::V6(_) => (128, 129),
+    };
+
+    let mut round = Round { address, sent: 0, replies: Vec::new() };
+    for sequence in 0..count as u16 {
+        // Type, code, checksum, identifier and sequence number, followed by
+        // a few bytes of payload.
+        let mut packet = [0; 16];
+        packet[0] = request;
+        packet[6..8].copy_from_slice(&sequence.to_be_bytes());
+
+        // Without a route to the host the ping is as good as lost, which is
+        // what the module is there to tell.
+        let sent = Instant::now();
+        round.sent += 1;
+        match socket.send_to(&packet, SocketAddr::new(address, 0)) {
+            Ok(_) => (),
+            Err(err) if matches!(err.kind(), std::io::ErrorKind::NetworkUnreachable
+                | std::io::ErrorKind::HostUnreachable | std::io::ErrorKind::NetworkDown) => continue,
+            Err(err) => return Err(Box::new(err)),
+        }
+
+        // Replies to earlier requests that came in late are skipped.
+        let mut buffer = [0; 1500];
+        while let Some(left) = timeout.checked_sub(sent.elapsed()).filter(|left| !left.is_zero()) {
+            socket.set_read_timeout(Some(left))?;
+            let length = match socket.recv(&mut buffer) {
+                Ok(length) => length,
+                Err(err) if matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => break,
+                Err(err) => return Err(Box::new(err)),
+            };
+
+            if length >= 8 && buffer[0] == reply && buffer[6..8] == sequence.to_be_bytes() {
+                round.replies.push(sent.elapsed().as_secs_f64() * 1000.0);
+                break;
+            }
+        }
+    }
+
+    Ok(round)
+}
+
+// Which of the module's colors the round calls for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Normal,
+    Warning,
+    Critical,
+    Down,
+}
+
+pub struct Ping {
+    config: PingConfig,
+    poller: Poller<Option<Round>>,
+    round: Option<Round>,
+    text: String,
+    level: Level,
+}
+
+impl Ping {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: PingConfig = section.clone().try_into()?;
+        let (host, count) = (config.host.clone(), config.count.max(1));
+        let timeout = Duration::from_secs_f64(config.timeout.max(0.1));
+
+        // Failing to ping at all is only worth a warning when it starts, not
+        // on every round.
+        let mut failing = false;
+        let poller = Poller::spawn(Duration::from_secs_f64(config.interval.max(1.0)), waker.clone(), move || {
+            match ping(&host, count, timeout) {
+                Ok(round) => {
+                    failing = false;
+                    Some(round)
+                },
+                Err(err) => {
+                    if !failing {
+                        warn!("Failed to ping {}: {}", host, err);
+                    }
+                    failing = true;
+                    None
+                },
+            }
+        });
+
+        Ok(Box::new(Ping { config, poller, round: None, text: String::new(), level: Level::Down }))
+    }
+
+    // Loss crosses its thresholds while the text may only show the round
+    // trip time, so the level is compared along with the text.
+    fn level(&self) -> Level {
+        let config = &self.config;
+        match &self.round {
+            Some(round) => match round.average() {
+                None => Level::Down,
+                Some(rtt) if rtt >= config.critical || round.loss() >= config.loss_critical => Level::Critical,
+                Some(rtt) if rtt >= config.warning || round.loss() >= config.loss_warning => Level::Warning,
+                Some(_) => Level::Normal,
+            },
+            None => Level::Down,
+        }
+    }
+
+    fn format(&self) -> String {
+        let round = match &self.round {
+            Some(round) => round,
+            None => return self.config.format_missing.clone(),
+        };
+        let average = match round.average() {
+            Some(average) => average,
+            None => return self.config.format_down.clone(),
+        };
+
+        let fold = |start: f64, pick: fn(f64, f64) -> f64| round.replies.iter().copied().fold(start, pick);
+        format::placeholders(&self.config.format, |key| match key {
+            "rtt" => Some(format!("{:.0}", average)),
+            "min" => Some(format!("{:.0}", fold(f64::INFINITY, f64::min))),
+            "max" => Some(format!("{:.0}", fold(0.0, f64::max))),
+            "loss" => Some(format!("{:.0}", round.loss())),
+            "host" => Some(self.config.host.clone()),
+            "address" => Some(round.address.to_string()),
+            _ => None,
+        })
+    }
+}
+
+impl Widget for Ping {
+    fn update(&mut self) -> bool {
+        let round = match self.poller.take() {
+            Some(round) => round,
+            None => return false,
+        };
+
+        self.round = round;
+        let (text, level) = (self.format(), self.level());
+        if text == self.text && level == self.level {
+            return false;
+        }
+
+        self.text = text;
+        self.level = level;
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let config = &self.config;
+        let color = match self.level {
+            Level::Normal => config.color.unwrap_or_else(|| ctx.foreground()),
+            Level::Warning => config.warning_color,
+            Level::Critical => config.critical_color,
+            Level::Down => config.down_color,
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let round = self.round.as_ref()?;
+        Some(format!("{} <span foreground=\"#888888\">{}</span>\n{} of {} replies",
+            markup::escape(&self.config.host), round.address, round.replies.len(), round.sent))
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.round.as_ref().and_then(Round::average)
+    }
+}
@@ -0,0 +1,216 @@
+use libloading::Library;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::warn;
+use crate::Error;
+use crate::render::RenderCtx;
+use super::{Button, Registry, Scroll, Waker, Widget};
+
+// Shared objects in ~/.config/rustybar/plugins add widget kinds through a C
+// ABI, WebAssembly plugins are loaded from there too when built with the
+// wasm feature. Each one exports a function returning a description of its widget:
+//
+//     typedef struct {
+//         uint32_t abi_version;    /* PLUGIN_ABI_VERSION */
+//         const char *kind;        /* used as the module type */
+//         void *(*create)(const char *config_json, void (*wake)(void *), void *wake_data);
+//         bool (*update)(void *state);
+//         char *(*text)(void *state);            /* may use markup */
+//         bool (*click)(void *state, float x, uint32_t button);
+//         void (*free_text)(char *text);
+//         void (*destroy)(void *state);
+//     } RustybarPlugin;
+//
+//     const RustybarPlugin *rustybar_plugin(void);
+//
+// `create` gets the module section as JSON and returns null on failure.
+// `wake` may be called from any thread to have `update` called soon, but
+// not after `destroy` returned. Buttons are numbered as in X11, 4 and 5
+// being scrolling up and down.
+const PLUGIN_ABI_VERSION: u32 = 1;
+const ENTRY_POINT: &[u8] = b"rustybar_plugin";
+
+#[repr(C)]
+struct Description {
+    abi_version: u32,
+    kind: *const c_char,
+    create: extern "C" fn(*const c_char, extern "C" fn(*mut c_void), *mut c_void) -> *mut c_void,
+    update: extern "C" fn(*mut c_void) -> bool,
+    text: extern "C" fn(*mut c_void) -> *mut c_char,
+    click: extern "C" fn(*mut c_void, f32, u32) -> bool,
+    free_text: extern "C" fn(*mut c_char),
+    destroy: extern "C" fn(*mut c_void),
+}
+
+#[derive(Debug)]
+struct PluginError(String);
+
+impl std::fmt::Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PluginError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// The library stays loaded for as long as any of its widgets exist.
+struct Plugin {
+    description: &'static Description,
+    _library: Library,
+}
+
+// The description only holds function pointers and a static string.
+unsafe impl Send for Plugin {}
+unsafe impl Sync for Plugin {}
+
+extern "C" fn wake(data: *mut c_void) {
+    let waker = unsafe { &*(data as *const Waker) };
+    waker.wake();
+}
+
+impl Plugin {
+    fn open(path: &Path) -> Result<(String, Plugin), Error> {
+        let library = unsafe { Library::new(path)? };
+        let description = unsafe {
+            let entry = library.get::<extern "C" fn() -> *const Description>(ENTRY_POINT)?;
+            entry().as_ref().ok_or_else(|| PluginError("the plugin returned no description".to_string()))?
+        };
+
+        if description.abi_version != PLUGIN_ABI_VERSION {
+            let message = format!("ABI version {} isn't supported, expected {}", description.abi_version,
+                PLUGIN_ABI_VERSION);
+            return Err(Box::new(PluginError(message)));
+        }
+        if description.kind.is_null() {
+            return Err(Box::new(PluginError("the plugin has no widget kind".to_string())));
+        }
+        let kind = unsafe { CStr::from_ptr(description.kind) }.to_string_lossy().to_string();
+
+        Ok((kind, Plugin { description, _library: library }))
+    }
+}
+
+struct PluginWidget {
+    plugin: Arc<Plugin>,
+    state: *mut c_void,
+    // Boxed so the plugin can hold on to its address.
+    waker: *mut Waker,
+    text: String,
+}
+
+impl PluginWidget {
+    fn build(plugin: &Arc<Plugin>, section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config = CString::new(serde_json::to_string(section)?)?;
+        let waker = Box::into_raw(Box::new(waker.clone()));
+
+        let state = (plugin.description.create)(config.as_ptr(), wake, waker as *mut c_void);
+        if state.is_null() {
+            drop(unsafe { Box::from_raw(waker) });
+            return Err(Box::new(PluginError("the plugin failed to create its widget".to_string())));
+        }
+
+        Ok(Box::new(PluginWidget { plugin: Arc::clone(plugin), state, waker, text: String::new() }))
+    }
+
+    fn click_button(&mut self, x: f32, button: u32) -> bool {
+        (self.plugin.description.click)(self.state, x, button) && self.refresh()
+    }
+
+    fn refresh(&mut self) -> bool {
+        let description = self.plugin.description;
+        let raw = (description.text)(self.state);
+        if raw.is_null() {
+            return false;
+        }
+
+        let text = unsafe { CStr::from_ptr(raw) }.to_string_lossy().to_string();
+        (description.free_text)(raw);
+
+        let changed = text != self.text;
+        self.text = text;
+        changed
+    }
+}
+
+impl Widget for PluginWidget {
+    fn update(&mut self) -> bool {
+        (self.plugin.description.update)(self.state) && self.refresh()
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = ctx.foreground();
+        ctx.text(&self.text, color);
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        let button = match button {
+            Button::Left => 1,
+            Button::Middle => 2,
+            Button::Right => 3,
+        };
+        self.click_button(x, button)
+    }
+
+    fn scroll(&mut self, x: f32, direction: Scroll) -> bool {
+        let button = match direction {
+            Scroll::Up => 4,
+            Scroll::Down => 5,
+        };
+        self.click_button(x, button)
+    }
+}
+
+impl Drop for PluginWidget {
+    fn drop(&mut self) {
+        (self.plugin.description.destroy)(self.state);
+        drop(unsafe { Box::from_raw(self.waker) });
+    }
+}
+
+fn directory() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rustybar").join("plugins"))
+}
+
+// Plugins that fail to load are reported and skipped, they can't replace
+// built-in widget kinds.
+pub fn load_all(registry: &mut Registry) {
+    let entries = match directory().and_then(|directory| std::fs::read_dir(directory).ok()) {
+        Some(entries) => entries,
+        None => return,
+    };
+
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+
+    #[cfg(feature = "wasm")]
+    for path in paths.iter().filter(|path| path.extension().is_some_and(|extension| extension == "wasm")) {
+        let kind = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        match super::wasm::WasmPlugin::open(path) {
+            Ok(_) if registry.has(&kind) => {
+                warn!("Skipping plugin {}: a widget kind '{}' already exists", path.display(), kind);
+            },
+            Ok(plugin) => registry.register(&kind, move |section: &toml::Value, _waker: &Waker| plugin.build(section)),
+            Err(err) => warn!("Skipping plugin {}: {}", path.display(), err),
+        }
+    }
+
+    for path in paths.iter().filter(|path| path.extension().is_some_and(|extension| extension == "so")) {
+        match Plugin::open(path) {
+            Ok((kind, _)) if registry.has(&kind) => {
+                warn!("Skipping plugin {}: a widget kind '{}' already exists", path.display(), kind);
+            },
+            Ok((kind, plugin)) => {
+                let plugin = Arc::new(plugin);
+                registry.register(&kind, move |section: &toml::Value, waker: &Waker| {
+                    PluginWidget::build(&plugin, section, waker)
+                });
+            },
+            Err(err) => warn!("Skipping plugin {}: {}", path.display(), err),
+        }
+    }
+}
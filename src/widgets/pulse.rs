@@ -0,0 +1,202 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::Error;
+use super::Waker;
+
+// PulseAudio (or pipewire-pulse) is driven through pactl, which keeps the
+// bar free of a libpulse link-time dependency while still using the server's
+// event subscription rather than polling.
+
+#[derive(Debug)]
+struct PactlError;
+
+impl std::fmt::Display for PactlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "pactl failed to update the sound server")
+    }
+}
+
+impl std::error::Error for PactlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Device {
+    Sink,
+    Source,
+}
+
+impl Device {
+    fn kind(&self) -> &'static str {
+        match self {
+            Device::Sink => "sink",
+            Device::Source => "source",
+        }
+    }
+
+    fn default_name(&self) -> &'static str {
+        match self {
+            Device::Sink => "@DEFAULT_SINK@",
+            Device::Source => "@DEFAULT_SOURCE@",
+        }
+    }
+
+    // What plays to a sink, or records from a source.
+    fn streams(&self) -> &'static str {
+        match self {
+            Device::Sink => "sink-input",
+            Device::Source => "source-output",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceState {
+    pub name: String,
+    pub description: String,
+    pub volume: u32,
+    pub muted: bool,
+    // How many streams play to or record from the device.
+    pub streams: usize,
+}
+
+fn pactl(args: &[&str]) -> Option<String> {
+    let output = Command::new("pactl").args(args).stderr(Stdio::null()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn description(device: Device, name: &str) -> Option<String> {
+    let listing = pactl(&["list", &format!("{}s", device.kind())])?;
+
+    let mut lines = listing.lines().map(str::trim);
+    lines.find(|line| line.strip_prefix("Name: ") == Some(name))?;
+    lines.find_map(|line| line.strip_prefix("Description: ").map(str::to_string))
+}
+
+pub fn query(device: Device) -> Option<DeviceState> {
+    let name = pactl(&[&format!("get-default-{}", device.kind())])?;
+
+    // "Volume: front-left: 65536 / 100% / 0.00 dB, ..." - the first channel
+    // is representative enough for a bar.
+    let volume = pactl(&[&format!("get-{}-volume", device.kind()), device.default_name()])?;
+    let volume = volume.split('/')
+        .find_map(|part| part.trim().strip_suffix('%').and_then(|percent| percent.parse().ok()))
+        .unwrap_or(0);
+
+    let muted = pactl(&[&format!("get-{}-mute", device.kind()), device.default_name()])?.ends_with("yes");
+    let description = description(device, &name).unwrap_or_else(|| name.clone());
+    let streams = streams(device, &name).unwrap_or(0);
+
+    Some(DeviceState { name, description, volume, muted, streams })
+}
+
+// Short listings start with the index, streams name the index of their
+// device next to it.
+fn streams(device: Device, name: &str) -> Option<usize> {
+    let devices = pactl(&["list", "short", &format!("{}s", device.kind())])?;
+    let index = devices.lines().find_map(|line| {
+        let mut fields = line.split('\t');
+        let index = fields.next()?;
+        (fields.next()? == name).then(|| index.to_string())
+    })?;
+
+    let streams = pactl(&["list", "short", &format!("{}s", device.streams())])?;
+    Some(streams.lines().filter(|line| line.split('\t').nth(1) == Some(&index)).count())
+}
+
+fn pactl_set(args: &[&str]) -> Result<(), Error> {
+    let status = Command::new("pactl").args(args).stderr(Stdio::null()).status()?;
+    if !status.success() {
+        return Err(Box::new(PactlError));
+    }
+
+    Ok(())
+}
+
+pub fn toggle_mute(device: Device) -> Result<(), Error> {
+    pactl_set(&[&format!("set-{}-mute", device.kind()), device.default_name(), "toggle"])
+}
+
+// Changes the volume by the given number of percentage points.
+pub fn adjust_volume(device: Device, percent: i32) -> Result<(), Error> {
+    pactl_set(&[&format!("set-{}-volume", device.kind()), device.default_name(), &format!("{:+}%", percent)])
+}
+
+// Keeps the latest state of the default sink or source up to date by
+// following `pactl subscribe`, restarting it if the sound server goes away.
+pub struct Monitor {
+    state: Arc<Mutex<Option<Option<DeviceState>>>>,
+    child: Arc<Mutex<Option<Child>>>,
+}
+
+impl Monitor {
+    pub fn spawn(device: Device, waker: Waker) -> Monitor {
+        let state = Arc::new(Mutex::new(None));
+        let child = Arc::new(Mutex::new(None));
+        let (shared_state, shared_child) = (Arc::downgrade(&state), Arc::downgrade(&child));
+
+        std::thread::spawn(move || loop {
+            let publish = || match shared_state.upgrade() {
+                Some(state) => {
+                    *state.lock().unwrap() = Some(query(device));
+                    waker.wake();
+                    true
+                },
+                None => false,
+            };
+
+            if !publish() {
+                return;
+            }
+
+            let spawned = Command::new("pactl").arg("subscribe")
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn();
+
+            if let (Ok(mut process), Some(child)) = (spawned, shared_child.upgrade()) {
+                let stdout = process.stdout.take().unwrap();
+                *child.lock().unwrap() = Some(process);
+                drop(child);
+
+                let relevant = [
+                    format!(" on {} #", device.kind()),
+                    format!(" on {} #", device.streams()),
+                    " on server".to_string(),
+                ];
+                for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                    if relevant.iter().any(|event| line.contains(event.as_str())) && !publish() {
+                        return;
+                    }
+                }
+            }
+
+            std::thread::sleep(Duration::from_secs(5));
+        });
+
+        Monitor { state, child }
+    }
+
+    // Returns the newest state if it changed since the last call; the inner
+    // None means the sound server couldn't be queried.
+    pub fn take(&self) -> Option<Option<DeviceState>> {
+        self.state.lock().unwrap().take()
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.lock().unwrap().take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
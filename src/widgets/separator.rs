@@ -0,0 +1,100 @@
+use serde::Deserialize;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{Primitive, RenderCtx, Shape};
+use super::{Waker, Widget};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Style {
+    Text,
+    Line,
+    Arrow,
+    ArrowLeft,
+    Slant,
+    SlantBack,
+    Round,
+    RoundLeft,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct SeparatorConfig {
+    style: Style,
+    // Shown by the text style.
+    text: String,
+    // Width of the line or transition, text takes the space it needs.
+    width: f32,
+    line_width: f32,
+    color: Option<Color>,
+    // Transitions blend between the backgrounds of the modules on either
+    // side unless these are set.
+    left: Option<Color>,
+    right: Option<Color>,
+}
+
+impl Default for SeparatorConfig {
+    fn default() -> SeparatorConfig {
+        SeparatorConfig {
+            style: Style::Line,
+            text: "|".to_string(),
+            width: 12.0,
+            line_width: 1.0,
+            color: None,
+            left: None,
+            right: None,
+        }
+    }
+}
+
+pub struct Separator {
+    config: SeparatorConfig,
+}
+
+impl Separator {
+    pub fn build(section: &toml::Value, _waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        Ok(Box::new(Separator { config: section.clone().try_into()? }))
+    }
+}
+
+impl Widget for Separator {
+    fn update(&mut self) -> bool {
+        false
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let config = &self.config;
+        let color = config.color.unwrap_or_else(|| ctx.foreground());
+
+        let shape = match config.style {
+            Style::Text => {
+                ctx.text(&config.text, color);
+                return;
+            },
+            Style::Line => {
+                let x = ((config.width - config.line_width) / 2.0).round();
+                ctx.rect(x, 0.0, config.line_width, ctx.height(), color);
+                ctx.advance((config.width - x - config.line_width).max(0.0));
+                return;
+            },
+            Style::Arrow => Shape::Arrow,
+            Style::ArrowLeft => Shape::ArrowLeft,
+            Style::Slant => Shape::Slant,
+            Style::SlantBack => Shape::SlantBack,
+            Style::Round => Shape::Round,
+            Style::RoundLeft => Shape::RoundLeft,
+        };
+
+        // Sides without a background show the bar's own through them.
+        let (before, after) = ctx.neighbours();
+        ctx.push(Primitive::Transition {
+            x: 0.0,
+            y: 0.0,
+            width: config.width,
+            height: ctx.height(),
+            shape,
+            left: config.left.or(before).unwrap_or(Color::TRANSPARENT),
+            right: config.right.or(after).unwrap_or(Color::TRANSPARENT),
+        });
+    }
+}
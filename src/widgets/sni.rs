@@ -0,0 +1,549 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use zbus::{MatchRule, Message};
+use zbus::blocking::{Connection, Proxy};
+use zbus::fdo::{RequestNameFlags, RequestNameReply};
+use zbus::message::{Header, Type as MessageType};
+use zbus::object_server::SignalEmitter;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use tracing::warn;
+use crate::Error;
+use crate::render::Image;
+use super::{icons, worker, Waker};
+use super::worker::Signals;
+
+// Tray icons follow the StatusNotifierItem protocol: applications register
+// their items with a watcher, and hosts like the bar list the registered
+// items from it. Desktops usually run a watcher, without one the bar runs
+// its own.
+const WATCHER_NAME: &str = "org.kde.StatusNotifierWatcher";
+const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+const DEFAULT_ITEM_PATH: &str = "/StatusNotifierItem";
+// Items export their menus through dbusmenu, at the path in their Menu
+// property.
+const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Passive,
+    Active,
+    NeedsAttention,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    // The bus name and object path the item was registered under.
+    pub bus: String,
+    pub path: String,
+    pub title: String,
+    pub status: Status,
+    pub icon: Option<Arc<Image>>,
+    // Symbolic icons are drawn in the tray's text color.
+    pub symbolic: bool,
+    // Items that only offer a menu want it on any click.
+    pub is_menu: bool,
+    // The object path of the item's dbusmenu.
+    pub menu: Option<String>,
+}
+
+// An entry of an item's menu. Hidden entries are left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuEntry {
+    pub id: i32,
+    pub label: String,
+    pub enabled: bool,
+    pub separator: bool,
+    pub toggle: Option<Toggle>,
+    pub children: Vec<MenuEntry>,
+}
+
+// Entries that can be ticked, and whether they are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Toggle {
+    Check(bool),
+    Radio(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    Primary,
+    Secondary,
+    ContextMenu,
+    // Steps of the scroll wheel, positive for scrolling up.
+    Scroll(i32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    pub theme: Option<String>,
+    pub size: u32,
+}
+
+struct WatcherService {
+    items: Arc<Mutex<Vec<String>>>,
+}
+
+#[zbus::interface(name = "org.kde.StatusNotifierWatcher")]
+impl WatcherService {
+    async fn register_status_notifier_item(
+        &self,
+        service: &str,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        // Items register either with their own bus name or, like the
+        // libappindicator ones, with an object path on the sender.
+        let item = if service.starts_with('/') {
+            format!("{}{}", header.sender().map(|sender| sender.as_str()).unwrap_or_default(), service)
+        } else {
+            format!("{}{}", service, DEFAULT_ITEM_PATH)
+        };
+
+        let added = {
+            let mut items = self.items.lock().unwrap();
+            let added = !items.contains(&item);
+            if added {
+                items.push(item.clone());
+            }
+            added
+        };
+
+        if added {
+            WatcherService::status_notifier_item_registered(&emitter, &item).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn register_status_notifier_host(
+        &self,
+        _service: &str,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        WatcherService::status_notifier_host_registered(&emitter).await?;
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn registered_status_notifier_items(&self) -> Vec<String> {
+        self.items.lock().unwrap().clone()
+    }
+
+    #[zbus(property)]
+    fn is_status_notifier_host_registered(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn protocol_version(&self) -> i32 {
+        0
+    }
+
+    #[zbus(signal)]
+    async fn status_notifier_item_registered(emitter: &SignalEmitter<'_>, service: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn status_notifier_item_unregistered(emitter: &SignalEmitter<'_>, service: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn status_notifier_host_registered(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+// Splits a registered item into its bus name and object path.
+fn parse_address(item: &str) -> (String, String) {
+    match item.find('/') {
+        Some(slash) => (item[..slash].to_string(), item[slash..].to_string()),
+        None => (item.to_string(), DEFAULT_ITEM_PATH.to_string()),
+    }
+}
+
+fn proxy<'a>(connection: &Connection, bus: &'a str, path: &'a str, interface: &'a str) -> Result<Proxy<'a>, Error> {
+    Ok(zbus::blocking::proxy::Builder::new(connection)
+        .destination(bus)?
+        .path(path)?
+        .interface(interface)?
+        .cache_properties(CacheProperties::No)
+        .build()?)
+}
+
+async fn async_proxy<'a>(connection: &zbus::Connection, bus: &'a str, path: &'a str, interface: &'a str)
+    -> Result<zbus::Proxy<'a>, Error>
+{
+    Ok(zbus::proxy::Builder::new(connection)
+        .destination(bus)?
+        .path(path)?
+        .interface(interface)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?)
+}
+
+// Pixmaps are ARGB32 in network byte order and not premultiplied.
+pub fn pixmap_image(pixmaps: Vec<(i32, i32, Vec<u8>)>, size: u32) -> Option<Image> {
+    let valid = pixmaps.into_iter()
+        .filter(|(width, height, data)| *width > 0 && *height > 0 && data.len() == (*width * *height * 4) as usize);
+    let (larger, smaller): (Vec<_>, Vec<_>) = valid.partition(|(width, _, _)| *width as u32 >= size);
+    let (width, height, data) = larger.into_iter().min_by_key(|(width, _, _)| *width)
+        .or_else(|| smaller.into_iter().max_by_key(|(width, _, _)| *width))?;
+
+    let mut pixels: Vec<u8> = data.chunks_exact(4).flat_map(|argb| [argb[1], argb[2], argb[3], argb[0]]).collect();
+    icons::premultiply(&mut pixels);
+
+    Some(Image::new(width as u32, height as u32, pixels))
+}
+
+// Also tells whether the icon is a symbolic one. Icons are looked up in
+// the theme without holding up the runtime's other tasks.
+async fn item_icon(item: &zbus::Proxy<'_>, attention: bool, lookup: &Lookup) -> Option<(Image, bool)> {
+    let (name_property, pixmap_property) = if attention {
+        ("AttentionIconName", "AttentionIconPixmap")
+    } else {
+        ("IconName", "IconPixmap")
+    };
+
+    let theme_path = item.get_property::<String>("IconThemePath").await.ok().filter(|path| !path.is_empty());
+    let name = item.get_property::<String>(name_property).await.ok().filter(|name| !name.is_empty());
+    let named = name.and_then(|name| tokio::task::block_in_place(|| {
+        let path = icons::find(&name, lookup.theme.as_deref(), lookup.size, theme_path.as_deref().map(Path::new))?;
+        Some((icons::load(&path, lookup.size).ok()?, icons::is_symbolic(&path)))
+    }));
+
+    match named {
+        Some(named) => Some(named),
+        None => {
+            let pixmaps = item.get_property(pixmap_property).await.ok()?;
+            Some((pixmap_image(pixmaps, lookup.size)?, false))
+        },
+    }
+}
+
+async fn query_item(connection: &zbus::Connection, address: &str, lookup: &Lookup) -> Result<Item, Error> {
+    let (bus, path) = parse_address(address);
+    let item = async_proxy(connection, &bus, &path, ITEM_INTERFACE).await?;
+
+    let status = match item.get_property::<String>("Status").await.unwrap_or_default().as_str() {
+        "Passive" => Status::Passive,
+        "NeedsAttention" => Status::NeedsAttention,
+        _ => Status::Active,
+    };
+
+    let title = match item.get_property::<String>("Title").await.ok().filter(|title| !title.is_empty()) {
+        Some(title) => title,
+        None => item.get_property("Id").await.unwrap_or_default(),
+    };
+
+    let icon = match status {
+        Status::NeedsAttention => match item_icon(&item, true, lookup).await {
+            Some(icon) => Some(icon),
+            None => item_icon(&item, false, lookup).await,
+        },
+        _ => item_icon(&item, false, lookup).await,
+    };
+
+    Ok(Item {
+        title,
+        status,
+        symbolic: icon.as_ref().is_some_and(|(_, symbolic)| *symbolic),
+        icon: icon.map(|(image, _)| Arc::new(image)),
+        is_menu: item.get_property("ItemIsMenu").await.unwrap_or(false),
+        menu: item.get_property::<OwnedObjectPath>("Menu").await.ok()
+            .map(|path| path.to_string())
+            .filter(|path| path != "/" && path != "/NO_DBUSMENU"),
+        bus: bus.clone(),
+        path: path.clone(),
+    })
+}
+
+async fn query(connection: &zbus::Connection, lookup: &Lookup, previous: &[Item]) -> Result<Vec<Item>, Error> {
+    let watcher = async_proxy(connection, WATCHER_NAME, WATCHER_PATH, WATCHER_NAME).await?;
+    let addresses: Vec<String> = watcher.get_property("RegisteredStatusNotifierItems").await?;
+
+    let mut items = Vec::new();
+    for address in &addresses {
+        if let Ok(item) = query_item(connection, address, lookup).await {
+            items.push(item);
+        }
+    }
+
+    // Unchanged icons keep their image so renderers keep its texture.
+    for item in &mut items {
+        let same = previous.iter()
+            .filter_map(|known| known.icon.as_ref())
+            .find(|known| Some(*known) == item.icon.as_ref());
+        if let Some(known) = same {
+            item.icon = Some(Arc::clone(known));
+        }
+    }
+
+    Ok(items)
+}
+
+fn signal_rule(interface: &'static str) -> Result<MatchRule<'static>, Error> {
+    Ok(MatchRule::builder().msg_type(MessageType::Signal).interface(interface)?.build())
+}
+
+enum Event {
+    Changed,
+    // A bus name lost its owner.
+    Vanished(String),
+    WatcherAppeared,
+}
+
+impl Event {
+    fn of(message: &Message) -> Option<Event> {
+        if message.header().member().map(|member| member.as_str()) != Some("NameOwnerChanged") {
+            return Some(Event::Changed);
+        }
+
+        match message.body().deserialize::<(String, String, String)>() {
+            Ok((name, _, owner)) if owner.is_empty() => Some(Event::Vanished(name)),
+            Ok((name, _, _)) if name == WATCHER_NAME => Some(Event::WatcherAppeared),
+            _ => None,
+        }
+    }
+}
+
+async fn watch(shared: Weak<Mutex<Option<Vec<Item>>>>, waker: Waker, lookup: Lookup) -> Result<(), Error> {
+    let connection = zbus::Connection::session().await?;
+
+    let items = Arc::new(Mutex::new(Vec::new()));
+    connection.object_server().at(WATCHER_PATH, WatcherService { items: Arc::clone(&items) }).await?;
+    let owns_watcher = connection.request_name_with_flags(WATCHER_NAME, RequestNameFlags::DoNotQueue.into()).await?
+        == RequestNameReply::PrimaryOwner;
+    if !owns_watcher {
+        connection.object_server().remove::<WatcherService, _>(WATCHER_PATH).await?;
+    }
+
+    let host = format!("org.kde.StatusNotifierHost-{}", std::process::id());
+    connection.request_name(host.as_str()).await?;
+    let watcher = async_proxy(&connection, WATCHER_NAME, WATCHER_PATH, WATCHER_NAME).await?;
+    watcher.call_method("RegisterStatusNotifierHost", &(&host,)).await?;
+
+    let owners = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender("org.freedesktop.DBus")?
+        .interface("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .build();
+    let rules = [signal_rule(WATCHER_NAME)?, signal_rule(ITEM_INTERFACE)?, owners];
+    let mut signals = Signals::subscribe(&connection, rules, 64).await?;
+
+    let mut known = Vec::new();
+    let mut changed = true;
+    loop {
+        if changed {
+            known = query(&connection, &lookup, &known).await?;
+            match shared.upgrade() {
+                Some(state) => *state.lock().unwrap() = Some(known.clone()),
+                None => return Ok(()),
+            }
+            waker.wake();
+        }
+
+        // Bursts of signals, like an application updating several
+        // properties at once, are handled with a single query.
+        changed = false;
+        let messages = signals.next().await?;
+        for event in messages.iter().filter_map(Event::of) {
+            match event {
+                Event::Changed => changed = true,
+                Event::Vanished(name) if name == WATCHER_NAME => Err(WatcherGoneError)?,
+                Event::Vanished(name) => {
+                    changed |= known.iter().any(|item| item.bus == name);
+                    if owns_watcher {
+                        unregister(&connection, &items, &name).await?;
+                    }
+                },
+                Event::WatcherAppeared => {
+                    watcher.call_method("RegisterStatusNotifierHost", &(&host,)).await?;
+                    changed = true;
+                },
+            }
+        }
+    }
+}
+
+// Drops the items of an application that quit from the watcher run by the
+// bar.
+async fn unregister(connection: &zbus::Connection, items: &Mutex<Vec<String>>, name: &str) -> Result<(), Error> {
+    let removed: Vec<String> = {
+        let mut items = items.lock().unwrap();
+        let (removed, kept) = items.drain(..).partition(|item| parse_address(item).0 == name);
+        *items = kept;
+        removed
+    };
+
+    for item in removed {
+        connection.emit_signal(None::<()>, WATCHER_PATH, WATCHER_NAME, "StatusNotifierItemUnregistered", &(item,))
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct WatcherGoneError;
+
+impl std::fmt::Display for WatcherGoneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the status notifier watcher went away")
+    }
+}
+
+impl std::error::Error for WatcherGoneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// Keeps the registered tray items up to date. Dropping the host ends its
+// watch and closes the bus connection, which lets go of the watcher and
+// host names for the tray of a reloaded bar.
+pub struct Host {
+    state: Arc<Mutex<Option<Vec<Item>>>>,
+    _watch: worker::Task,
+}
+
+impl Host {
+    pub fn spawn(lookup: Lookup, waker: Waker) -> Host {
+        let state = Arc::new(Mutex::new(None));
+        let shared = Arc::downgrade(&state);
+
+        let watch = worker::watch(Duration::from_secs(1), "tray items".to_string(),
+            move || watch(shared.clone(), waker.clone(), lookup.clone()));
+
+        Host { state, _watch: watch }
+    }
+
+    // Returns the items if they changed since the last call.
+    pub fn take(&self) -> Option<Vec<Item>> {
+        self.state.lock().unwrap().take()
+    }
+}
+
+fn send_activation(bus: &str, path: &str, activation: Activation) -> Result<(), Error> {
+    let connection = Connection::session()?;
+    let item = proxy(&connection, bus, path, ITEM_INTERFACE)?;
+
+    // The bar doesn't know where it is on screen, items place any window
+    // they open themselves.
+    match activation {
+        Activation::Primary => item.call_method("Activate", &(0i32, 0i32))?,
+        Activation::Secondary => item.call_method("SecondaryActivate", &(0i32, 0i32))?,
+        Activation::ContextMenu => item.call_method("ContextMenu", &(0i32, 0i32))?,
+        Activation::Scroll(delta) => item.call_method("Scroll", &(delta, "vertical"))?,
+    };
+
+    Ok(())
+}
+
+// Labels mark their access key with an underscore, and write a literal one
+// as two.
+fn strip_mnemonic(label: &str) -> String {
+    let mut stripped = String::with_capacity(label.len());
+    let mut characters = label.chars().peekable();
+    while let Some(character) = characters.next() {
+        match character {
+            '_' if characters.peek() == Some(&'_') => {
+                characters.next();
+                stripped.push('_');
+            },
+            '_' => (),
+            _ => stripped.push(character),
+        }
+    }
+
+    stripped
+}
+
+fn property<T: TryFrom<OwnedValue>>(properties: &HashMap<String, OwnedValue>, name: &str) -> Option<T> {
+    T::try_from(properties.get(name)?.try_clone().ok()?).ok()
+}
+
+// Entries are laid out as their id, their properties and their children,
+// which are entries in turn.
+type Layout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+fn menu_entry(value: OwnedValue) -> Option<MenuEntry> {
+    let (id, properties, children) = Layout::try_from(value).ok()?;
+    if property::<bool>(&properties, "visible") == Some(false) {
+        return None;
+    }
+
+    let on = property::<i32>(&properties, "toggle-state") == Some(1);
+    let toggle = match property::<String>(&properties, "toggle-type").as_deref() {
+        Some("checkmark") => Some(Toggle::Check(on)),
+        Some("radio") => Some(Toggle::Radio(on)),
+        _ => None,
+    };
+
+    Some(MenuEntry {
+        id,
+        label: strip_mnemonic(&property::<String>(&properties, "label").unwrap_or_default()),
+        enabled: property(&properties, "enabled").unwrap_or(true),
+        separator: property::<String>(&properties, "type").as_deref() == Some("separator"),
+        toggle,
+        children: children.into_iter().filter_map(menu_entry).collect(),
+    })
+}
+
+fn fetch_menu(bus: &str, path: &str) -> Result<Vec<MenuEntry>, Error> {
+    let connection = Connection::session()?;
+    let menu = proxy(&connection, bus, path, MENU_INTERFACE)?;
+
+    // Some applications only fill in their menu once it's about to be shown,
+    // not all of them implement the call.
+    let _ = menu.call_method("AboutToShow", &(0i32,));
+    let (_revision, (_, _, children)): (u32, Layout) = menu.call("GetLayout", &(0i32, -1i32, Vec::<String>::new()))?;
+
+    Ok(children.into_iter().filter_map(menu_entry).collect())
+}
+
+// Reads the item's menu from a separate thread, which puts it in the
+// returned slot and wakes the bar. Items whose menu can't be read are asked
+// to show it themselves instead.
+pub fn load_menu(item: &Item, waker: &Waker) -> Arc<Mutex<Option<Vec<MenuEntry>>>> {
+    let slot = Arc::new(Mutex::new(None));
+    let (filled, waker) = (Arc::clone(&slot), waker.clone());
+    let (bus, path, menu) = (item.bus.clone(), item.path.clone(), item.menu.clone().unwrap_or_default());
+    std::thread::spawn(move || match fetch_menu(&bus, &menu) {
+        Ok(entries) => {
+            *filled.lock().unwrap() = Some(entries);
+            waker.wake();
+        },
+        Err(err) => {
+            warn!("Failed to read the menu of tray item {}: {}", bus, err);
+            if let Err(err) = send_activation(&bus, &path, Activation::ContextMenu) {
+                warn!("Failed to activate tray item {}: {}", bus, err);
+            }
+        },
+    });
+
+    slot
+}
+
+fn send_menu_event(bus: &str, path: &str, id: i32) -> Result<(), Error> {
+    let connection = Connection::session()?;
+    // The timestamp is meant to be the window system's time of the click,
+    // which the bar doesn't have on every backend.
+    proxy(&connection, bus, path, MENU_INTERFACE)?.call_method("Event", &(id, "clicked", Value::from(0i32), 0u32))?;
+
+    Ok(())
+}
+
+// Tells the item that an entry of its menu was picked.
+pub fn menu_clicked(item: &Item, id: i32) {
+    let (bus, path) = (item.bus.clone(), item.menu.clone().unwrap_or_default());
+    worker::spawn_control(format!("pick an entry from the menu of tray item {}", bus),
+        move || send_menu_event(&bus, &path, id));
+}
+
+pub fn activate(item: &Item, activation: Activation) {
+    let (bus, path) = (item.bus.clone(), item.path.clone());
+    worker::spawn_control(format!("activate tray item {}", bus), move || send_activation(&bus, &path, activation));
+}
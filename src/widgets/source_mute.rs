@@ -0,0 +1,171 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, Button, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::pulse::{self, Device, DeviceState, Monitor};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct SourceMuteConfig {
+    format: String,
+    format_muted: String,
+    format_missing: String,
+    // The default microphone picks up sound in this color.
+    live_color: Color,
+    muted_color: Color,
+    // Blinks while something records from the microphone, every this many
+    // seconds.
+    flash: bool,
+    flash_interval: f64,
+}
+
+impl Default for SourceMuteConfig {
+    fn default() -> SourceMuteConfig {
+        SourceMuteConfig {
+            format: "MIC {volume}%".to_string(),
+            format_muted: "MIC muted".to_string(),
+            format_missing: String::new(),
+            live_color: Color::from_str("#ff5555").unwrap(),
+            muted_color: Color::from_str("#888888").unwrap(),
+            flash: false,
+            flash_interval: 0.5,
+        }
+    }
+}
+
+pub struct SourceMute {
+    config: SourceMuteConfig,
+    monitor: Monitor,
+    state: Option<DeviceState>,
+    // When the microphone started being recorded from, while it flashes.
+    recording: Option<Instant>,
+    dimmed: bool,
+    text: String,
+}
+
+impl SourceMute {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        Ok(Box::new(SourceMute {
+            config: section.clone().try_into()?,
+            monitor: Monitor::spawn(Device::Source, waker.clone()),
+            state: None,
+            recording: None,
+            dimmed: false,
+            text: String::new(),
+        }))
+    }
+
+    fn format(&self) -> String {
+        let state = match &self.state {
+            Some(state) => state,
+            None => return self.config.format_missing.clone(),
+        };
+
+        let template = if state.muted { &self.config.format_muted } else { &self.config.format };
+        format::placeholders(template, |key| match key {
+            "volume" => Some(state.volume.to_string()),
+            "source" => Some(state.name.clone()),
+            "description" => Some(state.description.clone()),
+            "streams" => Some(state.streams.to_string()),
+            _ => None,
+        })
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.config.flash_interval.max(0.1))
+    }
+
+    // Whether the flash is in its dim half at the given moment.
+    fn dimmed_at(&self, now: Instant) -> bool {
+        let since = match self.recording {
+            Some(since) => since,
+            None => return false,
+        };
+
+        (now.duration_since(since).as_secs_f64() / self.interval().as_secs_f64()) as u64 % 2 == 1
+    }
+
+    fn toggle_mute(&self) {
+        if let Err(err) = pulse::toggle_mute(Device::Source) {
+            warn!("Failed to toggle the microphone: {}", err);
+        }
+    }
+}
+
+impl Widget for SourceMute {
+    fn update(&mut self) -> bool {
+        let mut changed = false;
+        if let Some(state) = self.monitor.take().filter(|state| *state != self.state) {
+            self.state = state;
+            self.text = self.format();
+            changed = true;
+        }
+
+        let recording = self.config.flash && self.state.as_ref().is_some_and(|state| !state.muted && state.streams > 0);
+        match (recording, self.recording) {
+            (true, None) => self.recording = Some(Instant::now()),
+            (false, Some(_)) => self.recording = None,
+            _ => (),
+        }
+
+        let dimmed = self.dimmed_at(Instant::now());
+        changed |= dimmed != self.dimmed;
+        self.dimmed = dimmed;
+        changed
+    }
+
+    // The next time the flash turns on or off.
+    fn next_update(&self) -> Option<Instant> {
+        let since = self.recording?;
+        let interval = self.interval().as_secs_f64();
+        let phases = (since.elapsed().as_secs_f64() / interval).floor() + 1.0;
+        Some(since + Duration::from_secs_f64(phases * interval))
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match &self.state {
+            Some(state) if !state.muted => self.config.live_color,
+            _ => self.config.muted_color,
+        };
+        let color = if self.dimmed { color.faded(0.3) } else { color };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        if button == Button::Left {
+            self.toggle_mute();
+        }
+
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "toggle-mute" => self.toggle_mute(),
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(false)
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let state = self.state.as_ref()?;
+        let recording = match state.streams {
+            0 => "not recorded".to_string(),
+            1 => "1 recording".to_string(),
+            streams => format!("{} recordings", streams),
+        };
+
+        Some(format!("{}\n<span foreground=\"#888888\">{}</span>", markup::escape(&state.description), recording))
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.state.as_ref().map(|state| state.volume as f64)
+    }
+}
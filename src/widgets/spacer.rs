@@ -0,0 +1,51 @@
+use serde::Deserialize;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{Waker, Widget};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct SpacerConfig {
+    width: f32,
+    color: Option<Color>,
+    // Lets clicks through to the windows beneath the bar.
+    click_through: bool,
+}
+
+impl Default for SpacerConfig {
+    fn default() -> SpacerConfig {
+        SpacerConfig {
+            width: 10.0,
+            color: None,
+            click_through: false,
+        }
+    }
+}
+
+pub struct Spacer {
+    config: SpacerConfig,
+}
+
+impl Spacer {
+    pub fn build(section: &toml::Value, _waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        Ok(Box::new(Spacer { config: section.clone().try_into()? }))
+    }
+}
+
+impl Widget for Spacer {
+    fn update(&mut self) -> bool {
+        false
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        match self.config.color {
+            Some(color) => ctx.rect(0.0, 0.0, self.config.width, ctx.height(), color),
+            None => ctx.advance(self.config.width),
+        }
+    }
+
+    fn click_through(&self) -> bool {
+        self.config.click_through
+    }
+}
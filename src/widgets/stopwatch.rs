@@ -0,0 +1,146 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, Button, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::timer::clock;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct StopwatchConfig {
+    format: String,
+    format_paused: String,
+    // Shown before the stopwatch first starts and after it's reset.
+    format_idle: String,
+    color: Option<Color>,
+    paused_color: Color,
+}
+
+impl Default for StopwatchConfig {
+    fn default() -> StopwatchConfig {
+        StopwatchConfig {
+            format: "{elapsed}".to_string(),
+            format_paused: "{elapsed} paused".to_string(),
+            format_idle: "stopwatch".to_string(),
+            color: None,
+            paused_color: Color::from_str("#888888").unwrap(),
+        }
+    }
+}
+
+pub struct Stopwatch {
+    config: StopwatchConfig,
+    // Time counted before the stopwatch was last started, and when that was
+    // while it's running.
+    counted: Duration,
+    started: Option<Instant>,
+    text: String,
+}
+
+impl Stopwatch {
+    pub fn build(section: &toml::Value, _waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let mut stopwatch = Stopwatch {
+            config: section.clone().try_into()?,
+            counted: Duration::ZERO,
+            started: None,
+            text: String::new(),
+        };
+        stopwatch.text = stopwatch.format();
+        Ok(Box::new(stopwatch))
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.counted + self.started.map(|started| started.elapsed()).unwrap_or_default()
+    }
+
+    fn toggle(&mut self) {
+        match self.started.take() {
+            Some(started) => self.counted += started.elapsed(),
+            None => self.started = Some(Instant::now()),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.counted = Duration::ZERO;
+        self.started = None;
+    }
+
+    fn format(&self) -> String {
+        let template = match self.started {
+            Some(_) => &self.config.format,
+            None if self.counted > Duration::ZERO => &self.config.format_paused,
+            None => &self.config.format_idle,
+        };
+
+        let elapsed = self.elapsed().as_secs();
+        format::placeholders(template, |key| match key {
+            "elapsed" => Some(clock(elapsed)),
+            "hours" => Some((elapsed / 3600).to_string()),
+            "minutes" => Some((elapsed / 60).to_string()),
+            _ => None,
+        })
+    }
+
+    fn changed(&mut self) -> bool {
+        self.text = self.format();
+        true
+    }
+}
+
+impl Widget for Stopwatch {
+    fn update(&mut self) -> bool {
+        let text = self.format();
+        let changed = text != self.text;
+        self.text = text;
+        changed
+    }
+
+    // The next time another whole second has gone by.
+    fn next_update(&self) -> Option<Instant> {
+        let started = self.started?;
+        let elapsed = self.elapsed();
+        Some(started + (Duration::from_secs(elapsed.as_secs() + 1) - self.counted))
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match self.started {
+            Some(_) => self.config.color.unwrap_or_else(|| ctx.foreground()),
+            None => self.config.paused_color,
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        match button {
+            Button::Left => self.toggle(),
+            Button::Middle => self.reset(),
+            Button::Right => return false,
+        }
+
+        self.changed()
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "toggle" => self.toggle(),
+            "start" => if self.started.is_none() {
+                self.toggle();
+            },
+            "stop" => if self.started.is_some() {
+                self.toggle();
+            },
+            "reset" => self.reset(),
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(self.changed())
+    }
+
+    fn value(&self) -> Option<f64> {
+        Some(self.elapsed().as_secs_f64())
+    }
+}
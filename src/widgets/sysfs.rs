@@ -0,0 +1,10 @@
+use std::path::Path;
+use std::str::FromStr;
+
+pub fn read_string<P: AsRef<Path>>(path: P) -> Option<String> {
+    std::fs::read_to_string(path).ok().map(|contents| contents.trim().to_string())
+}
+
+pub fn read_number<T: FromStr, P: AsRef<Path>>(path: P) -> Option<T> {
+    read_string(path).and_then(|contents| contents.parse().ok())
+}
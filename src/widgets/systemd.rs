@@ -0,0 +1,198 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, Button, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::systemd1::{Control, State, Unit, Watcher};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct SystemdConfig {
+    units: Vec<String>,
+    // Watches the user's own units rather than the system's.
+    user: bool,
+    format: String,
+    format_failed: String,
+    color: Option<Color>,
+    inactive_color: Color,
+    failed_color: Color,
+}
+
+impl Default for SystemdConfig {
+    fn default() -> SystemdConfig {
+        SystemdConfig {
+            units: Vec::new(),
+            user: false,
+            format: "{active}/{count}".to_string(),
+            format_failed: "{failed_units} failed".to_string(),
+            color: None,
+            inactive_color: Color::from_str("#888888").unwrap(),
+            failed_color: Color::from_str("#ff5555").unwrap(),
+        }
+    }
+}
+
+pub struct Systemd {
+    config: SystemdConfig,
+    watcher: Watcher,
+    units: Vec<Unit>,
+    // Whether the popup listing the units is open.
+    list: bool,
+    text: String,
+}
+
+impl Systemd {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: SystemdConfig = section.clone().try_into()?;
+
+        Ok(Box::new(Systemd {
+            watcher: Watcher::spawn(waker.clone(), config.units.clone(), config.user),
+            config,
+            units: Vec::new(),
+            list: false,
+            text: String::new(),
+        }))
+    }
+
+    fn in_state(&self, state: State) -> impl Iterator<Item = &Unit> {
+        self.units.iter().filter(move |unit| unit.state == state)
+    }
+
+    fn format(&self) -> String {
+        let failed: Vec<&str> = self.in_state(State::Failed).map(|unit| unit.name.as_str()).collect();
+        let template = if failed.is_empty() { &self.config.format } else { &self.config.format_failed };
+
+        format::placeholders(template, |key| match key {
+            "active" => Some(self.in_state(State::Active).count().to_string()),
+            "failed" => Some(failed.len().to_string()),
+            "failed_units" => Some(failed.join(", ")),
+            "count" => Some(self.units.len().to_string()),
+            // Handy for modules watching a single unit.
+            "state" => Some(self.units.first().map(|unit| unit.state.name()).unwrap_or_default().to_string()),
+            _ => None,
+        })
+    }
+
+    // Starting goes to every unit that isn't running, stopping only happens
+    // once all of them are.
+    fn toggle(&self) {
+        let running = self.units.iter().all(|unit| unit.state == State::Active);
+        for unit in &self.units {
+            self.toggle_unit(unit, running);
+        }
+    }
+
+    fn toggle_unit(&self, unit: &Unit, stop: bool) {
+        match (unit.state, stop) {
+            (State::Active, true) => self.watcher.control(&unit.name, Control::Stop),
+            (State::Active, false) => (),
+            _ => self.watcher.control(&unit.name, Control::Start),
+        }
+    }
+
+    fn control(&self, control: Control) {
+        for unit in &self.units {
+            self.watcher.control(&unit.name, control);
+        }
+    }
+}
+
+impl Widget for Systemd {
+    fn update(&mut self) -> bool {
+        let units = match self.watcher.take() {
+            Some(units) => units,
+            None => return false,
+        };
+
+        if units == self.units {
+            return false;
+        }
+
+        self.units = units;
+        self.text = self.format();
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = if self.in_state(State::Failed).next().is_some() {
+            self.config.failed_color
+        } else if self.units.iter().any(|unit| unit.state != State::Active) {
+            self.config.inactive_color
+        } else {
+            self.config.color.unwrap_or_else(|| ctx.foreground())
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        match button {
+            Button::Left => self.toggle(),
+            Button::Middle => self.control(Control::Restart),
+            Button::Right => self.list = !self.list,
+        }
+
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "toggle" => self.toggle(),
+            "start" => self.control(Control::Start),
+            "stop" => self.control(Control::Stop),
+            "restart" => self.control(Control::Restart),
+            "units" => self.list = !self.list,
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(false)
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let lines: Vec<String> = self.units.iter()
+            .map(|unit| format!("{} <span foreground=\"#888888\">{} ({})</span>",
+                markup::escape(&unit.name), unit.state.name(), markup::escape(&unit.sub_state)))
+            .collect();
+
+        Some(lines.join("\n")).filter(|text| !text.is_empty())
+    }
+
+    // One line per unit, a left click starts or stops it and a middle click
+    // restarts it.
+    fn popup(&self) -> Option<String> {
+        if !self.list {
+            return None;
+        }
+
+        let lines: Vec<String> = self.units.iter()
+            .map(|unit| {
+                let mark = match unit.state {
+                    State::Active => "●",
+                    State::Failed => "✕",
+                    State::Activating | State::Deactivating => "◌",
+                    State::Inactive => "○",
+                };
+                format!("{} {} <span foreground=\"#888888\">{}</span>", mark, markup::escape(&unit.name), unit.state.name())
+            })
+            .collect();
+
+        Some(lines.join("\n")).filter(|text| !text.is_empty())
+    }
+
+    fn popup_click(&mut self, line: usize, button: Button) -> bool {
+        if let Some(unit) = self.units.get(line) {
+            match button {
+                Button::Middle => self.watcher.control(&unit.name, Control::Restart),
+                _ => self.toggle_unit(unit, unit.state == State::Active),
+            }
+        }
+
+        false
+    }
+
+    fn value(&self) -> Option<f64> {
+        Some(self.in_state(State::Failed).count() as f64)
+    }
+}
@@ -0,0 +1,184 @@
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use zbus::MatchRule;
+use zbus::blocking::{Connection, Proxy};
+use zbus::message::Type as MessageType;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::OwnedObjectPath;
+use crate::Error;
+use super::{worker, Waker};
+use super::worker::Signals;
+
+// Units are loaded through the systemd manager, which hands out an object
+// per unit that announces its own state changes once the bus connection
+// subscribed to them.
+const BUS_NAME: &str = "org.freedesktop.systemd1";
+const MANAGER_PATH: &str = "/org/freedesktop/systemd1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+const UNIT_INTERFACE: &str = "org.freedesktop.systemd1.Unit";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Active,
+    Inactive,
+    Failed,
+    Activating,
+    Deactivating,
+}
+
+impl State {
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Active => "active",
+            State::Inactive => "inactive",
+            State::Failed => "failed",
+            State::Activating => "activating",
+            State::Deactivating => "deactivating",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    pub name: String,
+    pub state: State,
+    // Such as "running" or "exited", what the state means for this kind of
+    // unit.
+    pub sub_state: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    Start,
+    Stop,
+    Restart,
+}
+
+// User units are managed by the user's own systemd on the session bus.
+fn connect(user: bool) -> Result<Connection, Error> {
+    Ok(if user { Connection::session()? } else { Connection::system()? })
+}
+
+fn proxy<'a>(connection: &Connection, path: &'a str, interface: &'a str) -> Result<Proxy<'a>, Error> {
+    Ok(zbus::blocking::proxy::Builder::new(connection)
+        .destination(BUS_NAME)?
+        .path(path)?
+        .interface(interface)?
+        .cache_properties(CacheProperties::No)
+        .build()?)
+}
+
+async fn async_proxy<'a>(connection: &zbus::Connection, path: &'a str, interface: &'a str)
+    -> Result<zbus::Proxy<'a>, Error>
+{
+    Ok(zbus::proxy::Builder::new(connection)
+        .destination(BUS_NAME)?
+        .path(path)?
+        .interface(interface)?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?)
+}
+
+async fn query(connection: &zbus::Connection, names: &[String], paths: &[OwnedObjectPath]) -> Result<Vec<Unit>, Error> {
+    let mut units = Vec::new();
+    for (name, path) in names.iter().zip(paths) {
+        let unit = async_proxy(connection, path.as_str(), UNIT_INTERFACE).await?;
+        let state = match unit.get_property::<String>("ActiveState").await?.as_str() {
+            "active" | "reloading" => State::Active,
+            "failed" => State::Failed,
+            "activating" => State::Activating,
+            "deactivating" => State::Deactivating,
+            _ => State::Inactive,
+        };
+
+        units.push(Unit { name: name.clone(), state, sub_state: unit.get_property("SubState").await? });
+    }
+
+    Ok(units)
+}
+
+async fn watch(shared: Weak<Mutex<Option<Vec<Unit>>>>, waker: Waker, names: Arc<[String]>, user: bool)
+    -> Result<(), Error>
+{
+    let connection = if user { zbus::Connection::session().await? } else { zbus::Connection::system().await? };
+    let manager = async_proxy(&connection, MANAGER_PATH, MANAGER_INTERFACE).await?;
+    manager.call_method("Subscribe", &()).await?;
+
+    // Loading a unit that doesn't exist still gives it an object, which
+    // starts reporting once the unit is installed.
+    let mut paths: Vec<OwnedObjectPath> = Vec::new();
+    for name in names.iter() {
+        paths.push(manager.call("LoadUnit", &(name,)).await?);
+    }
+
+    let mut rules = Vec::new();
+    for path in &paths {
+        rules.push(MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .sender(BUS_NAME)?
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .path(path.as_str())?
+            .build()
+            .into_owned());
+    }
+    let mut signals = Signals::subscribe(&connection, rules, 16).await?;
+
+    loop {
+        let units = query(&connection, &names, &paths).await?;
+        match shared.upgrade() {
+            Some(state) => *state.lock().unwrap() = Some(units),
+            None => return Ok(()),
+        }
+        waker.wake();
+
+        signals.next().await?;
+    }
+}
+
+// Keeps the state of the given units up to date, until it's dropped.
+pub struct Watcher {
+    state: Arc<Mutex<Option<Vec<Unit>>>>,
+    user: bool,
+    _watch: worker::Task,
+}
+
+impl Watcher {
+    pub fn spawn(waker: Waker, names: Vec<String>, user: bool) -> Watcher {
+        let state = Arc::new(Mutex::new(None));
+        let shared = Arc::downgrade(&state);
+        let names: Arc<[String]> = names.into();
+
+        let watch = worker::watch(Duration::from_secs(5), "systemd units".to_string(),
+            move || watch(shared.clone(), waker.clone(), Arc::clone(&names), user));
+
+        Watcher { state, user, _watch: watch }
+    }
+
+    // Returns the units if they changed since the last call.
+    pub fn take(&self) -> Option<Vec<Unit>> {
+        self.state.lock().unwrap().take()
+    }
+
+    pub fn control(&self, unit: &str, control: Control) {
+        self::control(unit, control, self.user);
+    }
+}
+
+fn send_control(unit: &str, control: Control, user: bool) -> Result<(), Error> {
+    let connection = connect(user)?;
+    let method = match control {
+        Control::Start => "StartUnit",
+        Control::Stop => "StopUnit",
+        Control::Restart => "RestartUnit",
+    };
+    let _job: OwnedObjectPath = proxy(&connection, MANAGER_PATH, MANAGER_INTERFACE)?.call(method, &(unit, "replace"))?;
+
+    Ok(())
+}
+
+pub fn control(unit: &str, control: Control, user: bool) {
+    let unit = unit.to_string();
+    worker::spawn_control(format!("control {}", unit), move || send_control(&unit, control, user));
+}
@@ -0,0 +1,338 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, Image, Primitive, RenderCtx};
+use crate::x11::{Atoms, X11};
+use super::{format, i3ipc, icons, sni, Button, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::buttons::{ButtonStyle, Buttons, Lines};
+use super::ewmh::Watcher;
+use super::i3ipc::Subscription;
+use super::sni::Lookup;
+use super::title::ellipsize;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct TaskbarConfig {
+    format: String,
+    // Longest a single window's label gets before it's cut short.
+    max_width: Option<f32>,
+    ellipsis: String,
+    icons: bool,
+    // Icons are scaled to the bar height minus the icon padding, this is
+    // only the size picked from the window's icons or looked up in themes.
+    icon_size: u32,
+    icon_theme: Option<String>,
+    icon_padding: f32,
+    // Only lists the windows on the current desktop, under X11 window
+    // managers.
+    current_desktop: bool,
+    padding: f32,
+    corner_radius: f32,
+    color: Option<Color>,
+    background: Option<Color>,
+    current_color: Option<Color>,
+    current_background: Option<Color>,
+    underline: Option<Color>,
+    current_underline: Option<Color>,
+    line_width: f32,
+}
+
+impl Default for TaskbarConfig {
+    fn default() -> TaskbarConfig {
+        TaskbarConfig {
+            format: "{title}".to_string(),
+            max_width: Some(200.0),
+            ellipsis: "...".to_string(),
+            icons: true,
+            icon_size: 22,
+            icon_theme: None,
+            icon_padding: 2.0,
+            current_desktop: false,
+            padding: 8.0,
+            corner_radius: 0.0,
+            color: None,
+            background: None,
+            current_color: None,
+            current_background: Some(Color::from_str("#444444").unwrap()),
+            underline: None,
+            current_underline: None,
+            line_width: 2.0,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct Task {
+    // The X11 window or the sway container.
+    id: u64,
+    title: String,
+    app: Option<String>,
+    focused: bool,
+    icon: Option<Arc<Image>>,
+}
+
+// Applications without an icon of their own are likely to have one in the
+// icon theme, named after their app id or class.
+fn theme_icon(app: &str, lookup: &Lookup) -> Option<Arc<Image>> {
+    let path = icons::find(&app.to_lowercase(), lookup.theme.as_deref(), lookup.size, None)?;
+    icons::open(&path, lookup.size).map_err(|err| warn!("Failed to load {}: {}", path.display(), err)).ok()
+}
+
+// _NET_WM_ICON holds any number of icons, each a width and a height followed
+// by that many ARGB pixels.
+fn window_icon(x11: &X11, window: u32, size: u32) -> Result<Option<Image>, Error> {
+    let mut data = &x11.cardinals(window, x11.atoms()._NET_WM_ICON)?[..];
+    let mut pixmaps = Vec::new();
+    while let [width, height, rest @ ..] = data {
+        let count = (*width as usize).saturating_mul(*height as usize);
+        if count == 0 || rest.len() < count {
+            break;
+        }
+
+        let pixels = rest[..count].iter().flat_map(|pixel| pixel.to_be_bytes()).collect();
+        pixmaps.push((*width as i32, *height as i32, pixels));
+        data = &rest[count..];
+    }
+
+    Ok(sni::pixmap_image(pixmaps, size))
+}
+
+fn query(x11: &X11, current_desktop: bool, lookup: Option<&Lookup>) -> Result<Vec<Task>, Error> {
+    let (root, atoms) = (x11.root(), x11.atoms());
+    let clients = x11.windows(root, atoms._NET_CLIENT_LIST)?;
+    let active = x11.window(root, atoms._NET_ACTIVE_WINDOW)?;
+    let desktop = x11.cardinal(root, atoms._NET_CURRENT_DESKTOP)?;
+
+    // Windows may be gone by the time they're queried, the next change of
+    // the client list drops them.
+    let task = |window: u32| -> Result<Option<Task>, Error> {
+        x11.select_property_changes(window)?;
+        if x11.atom_list(window, atoms._NET_WM_STATE)?.contains(&atoms._NET_WM_STATE_SKIP_TASKBAR) {
+            return Ok(None);
+        }
+
+        // Sticky windows are on every desktop.
+        if current_desktop {
+            let on = x11.cardinal(window, atoms._NET_WM_DESKTOP)?;
+            if on.is_some_and(|on| on != u32::MAX && Some(on) != desktop) {
+                return Ok(None);
+            }
+        }
+
+        let app = x11.class(window)?;
+        let icon = match lookup {
+            Some(lookup) => window_icon(x11, window, lookup.size)?.map(Arc::new)
+                .or_else(|| app.as_deref().and_then(|app| theme_icon(app, lookup))),
+            None => None,
+        };
+
+        Ok(Some(Task {
+            id: window as u64,
+            title: x11.title(window)?.unwrap_or_default(),
+            app,
+            focused: active == Some(window),
+            icon,
+        }))
+    };
+
+    Ok(clients.into_iter().filter_map(|window| task(window).unwrap_or(None)).collect())
+}
+
+// Like the title module, sway is asked over IPC since its Wayland clients
+// are invisible to EWMH.
+enum Source {
+    Ipc(Subscription<Vec<Task>>),
+    X11(Watcher<Vec<Task>>),
+}
+
+impl Source {
+    fn take(&self) -> Option<Vec<Task>> {
+        match self {
+            Source::Ipc(subscription) => subscription.take(),
+            Source::X11(watcher) => watcher.take(),
+        }
+    }
+
+    // Pagers and taskbars request with source indication 2.
+    fn activate(&self, id: u64) {
+        match self {
+            Source::Ipc(_) => run_command(format!("[con_id={}] focus", id)),
+            Source::X11(_) => send_message(id, |atoms| (atoms._NET_ACTIVE_WINDOW, [2, 0, 0, 0, 0])),
+        }
+    }
+
+    fn close(&self, id: u64) {
+        match self {
+            Source::Ipc(_) => run_command(format!("[con_id={}] kill", id)),
+            Source::X11(_) => send_message(id, |atoms| (atoms._NET_CLOSE_WINDOW, [0, 2, 0, 0, 0])),
+        }
+    }
+}
+
+fn run_command(command: String) {
+    std::thread::spawn(move || {
+        if let Err(err) = i3ipc::run_command(&command) {
+            warn!("Failed to run '{}': {}", command, err);
+        }
+    });
+}
+
+fn send_message<F>(window: u64, message: F)
+where F: FnOnce(&Atoms) -> (u32, [u32; 5]) {
+    let result = X11::connect().and_then(|x11| {
+        let (atom, data) = message(x11.atoms());
+        x11.send_root_message(window as u32, atom, data)
+    });
+
+    if let Err(err) = result {
+        warn!("Failed to send a message to the window manager: {}", err);
+    }
+}
+
+pub struct Taskbar {
+    config: TaskbarConfig,
+    source: Source,
+    tasks: Vec<Task>,
+    buttons: Buttons,
+}
+
+impl Taskbar {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: TaskbarConfig = section.clone().try_into()?;
+        let lookup = Some(Lookup { theme: config.icon_theme.clone(), size: config.icon_size })
+            .filter(|_| config.icons);
+
+        let source = if std::env::var_os("SWAYSOCK").is_some() {
+            Source::Ipc(Subscription::spawn(&["window", "workspace"], waker.clone(), move |connection| {
+                Ok(connection.windows()?.into_iter()
+                    .map(|window| Task {
+                        icon: lookup.as_ref()
+                            .and_then(|lookup| window.app_id.as_deref().and_then(|app| theme_icon(app, lookup))),
+                        id: window.id,
+                        title: window.title,
+                        app: window.app_id,
+                        focused: window.focused,
+                    })
+                    .collect())
+            }))
+        } else {
+            let current_desktop = config.current_desktop;
+            Source::X11(Watcher::spawn(waker.clone(), move |x11| query(x11, current_desktop, lookup.as_ref())))
+        };
+
+        Ok(Box::new(Taskbar { config, source, tasks: Vec::new(), buttons: Buttons::default() }))
+    }
+
+    fn label(&self, task: &Task, ctx: &RenderCtx) -> String {
+        let label = format::placeholders(&self.config.format, |key| match key {
+            "title" => Some(task.title.clone()),
+            "app" => Some(task.app.clone().unwrap_or_default()),
+            _ => None,
+        });
+
+        match self.config.max_width {
+            Some(max_width) => ellipsize(ctx, &label, &self.config.ellipsis, max_width),
+            None => label,
+        }
+    }
+
+    fn task_at(&self, x: f32) -> Option<&Task> {
+        self.buttons.at(x).and_then(|index| self.tasks.get(index))
+    }
+}
+
+impl Widget for Taskbar {
+    fn update(&mut self) -> bool {
+        match self.source.take() {
+            Some(tasks) if tasks != self.tasks => {
+                self.tasks = tasks;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // Every window is a button with its icon in front of its label.
+    fn render(&self, ctx: &mut RenderCtx) {
+        self.buttons.clear();
+        let config = &self.config;
+        let style = ButtonStyle { padding: config.padding, corner_radius: config.corner_radius, line_width: config.line_width };
+        let size = (ctx.height() - 2.0 * config.icon_padding).max(0.0);
+
+        for task in &self.tasks {
+            let (color, background, underline) = if task.focused {
+                (config.current_color, config.current_background, config.current_underline.or(config.underline))
+            } else {
+                (None, config.background, config.underline)
+            };
+            let color = color.or(config.color).unwrap_or_else(|| ctx.foreground());
+
+            let label = self.label(task, ctx);
+            let icon = task.icon.as_ref().map(|icon| (icon, size * icon.width() as f32 / icon.height() as f32));
+            let gap = if icon.is_some() && !label.is_empty() { config.padding / 2.0 } else { 0.0 };
+            let icon_width = icon.map(|(_, width)| width).unwrap_or(0.0);
+
+            let start = ctx.width();
+            let width = 2.0 * config.padding + icon_width + gap + ctx.measure(&label);
+            ctx.advance(width);
+            Buttons::highlight(ctx, (start, start + width), background, Lines { underline, overline: None }, &style);
+
+            if let Some((icon, icon_width)) = icon {
+                ctx.push(Primitive::Image {
+                    x: start + config.padding,
+                    y: config.icon_padding,
+                    width: icon_width,
+                    height: size,
+                    image: Arc::clone(icon),
+                    tint: None,
+                });
+            }
+            ctx.text_at(start + config.padding + icon_width + gap, &label, color);
+            self.buttons.push(start, start + width);
+        }
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        let task = match self.task_at(x) {
+            Some(task) => task,
+            None => return false,
+        };
+
+        match button {
+            Button::Left => self.source.activate(task.id),
+            Button::Middle => self.source.close(task.id),
+            Button::Right => (),
+        }
+
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        let focused = self.tasks.iter().find(|task| task.focused).map(|task| task.id);
+        match name {
+            "close" => if let Some(id) = focused {
+                self.source.close(id);
+            },
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(false)
+    }
+
+    fn tooltip(&self, x: f32) -> Option<String> {
+        let task = self.task_at(x)?;
+        let app = task.app.as_ref()
+            .map(|app| format!("\n<span foreground=\"#888888\">{}</span>", markup::escape(app)))
+            .unwrap_or_default();
+
+        Some(format!("{}{}", markup::escape(&task.title), app))
+    }
+
+    fn value(&self) -> Option<f64> {
+        Some(self.tasks.len() as f64)
+    }
+}
@@ -0,0 +1,206 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, sysfs, Waker, Widget};
+use super::worker::Poller;
+
+const HWMON: &str = "/sys/class/hwmon";
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Unit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl Unit {
+    fn convert(&self, celsius: f64) -> f64 {
+        match self {
+            Unit::Celsius => celsius,
+            Unit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct TemperatureConfig {
+    // The hwmon chip name such as "coretemp" or "k10temp", and the label of
+    // one of its sensors such as "Package id 0". Either defaults to the
+    // first one found.
+    chip: Option<String>,
+    label: Option<String>,
+    format: String,
+    format_missing: String,
+    unit: Unit,
+    interval: f64,
+    // In the configured unit.
+    warning: f64,
+    critical: f64,
+    color: Option<Color>,
+    warning_color: Color,
+    critical_color: Color,
+}
+
+impl Default for TemperatureConfig {
+    fn default() -> TemperatureConfig {
+        TemperatureConfig {
+            chip: None,
+            label: None,
+            format: "TEMP {temperature}{unit}".to_string(),
+            format_missing: String::new(),
+            unit: Unit::Celsius,
+            interval: 5.0,
+            warning: 70.0,
+            critical: 85.0,
+            color: None,
+            warning_color: Color::from_str("#ffaa00").unwrap(),
+            critical_color: Color::from_str("#ff3333").unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Sensor {
+    chip: String,
+    label: String,
+    input: PathBuf,
+}
+
+fn sensors(chip: &Path) -> Vec<Sensor> {
+    let name = sysfs::read_string(chip.join("name")).unwrap_or_default();
+    let entries = match std::fs::read_dir(chip) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    // temp<N>_input holds millidegrees Celsius, temp<N>_label the optional
+    // name of the same sensor.
+    let mut inputs: Vec<(u32, PathBuf)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| {
+            let file = path.file_name()?.to_str()?;
+            let index = file.strip_prefix("temp")?.strip_suffix("_input")?.parse().ok()?;
+            Some((index, path))
+        })
+        .collect();
+    inputs.sort();
+
+    inputs.into_iter()
+        .map(|(index, input)| Sensor {
+            chip: name.clone(),
+            label: sysfs::read_string(chip.join(format!("temp{}_label", index)))
+                .unwrap_or_else(|| format!("temp{}", index)),
+            input,
+        })
+        .collect()
+}
+
+fn find_sensor(chip: Option<&str>, label: Option<&str>) -> Option<Sensor> {
+    let mut chips: Vec<PathBuf> = std::fs::read_dir(HWMON).ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    chips.sort();
+
+    chips.iter()
+        .flat_map(|path| sensors(path))
+        .filter(|sensor| chip.is_none_or(|chip| sensor.chip == chip))
+        .find(|sensor| label.is_none_or(|label| sensor.label == label))
+}
+
+// hwmon numbering isn't stable across boots or module reloads, so the
+// sensor is looked up again whenever it can't be read. Readings are in
+// degrees Celsius.
+fn read(sensor: &mut Option<Sensor>, chip: Option<&str>, label: Option<&str>) -> Option<(f64, Sensor)> {
+    let reading = sensor.as_ref().and_then(|sensor| sysfs::read_number::<f64, _>(&sensor.input));
+    let reading = match reading {
+        Some(reading) => reading,
+        None => {
+            *sensor = find_sensor(chip, label);
+            sysfs::read_number::<f64, _>(&sensor.as_ref()?.input)?
+        },
+    };
+
+    Some((reading / 1000.0, sensor.clone()?))
+}
+
+pub struct Temperature {
+    config: TemperatureConfig,
+    poller: Poller<Option<(f64, Sensor)>>,
+    sensor: Option<Sensor>,
+    // In the configured unit.
+    temperature: Option<f64>,
+    text: String,
+}
+
+impl Temperature {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: TemperatureConfig = section.clone().try_into()?;
+        let (chip, label) = (config.chip.clone(), config.label.clone());
+        let mut sensor = None;
+        let poller = Poller::spawn(Duration::from_secs_f64(config.interval.max(0.1)), waker.clone(), move || {
+            read(&mut sensor, chip.as_deref(), label.as_deref())
+        });
+
+        Ok(Box::new(Temperature { config, poller, sensor: None, temperature: None, text: String::new() }))
+    }
+
+    fn format(&self) -> String {
+        let (temperature, sensor) = match (self.temperature, &self.sensor) {
+            (Some(temperature), Some(sensor)) => (temperature, sensor),
+            _ => return self.config.format_missing.clone(),
+        };
+
+        format::placeholders(&self.config.format, |key| match key {
+            "temperature" => Some(format!("{:.0}", temperature)),
+            "unit" => Some(self.config.unit.label().to_string()),
+            "chip" => Some(sensor.chip.clone()),
+            "label" => Some(sensor.label.clone()),
+            _ => None,
+        })
+    }
+}
+
+impl Widget for Temperature {
+    fn update(&mut self) -> bool {
+        let reading = match self.poller.take() {
+            Some(reading) => reading,
+            None => return false,
+        };
+
+        self.temperature = reading.as_ref().map(|(celsius, _)| self.config.unit.convert(*celsius));
+        self.sensor = reading.map(|(_, sensor)| sensor);
+        let text = self.format();
+        if text == self.text {
+            return false;
+        }
+
+        self.text = text;
+        true
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.temperature
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match self.temperature {
+            Some(temperature) if temperature >= self.config.critical => self.config.critical_color,
+            Some(temperature) if temperature >= self.config.warning => self.config.warning_color,
+            _ => self.config.color.unwrap_or_else(|| ctx.foreground()),
+        };
+
+        ctx.text(&self.text, color);
+    }
+}
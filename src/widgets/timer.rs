@@ -0,0 +1,466 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{actions, format, notify, Button, Scroll, Waker, Widget};
+use super::actions::UnknownActionError;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct TimerConfig {
+    format: String,
+    format_paused: String,
+    format_idle: String,
+    format_done: String,
+    // Lengths of the pomodoro phases in minutes, with a long break after
+    // every this many work phases.
+    work: f64,
+    short_break: f64,
+    long_break: f64,
+    cycles: u32,
+    // Starts the next phase as soon as one ends, rather than on the next
+    // click.
+    auto_advance: bool,
+    // Minutes scrolling adds to or takes off the timer.
+    scroll_step: f64,
+    notify: bool,
+    // Runs when time is up, such as to play a sound.
+    command: Option<String>,
+    color: Option<Color>,
+    break_color: Color,
+    inactive_color: Color,
+    done_color: Color,
+}
+
+impl Default for TimerConfig {
+    fn default() -> TimerConfig {
+        TimerConfig {
+            format: "{phase} {remaining}".to_string(),
+            format_paused: "{phase} {remaining} paused".to_string(),
+            format_idle: "pomodoro".to_string(),
+            format_done: "{phase} done".to_string(),
+            work: 25.0,
+            short_break: 5.0,
+            long_break: 15.0,
+            cycles: 4,
+            auto_advance: false,
+            scroll_step: 1.0,
+            notify: true,
+            command: None,
+            color: None,
+            break_color: Color::from_str("#50fa7b").unwrap(),
+            inactive_color: Color::from_str("#888888").unwrap(),
+            done_color: Color::from_str("#ff5555").unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Work,
+    ShortBreak,
+    LongBreak,
+    // A countdown of its own, outside the pomodoro cycle.
+    Countdown,
+}
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Work => "work",
+            Phase::ShortBreak => "break",
+            Phase::LongBreak => "long break",
+            Phase::Countdown => "timer",
+        }
+    }
+
+    fn announcement(self) -> &'static str {
+        match self {
+            Phase::Work => "Time for a break",
+            Phase::ShortBreak | Phase::LongBreak => "Back to work",
+            Phase::Countdown => "Time is up",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Running { phase: Phase, ends: Instant },
+    Paused { phase: Phase, remaining: Duration },
+    // The phase that ended, shown until the next one starts.
+    Done(Phase),
+}
+
+#[derive(Debug)]
+struct InvalidDurationError(String);
+
+impl std::fmt::Display for InvalidDurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid duration '{}', expected something like 90s, 10m or 1h30m", self.0)
+    }
+}
+
+impl std::error::Error for InvalidDurationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// Numbers followed by h, m or s, in any combination such as 1h30m. A bare
+// number counts minutes.
+fn parse_duration(text: &str) -> Result<Duration, InvalidDurationError> {
+    let invalid = || InvalidDurationError(text.to_string());
+    let seconds = match text.parse::<f64>() {
+        Ok(minutes) => minutes * 60.0,
+        Err(_) => {
+            let (mut seconds, mut number) = (0.0, String::new());
+            for c in text.chars() {
+                let unit = match c {
+                    '0'..='9' | '.' => {
+                        number.push(c);
+                        continue;
+                    },
+                    'h' => 3600.0,
+                    'm' => 60.0,
+                    's' => 1.0,
+                    _ => return Err(invalid()),
+                };
+                seconds += number.parse::<f64>().map_err(|_| invalid())? * unit;
+                number.clear();
+            }
+
+            if !number.is_empty() {
+                return Err(invalid());
+            }
+            seconds
+        },
+    };
+
+    match seconds > 0.0 {
+        true => Duration::try_from_secs_f64(seconds).map_err(|_| invalid()),
+        false => Err(invalid()),
+    }
+}
+
+// Lengths too long to count down are turned away by Timer::build.
+fn minutes(minutes: f64) -> Duration {
+    Duration::try_from_secs_f64((minutes * 60.0).max(1.0)).unwrap_or(Duration::MAX)
+}
+
+// Whole seconds, rounded up so the timer reads 00:00 only once it's done.
+fn seconds(duration: Duration) -> u64 {
+    duration.as_secs() + (duration.subsec_nanos() > 0) as u64
+}
+
+// Reads like a kitchen timer, with hours only when there are any. Shared with
+// the stopwatch.
+pub fn clock(seconds: u64) -> String {
+    match seconds / 3600 {
+        0 => format!("{:02}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+pub struct Timer {
+    config: TimerConfig,
+    state: State,
+    // Work phases finished since the timer was last stopped.
+    completed: u32,
+    text: String,
+}
+
+impl Timer {
+    pub fn build(section: &toml::Value, _waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: TimerConfig = section.clone().try_into()?;
+        for length in [config.work, config.short_break, config.long_break, config.scroll_step] {
+            if Instant::now().checked_add(minutes(length)).is_none() {
+                return Err(Box::new(InvalidDurationError(format!("{}m", length))));
+            }
+        }
+
+        let mut timer = Timer { config, state: State::Idle, completed: 0, text: String::new() };
+        timer.text = timer.format();
+        Ok(Box::new(timer))
+    }
+
+    fn length(&self, phase: Phase) -> Duration {
+        match phase {
+            Phase::Work => minutes(self.config.work),
+            Phase::ShortBreak => minutes(self.config.short_break),
+            Phase::LongBreak => minutes(self.config.long_break),
+            Phase::Countdown => minutes(self.config.scroll_step),
+        }
+    }
+
+    // Every work phase is followed by a break, every so often a long one.
+    fn after(&self, phase: Phase) -> Phase {
+        match phase {
+            Phase::Work if self.completed.is_multiple_of(self.config.cycles.max(1)) => Phase::LongBreak,
+            Phase::Work => Phase::ShortBreak,
+            _ => Phase::Work,
+        }
+    }
+
+    // Returns false when the timer would end too far in the future to
+    // tell when.
+    fn start(&mut self, phase: Phase, length: Duration) -> bool {
+        match Instant::now().checked_add(length) {
+            Some(ends) => {
+                self.state = State::Running { phase, ends };
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn start_next(&mut self, previous: Option<Phase>) {
+        let phase = previous.map(|previous| self.after(previous)).unwrap_or(Phase::Work);
+        self.start(phase, self.length(phase));
+    }
+
+    fn finish(&mut self, phase: Phase) {
+        if phase == Phase::Work {
+            self.completed += 1;
+        }
+
+        if self.config.notify {
+            notify::show(phase.announcement().to_string(), format!("The {} is over", phase.name()));
+        }
+        if let Some(command) = &self.config.command {
+            if let Err(err) = actions::spawn_command(command) {
+                warn!("Failed to run the timer command: {}", err);
+            }
+        }
+
+        match phase {
+            Phase::Countdown => self.state = State::Done(phase),
+            _ if self.config.auto_advance => self.start_next(Some(phase)),
+            _ => self.state = State::Done(phase),
+        }
+    }
+
+    // Starts the next phase when none is running, otherwise pauses or
+    // resumes the one that is.
+    fn toggle(&mut self) {
+        match self.state {
+            State::Idle | State::Done(Phase::Countdown) => self.start_next(None),
+            State::Done(phase) => self.start_next(Some(phase)),
+            State::Running { phase, ends } => {
+                self.state = State::Paused { phase, remaining: ends.saturating_duration_since(Instant::now()) };
+            },
+            State::Paused { phase, remaining } => {
+                self.start(phase, remaining);
+            },
+        }
+    }
+
+    fn stop(&mut self) {
+        self.state = State::Idle;
+        self.completed = 0;
+    }
+
+    // Cuts the current pomodoro phase short and moves on to the next, a
+    // countdown is just stopped.
+    fn skip(&mut self) {
+        match self.state {
+            State::Running { phase: Phase::Countdown, .. } | State::Paused { phase: Phase::Countdown, .. } => {
+                self.state = State::Idle;
+            },
+            State::Running { phase, .. } | State::Paused { phase, .. } => {
+                if phase == Phase::Work {
+                    self.completed += 1;
+                }
+                self.start_next(Some(phase));
+            },
+            State::Idle | State::Done(_) => (),
+        }
+    }
+
+    // Lengthens or shortens the timer, or starts a countdown when there is
+    // none to change. Returns false when that would take the timer too far
+    // out, like start.
+    fn add(&mut self, length: Duration, longer: bool) -> bool {
+        let now = Instant::now();
+        match &mut self.state {
+            State::Running { ends, .. } if longer => match ends.checked_add(length) {
+                Some(later) => *ends = later,
+                None => return false,
+            },
+            State::Running { ends, .. } => *ends = ends.checked_sub(length).map_or(now, |earlier| earlier.max(now)),
+            State::Paused { remaining, .. } if longer => {
+                match remaining.checked_add(length).filter(|longer| now.checked_add(*longer).is_some()) {
+                    Some(longer) => *remaining = longer,
+                    None => return false,
+                }
+            },
+            State::Paused { remaining, .. } => *remaining = remaining.saturating_sub(length),
+            State::Idle | State::Done(_) if longer => return self.start(Phase::Countdown, length),
+            State::Idle | State::Done(_) => (),
+        }
+
+        true
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        match self.state {
+            State::Running { ends, .. } => Some(ends.saturating_duration_since(Instant::now())),
+            State::Paused { remaining, .. } => Some(remaining),
+            State::Idle | State::Done(_) => None,
+        }
+    }
+
+    fn format(&self) -> String {
+        let (template, phase) = match self.state {
+            State::Idle => (&self.config.format_idle, None),
+            State::Running { phase, .. } => (&self.config.format, Some(phase)),
+            State::Paused { phase, .. } => (&self.config.format_paused, Some(phase)),
+            State::Done(phase) => (&self.config.format_done, Some(phase)),
+        };
+
+        format::placeholders(template, |key| match key {
+            "phase" => phase.map(|phase| phase.name().to_string()),
+            "remaining" => self.remaining().map(|remaining| clock(seconds(remaining))),
+            "completed" => Some(self.completed.to_string()),
+            _ => None,
+        })
+    }
+
+    // After a click or an action, which always change what's shown.
+    fn changed(&mut self) -> bool {
+        self.text = self.format();
+        true
+    }
+}
+
+impl Widget for Timer {
+    fn update(&mut self) -> bool {
+        let mut finished = false;
+        if let State::Running { phase, ends } = self.state {
+            if Instant::now() >= ends {
+                self.finish(phase);
+                finished = true;
+            }
+        }
+
+        let text = self.format();
+        let changed = finished || text != self.text;
+        self.text = text;
+        changed
+    }
+
+    // The next time the remaining whole seconds tick down.
+    fn next_update(&self) -> Option<Instant> {
+        match self.state {
+            State::Running { ends, .. } => {
+                let remaining = seconds(ends.saturating_duration_since(Instant::now()));
+                Some(ends - Duration::from_secs(remaining.saturating_sub(1)))
+            },
+            _ => None,
+        }
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match self.state {
+            State::Running { phase: Phase::ShortBreak, .. } | State::Running { phase: Phase::LongBreak, .. } => {
+                self.config.break_color
+            },
+            State::Running { .. } => self.config.color.unwrap_or_else(|| ctx.foreground()),
+            State::Idle | State::Paused { .. } => self.config.inactive_color,
+            State::Done(_) => self.config.done_color,
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        match button {
+            Button::Left => self.toggle(),
+            Button::Middle => self.stop(),
+            Button::Right => self.skip(),
+        }
+
+        self.changed()
+    }
+
+    fn scroll(&mut self, _x: f32, direction: Scroll) -> bool {
+        self.add(minutes(self.config.scroll_step), direction == Scroll::Up);
+        self.changed()
+    }
+
+    // Actions may be followed by a duration: "start 10m" counts down ten
+    // minutes, "add 90s" adds to the timer.
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        let (name, argument) = name.split_once(' ').map(|(name, argument)| (name, argument.trim())).unwrap_or((name, ""));
+        let length = match argument {
+            "" => None,
+            argument => Some(parse_duration(argument)?),
+        };
+
+        match (name, length) {
+            ("start", Some(length)) => if !self.start(Phase::Countdown, length) {
+                Err(InvalidDurationError(argument.to_string()))?
+            },
+            ("start", None) => if !matches!(self.state, State::Running { .. }) {
+                self.toggle();
+            },
+            ("toggle", _) => self.toggle(),
+            ("pause", _) => if matches!(self.state, State::Running { .. }) {
+                self.toggle();
+            },
+            ("stop", _) => self.stop(),
+            ("skip", _) => self.skip(),
+            ("add", length) => if !self.add(length.unwrap_or_else(|| minutes(self.config.scroll_step)), true) {
+                Err(InvalidDurationError(argument.to_string()))?
+            },
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(self.changed())
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.remaining().map(|remaining| remaining.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(text: &str) -> Option<Duration> {
+        parse_duration(text).ok()
+    }
+
+    #[test]
+    fn durations_combine_units() {
+        assert_eq!(parsed("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parsed("10m"), Some(Duration::from_secs(600)));
+        assert_eq!(parsed("1h30m"), Some(Duration::from_secs(5400)));
+        assert_eq!(parsed("1h1m1s"), Some(Duration::from_secs(3661)));
+        assert_eq!(parsed("1.5m"), Some(Duration::from_secs(90)));
+    }
+
+    #[test]
+    fn bare_numbers_count_minutes() {
+        assert_eq!(parsed("25"), Some(Duration::from_secs(1500)));
+        assert_eq!(parsed("0.5"), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn malformed_durations_are_errors() {
+        for text in ["", "0", "0s", "-5", "m", "10x", "5m3", "1..5m", "1 h", "1e300", "inf"] {
+            assert_eq!(parsed(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn clocks_show_hours_only_when_there_are_any() {
+        assert_eq!(clock(59), "00:59");
+        assert_eq!(clock(600), "10:00");
+        assert_eq!(clock(3661), "1:01:01");
+    }
+}
@@ -0,0 +1,149 @@
+use serde::Deserialize;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use crate::x11::X11;
+use super::{format, Waker, Widget};
+use super::ewmh::Watcher;
+use super::i3ipc::Subscription;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct TitleConfig {
+    format: String,
+    max_width: Option<f32>,
+    ellipsis: String,
+    color: Option<Color>,
+}
+
+impl Default for TitleConfig {
+    fn default() -> TitleConfig {
+        TitleConfig {
+            format: "{title}".to_string(),
+            max_width: None,
+            ellipsis: "...".to_string(),
+            color: None,
+        }
+    }
+}
+
+// Wayland clients under sway are invisible to EWMH, so the IPC socket is
+// used whenever sway is running. Everything else goes through the root
+// window properties.
+enum Source {
+    Ipc(Subscription<Option<String>>),
+    X11(Watcher<Option<String>>),
+}
+
+impl Source {
+    fn take(&self) -> Option<Option<String>> {
+        match self {
+            Source::Ipc(subscription) => subscription.take(),
+            Source::X11(watcher) => watcher.take(),
+        }
+    }
+}
+
+fn query(x11: &X11) -> Result<Option<String>, Error> {
+    let active = match x11.window(x11.root(), x11.atoms()._NET_ACTIVE_WINDOW)? {
+        Some(window) => window,
+        None => return Ok(None),
+    };
+
+    // The window may be gone by the time it is queried, which just means
+    // the next property change brings the new active window.
+    let title = || -> Result<Option<String>, Error> {
+        x11.select_property_changes(active)?;
+        x11.title(active)
+    };
+
+    Ok(title().unwrap_or(None))
+}
+
+pub struct Title {
+    config: TitleConfig,
+    source: Source,
+    // The whole title, for when the text is cut short.
+    title: String,
+    text: String,
+}
+
+impl Title {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let source = if std::env::var_os("SWAYSOCK").is_some() {
+            Source::Ipc(Subscription::spawn(&["window", "workspace"], waker.clone(), |connection| {
+                connection.focused_title()
+            }))
+        } else {
+            Source::X11(Watcher::spawn(waker.clone(), query))
+        };
+
+        Ok(Box::new(Title {
+            config: section.clone().try_into()?,
+            source,
+            title: String::new(),
+            text: String::new(),
+        }))
+    }
+}
+
+// Cuts text down to the longest prefix that, followed by the ellipsis, fits
+// into max_width.
+pub fn ellipsize(ctx: &RenderCtx, text: &str, ellipsis: &str, max_width: f32) -> String {
+    if text.is_empty() || ctx.measure(text) <= max_width {
+        return text.to_string();
+    }
+
+    let boundaries: Vec<usize> = text.char_indices().map(|(index, _)| index).collect();
+    let fits = |count: usize| ctx.measure(&format!("{}{}", &text[..boundaries[count]], ellipsis)) <= max_width;
+
+    // Binary search for the number of characters to keep.
+    let (mut low, mut high) = (0, boundaries.len() - 1);
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        if fits(middle) {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+
+    format!("{}{}", &text[..boundaries[low]], ellipsis)
+}
+
+impl Widget for Title {
+    fn update(&mut self) -> bool {
+        let title = match self.source.take() {
+            Some(title) => title.unwrap_or_default(),
+            None => return false,
+        };
+
+        let text = if title.is_empty() {
+            String::new()
+        } else {
+            format::placeholders(&self.config.format, |key| match key {
+                "title" => Some(title.clone()),
+                _ => None,
+            })
+        };
+
+        let changed = text != self.text;
+        self.title = title;
+        self.text = text;
+        changed
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = self.config.color.unwrap_or_else(|| ctx.foreground());
+        let text = match self.config.max_width {
+            Some(max_width) => ellipsize(ctx, &self.text, &self.config.ellipsis, max_width),
+            None => self.text.clone(),
+        };
+
+        ctx.text(&text, color);
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        Some(markup::escape(&self.title)).filter(|title| !title.is_empty())
+    }
+}
@@ -0,0 +1,256 @@
+use serde::Deserialize;
+use std::sync::Arc;
+use crate::Error;
+use crate::color::Color;
+use std::sync::Mutex;
+use crate::render::{markup, Primitive, RenderCtx};
+use super::{Button, Scroll, Waker, Widget};
+use super::buttons::{Buttons, ButtonStyle, Lines};
+use super::sni::{self, Activation, Host, Item, Lookup, MenuEntry, Status, Toggle};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct TrayConfig {
+    // Icons are scaled to the bar height minus the padding, this is only
+    // the size looked up in icon themes.
+    icon_size: u32,
+    icon_theme: Option<String>,
+    padding: f32,
+    spacing: f32,
+    show_passive: bool,
+    color: Option<Color>,
+}
+
+impl Default for TrayConfig {
+    fn default() -> TrayConfig {
+        TrayConfig {
+            icon_size: 22,
+            icon_theme: None,
+            padding: 2.0,
+            spacing: 4.0,
+            show_passive: false,
+            color: None,
+        }
+    }
+}
+
+// The menu of an item, shown in a popup one entry per line once it's been
+// read, along with the submenus opened in it.
+struct Menu {
+    item: Item,
+    loading: Arc<Mutex<Option<Vec<MenuEntry>>>>,
+    entries: Option<Vec<MenuEntry>>,
+    path: Vec<usize>,
+}
+
+impl Menu {
+    // The entry whose submenu is open, if one is.
+    fn parent(&self) -> Option<&MenuEntry> {
+        let (last, outer) = self.path.split_last()?;
+        let entries = outer.iter().try_fold(self.entries.as_deref()?, |entries, index| {
+            Some(&entries.get(*index)?.children[..])
+        })?;
+        entries.get(*last)
+    }
+
+    fn shown(&self) -> Option<&[MenuEntry]> {
+        match self.path.is_empty() {
+            true => self.entries.as_deref(),
+            false => self.parent().map(|parent| &parent.children[..]),
+        }
+    }
+
+    // The entry on a line of the popup, below the line that leads back out
+    // of a submenu.
+    fn entry(&self, line: usize) -> Option<(usize, &MenuEntry)> {
+        let index = line.checked_sub(if self.path.is_empty() { 0 } else { 1 })?;
+        Some((index, self.shown()?.get(index)?))
+    }
+
+    fn text(&self) -> Option<String> {
+        let entries = self.shown().filter(|entries| !entries.is_empty())?;
+        let back = self.parent().map(|parent| format!("‹ {}", markup::escape(&parent.label)));
+
+        Some(back.into_iter().chain(entries.iter().map(menu_line)).collect::<Vec<_>>().join("\n"))
+    }
+}
+
+fn menu_line(entry: &MenuEntry) -> String {
+    if entry.separator {
+        return "<span alpha=\"40%\">──────</span>".to_string();
+    }
+
+    let mark = match entry.toggle {
+        Some(Toggle::Check(on)) => if on { "☑ " } else { "☐ " },
+        Some(Toggle::Radio(on)) => if on { "◉ " } else { "○ " },
+        None => "",
+    };
+    let submenu = if entry.children.is_empty() { "" } else { " ›" };
+    let line = format!("{}{}{}", mark, markup::escape(&entry.label), submenu);
+    match entry.enabled {
+        true => line,
+        false => format!("<span alpha=\"50%\">{}</span>", line),
+    }
+}
+
+pub struct Tray {
+    config: TrayConfig,
+    host: Host,
+    items: Vec<Item>,
+    buttons: Buttons,
+    menu: Option<Menu>,
+    waker: Waker,
+}
+
+impl Tray {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: TrayConfig = section.clone().try_into()?;
+        let lookup = Lookup { theme: config.icon_theme.clone(), size: config.icon_size };
+
+        Ok(Box::new(Tray {
+            host: Host::spawn(lookup, waker.clone()),
+            config,
+            items: Vec::new(),
+            buttons: Buttons::default(),
+            menu: None,
+            waker: waker.clone(),
+        }))
+    }
+
+    fn visible(&self) -> impl Iterator<Item = &Item> {
+        let show_passive = self.config.show_passive;
+        self.items.iter().filter(move |item| show_passive || item.status != Status::Passive)
+    }
+
+    fn item_at(&self, x: f32) -> Option<&Item> {
+        self.buttons.at(x).and_then(|index| self.visible().nth(index))
+    }
+}
+
+impl Widget for Tray {
+    fn update(&mut self) -> bool {
+        if let Some(menu) = &mut self.menu {
+            if let Some(entries) = menu.loading.lock().unwrap().take() {
+                menu.entries = Some(entries);
+            }
+        }
+
+        let items = match self.host.take() {
+            Some(items) => items,
+            None => return false,
+        };
+
+        if items == self.items {
+            return false;
+        }
+
+        // The menu goes with its item.
+        self.menu = self.menu.take()
+            .filter(|menu| items.iter().any(|item| item.bus == menu.item.bus && item.path == menu.item.path));
+        self.items = items;
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        self.buttons.clear();
+
+        let color = self.config.color.unwrap_or_else(|| ctx.foreground());
+        let size = (ctx.height() - 2.0 * self.config.padding).max(0.0);
+        let style = ButtonStyle { padding: 0.0, corner_radius: 0.0, line_width: 0.0 };
+
+        for (index, item) in self.visible().enumerate() {
+            if index > 0 {
+                ctx.advance(self.config.spacing);
+            }
+
+            match &item.icon {
+                Some(icon) => {
+                    let x = ctx.width();
+                    let width = size * icon.width() as f32 / icon.height() as f32;
+                    ctx.push(Primitive::Image {
+                        x,
+                        y: self.config.padding,
+                        width,
+                        height: size,
+                        image: Arc::clone(icon),
+                        tint: Some(color).filter(|_| item.symbolic),
+                    });
+                    self.buttons.push(x, x + width);
+                },
+                // Without a usable icon the item still gets a clickable
+                // spot, labelled with the start of its title.
+                None => {
+                    let label: String = item.title.chars().take(1).flat_map(char::to_uppercase).collect();
+                    let label = if label.is_empty() { "?" } else { &label };
+                    self.buttons.draw(ctx, label, color, None, Lines::default(), &style);
+                },
+            }
+        }
+    }
+
+    // Any click on the tray closes an open menu, asking for the menu of
+    // another item opens that one.
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        let open = self.menu.take().map(|menu| menu.item);
+        if let Some(item) = self.item_at(x) {
+            let activation = match button {
+                _ if item.is_menu => Activation::ContextMenu,
+                Button::Left => Activation::Primary,
+                Button::Middle => Activation::Secondary,
+                Button::Right => Activation::ContextMenu,
+            };
+
+            let same = open.is_some_and(|open| open.bus == item.bus && open.path == item.path);
+            match activation {
+                Activation::ContextMenu if item.menu.is_some() => if !same {
+                    self.menu = Some(Menu {
+                        loading: sni::load_menu(item, &self.waker),
+                        item: item.clone(),
+                        entries: None,
+                        path: Vec::new(),
+                    });
+                },
+                _ => sni::activate(item, activation),
+            }
+        }
+
+        false
+    }
+
+    fn popup(&self) -> Option<String> {
+        self.menu.as_ref()?.text()
+    }
+
+    fn popup_click(&mut self, line: usize, _button: Button) -> bool {
+        let menu = match &mut self.menu {
+            Some(menu) => menu,
+            None => return false,
+        };
+        if line == 0 && !menu.path.is_empty() {
+            menu.path.pop();
+            return false;
+        }
+
+        let (index, entry) = match menu.entry(line) {
+            Some((_, entry)) if entry.separator || !entry.enabled => return false,
+            Some((index, entry)) => (index, entry),
+            None => return false,
+        };
+        if entry.children.is_empty() {
+            sni::menu_clicked(&menu.item, entry.id);
+            self.menu = None;
+        } else {
+            menu.path.push(index);
+        }
+
+        false
+    }
+
+    fn scroll(&mut self, x: f32, direction: Scroll) -> bool {
+        if let Some(item) = self.item_at(x) {
+            sni::activate(item, Activation::Scroll(if direction == Scroll::Up { 1 } else { -1 }));
+        }
+
+        false
+    }
+}
@@ -0,0 +1,322 @@
+use serde::Deserialize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, Button, Waker, Widget};
+use super::actions::{self, UnknownActionError};
+use super::worker::Poller;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Backend {
+    // The first package manager found on the PATH.
+    Auto,
+    Pacman,
+    Apt,
+    Dnf,
+    Nix,
+}
+
+impl Backend {
+    fn name(self) -> &'static str {
+        match self {
+            Backend::Auto => "auto",
+            Backend::Pacman => "pacman",
+            Backend::Apt => "apt",
+            Backend::Dnf => "dnf",
+            Backend::Nix => "nix",
+        }
+    }
+
+    // The command asking about updates, and the one installing them.
+    fn program(self) -> &'static str {
+        match self {
+            Backend::Auto => "",
+            Backend::Pacman => "checkupdates",
+            Backend::Apt => "apt",
+            Backend::Dnf => "dnf",
+            Backend::Nix => "nix-env",
+        }
+    }
+
+    fn updater(self) -> &'static str {
+        match self {
+            Backend::Auto => "",
+            Backend::Pacman => "sudo pacman -Syu",
+            Backend::Apt => "sudo apt update && sudo apt upgrade",
+            Backend::Dnf => "sudo dnf upgrade",
+            Backend::Nix => "nix-env -u",
+        }
+    }
+
+    fn detect(self) -> Option<Backend> {
+        if self != Backend::Auto {
+            return Some(self);
+        }
+
+        let path = std::env::var_os("PATH")?;
+        [Backend::Pacman, Backend::Apt, Backend::Dnf, Backend::Nix].iter().copied()
+            .find(|backend| std::env::split_paths(&path).any(|dir| Path::new(&dir).join(backend.program()).is_file()))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct UpdatesConfig {
+    backend: Backend,
+    // Seconds between checks, which go out to the network.
+    interval: f64,
+    format: String,
+    format_none: String,
+    // Clicking opens the updater in this terminal, which gets the command
+    // to run appended.
+    terminal: String,
+    // Replaces the backend's own updater.
+    update_command: Option<String>,
+    color: Option<Color>,
+}
+
+impl Default for UpdatesConfig {
+    fn default() -> UpdatesConfig {
+        UpdatesConfig {
+            backend: Backend::Auto,
+            interval: 3600.0,
+            format: "{count}".to_string(),
+            format_none: String::new(),
+            terminal: "${TERMINAL:-xterm} -e".to_string(),
+            update_command: None,
+            color: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CheckFailedError(String, Option<i32>);
+
+impl std::fmt::Display for CheckFailedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.1 {
+            Some(code) => write!(f, "'{}' failed with exit code {}", self.0, code),
+            None => write!(f, "'{}' was killed", self.0),
+        }
+    }
+}
+
+impl std::error::Error for CheckFailedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// Runs a check, with the exit codes it uses to say all went well. The
+// output is whatever it printed on both streams.
+fn run(program: &str, args: &[&str], success: &[i32]) -> Result<(String, String), Error> {
+    let output = Command::new(program).args(args)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.code().is_some_and(|code| success.contains(&code)) {
+        Err(CheckFailedError(program.to_string(), output.status.code()))?;
+    }
+
+    Ok((String::from_utf8_lossy(&output.stdout).into_owned(), String::from_utf8_lossy(&output.stderr).into_owned()))
+}
+
+fn first_word(line: &str) -> Option<String> {
+    line.split_whitespace().next().map(str::to_string)
+}
+
+// The names of the packages with an update.
+fn check(backend: Backend) -> Result<Vec<String>, Error> {
+    let (output, errors) = match backend {
+        Backend::Auto => return Ok(Vec::new()),
+        // checkupdates syncs a copy of the databases, so it needs no root.
+        // It exits with 2 when nothing is out of date.
+        Backend::Pacman => run("checkupdates", &[], &[0, 2])?,
+        Backend::Apt => run("apt", &["list", "--upgradable"], &[0])?,
+        // Exits with 100 when there are updates.
+        Backend::Dnf => run("dnf", &["check-update", "-q"], &[0, 100])?,
+        Backend::Nix => run("nix-env", &["-u", "--dry-run"], &[0])?,
+    };
+
+    Ok(packages(backend, &output, &errors))
+}
+
+// Picks the package names out of what a check printed.
+fn packages(backend: Backend, output: &str, errors: &str) -> Vec<String> {
+    match backend {
+        Backend::Auto => Vec::new(),
+        Backend::Pacman => output.lines().filter_map(first_word).collect(),
+        // Lists what the last `apt update` found, under a heading.
+        Backend::Apt => output.lines()
+            .filter(|line| line.contains('/'))
+            .filter_map(|line| line.split('/').next().map(str::to_string))
+            .collect(),
+        // Lists obsoleted packages after the updates.
+        Backend::Dnf => output.lines()
+            .take_while(|line| !line.starts_with("Obsoleting"))
+            .filter(|line| line.split_whitespace().count() == 3)
+            .filter_map(first_word)
+            .collect(),
+        // Tells what it would do with the user's profile on stderr, one
+        // "upgrading 'hello-2.10' to 'hello-2.12'" per package.
+        Backend::Nix => errors.lines()
+            .filter(|line| line.starts_with("upgrading"))
+            .filter_map(|line| line.split('\'').nth(1).map(str::to_string))
+            .collect(),
+    }
+}
+
+// Quotes text for the shell, as a single argument.
+fn quoted(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+pub struct Updates {
+    config: UpdatesConfig,
+    backend: Option<Backend>,
+    poller: Poller<Option<Vec<String>>>,
+    packages: Option<Vec<String>>,
+    text: String,
+}
+
+impl Updates {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: UpdatesConfig = section.clone().try_into()?;
+        let backend = config.backend.detect();
+        if backend.is_none() {
+            warn!("No package manager found to check for updates");
+        }
+
+        let poller = Poller::spawn(Duration::from_secs_f64(config.interval.max(60.0)), waker.clone(), move || {
+            let backend = backend?;
+            check(backend)
+                .map_err(|err| warn!("Checking for updates failed: {}", err))
+                .ok()
+        });
+
+        Ok(Box::new(Updates { config, backend, poller, packages: None, text: String::new() }))
+    }
+
+    // The updater keeps the terminal open once it's done, so what it did
+    // can be read.
+    fn open_updater(&self) {
+        let command = match (&self.config.update_command, self.backend) {
+            (Some(command), _) => command.clone(),
+            (None, Some(backend)) => backend.updater().to_string(),
+            (None, None) => return,
+        };
+
+        let script = format!("{}; echo; printf 'Press enter to close'; read -r _", command);
+        if let Err(err) = actions::spawn_command(&format!("{} sh -c {}", self.config.terminal, quoted(&script))) {
+            warn!("Failed to open the updater: {}", err);
+        }
+    }
+}
+
+impl Widget for Updates {
+    fn update(&mut self) -> bool {
+        let packages = match self.poller.take() {
+            Some(Some(packages)) if Some(&packages) != self.packages.as_ref() => packages,
+            _ => return false,
+        };
+
+        let template = if packages.is_empty() { &self.config.format_none } else { &self.config.format };
+        self.text = format::placeholders(template, |key| match key {
+            "count" => Some(packages.len().to_string()),
+            "backend" => self.backend.map(|backend| backend.name().to_string()),
+            _ => None,
+        });
+        self.packages = Some(packages);
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = self.config.color.unwrap_or_else(|| ctx.foreground());
+        ctx.text(&self.text, color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        if button == Button::Left {
+            self.open_updater();
+        }
+
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "update" => self.open_updater(),
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(false)
+    }
+
+    // Long lists are cut short, the updater shows them all.
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        const SHOWN: usize = 20;
+
+        let packages = self.packages.as_ref().filter(|packages| !packages.is_empty())?;
+        let mut lines: Vec<String> = packages.iter().take(SHOWN).map(|package| markup::escape(package)).collect();
+        if packages.len() > SHOWN {
+            lines.push(format!("<span foreground=\"#888888\">and {} more</span>", packages.len() - SHOWN));
+        }
+
+        Some(lines.join("\n"))
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.packages.as_ref().map(|packages| packages.len() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pacman_lists_a_package_to_a_line() {
+        let output = "linux 6.9.1.arch1-1 -> 6.9.2.arch1-1\nfirefox 126.0-1 -> 126.0.1-1\n";
+        assert_eq!(packages(Backend::Pacman, output, ""), ["linux", "firefox"]);
+        assert!(packages(Backend::Pacman, "", "").is_empty());
+    }
+
+    #[test]
+    fn apt_lists_packages_under_a_heading() {
+        let output = "Listing... Done\n\
+            curl/noble-updates 8.5.0-2ubuntu10.2 amd64 [upgradable from: 8.5.0-2ubuntu10.1]\n\
+            libcurl4t64/noble-updates 8.5.0-2ubuntu10.2 amd64 [upgradable from: 8.5.0-2ubuntu10.1]\n";
+        assert_eq!(packages(Backend::Apt, output, "WARNING: apt does not have a stable CLI interface."),
+            ["curl", "libcurl4t64"]);
+    }
+
+    #[test]
+    fn dnf_lists_updates_before_obsoleted_packages() {
+        let output = "\n\
+            kernel.x86_64    6.8.10-300.fc40    updates\n\
+            Last metadata expiration check: 0:10:00 ago.\n\
+            vim-common.x86_64    2:9.1.393-1.fc40    updates\n\
+            Obsoleting Packages\n\
+            grub2-tools.x86_64    1:2.06-121.fc40    updates\n";
+        assert_eq!(packages(Backend::Dnf, output, ""), ["kernel.x86_64", "vim-common.x86_64"]);
+    }
+
+    #[test]
+    fn nix_tells_about_upgrades_on_stderr() {
+        let errors = "(dry run; not doing anything)\n\
+            upgrading 'hello-2.10' to 'hello-2.12'\n\
+            upgrading 'ripgrep-13.0.0' to 'ripgrep-14.1.0'\n";
+        assert_eq!(packages(Backend::Nix, "upgrading 'ignored-1' to 'ignored-2'", errors),
+            ["hello-2.10", "ripgrep-13.0.0"]);
+    }
+
+    #[test]
+    fn quoting_survives_quotes() {
+        assert_eq!(quoted("it's"), "'it'\\''s'");
+        assert_eq!(quoted("sudo apt update && sudo apt upgrade"), "'sudo apt update && sudo apt upgrade'");
+    }
+}
@@ -0,0 +1,114 @@
+use serde::Deserialize;
+use std::time::Duration;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, Waker, Widget};
+use super::worker::Poller;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct UptimeConfig {
+    format: String,
+    precision: usize,
+    interval: f64,
+    color: Option<Color>,
+}
+
+impl Default for UptimeConfig {
+    fn default() -> UptimeConfig {
+        UptimeConfig {
+            format: "UP {uptime} {load1}".to_string(),
+            precision: 2,
+            interval: 5.0,
+            color: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    // Seconds since boot, not counting time spent suspended.
+    uptime: u64,
+    // Averages over the last 1, 5 and 15 minutes.
+    load: [f64; 3],
+}
+
+impl Sample {
+    fn read() -> Option<Sample> {
+        let uptime = std::fs::read_to_string("/proc/uptime").ok()?;
+        let uptime: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+
+        let loadavg = std::fs::read_to_string("/proc/loadavg").ok()?;
+        let mut fields = loadavg.split_whitespace().map(|field| field.parse().ok());
+        let load = [fields.next()??, fields.next()??, fields.next()??];
+
+        Some(Sample { uptime: uptime as u64, load })
+    }
+}
+
+// The two largest units, which is as exact as an uptime needs to be.
+fn human(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+pub struct Uptime {
+    config: UptimeConfig,
+    poller: Poller<Option<Sample>>,
+    sample: Option<Sample>,
+    text: String,
+}
+
+impl Uptime {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: UptimeConfig = section.clone().try_into()?;
+        let poller = Poller::spawn(Duration::from_secs_f64(config.interval.max(0.1)), waker.clone(), Sample::read);
+
+        Ok(Box::new(Uptime { config, poller, sample: None, text: String::new() }))
+    }
+
+    fn format(&self, sample: &Sample) -> String {
+        let load = |index: usize| format!("{:.*}", self.config.precision, sample.load[index]);
+
+        format::placeholders(&self.config.format, |key| match key {
+            "uptime" => Some(human(sample.uptime)),
+            "days" => Some((sample.uptime / 86400).to_string()),
+            "hours" => Some((sample.uptime / 3600).to_string()),
+            "minutes" => Some((sample.uptime / 60).to_string()),
+            "load1" => Some(load(0)),
+            "load5" => Some(load(1)),
+            "load15" => Some(load(2)),
+            _ => None,
+        })
+    }
+}
+
+impl Widget for Uptime {
+    fn update(&mut self) -> bool {
+        let sample = match self.poller.take() {
+            Some(Some(sample)) => sample,
+            _ => return false,
+        };
+
+        let text = self.format(&sample);
+        let changed = text != self.text;
+        self.sample = Some(sample);
+        self.text = text;
+        changed
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = self.config.color.unwrap_or_else(|| ctx.foreground());
+        ctx.text(&self.text, color);
+    }
+
+    // The one minute load, which the module's states can pick colors by.
+    fn value(&self) -> Option<f64> {
+        self.sample.as_ref().map(|sample| sample.load[0])
+    }
+}
@@ -0,0 +1,138 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, Scroll, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::meter::{self, BarStyle, Display, RingStyle};
+use super::pulse::{self, Device, DeviceState, Monitor};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct VolumeConfig {
+    format: String,
+    format_muted: String,
+    format_missing: String,
+    color: Option<Color>,
+    muted_color: Color,
+    scroll_step: u32,
+    max_volume: u32,
+    display: Display,
+    bar: BarStyle,
+    ring: RingStyle,
+}
+
+impl Default for VolumeConfig {
+    fn default() -> VolumeConfig {
+        VolumeConfig {
+            format: "VOL {volume}%".to_string(),
+            format_muted: "VOL muted".to_string(),
+            format_missing: String::new(),
+            color: None,
+            muted_color: Color::from_str("#888888").unwrap(),
+            scroll_step: 5,
+            max_volume: 100,
+            display: Display::Text,
+            bar: BarStyle::default(),
+            ring: RingStyle::default(),
+        }
+    }
+}
+
+pub struct Volume {
+    config: VolumeConfig,
+    monitor: Monitor,
+    state: Option<DeviceState>,
+    text: String,
+}
+
+impl Volume {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        Ok(Box::new(Volume {
+            config: section.clone().try_into()?,
+            monitor: Monitor::spawn(Device::Sink, waker.clone()),
+            state: None,
+            text: String::new(),
+        }))
+    }
+
+    fn format(&self) -> String {
+        let state = match &self.state {
+            Some(state) => state,
+            None => return self.config.format_missing.clone(),
+        };
+
+        let template = if state.muted { &self.config.format_muted } else { &self.config.format };
+        format::placeholders(template, |key| match key {
+            "volume" => Some(state.volume.to_string()),
+            "sink" => Some(state.name.clone()),
+            "description" => Some(state.description.clone()),
+            _ => None,
+        })
+    }
+}
+
+impl Widget for Volume {
+    fn update(&mut self) -> bool {
+        let state = match self.monitor.take() {
+            Some(state) => state,
+            None => return false,
+        };
+
+        if state == self.state {
+            return false;
+        }
+
+        self.state = state;
+        self.text = self.format();
+        true
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.state.as_ref().map(|state| state.volume as f64)
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match &self.state {
+            Some(state) if state.muted => self.config.muted_color,
+            _ => self.config.color.unwrap_or_else(|| ctx.foreground()),
+        };
+
+        // Volumes above 100% fill the whole bar or ring.
+        let value = self.state.as_ref().map(|state| state.volume as f32 / 100.0);
+        match (self.config.display, value) {
+            (Display::Bar, Some(value)) => meter::draw_bar(ctx, value, color, &self.config.bar),
+            (Display::Ring, Some(value)) => meter::draw_ring(ctx, value, color, &self.config.ring),
+            _ => ctx.text(&self.text, color),
+        }
+    }
+
+    fn scroll(&mut self, _x: f32, direction: Scroll) -> bool {
+        let step = self.config.scroll_step as i32;
+        let volume = self.state.as_ref().map(|state| state.volume as i32).unwrap_or(0);
+
+        // Scrolling up never pushes the volume past max-volume, though it
+        // doesn't pull down a volume that is already above it either.
+        let change = match direction {
+            Scroll::Up => step.min(self.config.max_volume as i32 - volume).max(0),
+            Scroll::Down => -step,
+        };
+
+        if change != 0 {
+            if let Err(err) = pulse::adjust_volume(Device::Sink, change) {
+                warn!("Failed to change the volume: {}", err);
+            }
+        }
+
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "toggle-mute" => pulse::toggle_mute(Device::Sink).map(|()| false),
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use serde::Deserialize;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, sysfs, Button, Waker, Widget};
+use super::actions::UnknownActionError;
+use super::systemd1::{self, Control};
+use super::worker::{self, Poller};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    NetworkManager,
+    WireGuard,
+    OpenVpn,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::NetworkManager => "networkmanager",
+            Kind::WireGuard => "wireguard",
+            Kind::OpenVpn => "openvpn",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Tunnel {
+    // The connection, interface or config name.
+    name: String,
+    kind: Kind,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct VpnConfig {
+    format: String,
+    format_down: String,
+    interval: f64,
+    // Clicking brings this NetworkManager connection up or down.
+    connection: Option<String>,
+    // Clicking starts or stops this systemd unit, such as wg-quick@wg0,
+    // when there's no NetworkManager connection to toggle.
+    unit: Option<String>,
+    user: bool,
+    color: Option<Color>,
+    down_color: Color,
+}
+
+impl Default for VpnConfig {
+    fn default() -> VpnConfig {
+        VpnConfig {
+            format: "{name}".to_string(),
+            format_down: String::new(),
+            interval: 5.0,
+            connection: None,
+            unit: None,
+            user: false,
+            color: None,
+            down_color: Color::from_str("#888888").unwrap(),
+        }
+    }
+}
+
+// Active VPN and WireGuard connections with the devices they're up on.
+fn network_manager() -> Vec<(Tunnel, String)> {
+    let output = Command::new("nmcli").args(["-t", "-f", "NAME,TYPE,DEVICE", "connection", "show", "--active"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).into_owned(),
+        _ => return Vec::new(),
+    };
+
+    output.lines().filter_map(active_connection).collect()
+}
+
+// A line of nmcli's terse output, if it's a tunnel. Terse output escapes
+// colons in names, so fields are split off the end.
+fn active_connection(line: &str) -> Option<(Tunnel, String)> {
+    let mut fields = line.rsplitn(3, ':');
+    let (device, kind, name) = (fields.next()?, fields.next()?, fields.next()?);
+    if kind != "vpn" && kind != "wireguard" {
+        return None;
+    }
+
+    Some((Tunnel { name: name.replace("\\:", ":"), kind: Kind::NetworkManager }, device.to_string()))
+}
+
+fn wireguard_interfaces() -> Vec<String> {
+    let entries = match std::fs::read_dir("/sys/class/net") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut interfaces: Vec<String> = entries.filter_map(|entry| entry.ok())
+        .filter(|entry| sysfs::read_string(entry.path().join("uevent")).is_some_and(|uevent| {
+            uevent.lines().any(|line| line == "DEVTYPE=wireguard")
+        }))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    interfaces.sort();
+    interfaces
+}
+
+// Running OpenVPN clients, named after their config file.
+fn openvpn_processes() -> Vec<String> {
+    let entries = match std::fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries.filter_map(|entry| entry.ok())
+        .filter(|entry| sysfs::read_string(entry.path().join("comm")).is_some_and(|comm| comm == "openvpn"))
+        .filter_map(|entry| std::fs::read(entry.path().join("cmdline")).ok())
+        .map(|cmdline| {
+            let args: Vec<String> = cmdline.split(|byte| *byte == 0)
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect();
+            args.iter()
+                .position(|arg| arg == "--config")
+                .and_then(|index| args.get(index + 1))
+                .and_then(|config| Path::new(config).file_stem())
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "openvpn".to_string())
+        })
+        .collect()
+}
+
+// WireGuard interfaces NetworkManager brought up are only listed once,
+// under the connection's name.
+fn tunnels() -> Vec<Tunnel> {
+    let managed = network_manager();
+    let wireguard: Vec<Tunnel> = wireguard_interfaces().into_iter()
+        .filter(|interface| !managed.iter().any(|(_, device)| device == interface))
+        .map(|name| Tunnel { name, kind: Kind::WireGuard })
+        .collect();
+    let openvpn = openvpn_processes().into_iter().map(|name| Tunnel { name, kind: Kind::OpenVpn });
+
+    managed.into_iter().map(|(tunnel, _)| tunnel).chain(wireguard).chain(openvpn).collect()
+}
+
+#[derive(Debug)]
+struct NmcliError(ExitStatus);
+
+impl std::fmt::Display for NmcliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "nmcli failed with {}", self.0)
+    }
+}
+
+impl std::error::Error for NmcliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// Bringing a connection up can take a while.
+fn nmcli(args: Vec<String>) {
+    worker::spawn_control(format!("run nmcli {}", args.join(" ")), move || {
+        let status = Command::new("nmcli").args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .status()?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(Box::new(NmcliError(status))),
+        }
+    });
+}
+
+pub struct Vpn {
+    config: VpnConfig,
+    poller: Poller<Vec<Tunnel>>,
+    tunnels: Option<Vec<Tunnel>>,
+    text: String,
+}
+
+impl Vpn {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: VpnConfig = section.clone().try_into()?;
+        let poller = Poller::spawn(Duration::from_secs_f64(config.interval.max(0.5)), waker.clone(), tunnels);
+
+        Ok(Box::new(Vpn { config, poller, tunnels: None, text: String::new() }))
+    }
+
+    fn up(&self) -> bool {
+        self.tunnels.as_ref().is_some_and(|tunnels| !tunnels.is_empty())
+    }
+
+    // A NetworkManager connection goes down when it's the one that's up,
+    // a unit is stopped while any VPN is up.
+    fn toggle(&self) {
+        if let Some(connection) = &self.config.connection {
+            let active = self.tunnels.iter().flatten()
+                .any(|tunnel| tunnel.kind == Kind::NetworkManager && tunnel.name == *connection);
+            let command = if active { "down" } else { "up" };
+            nmcli(vec!["connection".to_string(), command.to_string(), "id".to_string(), connection.clone()]);
+        } else if let Some(unit) = &self.config.unit {
+            let control = if self.up() { Control::Stop } else { Control::Start };
+            systemd1::control(unit, control, self.config.user);
+        }
+    }
+}
+
+impl Widget for Vpn {
+    fn update(&mut self) -> bool {
+        let tunnels = match self.poller.take() {
+            Some(tunnels) if Some(&tunnels) != self.tunnels.as_ref() => tunnels,
+            _ => return false,
+        };
+
+        let template = if tunnels.is_empty() { &self.config.format_down } else { &self.config.format };
+        self.text = format::placeholders(template, |key| match key {
+            "name" => Some(tunnels.iter().map(|tunnel| tunnel.name.as_str()).collect::<Vec<_>>().join(", ")),
+            "kind" => Some(tunnels.first().map(|tunnel| tunnel.kind.name()).unwrap_or_default().to_string()),
+            "count" => Some(tunnels.len().to_string()),
+            _ => None,
+        });
+        self.tunnels = Some(tunnels);
+        true
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match self.up() {
+            true => self.config.color.unwrap_or_else(|| ctx.foreground()),
+            false => self.config.down_color,
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn click(&mut self, _x: f32, button: Button) -> bool {
+        if button == Button::Left {
+            self.toggle();
+        }
+
+        false
+    }
+
+    fn action(&mut self, name: &str) -> Result<bool, Error> {
+        match name {
+            "toggle" => self.toggle(),
+            _ => Err(UnknownActionError(name.to_string()))?,
+        }
+
+        Ok(false)
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let tunnels = self.tunnels.as_ref().filter(|tunnels| !tunnels.is_empty())?;
+        let lines: Vec<String> = tunnels.iter()
+            .map(|tunnel| format!("{} <span foreground=\"#888888\">{}</span>", markup::escape(&tunnel.name), tunnel.kind.name()))
+            .collect();
+
+        Some(lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(line: &str) -> Option<(String, String)> {
+        active_connection(line).map(|(tunnel, device)| (tunnel.name, device))
+    }
+
+    #[test]
+    fn nmcli_lines_name_tunnels_and_their_devices() {
+        assert_eq!(connection("Office:vpn:tun0"), Some(("Office".to_string(), "tun0".to_string())));
+        assert_eq!(connection("wg-home:wireguard:wg0"), Some(("wg-home".to_string(), "wg0".to_string())));
+        assert_eq!(connection("Home\\: Berlin:wireguard:wg1"), Some(("Home: Berlin".to_string(), "wg1".to_string())));
+        assert_eq!(connection("Office:vpn:"), Some(("Office".to_string(), String::new())));
+    }
+
+    #[test]
+    fn nmcli_lines_of_other_connections_are_skipped() {
+        for line in ["Wired connection 1:802-3-ethernet:enp3s0", "lo:loopback:lo", "Office:vpn", ""] {
+            assert_eq!(connection(line), None, "{}", line);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use serde::Deserialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use wasmtime::{Caller, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+use tracing::{info, warn};
+use crate::Error;
+use crate::render::RenderCtx;
+use super::{Button, Scroll, Widget};
+
+// WebAssembly plugins in ~/.config/rustybar/plugins add a widget kind named
+// after the file. They run sandboxed: a plugin that traps or runs out of
+// fuel only stops its own module. A plugin exports
+//
+//     memory
+//     alloc(len: i32) -> i32           room for the host to write into
+//     update() -> i32                  1 if the text changed
+//     text() -> i64                    pointer << 32 | length of UTF-8 text
+//
+// and optionally
+//
+//     init(config: i32, len: i32) -> i32    module section as JSON, 0 if ok
+//     click(x: f32, button: i32) -> i32     1 if the text changed
+//
+// Buttons are numbered as in X11, 4 and 5 being scrolling up and down.
+// `rustybar.log(pointer: i32, len: i32)` can be imported to print messages.
+// update is called every `interval` seconds of the module section.
+
+// Instructions a single call may run, so a looping plugin can't hang the bar.
+const FUEL_PER_CALL: u64 = 50_000_000;
+// Fuel doesn't bound memory.grow, so a plugin's memories and tables are capped
+// to keep one from taking all of the bar's memory.
+const MAX_MEMORY: usize = 64 << 20;
+const MAX_TABLE_ELEMENTS: u32 = 100_000;
+
+#[derive(Debug)]
+struct WasmError(String);
+
+impl std::fmt::Display for WasmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for WasmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct WasmConfig {
+    interval: f64,
+}
+
+impl Default for WasmConfig {
+    fn default() -> WasmConfig {
+        WasmConfig { interval: 1.0 }
+    }
+}
+
+// Compiled once when the plugin is loaded, instantiated for every module.
+#[derive(Clone)]
+pub struct WasmPlugin {
+    engine: Engine,
+    module: Module,
+}
+
+impl WasmPlugin {
+    pub fn open(path: &Path) -> Result<WasmPlugin, Error> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::from_file(&engine, path)?;
+
+        Ok(WasmPlugin { engine, module })
+    }
+
+    pub fn build(&self, section: &toml::Value) -> Result<Box<dyn Widget>, Error> {
+        let config: WasmConfig = section.clone().try_into()?;
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY)
+            .table_elements(MAX_TABLE_ELEMENTS)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL_PER_CALL)?;
+
+        let mut linker = Linker::new(&self.engine);
+        linker.func_wrap("rustybar", "log", |mut caller: Caller<'_, StoreLimits>, pointer: i32, len: i32| {
+            let memory = caller.get_export("memory").and_then(|export| export.into_memory());
+            if let Some(bytes) = memory.and_then(|memory| read(&memory, &caller, pointer as u32, len as u32)) {
+                info!("{}", String::from_utf8_lossy(&bytes));
+            }
+        })?;
+        let instance = linker.instantiate(&mut store, &self.module)?;
+
+        let memory = instance.get_memory(&mut store, "memory")
+            .ok_or_else(|| WasmError("the plugin exports no memory".to_string()))?;
+        let mut widget = WasmWidget {
+            alloc: instance.get_typed_func(&mut store, "alloc")?,
+            update: instance.get_typed_func(&mut store, "update")?,
+            text_at: instance.get_typed_func(&mut store, "text")?,
+            click: instance.get_typed_func(&mut store, "click").ok(),
+            memory,
+            store,
+            interval: Duration::from_secs_f64(config.interval.max(0.1)),
+            next: Instant::now(),
+            failed: false,
+            text: String::new(),
+        };
+        widget.init(&instance, section)?;
+
+        Ok(Box::new(widget))
+    }
+}
+
+fn read(memory: &Memory, store: impl wasmtime::AsContext, pointer: u32, len: u32) -> Option<Vec<u8>> {
+    let data = memory.data(&store);
+    let start = pointer as usize;
+    data.get(start..start.checked_add(len as usize)?).map(<[u8]>::to_vec)
+}
+
+struct WasmWidget {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    update: TypedFunc<(), i32>,
+    text_at: TypedFunc<(), i64>,
+    click: Option<TypedFunc<(f32, i32), i32>>,
+    interval: Duration,
+    next: Instant,
+    // Set once the plugin trapped, it isn't called again.
+    failed: bool,
+    text: String,
+}
+
+impl WasmWidget {
+    fn init(&mut self, instance: &Instance, section: &toml::Value) -> Result<(), Error> {
+        let init = match instance.get_typed_func::<(i32, i32), i32>(&mut self.store, "init") {
+            Ok(init) => init,
+            Err(_) => return Ok(()),
+        };
+
+        let config = serde_json::to_vec(section)?;
+        let pointer = self.alloc.call(&mut self.store, config.len() as i32)?;
+        self.memory.write(&mut self.store, pointer as usize, &config)?;
+        match init.call(&mut self.store, (pointer, config.len() as i32))? {
+            0 => Ok(()),
+            code => Err(Box::new(WasmError(format!("the plugin failed to start with code {}", code)))),
+        }
+    }
+
+    // Every call starts with a full tank of fuel.
+    fn call<F: FnOnce(&mut WasmWidget) -> Result<i32, Error>>(&mut self, call: F) -> bool {
+        if self.failed {
+            return false;
+        }
+
+        let result = self.store.set_fuel(FUEL_PER_CALL).map_err(Error::from)
+            .and_then(|()| call(self))
+            .and_then(|changed| if changed == 1 { self.read_text() } else { Ok(false) });
+        match result {
+            Ok(changed) => changed,
+            Err(err) => {
+                warn!("Wasm plugin stopped: {}", err);
+                self.failed = true;
+                self.text.clear();
+                true
+            },
+        }
+    }
+
+    fn read_text(&mut self) -> Result<bool, Error> {
+        let packed = self.text_at.call(&mut self.store, ())? as u64;
+        let (pointer, len) = ((packed >> 32) as u32, packed as u32);
+        let bytes = read(&self.memory, &self.store, pointer, len)
+            .ok_or_else(|| WasmError("the plugin's text is out of bounds".to_string()))?;
+
+        let text = String::from_utf8_lossy(&bytes).to_string();
+        let changed = text != self.text;
+        self.text = text;
+        Ok(changed)
+    }
+
+    fn click_button(&mut self, x: f32, button: i32) -> bool {
+        let click = match self.click.clone() {
+            Some(click) => click,
+            None => return false,
+        };
+        self.call(|widget| Ok(click.call(&mut widget.store, (x, button))?))
+    }
+}
+
+impl Widget for WasmWidget {
+    fn update(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next {
+            return false;
+        }
+        self.next = now + self.interval;
+
+        let update = self.update.clone();
+        self.call(|widget| Ok(update.call(&mut widget.store, ())?))
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = ctx.foreground();
+        ctx.text(&self.text, color);
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        Some(self.next).filter(|_| !self.failed)
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        let button = match button {
+            Button::Left => 1,
+            Button::Middle => 2,
+            Button::Right => 3,
+        };
+        self.click_button(x, button)
+    }
+
+    fn scroll(&mut self, x: f32, direction: Scroll) -> bool {
+        let button = match direction {
+            Scroll::Up => 4,
+            Scroll::Down => 5,
+        };
+        self.click_button(x, button)
+    }
+}
@@ -0,0 +1,572 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+use crate::Error;
+use crate::color::Color;
+use crate::render::{markup, RenderCtx};
+use super::{format, Waker, Widget};
+use super::worker::Poller;
+
+// How often a failed fetch is retried, unless the interval is shorter.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const TIMEOUT_SECS: &str = "30";
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ProviderKind {
+    WttrIn,
+    OpenMeteo,
+    Openweathermap,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Units {
+    Metric,
+    Imperial,
+}
+
+impl Units {
+    fn temperature(&self, celsius: f64) -> f64 {
+        match self {
+            Units::Metric => celsius,
+            Units::Imperial => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    fn speed(&self, meters_per_second: f64) -> f64 {
+        match self {
+            Units::Metric => meters_per_second * 3.6,
+            Units::Imperial => meters_per_second * 2.236_936,
+        }
+    }
+
+    fn temperature_label(&self) -> &'static str {
+        match self {
+            Units::Metric => "°C",
+            Units::Imperial => "°F",
+        }
+    }
+
+    fn speed_label(&self) -> &'static str {
+        match self {
+            Units::Metric => "km/h",
+            Units::Imperial => "mph",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct WeatherConfig {
+    provider: ProviderKind,
+    // A city name for wttr.in and OpenWeatherMap, wttr.in guesses from the
+    // IP address when neither a location nor coordinates are set.
+    location: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    api_key: Option<String>,
+    format: String,
+    format_missing: String,
+    units: Units,
+    // Seconds between fetches, the last report is reused until then, even
+    // across restarts.
+    interval: f64,
+    // Icons by condition: clear, partly-cloudy, cloudy, fog, rain, snow,
+    // thunder and unknown. Missing ones fall back to the built-in set.
+    icons: HashMap<String, String>,
+    color: Option<Color>,
+    // Used while the report is older than the interval because fetching
+    // keeps failing.
+    stale_color: Color,
+}
+
+impl Default for WeatherConfig {
+    fn default() -> WeatherConfig {
+        WeatherConfig {
+            provider: ProviderKind::WttrIn,
+            location: None,
+            latitude: None,
+            longitude: None,
+            api_key: None,
+            format: "{icon} {temperature}{unit}".to_string(),
+            format_missing: String::new(),
+            units: Units::Metric,
+            interval: 600.0,
+            icons: HashMap::new(),
+            color: None,
+            stale_color: Color::from_str("#888888").unwrap(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MissingSettingError {
+    provider: &'static str,
+    setting: &'static str,
+}
+
+impl std::fmt::Display for MissingSettingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The {} weather provider needs '{}' to be set", self.provider, self.setting)
+    }
+}
+
+impl std::error::Error for MissingSettingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug)]
+struct FetchError(String);
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to fetch the weather: {}", self.0)
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug)]
+struct MalformedReportError;
+
+impl std::fmt::Display for MalformedReportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The weather provider sent a report without current conditions")
+    }
+}
+
+impl std::error::Error for MalformedReportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Condition {
+    Clear,
+    PartlyCloudy,
+    Cloudy,
+    Fog,
+    Rain,
+    Snow,
+    Thunder,
+    Unknown,
+}
+
+impl Condition {
+    // WMO weather interpretation codes, as used by Open-Meteo.
+    fn from_wmo(code: u64) -> Condition {
+        match code {
+            0 => Condition::Clear,
+            1 | 2 => Condition::PartlyCloudy,
+            3 => Condition::Cloudy,
+            45 | 48 => Condition::Fog,
+            51..=67 | 80..=82 => Condition::Rain,
+            71..=77 | 85 | 86 => Condition::Snow,
+            95..=99 => Condition::Thunder,
+            _ => Condition::Unknown,
+        }
+    }
+
+    // World Weather Online codes, as used by wttr.in.
+    fn from_wwo(code: u64) -> Condition {
+        match code {
+            113 => Condition::Clear,
+            116 => Condition::PartlyCloudy,
+            119 | 122 => Condition::Cloudy,
+            143 | 248 | 260 => Condition::Fog,
+            200 | 386 | 389 | 392 | 395 => Condition::Thunder,
+            179 | 182 | 185 | 227 | 230 | 281 | 284 | 311 | 314 | 317 | 320 | 323 | 326 | 329 | 332 | 335 | 338
+                | 350 | 362 | 365 | 368 | 371 | 374 | 377 => Condition::Snow,
+            176 | 263 | 266 | 293 | 296 | 299 | 302 | 305 | 308 | 353 | 356 | 359 => Condition::Rain,
+            _ => Condition::Unknown,
+        }
+    }
+
+    fn from_openweathermap(id: u64) -> Condition {
+        match id {
+            200..=299 => Condition::Thunder,
+            300..=399 | 500..=599 => Condition::Rain,
+            600..=699 => Condition::Snow,
+            700..=799 => Condition::Fog,
+            800 => Condition::Clear,
+            801 | 802 => Condition::PartlyCloudy,
+            803 | 804 => Condition::Cloudy,
+            _ => Condition::Unknown,
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            Condition::Clear => "clear",
+            Condition::PartlyCloudy => "partly-cloudy",
+            Condition::Cloudy => "cloudy",
+            Condition::Fog => "fog",
+            Condition::Rain => "rain",
+            Condition::Snow => "snow",
+            Condition::Thunder => "thunder",
+            Condition::Unknown => "unknown",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Condition::Clear => "Clear",
+            Condition::PartlyCloudy => "Partly cloudy",
+            Condition::Cloudy => "Cloudy",
+            Condition::Fog => "Fog",
+            Condition::Rain => "Rain",
+            Condition::Snow => "Snow",
+            Condition::Thunder => "Thunderstorm",
+            Condition::Unknown => "Unknown",
+        }
+    }
+
+    fn icon(&self) -> &'static str {
+        match self {
+            Condition::Clear => "☀",
+            Condition::PartlyCloudy => "⛅",
+            Condition::Cloudy => "☁",
+            Condition::Fog => "🌫",
+            Condition::Rain => "🌧",
+            Condition::Snow => "❄",
+            Condition::Thunder => "⛈",
+            Condition::Unknown => "?",
+        }
+    }
+}
+
+// Current conditions in metric units, converted only for display.
+#[derive(Debug, Clone, PartialEq)]
+struct Report {
+    condition: Condition,
+    description: String,
+    temperature: f64,
+    feels_like: Option<f64>,
+    humidity: Option<f64>,
+    wind: Option<f64>,
+}
+
+// A weather service: where to fetch the current conditions from and how to
+// read its response.
+trait Provider: Send {
+    fn url(&self) -> String;
+
+    fn parse(&self, body: &[u8]) -> Result<Report, Error>;
+}
+
+// Numbers arrive as JSON numbers from most services, but as strings from
+// wttr.in.
+fn number(value: &serde_json::Value) -> Option<f64> {
+    value.as_f64().or_else(|| value.as_str()?.trim().parse().ok())
+}
+
+fn encode(text: &str) -> String {
+    text.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+struct WttrIn {
+    location: String,
+}
+
+impl Provider for WttrIn {
+    fn url(&self) -> String {
+        format!("https://wttr.in/{}?format=j1", encode(&self.location))
+    }
+
+    fn parse(&self, body: &[u8]) -> Result<Report, Error> {
+        let response: serde_json::Value = serde_json::from_slice(body)?;
+        let current = &response["current_condition"][0];
+        let temperature = number(&current["temp_C"]).ok_or(MalformedReportError)?;
+        let condition = number(&current["weatherCode"]).map_or(Condition::Unknown, |code| Condition::from_wwo(code as u64));
+
+        Ok(Report {
+            condition,
+            description: current["weatherDesc"][0]["value"].as_str()
+                .map_or_else(|| condition.description().to_string(), |description| description.trim().to_string()),
+            temperature,
+            feels_like: number(&current["FeelsLikeC"]),
+            humidity: number(&current["humidity"]),
+            wind: number(&current["windspeedKmph"]).map(|speed| speed / 3.6),
+        })
+    }
+}
+
+struct OpenMeteo {
+    latitude: f64,
+    longitude: f64,
+}
+
+impl Provider for OpenMeteo {
+    fn url(&self) -> String {
+        format!("https://api.open-meteo.com/v1/forecast?latitude={}&longitude={}\
+            &current=temperature_2m,apparent_temperature,relative_humidity_2m,weather_code,wind_speed_10m\
+            &wind_speed_unit=ms", self.latitude, self.longitude)
+    }
+
+    fn parse(&self, body: &[u8]) -> Result<Report, Error> {
+        let response: serde_json::Value = serde_json::from_slice(body)?;
+        let current = &response["current"];
+        let temperature = number(&current["temperature_2m"]).ok_or(MalformedReportError)?;
+        let condition = number(&current["weather_code"]).map_or(Condition::Unknown, |code| Condition::from_wmo(code as u64));
+
+        Ok(Report {
+            condition,
+            description: condition.description().to_string(),
+            temperature,
+            feels_like: number(&current["apparent_temperature"]),
+            humidity: number(&current["relative_humidity_2m"]),
+            wind: number(&current["wind_speed_10m"]),
+        })
+    }
+}
+
+struct OpenWeatherMap {
+    // Either "q=<city>" or "lat=<latitude>&lon=<longitude>".
+    query: String,
+    api_key: String,
+}
+
+impl Provider for OpenWeatherMap {
+    fn url(&self) -> String {
+        format!("https://api.openweathermap.org/data/2.5/weather?{}&appid={}&units=metric", self.query,
+            encode(&self.api_key))
+    }
+
+    fn parse(&self, body: &[u8]) -> Result<Report, Error> {
+        let response: serde_json::Value = serde_json::from_slice(body)?;
+        let temperature = number(&response["main"]["temp"]).ok_or(MalformedReportError)?;
+        let weather = &response["weather"][0];
+        let condition = number(&weather["id"]).map_or(Condition::Unknown, |id| Condition::from_openweathermap(id as u64));
+
+        // Descriptions come in lower case, such as "light rain".
+        let description = match weather["description"].as_str() {
+            Some(description) => {
+                let mut chars = description.chars();
+                chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+            },
+            None => condition.description().to_string(),
+        };
+
+        Ok(Report {
+            condition,
+            description,
+            temperature,
+            feels_like: number(&response["main"]["feels_like"]),
+            humidity: number(&response["main"]["humidity"]),
+            wind: number(&response["wind"]["speed"]),
+        })
+    }
+}
+
+fn provider(config: &WeatherConfig) -> Result<Box<dyn Provider>, Error> {
+    let coordinates = config.latitude.zip(config.longitude);
+
+    Ok(match config.provider {
+        ProviderKind::WttrIn => Box::new(WttrIn {
+            location: match (&config.location, coordinates) {
+                (Some(location), _) => location.clone(),
+                (None, Some((latitude, longitude))) => format!("{},{}", latitude, longitude),
+                (None, None) => String::new(),
+            },
+        }),
+        ProviderKind::OpenMeteo => {
+            let (latitude, longitude) = coordinates
+                .ok_or(MissingSettingError { provider: "open-meteo", setting: "latitude and longitude" })?;
+            Box::new(OpenMeteo { latitude, longitude })
+        },
+        ProviderKind::Openweathermap => {
+            let query = match (&config.location, coordinates) {
+                (_, Some((latitude, longitude))) => format!("lat={}&lon={}", latitude, longitude),
+                (Some(location), None) => format!("q={}", encode(location)),
+                (None, None) => Err(MissingSettingError { provider: "openweathermap", setting: "location" })?,
+            };
+            let api_key = config.api_key.clone()
+                .ok_or(MissingSettingError { provider: "openweathermap", setting: "api-key" })?;
+            Box::new(OpenWeatherMap { query, api_key })
+        },
+    })
+}
+
+// The URL goes to curl through its config on stdin, which keeps API keys out
+// of the process list.
+fn fetch(url: &str) -> Result<Vec<u8>, Error> {
+    let mut child = Command::new("curl").args(["--silent", "--show-error", "--fail", "--location",
+            "--max-time", TIMEOUT_SECS, "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "url = \"{}\"", url.replace('\\', "\\\\").replace('"', "\\\""))?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Box::new(FetchError(String::from_utf8_lossy(&output.stderr).trim().to_string())));
+    }
+
+    Ok(output.stdout)
+}
+
+// Responses are cached per URL, so switching providers or locations never
+// shows a report for the wrong place.
+fn cache_path(url: &str) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    Some(dirs::cache_dir()?.join("rustybar").join(format!("weather-{:016x}.json", hasher.finish())))
+}
+
+fn load_cache(provider: &dyn Provider, path: &Option<PathBuf>) -> Option<(SystemTime, Report)> {
+    let path = path.as_ref()?;
+    let fetched = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    let report = provider.parse(&std::fs::read(path).ok()?).ok()?;
+
+    Some((fetched, report))
+}
+
+fn store_cache(path: &Option<PathBuf>, body: &[u8]) {
+    let path = match path {
+        Some(path) => path,
+        None => return,
+    };
+
+    let stored = path.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|_| std::fs::write(path, body));
+    if let Err(err) = stored {
+        warn!("Failed to cache the weather in {}: {}", path.display(), err);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    report: Report,
+    stale: bool,
+}
+
+pub struct Weather {
+    config: WeatherConfig,
+    poller: Poller<Option<Sample>>,
+    sample: Option<Sample>,
+    text: String,
+}
+
+impl Weather {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: WeatherConfig = section.clone().try_into()?;
+        let provider = provider(&config)?;
+        let interval = Duration::from_secs_f64(config.interval.max(1.0));
+
+        let url = provider.url();
+        let cache = cache_path(&url);
+        let mut last = load_cache(&*provider, &cache);
+
+        // The poller wakes up often enough to retry failed fetches, but only
+        // fetches once the last report is older than the interval. Until a
+        // fetch succeeds the last report is shown as stale.
+        let poller = Poller::spawn(interval.min(RETRY_INTERVAL), waker.clone(), move || {
+            let age = |fetched: SystemTime| fetched.elapsed().unwrap_or_default();
+            if let Some((fetched, report)) = &last {
+                if age(*fetched) < interval {
+                    return Some(Sample { report: report.clone(), stale: false });
+                }
+            }
+
+            let fetched = fetch(&url).and_then(|body| Ok((provider.parse(&body)?, body)));
+            match fetched {
+                Ok((report, body)) => {
+                    store_cache(&cache, &body);
+                    last = Some((SystemTime::now(), report.clone()));
+                    Some(Sample { report, stale: false })
+                },
+                Err(err) => {
+                    warn!("{}", err);
+                    last.as_ref().map(|(_, report)| Sample { report: report.clone(), stale: true })
+                },
+            }
+        });
+
+        Ok(Box::new(Weather { config, poller, sample: None, text: String::new() }))
+    }
+
+    fn format(&self, report: &Report) -> String {
+        let units = self.config.units;
+        let round = |value: f64| format!("{:.0}", value);
+        // Not every provider reports everything, those are left blank.
+        let optional = |value: Option<f64>| Some(value.map(round).unwrap_or_default());
+
+        format::placeholders(&self.config.format, |key| match key {
+            "icon" => Some(self.config.icons.get(report.condition.key()).map_or(report.condition.icon(), |icon| icon)
+                .to_string()),
+            "condition" => Some(report.description.clone()),
+            "temperature" => Some(round(units.temperature(report.temperature))),
+            "feels-like" => optional(report.feels_like.map(|celsius| units.temperature(celsius))),
+            "unit" => Some(units.temperature_label().to_string()),
+            "humidity" => optional(report.humidity),
+            "wind" => optional(report.wind.map(|speed| units.speed(speed))),
+            "wind-unit" => Some(units.speed_label().to_string()),
+            _ => None,
+        })
+    }
+}
+
+impl Widget for Weather {
+    fn update(&mut self) -> bool {
+        let sample = match self.poller.take() {
+            Some(sample) => sample,
+            None => return false,
+        };
+
+        let text = match &sample {
+            Some(sample) => self.format(&sample.report),
+            None => self.config.format_missing.clone(),
+        };
+
+        let changed = text != self.text || sample != self.sample;
+        self.text = text;
+        self.sample = sample;
+        changed
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match &self.sample {
+            Some(sample) if sample.stale => self.config.stale_color,
+            _ => self.config.color.unwrap_or_else(|| ctx.foreground()),
+        };
+
+        ctx.text(&self.text, color);
+    }
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        let Sample { report, stale } = self.sample.as_ref()?;
+        let units = self.config.units;
+        let temperature = |celsius: f64| format!("{:.0}{}", units.temperature(celsius), units.temperature_label());
+
+        let mut lines = vec![format!("{} {}", markup::escape(&report.description), temperature(report.temperature))];
+        lines.extend(report.feels_like.map(|celsius| format!("Feels like {}", temperature(celsius))));
+        lines.extend(report.humidity.map(|humidity| format!("Humidity {:.0}%", humidity)));
+        lines.extend(report.wind.map(|speed| format!("Wind {:.0} {}", units.speed(speed), units.speed_label())));
+        if *stale {
+            lines.push("The last fetch failed, this report is out of date".to_string());
+        }
+
+        Some(lines.join("\n"))
+    }
+}
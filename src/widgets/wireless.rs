@@ -0,0 +1,200 @@
+use serde::Deserialize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+use crate::Error;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, Waker, Widget};
+use super::worker::Poller;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct WirelessConfig {
+    // Uses the first wireless interface when unset.
+    interface: Option<String>,
+    format: String,
+    format_disconnected: String,
+    format_missing: String,
+    interval: f64,
+    // Shown through {icon}, from the weakest signal to the strongest.
+    icons: Vec<String>,
+    color: Option<Color>,
+    disconnected_color: Color,
+}
+
+impl Default for WirelessConfig {
+    fn default() -> WirelessConfig {
+        WirelessConfig {
+            interface: None,
+            format: "{icon} {ssid} {quality}%".to_string(),
+            format_disconnected: "WIFI disconnected".to_string(),
+            format_missing: String::new(),
+            interval: 5.0,
+            icons: ["▂", "▄", "▆", "█"].iter().map(|icon| icon.to_string()).collect(),
+            color: None,
+            disconnected_color: Color::from_str("#888888").unwrap(),
+        }
+    }
+}
+
+fn first_wireless_interface() -> Option<String> {
+    let mut interfaces: Vec<String> = std::fs::read_dir("/sys/class/net").ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("wireless").exists() || entry.path().join("phy80211").exists())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    interfaces.sort();
+
+    interfaces.into_iter().next()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Link {
+    ssid: String,
+    bssid: String,
+    // In dBm.
+    signal: i32,
+    // In MHz.
+    frequency: u32,
+}
+
+impl Link {
+    // Maps -100 dBm (unusable) to -50 dBm (excellent) onto 0-100%, the way
+    // NetworkManager reports signal quality.
+    fn quality(&self) -> u32 {
+        (2 * (self.signal + 100)).clamp(0, 100) as u32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    interface: String,
+    link: Option<Link>,
+}
+
+// Reads the current association from `iw dev <interface> link`, which
+// talks nl80211 on our behalf.
+fn query_link(interface: &str) -> Option<Option<Link>> {
+    let output = Command::new("iw").args(["dev", interface, "link"]).stderr(Stdio::null()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let listing = String::from_utf8_lossy(&output.stdout);
+    let bssid = match listing.lines().next().and_then(|line| line.strip_prefix("Connected to ")) {
+        Some(rest) => rest.split_whitespace().next().unwrap_or_default().to_string(),
+        None => return Some(None),
+    };
+
+    let field = |name: &str| listing.lines()
+        .find_map(|line| line.trim().strip_prefix(name).map(|value| value.trim().to_string()));
+
+    Some(Some(Link {
+        ssid: field("SSID:").unwrap_or_default(),
+        bssid,
+        signal: field("signal:")
+            .and_then(|signal| signal.split_whitespace().next().and_then(|dbm| dbm.parse().ok()))
+            .unwrap_or(-100),
+        frequency: field("freq:")
+            .and_then(|frequency| frequency.split('.').next().and_then(|mhz| mhz.parse().ok()))
+            .unwrap_or(0),
+    }))
+}
+
+fn sample(interface: &str) -> Option<Sample> {
+    if !Path::new("/sys/class/net").join(interface).exists() {
+        return None;
+    }
+
+    // An interface that is down can't be asked about its link at all.
+    let link = query_link(interface).flatten();
+    Some(Sample { interface: interface.to_string(), link })
+}
+
+pub struct Wireless {
+    config: WirelessConfig,
+    poller: Poller<Option<Sample>>,
+    sample: Option<Sample>,
+    text: String,
+}
+
+impl Wireless {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: WirelessConfig = section.clone().try_into()?;
+        let period = Duration::from_secs_f64(config.interval.max(0.1));
+
+        let interface = config.interface.clone();
+        let poller = Poller::spawn(period, waker.clone(), move || {
+            let interface = interface.clone().or_else(first_wireless_interface)?;
+            sample(&interface)
+        });
+
+        Ok(Box::new(Wireless { config, poller, sample: None, text: String::new() }))
+    }
+
+    fn icon(&self, quality: u32) -> String {
+        let icons = &self.config.icons;
+        if icons.is_empty() {
+            return String::new();
+        }
+
+        let tier = (quality as usize * icons.len() / 101).min(icons.len() - 1);
+        icons[tier].clone()
+    }
+
+    fn format(&self) -> String {
+        let sample = match &self.sample {
+            Some(sample) => sample,
+            None => return self.config.format_missing.clone(),
+        };
+
+        let link = sample.link.as_ref();
+        let template = if link.is_some() { &self.config.format } else { &self.config.format_disconnected };
+
+        // Link details are blank while disconnected rather than left as
+        // literal placeholders.
+        let detail = |value: &dyn Fn(&Link) -> String| Some(link.map(value).unwrap_or_default());
+        format::placeholders(template, |key| match key {
+            "interface" => Some(sample.interface.clone()),
+            "ssid" => detail(&|link| link.ssid.clone()),
+            "bssid" => detail(&|link| link.bssid.clone()),
+            "signal" => detail(&|link| link.signal.to_string()),
+            "quality" => detail(&|link| link.quality().to_string()),
+            "frequency" => detail(&|link| link.frequency.to_string()),
+            "icon" => detail(&|link| self.icon(link.quality())),
+            _ => None,
+        })
+    }
+}
+
+impl Widget for Wireless {
+    fn update(&mut self) -> bool {
+        let sample = match self.poller.take() {
+            Some(sample) => sample,
+            None => return false,
+        };
+
+        if sample == self.sample {
+            return false;
+        }
+
+        self.sample = sample;
+        self.text = self.format();
+        true
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.sample.as_ref()?.link.as_ref().map(|link| link.quality() as f64)
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        let color = match &self.sample {
+            Some(Sample { link: None, .. }) => self.config.disconnected_color,
+            _ => self.config.color.unwrap_or_else(|| ctx.foreground()),
+        };
+
+        ctx.text(&self.text, color);
+    }
+}
@@ -0,0 +1,164 @@
+use futures_lite::{future, Stream, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::task::AbortHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+use zbus::{Connection, MatchRule, Message, MessageStream};
+use crate::Error;
+use super::Waker;
+
+// Module updates run as tasks on one runtime shared by every widget, so the
+// event loop thread only ever picks up finished results. Polled modules and
+// the D-Bus watchers run there, watchers reading from X11, i3, pactl or a
+// command's output are still threads of their own.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("rustybar-modules")
+            .enable_time()
+            .build()
+            .expect("Failed to start the module runtime")
+    })
+}
+
+// Runs a sampling function as a task once per period and hands the newest
+// result to the widget, waking the event loop whenever a sample is ready.
+// Samples may block, they run on the runtime's blocking threads. The task
+// ends on its own once the widget owning the Poller is dropped.
+pub struct Poller<T> {
+    latest: Arc<Mutex<Option<T>>>,
+}
+
+impl<T: Send + 'static> Poller<T> {
+    pub fn spawn<F: FnMut() -> T + Send + 'static>(period: Duration, waker: Waker, sample: F) -> Poller<T> {
+        let latest = Arc::new(Mutex::new(None));
+        let shared = Arc::downgrade(&latest);
+
+        runtime().spawn(async move {
+            let mut ticks = tokio::time::interval(period);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut sample = sample;
+
+            loop {
+                ticks.tick().await;
+                let sampled = tokio::task::spawn_blocking(move || {
+                    let value = sample();
+                    (sample, value)
+                }).await;
+                let value = match sampled {
+                    Ok((returned, value)) => {
+                        sample = returned;
+                        value
+                    },
+                    Err(_) => return,
+                };
+
+                match shared.upgrade() {
+                    Some(latest) => *latest.lock().unwrap() = Some(value),
+                    None => return,
+                }
+                waker.wake();
+            }
+        });
+
+        Poller { latest }
+    }
+
+    pub fn take(&self) -> Option<T> {
+        self.latest.lock().unwrap().take()
+    }
+}
+
+// Sends a control to a service from the runtime's blocking threads. Services
+// can take a good while to answer, and system ones may have polkit ask for a
+// password first, which must not stall the bar.
+pub fn spawn_control<F: FnOnce() -> Result<(), Error> + Send + 'static>(description: String, control: F) {
+    runtime().spawn_blocking(move || {
+        if let Err(err) = control() {
+            warn!("Failed to {}: {}", description, err);
+        }
+    });
+}
+
+// A task on the runtime that's cancelled once dropped, with the widget
+// holding it.
+pub struct Task(AbortHandle);
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+// Keeps a watch over a service going, starting it again `retry` after it
+// ends. The watch is cancelled wherever it's waiting once the Task is
+// dropped, which closes its bus connection along with it.
+pub fn watch<F, W>(retry: Duration, what: String, watch: F) -> Task
+where
+    F: FnMut() -> W + Send + 'static,
+    W: Future<Output = Result<(), Error>> + Send,
+{
+    let handle = runtime().spawn(async move {
+        let mut watch = watch;
+
+        loop {
+            if let Err(err) = watch().await {
+                warn!("Lost track of {}: {}", what, err);
+            }
+
+            tokio::time::sleep(retry).await;
+        }
+    });
+
+    Task(handle.abort_handle())
+}
+
+#[derive(Debug)]
+struct ConnectionLostError;
+
+impl std::fmt::Display for ConnectionLostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the bus connection was lost")
+    }
+}
+
+impl std::error::Error for ConnectionLostError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// The signals matching any of a set of rules, as one stream.
+pub struct Signals(Pin<Box<dyn Stream<Item = zbus::Result<Message>> + Send>>);
+
+impl Signals {
+    pub async fn subscribe<I>(connection: &Connection, rules: I, queued: usize) -> Result<Signals, Error>
+    where
+        I: IntoIterator<Item = MatchRule<'static>>,
+    {
+        let mut signals: Pin<Box<dyn Stream<Item = zbus::Result<Message>> + Send>> = Box::pin(futures_lite::stream::empty());
+        for rule in rules {
+            let stream = MessageStream::for_match_rule(rule, connection, Some(queued)).await?;
+            signals = Box::pin(signals.or(stream));
+        }
+
+        Ok(Signals(signals))
+    }
+
+    // Waits for a signal and returns it with the ones that came along, so
+    // bursts of changes are handled at once.
+    pub async fn next(&mut self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![self.0.next().await.ok_or(ConnectionLostError)??];
+        while let Some(Some(message)) = future::poll_once(self.0.next()).await {
+            messages.push(message?);
+        }
+
+        Ok(messages)
+    }
+}
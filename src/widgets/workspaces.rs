@@ -0,0 +1,204 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use tracing::warn;
+use crate::Error;
+use crate::animation::Easing;
+use crate::color::Color;
+use crate::render::RenderCtx;
+use super::{format, Button, Scroll, Waker, Widget};
+use super::buttons::{ButtonStyle, Buttons, Indicator, Lines};
+use super::i3ipc::{self, Subscription, Workspace};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct WorkspacesConfig {
+    format: String,
+    padding: f32,
+    corner_radius: f32,
+    color: Option<Color>,
+    background: Option<Color>,
+    focused_color: Option<Color>,
+    focused_background: Option<Color>,
+    visible_color: Option<Color>,
+    visible_background: Option<Color>,
+    urgent_color: Option<Color>,
+    urgent_background: Option<Color>,
+    underline: Option<Color>,
+    overline: Option<Color>,
+    focused_underline: Option<Color>,
+    focused_overline: Option<Color>,
+    line_width: f32,
+    // Seconds the focused workspace's highlight takes to move over to the
+    // next one, 0 to have it jump.
+    indicator_duration: f64,
+    indicator_easing: Easing,
+}
+
+impl Default for WorkspacesConfig {
+    fn default() -> WorkspacesConfig {
+        WorkspacesConfig {
+            format: "{name}".to_string(),
+            padding: 8.0,
+            corner_radius: 0.0,
+            color: None,
+            background: None,
+            focused_color: None,
+            focused_background: Some(Color::from_str("#444444").unwrap()),
+            visible_color: None,
+            visible_background: Some(Color::from_str("#333333").unwrap()),
+            urgent_color: None,
+            urgent_background: Some(Color::from_str("#aa3333").unwrap()),
+            underline: None,
+            overline: None,
+            focused_underline: None,
+            focused_overline: None,
+            line_width: 2.0,
+            indicator_duration: 0.0,
+            indicator_easing: Easing::EaseInOut,
+        }
+    }
+}
+
+fn switch(command: String) {
+    std::thread::spawn(move || {
+        if let Err(err) = i3ipc::run_command(&command) {
+            warn!("Failed to switch workspace: {}", err);
+        }
+    });
+}
+
+pub struct Workspaces {
+    config: WorkspacesConfig,
+    subscription: Subscription<Vec<Workspace>>,
+    workspaces: Vec<Workspace>,
+    buttons: Buttons,
+    indicator: Indicator,
+}
+
+impl Workspaces {
+    pub fn build(section: &toml::Value, waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let config: WorkspacesConfig = section.clone().try_into()?;
+        Ok(Box::new(Workspaces {
+            indicator: Indicator::new(config.indicator_duration, config.indicator_easing),
+            config,
+            subscription: Subscription::spawn(&["workspace", "output"], waker.clone(), |connection| {
+                connection.workspaces()
+            }),
+            workspaces: Vec::new(),
+            buttons: Buttons::default(),
+        }))
+    }
+
+    fn style(&self, workspace: &Workspace) -> (Option<Color>, Option<Color>) {
+        let config = &self.config;
+        if workspace.urgent {
+            (config.urgent_color, config.urgent_background)
+        } else if workspace.focused {
+            (config.focused_color, config.focused_background)
+        } else if workspace.visible {
+            (config.visible_color, config.visible_background)
+        } else {
+            (config.color, config.background)
+        }
+    }
+
+    fn lines(&self, workspace: &Workspace) -> Lines {
+        let config = &self.config;
+        if workspace.focused {
+            Lines {
+                underline: config.focused_underline.or(config.underline),
+                overline: config.focused_overline.or(config.overline),
+            }
+        } else {
+            Lines { underline: config.underline, overline: config.overline }
+        }
+    }
+}
+
+impl Widget for Workspaces {
+    fn update(&mut self) -> bool {
+        match self.subscription.take() {
+            Some(workspaces) if workspaces != self.workspaces => {
+                self.workspaces = workspaces;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn render(&self, ctx: &mut RenderCtx) {
+        self.buttons.clear();
+        let style = ButtonStyle {
+            padding: self.config.padding,
+            corner_radius: self.config.corner_radius,
+            line_width: self.config.line_width,
+        };
+
+        let labels: Vec<String> = self.workspaces.iter()
+            .map(|workspace| format::placeholders(&self.config.format, |key| match key {
+                "name" => Some(workspace.name.clone()),
+                "num" => Some(workspace.num.to_string()),
+                "output" => Some(workspace.output.clone()),
+                _ => None,
+            }))
+            .collect();
+
+        // While the focus moves its highlight is drawn on its own, under a
+        // row of buttons drawn into a context of their own.
+        let focused = self.workspaces.iter().position(|workspace| workspace.focused);
+        let slide = focused.and_then(|index| {
+            let start = ctx.width() + labels[..index].iter().map(|label| style.width(ctx, label)).sum::<f32>();
+            self.indicator.place((start, start + style.width(ctx, &labels[index])))
+        });
+        let mut row = ctx.child(ctx.height());
+        row.advance(ctx.width());
+
+        for (index, (workspace, label)) in self.workspaces.iter().zip(&labels).enumerate() {
+            let (color, background) = self.style(workspace);
+            let color = color.or(self.config.color).unwrap_or_else(|| ctx.foreground());
+            match slide.filter(|_| focused == Some(index)) {
+                Some(extent) => {
+                    Buttons::highlight(ctx, extent, background, self.lines(workspace), &style);
+                    self.buttons.draw(&mut row, label, color, None, Lines::default(), &style);
+                },
+                None => self.buttons.draw(&mut row, label, color, background, self.lines(workspace), &style),
+            }
+        }
+
+        ctx.append(row, 0.0, 0.0);
+    }
+
+    fn animating(&self) -> bool {
+        self.indicator.is_moving()
+    }
+
+    fn click(&mut self, x: f32, button: Button) -> bool {
+        if button != Button::Left {
+            return false;
+        }
+
+        let workspace = match self.buttons.at(x).and_then(|index| self.workspaces.get(index)) {
+            Some(workspace) => workspace,
+            None => return false,
+        };
+
+        let command = if workspace.num >= 0 {
+            format!("workspace number {}", workspace.num)
+        } else {
+            format!("workspace \"{}\"", workspace.name.replace('\\', "\\\\").replace('"', "\\\""))
+        };
+
+        switch(command);
+        false
+    }
+
+    fn scroll(&mut self, _x: f32, direction: Scroll) -> bool {
+        let command = match direction {
+            Scroll::Up => "workspace prev_on_output",
+            Scroll::Down => "workspace next_on_output",
+        };
+
+        switch(command.to_string());
+        false
+    }
+}
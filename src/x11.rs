@@ -0,0 +1,343 @@
+use x11rb::connection::Connection;
+use x11rb::properties::WmHints;
+use x11rb::protocol::Event;
+use x11rb::protocol::randr::{ConnectionExt as _, NotifyMask};
+use x11rb::protocol::shape::{self, ConnectionExt as _};
+use x11rb::protocol::xkb::{
+    ConnectionExt as _, EventType, Group, MapPart, SelectEventsAux, SelectEventsAuxStateNotify, StatePart, ID,
+};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ClipOrdering, ConnectionExt as _, EventMask,
+    ModMask, PropMode, Rectangle, Window,
+};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use std::time::Duration;
+use crate::{Error, Vector2};
+use crate::bar::Anchor;
+use crate::config::WindowHints;
+
+x11rb::atom_manager! {
+    pub Atoms: AtomsCookie {
+        _NET_WM_STRUT,
+        _NET_WM_STRUT_PARTIAL,
+        _KDE_NET_WM_BLUR_BEHIND_REGION,
+        _NET_NUMBER_OF_DESKTOPS,
+        _NET_CURRENT_DESKTOP,
+        _NET_DESKTOP_NAMES,
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_CLOSE_WINDOW,
+        _NET_WM_DESKTOP,
+        _NET_WM_ICON,
+        _NET_WM_NAME,
+        _NET_WM_STATE,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_STATE_STICKY,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_SKIP_TASKBAR,
+        _NET_WM_STATE_SKIP_PAGER,
+        _XKB_RULES_NAMES,
+        UTF8_STRING,
+    }
+}
+
+// A second connection next to the one glutin owns, used for the window
+// manager hints winit has no API for.
+pub struct X11 {
+    connection: RustConnection,
+    root: Window,
+    root_size: Vector2<u32>,
+    atoms: Atoms,
+}
+
+impl X11 {
+    pub fn connect() -> Result<X11, Error> {
+        let (connection, screen) = x11rb::connect(None)?;
+        let root = &connection.setup().roots[screen];
+        let root_size = (root.width_in_pixels as u32, root.height_in_pixels as u32);
+        let root = root.root;
+        let atoms = Atoms::new(&connection)?.reply()?;
+
+        Ok(X11 { connection, root, root_size, atoms })
+    }
+
+    // Reserves the screen edge covered by a window so that the window manager
+    // keeps other windows from overlapping it. Position and size are in
+    // physical pixels relative to the root window.
+    pub fn set_struts(&self, window: u32, anchor: Anchor, position: Vector2<u32>, size: Vector2<u32>) -> Result<(), Error> {
+        let partial = compute_struts(anchor, position, size, self.root_size);
+
+        self.connection.change_property32(PropMode::REPLACE, window, self.atoms._NET_WM_STRUT_PARTIAL,
+            AtomEnum::CARDINAL, &partial)?;
+        self.connection.change_property32(PropMode::REPLACE, window, self.atoms._NET_WM_STRUT,
+            AtomEnum::CARDINAL, &partial[..4])?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    // KWin blurs behind the given rectangles of the window, as x, y, width
+    // and height in window coordinates.
+    pub fn set_blur(&self, window: u32, region: &[(i32, i32, i32, i32)]) -> Result<(), Error> {
+        let values: Vec<u32> = region.iter()
+            .flat_map(|&(x, y, width, height)| vec![x as u32, y as u32, width as u32, height as u32])
+            .collect();
+
+        self.connection.change_property32(PropMode::REPLACE, window, self.atoms._KDE_NET_WM_BLUR_BEHIND_REGION,
+            AtomEnum::CARDINAL, &values)?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    // Window managers only read the states they're asked for when a window
+    // is mapped, and drop them once it's unmapped, so they're set on the
+    // unmapped window before every time it's shown.
+    pub fn set_window_state(&self, window: u32, hints: &WindowHints) -> Result<(), Error> {
+        let atoms = &self.atoms;
+        let states: Vec<Atom> = [
+            (hints.sticky, atoms._NET_WM_STATE_STICKY),
+            (hints.above, atoms._NET_WM_STATE_ABOVE),
+            (hints.skip_taskbar, atoms._NET_WM_STATE_SKIP_TASKBAR),
+            (hints.skip_pager, atoms._NET_WM_STATE_SKIP_PAGER),
+        ].iter().filter(|(set, _)| *set).map(|(_, state)| *state).collect();
+
+        self.connection.change_property32(PropMode::REPLACE, window, atoms._NET_WM_STATE, AtomEnum::ATOM, &states)?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    // Whether the window manager ever gives the window keyboard focus, kept
+    // in the window's WM_HINTS along with whatever else winit put there.
+    pub fn set_input_hint(&self, window: u32, input: bool) -> Result<(), Error> {
+        let mut hints = WmHints::get(&self.connection, window)?.reply()?.unwrap_or_else(WmHints::new);
+        hints.input = Some(input);
+        hints.set(&self.connection, window)?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    // Only the given rectangles of the window take pointer input, as x, y,
+    // width and height in window coordinates, the rest goes to what's
+    // beneath it. No rectangles let everything through.
+    pub fn set_input_region(&self, window: u32, region: &[(i32, i32, i32, i32)]) -> Result<(), Error> {
+        let rectangles: Vec<Rectangle> = region.iter()
+            .map(|&(x, y, width, height)| Rectangle { x: x as i16, y: y as i16, width: width as u16, height: height as u16 })
+            .collect();
+
+        self.connection.shape_rectangles(shape::SO::SET, shape::SK::INPUT, ClipOrdering::UNSORTED, window, 0, 0,
+            &rectangles)?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    pub fn root(&self) -> Window {
+        self.root
+    }
+
+    pub fn atoms(&self) -> &Atoms {
+        &self.atoms
+    }
+
+    pub fn select_property_changes(&self, window: Window) -> Result<(), Error> {
+        let attributes = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+        self.connection.change_window_attributes(window, &attributes)?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    // Blocks until a property changes on any window selected with
+    // select_property_changes, or the keyboard changes after
+    // select_keyboard_changes.
+    pub fn wait_for_change(&self) -> Result<(), Error> {
+        loop {
+            match self.connection.wait_for_event()? {
+                Event::PropertyNotify(_) | Event::XkbStateNotify(_) | Event::XkbNewKeyboardNotify(_) => return Ok(()),
+                _ => (),
+            }
+        }
+    }
+
+    // Asks for XKB events whenever the keyboard group, which is the active
+    // layout, changes or another keyboard takes over.
+    pub fn select_keyboard_changes(&self) -> Result<(), Error> {
+        self.connection.xkb_use_extension(1, 0)?.reply()?;
+
+        let group = SelectEventsAuxStateNotify { affect_state: StatePart::GROUP_STATE, state_details: StatePart::GROUP_STATE };
+        self.connection.xkb_select_events(ID::USE_CORE_KBD.into(), EventType::from(0u16),
+            EventType::NEW_KEYBOARD_NOTIFY, MapPart::from(0u16), MapPart::from(0u16),
+            &SelectEventsAux::new().state_notify(group))?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    pub fn keyboard_group(&self) -> Result<u8, Error> {
+        Ok(self.connection.xkb_get_state(ID::USE_CORE_KBD.into())?.reply()?.group.into())
+    }
+
+    pub fn lock_keyboard_group(&self, group: u8) -> Result<(), Error> {
+        let none = ModMask::from(0u16);
+        self.connection.xkb_latch_lock_state(ID::USE_CORE_KBD.into(), none, none, true, Group::from(group), none,
+            false, 0)?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    // The layouts configured with setxkbmap or the X server config, such as
+    // ["us", "de"]; one per keyboard group.
+    pub fn keyboard_layouts(&self) -> Result<Vec<String>, Error> {
+        let reply = self.connection.get_property(false, self.root, self.atoms._XKB_RULES_NAMES, AtomEnum::STRING,
+            0, u32::MAX)?.reply()?;
+
+        // Rules, model, layouts, variants and options, null separated.
+        let layouts = reply.value.split(|byte| *byte == 0).nth(2).unwrap_or_default();
+        Ok(String::from_utf8_lossy(layouts).split(',').map(str::to_string).filter(|layout| !layout.is_empty()).collect())
+    }
+
+    pub fn cardinal(&self, window: Window, property: Atom) -> Result<Option<u32>, Error> {
+        self.value32(window, property, AtomEnum::CARDINAL.into())
+    }
+
+    pub fn window(&self, window: Window, property: Atom) -> Result<Option<Window>, Error> {
+        Ok(self.value32(window, property, AtomEnum::WINDOW.into())?.filter(|window| *window != 0))
+    }
+
+    // Reads a list of windows, such as the _NET_CLIENT_LIST of the root
+    // window.
+    pub fn windows(&self, window: Window, property: Atom) -> Result<Vec<Window>, Error> {
+        let reply = self.connection.get_property(false, window, property, AtomEnum::WINDOW, 0, u32::MAX)?.reply()?;
+        Ok(reply.value32().map(|values| values.collect()).unwrap_or_default())
+    }
+
+    pub fn cardinals(&self, window: Window, property: Atom) -> Result<Vec<u32>, Error> {
+        let reply = self.connection.get_property(false, window, property, AtomEnum::CARDINAL, 0, u32::MAX)?.reply()?;
+        Ok(reply.value32().map(|values| values.collect()).unwrap_or_default())
+    }
+
+    fn value32(&self, window: Window, property: Atom, kind: Atom) -> Result<Option<u32>, Error> {
+        let reply = self.connection.get_property(false, window, property, kind, 0, 1)?.reply()?;
+        Ok(reply.value32().and_then(|mut values| values.next()))
+    }
+
+    // Prefers the EWMH title and falls back to the legacy WM_NAME.
+    pub fn title(&self, window: Window) -> Result<Option<String>, Error> {
+        if let Some(title) = self.utf8_strings(window, self.atoms._NET_WM_NAME)?.into_iter().next() {
+            return Ok(Some(title));
+        }
+
+        let reply = self.connection.get_property(false, window, AtomEnum::WM_NAME, AtomEnum::ANY, 0, u32::MAX)?
+            .reply()?;
+        if reply.value.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(String::from_utf8_lossy(&reply.value).into_owned()))
+    }
+
+    // The class half of WM_CLASS, which names the application rather than
+    // the instance.
+    pub fn class(&self, window: Window) -> Result<Option<String>, Error> {
+        let reply = self.connection.get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, u32::MAX)?
+            .reply()?;
+        Ok(reply.value.split(|byte| *byte == 0).nth(1)
+            .filter(|class| !class.is_empty())
+            .map(|class| String::from_utf8_lossy(class).into_owned()))
+    }
+
+    pub fn atom_list(&self, window: Window, property: Atom) -> Result<Vec<Atom>, Error> {
+        let reply = self.connection.get_property(false, window, property, AtomEnum::ATOM, 0, u32::MAX)?.reply()?;
+        Ok(reply.value32().map(|values| values.collect()).unwrap_or_default())
+    }
+
+    // Reads a list of null separated UTF-8 strings.
+    pub fn utf8_strings(&self, window: Window, property: Atom) -> Result<Vec<String>, Error> {
+        let reply = self.connection.get_property(false, window, property, self.atoms.UTF8_STRING, 0, u32::MAX)?
+            .reply()?;
+        let mut strings: Vec<String> = reply.value.split(|byte| *byte == 0)
+            .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+            .collect();
+
+        if reply.value.last() == Some(&0) {
+            strings.pop();
+        }
+
+        Ok(strings)
+    }
+
+    // Sends a client message to the root window the way EWMH pagers and
+    // taskbars make requests to the window manager.
+    pub fn send_root_message(&self, window: Window, message: Atom, data: [u32; 5]) -> Result<(), Error> {
+        let event = ClientMessageEvent::new(32, window, message, data);
+        self.connection.send_event(false, self.root,
+            EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT, event)?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    // Calls on_change from a background thread whenever a monitor is plugged
+    // in, removed or changes resolution. winit refreshes its own monitor list
+    // from the same RandR events, so bursts of events are allowed to settle
+    // before anyone is told about them.
+    pub fn watch_outputs<F: Fn() + Send + 'static>(self, on_change: F) -> Result<(), Error> {
+        self.connection.randr_query_version(1, 2)?.reply()?;
+        self.connection.randr_select_input(self.root,
+            NotifyMask::SCREEN_CHANGE | NotifyMask::OUTPUT_CHANGE | NotifyMask::CRTC_CHANGE)?;
+        self.connection.flush()?;
+
+        std::thread::spawn(move || {
+            while let Ok(event) = self.connection.wait_for_event() {
+                if !matches!(event, Event::RandrScreenChangeNotify(_) | Event::RandrNotify(_)) {
+                    continue;
+                }
+
+                std::thread::sleep(Duration::from_millis(500));
+                while let Ok(Some(_)) = self.connection.poll_for_event() {}
+
+                on_change();
+            }
+        });
+
+        Ok(())
+    }
+}
+
+// Layout of _NET_WM_STRUT_PARTIAL: left, right, top, bottom, then the start
+// and end coordinates of each of those four edges.
+fn compute_struts(anchor: Anchor, position: Vector2<u32>, size: Vector2<u32>, root_size: Vector2<u32>) -> [u32; 12] {
+    let mut struts = [0; 12];
+    let (start_x, end_x) = (position.0, (position.0 + size.0).saturating_sub(1));
+    let (start_y, end_y) = (position.1, (position.1 + size.1).saturating_sub(1));
+
+    match anchor {
+        Anchor::Left => {
+            struts[0] = position.0 + size.0;
+            struts[4] = start_y;
+            struts[5] = end_y;
+        },
+        Anchor::Right => {
+            struts[1] = root_size.0.saturating_sub(position.0);
+            struts[6] = start_y;
+            struts[7] = end_y;
+        },
+        Anchor::Top => {
+            struts[2] = position.1 + size.1;
+            struts[8] = start_x;
+            struts[9] = end_x;
+        },
+        Anchor::Bottom => {
+            struts[3] = root_size.1.saturating_sub(position.1);
+            struts[10] = start_x;
+            struts[11] = end_x;
+        },
+    }
+
+    struts
+}
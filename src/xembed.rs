@@ -0,0 +1,273 @@
+use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME};
+use x11rb::connection::Connection;
+use x11rb::protocol::Event;
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConfigureWindowAux, ConnectionExt as _,
+    CreateWindowAux, EventMask, PropMode, Window, WindowClass,
+};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use std::sync::{Arc, Mutex, Weak};
+use crate::{Error, Vector2};
+use crate::widgets::Waker;
+
+// The system tray protocol older X11 applications use: the tray owns the
+// _NET_SYSTEM_TRAY_S<screen> selection, applications ask it to dock their
+// icon window and the tray reparents that window into the bar following
+// XEmbed.
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_SYSTEM_TRAY_OPCODE,
+        _NET_SYSTEM_TRAY_ORIENTATION,
+        _NET_SYSTEM_TRAY_VISUAL,
+        _XEMBED,
+        _XEMBED_INFO,
+        MANAGER,
+    }
+}
+
+const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+const XEMBED_MAPPED: u32 = 1;
+
+#[derive(Debug)]
+struct TrayTakenError;
+
+impl std::fmt::Display for TrayTakenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Another system tray is already running")
+    }
+}
+
+impl std::error::Error for TrayTakenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+struct Icon {
+    window: Window,
+    mapped: bool,
+}
+
+struct State {
+    parent: Window,
+    icons: Vec<Icon>,
+    // Position and size the icons were last placed at, in physical pixels.
+    layout: Option<(i32, i32, u32)>,
+    changed: bool,
+}
+
+pub struct Tray {
+    connection: Arc<RustConnection>,
+    atoms: Atoms,
+    root: Window,
+    owner: Window,
+    state: Arc<Mutex<State>>,
+}
+
+impl Tray {
+    // Takes over the tray selection and docks icons into the parent window.
+    pub fn spawn(parent: Window, waker: Waker) -> Result<Tray, Error> {
+        let (connection, screen) = x11rb::connect(None)?;
+        let connection = Arc::new(connection);
+        let root = connection.setup().roots[screen].root;
+        let atoms = Atoms::new(&*connection)?.reply()?;
+        let selection = connection.intern_atom(false, format!("_NET_SYSTEM_TRAY_S{}", screen).as_bytes())?
+            .reply()?.atom;
+
+        if connection.get_selection_owner(selection)?.reply()?.owner != x11rb::NONE {
+            return Err(Box::new(TrayTakenError));
+        }
+
+        let owner = connection.generate_id()?;
+        connection.create_window(COPY_DEPTH_FROM_PARENT, owner, root, -1, -1, 1, 1, 0, WindowClass::INPUT_ONLY,
+            COPY_FROM_PARENT, &CreateWindowAux::new().event_mask(EventMask::STRUCTURE_NOTIFY))?;
+
+        // Icons are asked to match the bar's visual, so that transparent
+        // bars get icons with an alpha channel.
+        let visual = connection.get_window_attributes(parent)?.reply()?.visual;
+        connection.change_property32(PropMode::REPLACE, owner, atoms._NET_SYSTEM_TRAY_ORIENTATION,
+            AtomEnum::CARDINAL, &[0])?;
+        connection.change_property32(PropMode::REPLACE, owner, atoms._NET_SYSTEM_TRAY_VISUAL,
+            AtomEnum::VISUALID, &[visual])?;
+
+        connection.set_selection_owner(owner, selection, CURRENT_TIME)?;
+        if connection.get_selection_owner(selection)?.reply()?.owner != owner {
+            return Err(Box::new(TrayTakenError));
+        }
+
+        let announcement = ClientMessageEvent::new(32, root, atoms.MANAGER, [CURRENT_TIME, selection, owner, 0, 0]);
+        connection.send_event(false, root, EventMask::STRUCTURE_NOTIFY, announcement)?;
+        connection.flush()?;
+
+        let state = Arc::new(Mutex::new(State { parent, icons: Vec::new(), layout: None, changed: false }));
+        let tray = Tray { connection, atoms, root, owner, state };
+        tray.listen(Arc::downgrade(&tray.state), waker);
+
+        Ok(tray)
+    }
+
+    fn listen(&self, shared: Weak<Mutex<State>>, waker: Waker) {
+        let (connection, atoms, root, owner) = (Arc::clone(&self.connection), self.atoms, self.root, self.owner);
+
+        std::thread::spawn(move || {
+            // Errors about icons that went away are reported as events too,
+            // they need no handling beyond the DestroyNotify that follows.
+            while let Ok(event) = connection.wait_for_event() {
+                let state = match shared.upgrade() {
+                    Some(state) => state,
+                    None => return,
+                };
+                let mut state = state.lock().unwrap();
+
+                let changed = match event {
+                    Event::ClientMessage(message) if message.type_ == atoms._NET_SYSTEM_TRAY_OPCODE => {
+                        let data = message.data.as_data32();
+                        data[1] == SYSTEM_TRAY_REQUEST_DOCK && dock(&connection, &atoms, &mut state, data[2]).is_ok()
+                    },
+                    Event::DestroyNotify(event) if event.window == owner => return,
+                    Event::DestroyNotify(event) => forget(&mut state, event.window),
+                    Event::ReparentNotify(event) if event.parent != state.parent => forget(&mut state, event.window),
+                    Event::PropertyNotify(event) if event.atom == atoms._XEMBED_INFO => {
+                        update_mapping(&connection, &atoms, &mut state, event.window).unwrap_or(false)
+                    },
+                    Event::SelectionClear(_) => {
+                        // Another tray took over, it gets the icons.
+                        release(&connection, root, &mut state);
+                        state.changed = true;
+                        drop(state);
+                        waker.wake();
+                        return;
+                    },
+                    _ => false,
+                };
+
+                if changed {
+                    state.layout = None;
+                    state.changed = true;
+                    drop(state);
+                    waker.wake();
+                }
+            }
+        });
+    }
+
+    // Moves the icons into another window, for when the bar's windows are
+    // recreated.
+    pub fn set_parent(&self, parent: Window) {
+        let mut state = self.state.lock().unwrap();
+        for icon in &state.icons {
+            let _ = self.connection.reparent_window(icon.window, parent, 0, 0);
+        }
+
+        state.parent = parent;
+        state.layout = None;
+        let _ = self.connection.flush();
+    }
+
+    // Returns true once after icons were docked, removed, shown or hidden.
+    pub fn take_changed(&self) -> bool {
+        std::mem::replace(&mut self.state.lock().unwrap().changed, false)
+    }
+
+    pub fn count(&self) -> usize {
+        self.state.lock().unwrap().icons.iter().filter(|icon| icon.mapped).count()
+    }
+
+    // Lays the shown icons out left to right, or top to bottom, from the
+    // given position in physical pixels relative to the parent window.
+    pub fn place(&self, position: Vector2<f32>, size: f32, spacing: f32, vertical: bool) {
+        let mut state = self.state.lock().unwrap();
+        let layout = (position.0.round() as i32, position.1.round() as i32, size.round().max(1.0) as u32);
+        if state.layout == Some(layout) {
+            return;
+        }
+
+        let (mut x, mut y, size) = layout;
+        let step = size as i32 + spacing.round() as i32;
+        for icon in state.icons.iter().filter(|icon| icon.mapped) {
+            let geometry = ConfigureWindowAux::new().x(x).y(y).width(size).height(size);
+            let _ = self.connection.configure_window(icon.window, &geometry);
+            match vertical {
+                true => y += step,
+                false => x += step,
+            }
+        }
+
+        state.layout = Some(layout);
+        let _ = self.connection.flush();
+    }
+}
+
+impl Drop for Tray {
+    // Icons are handed back to the root window before the bar's windows,
+    // and with them any child windows, are destroyed.
+    fn drop(&mut self) {
+        release(&self.connection, self.root, &mut self.state.lock().unwrap());
+        let _ = self.connection.destroy_window(self.owner);
+        let _ = self.connection.flush();
+    }
+}
+
+fn dock(connection: &RustConnection, atoms: &Atoms, state: &mut State, window: Window) -> Result<(), Error> {
+    if state.icons.iter().any(|icon| icon.window == window) {
+        return Ok(());
+    }
+
+    let events = ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE);
+    connection.change_window_attributes(window, &events)?;
+    connection.reparent_window(window, state.parent, 0, 0)?;
+
+    let notify = ClientMessageEvent::new(32, window, atoms._XEMBED,
+        [CURRENT_TIME, XEMBED_EMBEDDED_NOTIFY, 0, state.parent, 0]);
+    connection.send_event(false, window, EventMask::NO_EVENT, notify)?;
+
+    state.icons.push(Icon { window, mapped: false });
+    update_mapping(connection, atoms, state, window)?;
+    connection.flush()?;
+
+    Ok(())
+}
+
+// Maps or unmaps an icon as its _XEMBED_INFO asks, icons without the
+// property are always shown. Returns true when that changed.
+fn update_mapping(connection: &RustConnection, atoms: &Atoms, state: &mut State, window: Window) -> Result<bool, Error> {
+    let icon = match state.icons.iter_mut().find(|icon| icon.window == window) {
+        Some(icon) => icon,
+        None => return Ok(false),
+    };
+
+    let info = connection.get_property(false, window, atoms._XEMBED_INFO, atoms._XEMBED_INFO, 0, 2)?.reply()?;
+    let flags = info.value32().and_then(|mut values| values.nth(1));
+    let mapped = flags.is_none_or(|flags| flags & XEMBED_MAPPED != 0);
+
+    if mapped == icon.mapped {
+        return Ok(false);
+    }
+
+    icon.mapped = mapped;
+    if mapped {
+        connection.map_window(window)?;
+    } else {
+        connection.unmap_window(window)?;
+    }
+    connection.flush()?;
+
+    Ok(true)
+}
+
+fn forget(state: &mut State, window: Window) -> bool {
+    let count = state.icons.len();
+    state.icons.retain(|icon| icon.window != window);
+    state.icons.len() != count
+}
+
+fn release(connection: &RustConnection, root: Window, state: &mut State) {
+    for icon in state.icons.drain(..) {
+        let _ = connection.unmap_window(icon.window);
+        let _ = connection.reparent_window(icon.window, root, 0, 0);
+    }
+    let _ = connection.flush();
+}
@@ -0,0 +1,66 @@
+// Uses the library the way a binary with widgets of its own would.
+use rustybar::render::RenderCtx;
+use rustybar::widgets::{Registry, Waker, Widget};
+use rustybar::{Config, Error};
+
+struct Fixed(String);
+
+impl Fixed {
+    fn build(section: &toml::Value, _waker: &Waker) -> Result<Box<dyn Widget>, Error> {
+        let text = section.get("text").and_then(|text| text.as_str()).unwrap_or("fixed");
+        Ok(Box::new(Fixed(text.to_string())))
+    }
+}
+
+impl Widget for Fixed {
+    fn update(&mut self) -> bool {
+        false
+    }
+
+    fn render(&self, _ctx: &mut RenderCtx) {}
+
+    fn tooltip(&self, _x: f32) -> Option<String> {
+        Some(self.0.clone())
+    }
+}
+
+fn waker() -> Waker {
+    Waker::new(|| {})
+}
+
+#[test]
+fn registered_widgets_are_built_by_name_and_type() {
+    let mut registry = Registry::default();
+    assert!(!registry.has("fixed"));
+    registry.register("fixed", Fixed::build);
+    assert!(registry.has("fixed"));
+
+    let widget = registry.build("fixed", None, &waker()).unwrap();
+    assert_eq!(widget.tooltip(0.0).as_deref(), Some("fixed"));
+
+    let section: toml::Value = toml::from_str("type = \"fixed\"\ntext = \"hello\"").unwrap();
+    let widget = registry.build("greeting", Some(&section), &waker()).unwrap();
+    assert_eq!(widget.tooltip(0.0).as_deref(), Some("hello"));
+}
+
+#[test]
+fn the_default_registry_has_the_builtin_widgets() {
+    let registry = Registry::default();
+    for kind in ["clock", "cpu", "custom", "memory", "separator", "spacer"] {
+        assert!(registry.has(kind), "{}", kind);
+    }
+    assert!(registry.build("no-such-widget", None, &waker()).is_err());
+}
+
+#[test]
+fn configs_load_from_a_path() {
+    let path = std::env::temp_dir().join(format!("rustybar-library-{}.toml", std::process::id()));
+    std::fs::write(&path, "modules-left = [\"fixed\"]\n\n[module.fixed]\ntext = \"hello\"\n").unwrap();
+    let config = Config::load_from(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let config = config.unwrap();
+    assert_eq!(config.modules_left, ["fixed"]);
+    assert_eq!(config.module["fixed"].get("text").and_then(|text| text.as_str()), Some("hello"));
+    assert!(Config::load_from(&path).is_err());
+}